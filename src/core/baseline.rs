@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use super::error::Result;
+use super::event::ProcessEvent;
+use crate::utils::process::parent_and_comm;
+
+// what counts as "seen before", the pid, arguments and time are left out on purpose
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub exe: String,
+    pub uid: Option<u32>,
+    // comm of the parent, exe links of other users' processes are not readable
+    pub parent: Option<String>,
+}
+
+impl BaselineEntry {
+    pub fn of(process: &ProcessEvent) -> Self {
+        let exe = match &process.exe {
+            Some(exe) => exe.display().to_string(),
+            None => process
+                .cmdline
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        };
+        let parent = process.ppid.and_then(parent_and_comm).map(|(_, comm)| comm);
+        Self {
+            exe,
+            uid: process.uid,
+            parent,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BaselineFile {
+    entries: BTreeSet<BaselineEntry>,
+}
+
+pub struct Baseline {
+    path: PathBuf,
+    entries: BTreeSet<BaselineEntry>,
+    learning: bool,
+}
+
+impl Baseline {
+    // learning extends an existing file, so a baseline can be built over several runs
+    pub fn learn(path: &Path) -> Result<Self> {
+        let entries = if path.exists() {
+            Self::read(path)?
+        } else {
+            BTreeSet::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+            learning: true,
+        })
+    }
+
+    pub fn enforce(path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: Self::read(path)?,
+            learning: false,
+        })
+    }
+
+    fn read(path: &Path) -> Result<BTreeSet<BaselineEntry>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read baseline {}: {}", path.display(), e))?;
+        let file: BaselineFile = serde_json::from_str(&content)
+            .map_err(|e| format!("invalid baseline {}: {}", path.display(), e))?;
+        Ok(file.entries)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // true when the process should be printed, i.e. always while learning
+    pub fn check(&mut self, process: &ProcessEvent) -> bool {
+        let entry = BaselineEntry::of(process);
+        if self.learning {
+            self.entries.insert(entry);
+            true
+        } else {
+            !self.entries.contains(&entry)
+        }
+    }
+
+    // writes what was learned and switches to enforcing it
+    pub fn save(&mut self) -> Result<()> {
+        let file = BaselineFile {
+            entries: std::mem::take(&mut self.entries),
+        };
+        let written = serde_json::to_string_pretty(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                std::fs::write(&self.path, content + "\n").map_err(|e| e.to_string())
+            });
+        self.entries = file.entries;
+        self.learning = false;
+        written.map_err(|e| format!("failed to write baseline {}: {}", self.path.display(), e))?;
+        Ok(())
+    }
+}
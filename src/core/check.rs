@@ -0,0 +1,335 @@
+use colored::Colorize;
+use std::sync::mpsc;
+
+use super::{
+    constants::{CHECK_MIN_INOTIFY_WATCHES, ROOT_UID, YAMA_PTRACE_SCOPE_FILE},
+    error::Result,
+    logger::Logger,
+};
+use crate::monitoring::{
+    audit::AuditListener, fanotify::FanotifyWatcher, filesystem::max_user_watches,
+    udev::UdevListener,
+};
+use crate::utils::{caps, process::proc_mount, procparse::ProcReader};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warning,
+    Failed,
+}
+
+struct Finding {
+    status: Status,
+    area: &'static str,
+    detail: String,
+    // what to run or pass instead
+    hint: Option<String>,
+}
+
+impl Finding {
+    fn new(status: Status, area: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            area,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+// `rspy check`, tries what the monitors need as the current user and says which flags will
+// see nothing, before a run that silently prints nothing does
+pub fn run() -> Result<()> {
+    let euid = unsafe { libc::geteuid() };
+    let findings = [
+        identity(euid),
+        procfs(euid),
+        process_details(),
+        ptrace_scope(),
+        inotify(),
+        fanotify(),
+        audit(),
+        udev(),
+        dbus(euid),
+    ];
+
+    for finding in &findings {
+        let status = match finding.status {
+            Status::Ok => "ok".green(),
+            Status::Warning => "warn".yellow().bold(),
+            Status::Failed => "fail".red().bold(),
+        };
+        Logger::status(format!(
+            "  {:<6}{:<10}{}",
+            status, finding.area, finding.detail
+        ));
+        if let Some(hint) = &finding.hint {
+            Logger::status(format!("{:16}-> {}", "", hint).dimmed());
+        }
+    }
+
+    let count = |status| {
+        findings
+            .iter()
+            .filter(|finding| finding.status == status)
+            .count()
+    };
+    Logger::info(format!(
+        "{} checks, {} warnings, {} failed",
+        findings.len(),
+        count(Status::Warning),
+        count(Status::Failed)
+    ));
+    Ok(())
+}
+
+fn identity(euid: u32) -> Finding {
+    let capeff = ProcReader::new()
+        .status(std::process::id() as i32)
+        .map(|status| status.capeff)
+        .unwrap_or_default();
+    let names = caps::names(capeff);
+    let missing = caps::names(caps::known() & !capeff);
+    // root usually has (nearly) the full set, then what is missing says more
+    let capabilities = if names.is_empty() {
+        "no capabilities".to_string()
+    } else if missing.is_empty() {
+        "all capabilities".to_string()
+    } else if missing.len() < names.len() {
+        format!("all capabilities but {}", missing.join(","))
+    } else {
+        format!("capabilities {}", names.join(","))
+    };
+    let detail = format!("running as uid {} with {}", euid, capabilities);
+    if euid == ROOT_UID {
+        Finding::new(Status::Ok, "user", detail)
+    } else {
+        Finding::new(Status::Warning, "user", detail)
+            .hint("most of what follows works best as root, e.g. `sudo rspy check`")
+    }
+}
+
+// hidepid= on /proc hides the processes of other users from everyone outside its gid=
+fn procfs(euid: u32) -> Finding {
+    let Some(mount) = proc_mount() else {
+        return Finding::new(Status::Failed, "procfs", "/proc is not mounted")
+            .hint("mount -t proc proc /proc");
+    };
+    match (mount.hidepid, euid == ROOT_UID) {
+        (None, _) => Finding::new(
+            Status::Ok,
+            "procfs",
+            "every process is visible (no hidepid on /proc)",
+        ),
+        (Some(hidepid), true) => Finding::new(
+            Status::Ok,
+            "procfs",
+            format!(
+                "/proc has hidepid={}, root still sees every process",
+                hidepid
+            ),
+        ),
+        (Some(hidepid), false) => {
+            let finding = Finding::new(
+                Status::Failed,
+                "procfs",
+                format!(
+                    "/proc has hidepid={}, processes of other users are invisible to the scanner",
+                    hidepid
+                ),
+            );
+            match mount.gid {
+                Some(gid) => finding.hint(format!("run as root or as a member of group {}", gid)),
+                None => {
+                    finding.hint("run as root, or use --dbus to learn about processes from systemd")
+                }
+            }
+        }
+    }
+}
+
+// the exe, cwd and fd links of a process need ptrace read access, pid 1 is never ours
+fn process_details() -> Finding {
+    match std::fs::read_link("/proc/1/exe") {
+        Ok(_) => Finding::new(
+            Status::Ok,
+            "details",
+            "exe, cwd and open fds of other users' processes are readable",
+        ),
+        Err(e) => Finding::new(
+            Status::Warning,
+            "details",
+            format!("exe, cwd and open fds of other users' processes can not be read ({})", e),
+        )
+        .hint("that takes root with cap_sys_ptrace in the host's namespaces, without it --show-fds, --show-cwd, --hash-exe, --preserve-exe and the socket owners of --network only see your own processes"),
+    }
+}
+
+fn ptrace_scope() -> Finding {
+    let Ok(scope) = std::fs::read_to_string(YAMA_PTRACE_SCOPE_FILE) else {
+        return Finding::new(
+            Status::Ok,
+            "ptrace",
+            "yama is not enabled, ptrace is not restricted",
+        );
+    };
+    let meaning = match scope.trim() {
+        "0" => "any process of the same user can be traced",
+        "1" => "only descendants can be traced",
+        "2" => "only CAP_SYS_PTRACE can trace",
+        "3" => "no process can be traced",
+        _ => "unknown setting",
+    };
+    // yama only restricts attaching, not the read access rspy needs for /proc
+    Finding::new(
+        Status::Ok,
+        "ptrace",
+        format!(
+            "kernel.yama.ptrace_scope is {}, {}. rspy never attaches, it does not depend on it",
+            scope.trim(),
+            meaning
+        ),
+    )
+}
+
+fn inotify() -> Finding {
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd == -1 {
+        return Finding::new(
+            Status::Failed,
+            "inotify",
+            format!(
+                "inotify is not available ({})",
+                std::io::Error::last_os_error()
+            ),
+        )
+        .hint("other programs may use up fs.inotify.max_user_instances, raise it with sysctl");
+    }
+    unsafe {
+        libc::close(fd);
+    }
+
+    match max_user_watches() {
+        Some(limit) if limit < CHECK_MIN_INOTIFY_WATCHES => Finding::new(
+            Status::Warning,
+            "inotify",
+            format!(
+                "fs.inotify.max_user_watches is {}, recursive watches of large trees stop early",
+                limit
+            ),
+        )
+        .hint(format!(
+            "sysctl fs.inotify.max_user_watches={}, or narrow them with -r/-d and --max-watches",
+            CHECK_MIN_INOTIFY_WATCHES * 8
+        )),
+        Some(limit) => Finding::new(
+            Status::Ok,
+            "inotify",
+            format!("fs.inotify.max_user_watches is {}", limit),
+        ),
+        None => Finding::new(
+            Status::Ok,
+            "inotify",
+            "available, the watch limit is unknown",
+        ),
+    }
+}
+
+fn fanotify() -> Finding {
+    let (sender, _) = mpsc::channel();
+    let (trigger, _) = mpsc::channel();
+    match FanotifyWatcher::new(sender, trigger, Vec::new(), Vec::new(), false, false, false) {
+        Ok(_) => Finding::new(Status::Ok, "fanotify", "available, --fanotify works"),
+        Err(e) => Finding::new(
+            Status::Warning,
+            "fanotify",
+            format!("not available ({}), it needs CAP_SYS_ADMIN", e),
+        )
+        .hint("without --fanotify rspy uses inotify, which does not tell who opened a file"),
+    }
+}
+
+fn audit() -> Finding {
+    let (sender, _) = mpsc::channel();
+    match AuditListener::new(sender) {
+        Ok(_) => Finding::new(
+            Status::Ok,
+            "audit",
+            "the audit log can be read, --audit works",
+        )
+        .hint("execve records also need a rule: auditctl -a always,exit -F arch=b64 -S execve"),
+        Err(e) => Finding::new(
+            Status::Warning,
+            "audit",
+            format!(
+                "the audit log can not be read ({}), it needs CAP_AUDIT_READ",
+                e
+            ),
+        )
+        .hint(
+            "leave out --audit, the procfs scanner still sees processes that live a scan interval",
+        ),
+    }
+}
+
+fn udev() -> Finding {
+    let (sender, _) = mpsc::channel();
+    match UdevListener::new(sender) {
+        Ok(_) => Finding::new(
+            Status::Ok,
+            "udev",
+            "kernel uevents can be read, --udev works",
+        ),
+        Err(e) => Finding::new(
+            Status::Warning,
+            "udev",
+            format!(
+                "kernel uevents can not be read ({}), --udev sees nothing",
+                e
+            ),
+        ),
+    }
+}
+
+#[cfg(feature = "dbus")]
+fn dbus(euid: u32) -> Finding {
+    match crate::monitoring::dbus::probe_systemd() {
+        Ok(processes) => {
+            let finding = Finding::new(
+                Status::Ok,
+                "dbus",
+                format!(
+                    "systemd lists {} processes, --dbus and --show-unit work",
+                    processes
+                ),
+            );
+            if euid == ROOT_UID {
+                finding
+            } else {
+                finding.hint("--dbus-sniff and --polkit need root to monitor the bus")
+            }
+        }
+        Err(e) => Finding::new(
+            Status::Warning,
+            "dbus",
+            format!("systemd can not be asked over the system bus ({})", e),
+        )
+        .hint("leave out --dbus, --dbus-only, --show-unit, --dbus-sniff and --polkit"),
+    }
+}
+
+#[cfg(not(feature = "dbus"))]
+fn dbus(_euid: u32) -> Finding {
+    Finding::new(
+        Status::Warning,
+        "dbus",
+        "rspy was built without dbus support",
+    )
+    .hint("rebuild with `--features dbus` to use --dbus")
+}
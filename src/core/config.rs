@@ -1,36 +1,197 @@
-use clap::Parser;
+use clap::builder::FalseyValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use globset::Glob;
+use regex::Regex;
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use super::constants::{DEFAULT_RECURSIVE_DIRS, DEFAULT_SCAN_INTERVAL_MS, LOW_RESOURCE_WATCH_DIRS};
+use crate::monitoring::filesystem::parse_watch_events;
+use crate::utils::daemon;
+use crate::utils::format::{Template, parse_duration, parse_millis};
+use crate::utils::lru::CacheLimits;
+use crate::utils::throttle::Priority;
+
+use super::constants::{
+    DEFAULT_BURST_COUNT, DEFAULT_BURST_INTERVAL_MS, DEFAULT_RECURSIVE_DIRS,
+    DEFAULT_SCAN_INTERVAL_MS, FORMAT_TEMPLATE_FIELDS, LOW_RESOURCE_CPU_LIMIT_PERCENT,
+    LOW_RESOURCE_NICE, LOW_RESOURCE_WATCH_DIRS, ROOT_UID, STEALTH_SCAN_INTERVAL_MS,
+    STEALTH_SCAN_JITTER_PERCENT,
+};
+use super::output::OutputFormat;
+use super::profiles::Profile;
+use super::severity::Severity;
+use super::sinks::route::Route;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    // 2024-01-31 13:37:00
+    #[default]
+    Default,
+    // 2024-01-31 13:37:00.123
+    Ms,
+    // 2024-01-31T13:37:00.123+01:00
+    Iso,
+    // 1706704620.123
+    Epoch,
+    // +12.345s since rspy started
+    Relative,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    // parsed again as the top-level flags, so `rspy monitor -r /tmp` and `rspy -r /tmp` are
+    // the same and the monitoring flags do not leak into the other commands
+    #[command(
+        about = "watch processes, files and everything else the flags enable (the default without a command)",
+        disable_help_flag = true
+    )]
+    Monitor {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+        args: Vec<OsString>,
+    },
+    #[command(
+        about = "print every running process with its exe, working directory and sockets, then exit"
+    )]
+    Snapshot,
+    #[command(about = "keep the process tree on screen, processes started meanwhile are marked")]
+    Tree {
+        #[arg(long, value_parser = parse_millis, default_value = "500ms")]
+        #[arg(
+            help = "interval between procfs scans, the tree is drawn again after each one that changed it"
+        )]
+        interval: Duration,
+    },
+    #[command(
+        about = "try what the monitors need as the current user and suggest flags for what does not work"
+    )]
+    Check,
+    #[command(about = "play a recording made with --record back through the filters")]
+    Replay {
+        #[arg(help = "recording to play back")]
+        file: PathBuf,
+
+        #[arg(long, default_value_t = 1.0)]
+        #[arg(help = "playback speed multiplier, 0 replays as fast as possible")]
+        speed: f64,
+    },
+    #[command(about = "print the commands, files and connections only one of two recordings saw")]
+    Diff {
+        #[arg(help = "recording to compare, e.g. of a quiet baseline window")]
+        first: PathBuf,
+
+        #[arg(help = "recording to compare it with, e.g. of an incident window")]
+        second: PathBuf,
+    },
+}
 
 #[derive(Parser)]
 #[command(name = "rspy")]
 pub struct Config {
-    #[arg(short = 'f', long = "print-filesystem-events")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(short = 'f', long = "print-filesystem-events", global = true)]
     #[arg(help = "enables printing file system events to stdout (disabled by default)")]
     pub print_filesystem_events: bool,
 
     #[arg(short = 'r', long = "recursive-watch")]
-    #[arg(help = "list of directories to watch with Inotify recursively")]
+    #[arg(
+        help = "list of directories to watch with Inotify recursively, dir:create,delete limits the events for one directory"
+    )]
     pub recursive_watch_dirs: Vec<String>,
 
     #[arg(short = 'd', long = "direct-watch")]
-    #[arg(help = "list of directories to watch with inotify directly, not the subdirectories")]
+    #[arg(
+        help = "list of directories to watch with inotify directly, not the subdirectories, or single files like /etc/passwd"
+    )]
     pub direct_watch_dirs: Vec<String>,
 
+    #[arg(long = "watch-events", value_parser = parse_watch_events, global = true)]
+    #[arg(help = "inotify events to watch instead of all of them, e.g. create,modify,close_write")]
+    pub watch_events: Option<u32>,
+
+    #[arg(long = "watch-exclude", value_parser = Glob::new, global = true)]
+    #[arg(
+        help = "do not watch or report paths matching this glob, e.g. '/var/log/**' or '*.swp' (repeatable)"
+    )]
+    pub watch_exclude: Vec<Glob>,
+
+    #[arg(long = "max-watches", global = true)]
+    #[arg(
+        help = "watch at most this many directories, the most deeply nested ones are left out first"
+    )]
+    pub max_watches: Option<usize>,
+
+    #[arg(
+        long = "strict-watches",
+        value_name = "PERCENT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "100",
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        global = true
+    )]
+    #[arg(
+        help = "exit at startup when fewer than this percent of the directories to watch could be watched (--strict-watches alone means all of them)"
+    )]
+    pub strict_watches: Option<u8>,
+
     #[arg(long)]
     #[arg(
-        help = "low-resource mode: only monitors /etc and /etc/ld.so.cache with no scan interval"
+        help = "low-resource mode: only monitors /etc and /etc/ld.so.cache with no scan interval, at nice 10, idle io priority and a 5% cpu limit unless --nice or --cpu-limit say otherwise"
     )]
     pub low_resource: bool,
 
-    #[arg(long = "scan-interval")]
-    #[arg(help = "interval in milliseconds between procfs scans")]
-    pub scan_interval_ms: Option<u64>,
+    #[arg(long = "config", value_name = "FILE")]
+    #[arg(
+        help = "read flags from a toml file of long names and values, e.g. scan-interval = \"1s\", the command line and environment win over it"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, value_enum)]
+    #[arg(
+        help = "start from the flags of a scenario, flags given as well win over the profile's (see the README for what each one sets)"
+    )]
+    pub profile: Option<Profile>,
+
+    #[arg(long, value_parser = clap::value_parser!(i32).range(-20..=19), allow_negative_numbers = true)]
+    #[arg(
+        help = "run the collector threads at this niceness, with the matching best-effort io priority"
+    )]
+    pub nice: Option<i32>,
+
+    #[arg(long = "cpu-limit", value_parser = clap::value_parser!(u32).range(1..=100))]
+    #[arg(help = "hold off procfs scans while rspy uses more than this percentage of one cpu")]
+    pub cpu_limit: Option<u32>,
+
+    #[arg(long = "max-memory-mb", value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    #[arg(
+        help = "size the command dedup, exe hash and dbus pid caches to fit in about this many megabytes, the least recently used entries are evicted first"
+    )]
+    pub max_memory_mb: Option<u64>,
+
+    #[arg(long = "scan-interval", value_parser = parse_millis)]
+    #[arg(
+        help = "interval between procfs scans, e.g. 250ms or 1s, plain numbers are milliseconds"
+    )]
+    pub scan_interval: Option<Duration>,
 
-    #[arg(long = "dbus-interval")]
-    #[arg(help = "interval in milliseconds between DBUS polls")]
-    pub dbus_interval_ms: Option<u64>,
+    #[arg(long = "burst-count")]
+    #[arg(help = "number of back-to-back procfs scans to run after a filesystem trigger")]
+    pub burst_count: Option<u32>,
+
+    #[arg(long = "burst-interval", alias = "burst-interval-ms", value_parser = parse_millis)]
+    #[arg(
+        help = "interval between the scans of a burst, e.g. 2ms, plain numbers are milliseconds"
+    )]
+    pub burst_interval: Option<Duration>,
+
+    #[arg(long = "dbus-interval", value_parser = parse_millis)]
+    #[arg(help = "interval between DBUS polls, e.g. 500ms or 2s, plain numbers are milliseconds")]
+    pub dbus_interval: Option<Duration>,
 
     #[arg(long)]
     #[arg(help = "enables debug level logging")]
@@ -44,15 +205,415 @@ pub struct Config {
     #[arg(help = "use only dbus monitoring (disables proc scanning + inotify)")]
     pub dbus_only: bool,
 
+    #[arg(long = "dbus-sniff")]
+    #[arg(
+        help = "report method calls and signals on the system bus with the pid that sent them (needs root)"
+    )]
+    pub dbus_sniff: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "report polkit authorization checks (pkexec, systemctl, ...) and their answers (needs root)"
+    )]
+    pub polkit: bool,
+
     #[arg(long = "no-interval")]
     #[arg(help = "disable periodic scanning, only trigger scans on filesystem events")]
     pub no_interval: bool,
+
+    #[arg(short = 'y', long = "yes")]
+    #[arg(help = "skip the confirmation prompt (implied when stdin is not a terminal)")]
+    pub yes: bool,
+
+    #[arg(long = "unique-cmd", global = true)]
+    #[arg(help = "print each (uid, command line) pair only once and count repeats")]
+    pub unique_cmd: bool,
+
+    #[arg(long = "dedup-window", value_parser = parse_duration, global = true)]
+    #[arg(
+        help = "collapse identical (uid, command line) events within this many seconds into one line with a repeat count"
+    )]
+    pub dedup_window: Option<Duration>,
+
+    #[arg(long = "correlate", value_parser = parse_duration, global = true)]
+    #[arg(
+        help = "tell what a process, its children, the files they write and the sockets they open did within this window as one line"
+    )]
+    pub correlate: Option<Duration>,
+
+    #[arg(long, value_parser = Regex::new, global = true)]
+    #[arg(help = "only print processes whose command line matches this regex")]
+    pub filter: Option<Regex>,
+
+    #[arg(long, value_parser = Regex::new, global = true)]
+    #[arg(help = "do not print processes whose command line matches this regex")]
+    pub exclude: Option<Regex>,
+
+    #[arg(long = "uid", global = true)]
+    #[arg(help = "only print processes running as this uid (repeatable)")]
+    pub uids: Vec<u32>,
+
+    #[arg(long = "exclude-uid", global = true)]
+    #[arg(help = "do not print processes running as this uid (repeatable)")]
+    pub excluded_uids: Vec<u32>,
+
+    #[arg(
+        long = "ignore-self",
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_value_t = true,
+        default_missing_value = "true",
+        global = true
+    )]
+    #[arg(
+        help = "hide rspy, its threads and the commands it runs, on by default, --ignore-self=false shows them"
+    )]
+    pub ignore_self: bool,
+
+    #[arg(long = "ignore-pid", global = true)]
+    #[arg(help = "hide this process and its descendants (repeatable)")]
+    pub ignore_pids: Vec<u32>,
+
+    #[arg(long)]
+    #[arg(help = "print the parent chain (pid and command) of every new process")]
+    pub tree: bool,
+
+    #[arg(long = "show-exits", global = true)]
+    #[arg(help = "print an EXIT event with the lifetime of processes that were printed")]
+    pub show_exits: bool,
+
+    #[arg(
+        long = "lifetimes",
+        value_parser = parse_duration,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1s",
+        global = true
+    )]
+    #[arg(
+        help = "print how long each printed process lived when it exits and summarize the lifetimes at the end, processes shorter than the threshold (--lifetimes=1s by default) are flagged transient"
+    )]
+    pub lifetimes: Option<Duration>,
+
+    #[arg(long = "track-cmdline")]
+    #[arg(
+        help = "re-read the command line and exe of every process on each scan and report changes, e.g. argv rewriting or an exec in the same pid"
+    )]
+    pub track_cmdline: bool,
+
+    #[arg(long = "show-fds")]
+    #[arg(
+        help = "list the sockets, deleted files and pipes to other processes that every new process holds open"
+    )]
+    pub show_fds: bool,
+
+    #[arg(long = "hash-exe")]
+    #[arg(help = "print the sha256 of the binary behind every new process")]
+    pub hash_exe: bool,
+
+    #[arg(
+        long = "highlight-tmp-exec",
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_value_t = true,
+        default_missing_value = "true"
+    )]
+    #[arg(
+        help = "flag processes whose binary lies below /tmp, /var/tmp, /dev/shm or a world-writable directory, on by default, --highlight-tmp-exec=false turns it off"
+    )]
+    pub highlight_tmp_exec: bool,
+
+    #[arg(long = "check-libraries")]
+    #[arg(
+        help = "compare the shared objects mapped into every new process with the ones its binary needs and flag the rest, plugins loaded with dlopen show up as well"
+    )]
+    pub check_libraries: bool,
+
+    #[arg(long = "timestamp-format", value_enum, default_value_t, global = true)]
+    #[arg(
+        help = "how timestamps are printed, use ms, iso, epoch or relative to order bursts of events"
+    )]
+    pub timestamp_format: TimestampFormat,
+
+    #[arg(long = "output-format", value_enum, default_value_t, global = true)]
+    #[arg(
+        help = "print events as json, cef or leef lines for a SIEM, other messages go to stderr"
+    )]
+    pub output_format: OutputFormat,
+
+    #[arg(long = "format-template", value_parser = |template: &str| Template::parse(template, FORMAT_TEMPLATE_FIELDS), global = true)]
+    #[arg(
+        help = "lay out process lines with {time}, {type}, {uid}, {user}, {pid}, {ppid}, {cmd} and {tags}, e.g. '{time} {uid} {pid} {cmd}'"
+    )]
+    pub format_template: Option<Template>,
+
+    #[arg(long = "no-color", global = true)]
+    #[arg(help = "disable colored output, also done when NO_COLOR is set or TERM=dumb")]
+    pub no_color: bool,
+
+    #[arg(long, global = true)]
+    #[arg(help = "machine friendly output: no colors, banner, configuration dump or confirmation")]
+    pub plain: bool,
+
+    #[arg(long)]
+    #[arg(help = "detach from the terminal and keep running in the background, implies --plain")]
+    pub daemon: bool,
+
+    #[arg(long)]
+    #[arg(help = "run under a service manager: no banner or prompt, notifies systemd when ready")]
+    pub service: bool,
+
+    #[arg(long)]
+    #[arg(help = "write rspy's pid to this file and remove it on exit")]
+    pub pidfile: Option<PathBuf>,
+
+    #[arg(long = "log-file")]
+    #[arg(help = "append the output of --daemon to this file instead of discarding it")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(long)]
+    #[arg(
+        help = "restrict rspy to the system calls it needs once it is running, it can no longer run programs"
+    )]
+    pub sandbox: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "keep rspy inconspicuous: no banner, nothing written to disk, open-only inotify watches, randomized and slower scans, and a kworker-like process name"
+    )]
+    pub stealth: bool,
+
+    #[arg(long = "resolve-users", global = true)]
+    #[arg(help = "print user names from /etc/passwd next to uids")]
+    pub resolve_users: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "read crontabs, at jobs and systemd timers and mark execs as expected by them or unexpected"
+    )]
+    pub schedules: bool,
+
+    #[arg(long = "scan-threads")]
+    #[arg(
+        help = "also report new threads of running processes with their comm, e.g. threads injected into daemons"
+    )]
+    pub scan_threads: bool,
+
+    #[arg(long = "min-severity", value_enum, global = true)]
+    #[arg(
+        help = "only print events of at least this severity, as assigned by the event type and rules"
+    )]
+    pub min_severity: Option<Severity>,
+
+    #[arg(long = "suid-only", global = true)]
+    #[arg(help = "only print processes started from setuid or setgid binaries")]
+    pub suid_only: bool,
+
+    #[arg(
+        long = "analyze-writability",
+        value_name = "UID",
+        num_args = 0..=1,
+        require_equals = true,
+        global = true
+    )]
+    #[arg(
+        help = "alert on processes running as root whose binary, script or a directory above them the current user (or this uid) could write to"
+    )]
+    pub analyze_writability: Option<Option<u32>>,
+
+    #[arg(long = "show-caps")]
+    #[arg(help = "flag non-root processes holding capabilities, enables `capabilities` in rules")]
+    pub show_caps: bool,
+
+    #[arg(long = "show-cwd")]
+    #[arg(help = "show the working directory of new processes and flag chrooted ones")]
+    pub show_cwd: bool,
+
+    #[arg(long = "show-unit")]
+    #[arg(help = "annotate processes with the systemd unit they belong to, asked over dbus")]
+    pub show_unit: bool,
+
+    #[arg(long = "show-cgroup")]
+    #[arg(help = "annotate processes with their container id, kubernetes pod or systemd unit")]
+    pub show_cgroup: bool,
+
+    #[arg(long = "preserve-exe")]
+    #[arg(
+        help = "copy the binary and cmdline of every new process into this directory, once per distinct binary"
+    )]
+    pub preserve_exe: Option<PathBuf>,
+
+    #[arg(long = "preserve-environ", requires = "preserve_exe")]
+    #[arg(help = "also keep the environment of preserved processes, it may contain secrets")]
+    pub preserve_environ: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "use fanotify instead of inotify to report which pid touched each file (needs CAP_SYS_ADMIN)"
+    )]
+    pub fanotify: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "also read execve records from the kernel audit netlink socket (needs CAP_AUDIT_READ and an execve audit rule)"
+    )]
+    pub audit: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "report devices that are added or removed (usb storage, network interfaces) from kernel uevents"
+    )]
+    pub udev: bool,
+
+    #[arg(
+        long = "watch-loader",
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_value_t = true,
+        default_missing_value = "true"
+    )]
+    #[arg(
+        help = "report changes to /etc/ld.so.preload, ld.so.cache, ld.so.conf and ld.so.conf.d as alerts with a diff of the content, on by default, --watch-loader=false turns it off"
+    )]
+    pub watch_loader: bool,
+
+    #[arg(long = "diff-files", value_delimiter = ',')]
+    #[arg(
+        help = "keep a copy of these small files, e.g. /etc/sudoers,/etc/passwd,/root/.ssh/authorized_keys, and print a unified diff whenever one changes, a directory stands for the files in it"
+    )]
+    pub diff_files: Vec<PathBuf>,
+
+    #[arg(long = "persistence-watch")]
+    #[arg(
+        help = "report changes to authorized_keys and shell profiles of every user, sudoers, systemd units, rc.local and cron spools as PERSISTENCE events with a diff, ~/.ssh has to exist to be watched"
+    )]
+    pub persistence_watch: bool,
+
+    #[arg(long)]
+    #[arg(help = "write every event as JSON lines to this file, play it back with `rspy replay`")]
+    pub record: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    #[arg(help = "store every event in this sqlite database (needs the sqlite feature)")]
+    pub sqlite: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    #[arg(
+        help = "rules file (TOML, or YAML with a .yaml/.yml extension) to highlight, suppress or act on events"
+    )]
+    pub rules: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    #[arg(help = "only print processes whose (exe, uid, parent) is not in this baseline file")]
+    pub baseline: Option<PathBuf>,
+
+    #[arg(long, value_parser = parse_duration, global = true)]
+    #[arg(
+        help = "record the processes seen for this long into the --baseline file, then enforce it, e.g. 30m"
+    )]
+    pub learn: Option<Duration>,
+
+    #[arg(long = "route", value_parser = Route::parse, global = true)]
+    #[arg(
+        help = "send the events of one source elsewhere, e.g. fs=file:/tmp/fs.log, dbus=json:/tmp/dbus.jsonl or proc=stdout, sources are proc, audit, dbus, polkit, fs, net, session and device (repeatable)"
+    )]
+    pub routes: Vec<Route>,
+
+    #[arg(long = "exec-on-match", global = true)]
+    #[arg(
+        help = "run this shell command for every event that passes the filters, with RSPY_PID, RSPY_CMD, RSPY_UID, ... set"
+    )]
+    pub exec_on_match: Option<String>,
+
+    #[arg(long, global = true)]
+    #[arg(help = "post events that pass the filters as JSON to this url, in batches")]
+    pub webhook: Option<String>,
+
+    #[arg(long, global = true)]
+    #[arg(help = "send events that pass the filters to the systemd journal with RSPY_* fields")]
+    pub journald: bool,
+
+    #[arg(long, global = true)]
+    #[arg(
+        help = "stream every event as a JSON line to a collector at host:port, buffered in memory while it is down"
+    )]
+    pub remote: Option<String>,
+
+    #[arg(long, global = true)]
+    #[arg(help = "connect to the --remote collector over tls")]
+    pub tls: bool,
+
+    #[arg(long = "tls-ca", global = true)]
+    #[arg(help = "trust the certificates in this pem file for --tls instead of the bundled roots")]
+    pub tls_ca: Option<PathBuf>,
+
+    #[arg(long = "tls-cert", global = true, requires = "tls_key")]
+    #[arg(help = "client certificate chain in pem format for --tls")]
+    pub tls_cert: Option<PathBuf>,
+
+    #[arg(long = "tls-key", global = true, requires = "tls_cert")]
+    #[arg(help = "private key of the --tls-cert in pem format")]
+    pub tls_key: Option<PathBuf>,
+
+    #[arg(long, value_parser = parse_duration, global = true)]
+    #[arg(help = "stop after monitoring for this long, e.g. 30s, 15m or 2h")]
+    pub duration: Option<Duration>,
+
+    #[arg(long = "max-events", global = true)]
+    #[arg(help = "stop after this many process events were printed")]
+    pub max_events: Option<u64>,
+
+    #[arg(long = "async-runtime")]
+    #[arg(
+        help = "run all collectors as tasks on a single tokio thread instead of a thread each (needs the tokio feature)"
+    )]
+    pub async_runtime: bool,
+
+    #[arg(long = "metrics-listen")]
+    #[arg(
+        help = "serve prometheus metrics about rspy itself on this address, e.g. 127.0.0.1:9300"
+    )]
+    pub metrics_listen: Option<SocketAddr>,
+
+    #[arg(long = "control-socket")]
+    #[arg(
+        help = "accept commands like add-watch, set-filter, pause, resume and stats on this unix socket"
+    )]
+    pub control_socket: Option<PathBuf>,
+
+    #[arg(long)]
+    #[arg(
+        help = "poll /proc/net for new tcp and udp connections and listeners and report the process behind them"
+    )]
+    pub network: bool,
+
+    #[arg(long = "unix-sockets")]
+    #[arg(
+        help = "poll /proc/net/unix for new listening unix and abstract sockets and report the process behind them"
+    )]
+    pub unix_sockets: bool,
+
+    #[arg(long)]
+    #[arg(help = "report logins and logouts from utmp with the tty and remote host")]
+    pub sessions: bool,
+
+    #[arg(long = "detect-hidden")]
+    #[arg(
+        help = "periodically compare procfs, dbus and /proc/loadavg views and alert on pids hidden from /proc"
+    )]
+    pub detect_hidden: bool,
 }
 
 impl Config {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let config = Self::parse();
+        let mut argv = Self::argv();
+        let mut config = Self::parse_with_env(&argv);
+        while let Some(Command::Monitor { args }) = &config.command {
+            // the same command line without the `monitor` in front of its flags
+            argv.remove(argv.len() - args.len() - 1);
+            config = Self::parse_with_env(&argv);
+        }
         config.validate().unwrap_or_else(|e| {
             eprintln!("configuration error: {}", e);
             std::process::exit(1);
@@ -60,27 +621,205 @@ impl Config {
         config
     }
 
+    // a disguised rspy is handed its flags in a memfd instead, see daemon::disguise
+    fn argv() -> Vec<OsString> {
+        let mut argv: Vec<OsString> = std::env::args_os().collect();
+        match daemon::take_disguised_flags() {
+            Some(Ok(flags)) => {
+                argv.truncate(1);
+                argv.extend(flags);
+            }
+            Some(Err(e)) => {
+                eprintln!(
+                    "configuration error: failed to read the disguised flags: {}",
+                    e
+                );
+                std::process::exit(1);
+            }
+            None => {}
+        }
+        argv
+    }
+
+    // every flag can also be set as RSPY_<FLAG>, e.g. RSPY_SCAN_INTERVAL=1s for --scan-interval,
+    // for containers and systemd units, or in the --config file. the command line wins over
+    // the environment, the environment over the file and the file over the profile
+    fn parse_with_env(argv: &[OsString]) -> Self {
+        let command = Self::command().mut_args(|arg| match arg.get_long() {
+            Some(long) if !matches!(arg.get_action(), ArgAction::Append) => {
+                let name = env_name(long);
+                // RSPY_YES=1 as well as =true, and =0, =no or =off to turn a switch off
+                if matches!(arg.get_action(), ArgAction::SetTrue) {
+                    arg.env(name).value_parser(FalseyValueParser::new())
+                } else {
+                    arg.env(name)
+                }
+            }
+            _ => arg,
+        });
+        let mut matches = command.clone().get_matches_from(argv);
+
+        let file_flags = match matches.get_one::<PathBuf>("config") {
+            Some(path) => config_file_flags(&command, &matches, path).unwrap_or_else(|e| {
+                eprintln!("configuration error: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }),
+            None => Vec::new(),
+        };
+
+        // the last value of a flag wins, so the profile goes first and the file after it
+        let mut extra: Vec<OsString> = Vec::new();
+        let profile = matches.get_one::<Profile>("profile").copied().or_else(|| {
+            file_flags
+                .iter()
+                .find_map(|(long, flag)| (long == "profile").then_some(flag.to_str()?))
+                .and_then(|flag| flag.strip_prefix("--profile="))
+                .and_then(|name| Profile::from_str(name, true).ok())
+        });
+        if let Some(profile) = profile {
+            extra.extend(
+                profile
+                    .flags()
+                    .iter()
+                    .filter(|flag| {
+                        let long = flag.trim_start_matches('-');
+                        let long = long.split_once('=').map_or(long, |(long, _)| long);
+                        let given = command
+                            .get_arguments()
+                            .find(|arg| arg.get_long() == Some(long))
+                            .and_then(|arg| matches.value_source(arg.get_id().as_str()));
+                        given != Some(ValueSource::CommandLine)
+                            && std::env::var_os(env_name(long)).is_none()
+                            && !file_flags.iter().any(|(given, _)| given == long)
+                    })
+                    .map(OsString::from),
+            );
+        }
+        extra.extend(file_flags.into_iter().map(|(_, flag)| flag));
+
+        // clap reads a variable as one value, lists like RSPY_UID="0 33" are split on
+        // whitespace and passed as if every value was given as a flag
+        extra.extend(
+            command
+                .get_arguments()
+                .filter(|arg| matches!(arg.get_action(), ArgAction::Append))
+                .filter(|arg| {
+                    matches.value_source(arg.get_id().as_str()) != Some(ValueSource::CommandLine)
+                })
+                .filter_map(|arg| {
+                    let long = arg.get_long()?;
+                    let values = std::env::var(env_name(long)).ok()?;
+                    Some(
+                        values
+                            .split_whitespace()
+                            .map(|value| OsString::from(format!("--{}={}", long, value)))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .flatten(),
+        );
+        if !extra.is_empty() {
+            let mut argv = argv.to_vec();
+            argv.splice(1..1, extra);
+            matches = command.get_matches_from(argv);
+        }
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
     pub fn scan_interval(&self) -> Option<Duration> {
         if self.no_interval {
             None
         } else {
-            let interval_ms = self.scan_interval_ms.unwrap_or(DEFAULT_SCAN_INTERVAL_MS);
-            Some(Duration::from_millis(interval_ms))
+            let default = if self.stealth {
+                STEALTH_SCAN_INTERVAL_MS
+            } else {
+                DEFAULT_SCAN_INTERVAL_MS
+            };
+            Some(self.scan_interval.unwrap_or(Duration::from_millis(default)))
+        }
+    }
+
+    pub fn use_color(&self) -> bool {
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let dumb_terminal = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+        !(self.no_color || self.unattended() || no_color_env || dumb_terminal)
+    }
+
+    // nobody is watching the terminal, so no banner, prompt or colors
+    pub fn unattended(&self) -> bool {
+        self.plain || self.daemon || self.service || self.stealth
+    }
+
+    // --stealth only watches opens, the most common event that looks like any reader
+    pub fn watch_events(&self) -> Option<u32> {
+        self.watch_events.or(self.stealth.then_some(libc::IN_OPEN))
+    }
+
+    pub fn scan_jitter(&self) -> Option<u32> {
+        self.stealth.then_some(STEALTH_SCAN_JITTER_PERCENT)
+    }
+
+    // --lifetimes needs the exits --show-exits prints
+    pub fn track_exits(&self) -> bool {
+        self.show_exits || self.lifetimes.is_some()
+    }
+
+    // sinks and --correlate use filesystem events even when they are not printed
+    pub fn emit_filesystem_events(&self) -> bool {
+        self.print_filesystem_events
+            || self.record.is_some()
+            || self.sqlite.is_some()
+            || self.rules.is_some()
+            || self.correlate.is_some()
+    }
+
+    pub fn priority(&self) -> Option<Priority> {
+        match (self.nice, self.low_resource) {
+            (Some(nice), _) => Some(Priority {
+                nice,
+                idle_io: false,
+            }),
+            (None, true) => Some(Priority {
+                nice: LOW_RESOURCE_NICE,
+                idle_io: true,
+            }),
+            (None, false) => None,
         }
     }
 
+    pub fn cpu_limit(&self) -> Option<u32> {
+        self.cpu_limit
+            .or(self.low_resource.then_some(LOW_RESOURCE_CPU_LIMIT_PERCENT))
+    }
+
+    pub fn cache_limits(&self) -> CacheLimits {
+        self.max_memory_mb
+            .map_or_else(CacheLimits::default, CacheLimits::from_megabytes)
+    }
+
+    pub fn burst_count(&self) -> u32 {
+        self.burst_count.unwrap_or(DEFAULT_BURST_COUNT).max(1)
+    }
+
+    pub fn burst_interval(&self) -> Duration {
+        self.burst_interval
+            .unwrap_or(Duration::from_millis(DEFAULT_BURST_INTERVAL_MS))
+    }
+
     pub fn dbus_interval(&self) -> Option<Duration> {
-        self.dbus_interval_ms
-            .map(Duration::from_millis)
-            .or_else(|| {
-                Some(Duration::from_millis(
-                    super::constants::DBUS_DEFAULT_SLEEP_MS,
-                ))
-            })
+        self.dbus_interval.or_else(|| {
+            Some(Duration::from_millis(
+                super::constants::DBUS_DEFAULT_SLEEP_MS,
+            ))
+        })
     }
 
     pub fn get_direct_watch_dirs(&self) -> Vec<String> {
-        let mut dirs = self.direct_watch_dirs.clone();
+        let mut dirs: Vec<String> = self
+            .direct_watch_dirs
+            .iter()
+            .map(|dir| split_watch_dir(dir).0.to_string())
+            .collect();
         if self.low_resource {
             dirs.extend(LOW_RESOURCE_WATCH_DIRS.iter().map(|&s| s.to_string()));
         }
@@ -89,7 +828,11 @@ impl Config {
 
     pub fn get_recursive_watch_dirs(&self) -> Vec<String> {
         if !self.recursive_watch_dirs.is_empty() {
-            return self.recursive_watch_dirs.clone();
+            return self
+                .recursive_watch_dirs
+                .iter()
+                .map(|dir| split_watch_dir(dir).0.to_string())
+                .collect();
         }
 
         if !self.low_resource && self.direct_watch_dirs.is_empty() {
@@ -102,17 +845,263 @@ impl Config {
         }
     }
 
+    pub fn watch_masks(&self) -> Vec<(PathBuf, u32)> {
+        self.recursive_watch_dirs
+            .iter()
+            .chain(&self.direct_watch_dirs)
+            .filter_map(|dir| match split_watch_dir(dir) {
+                (path, Some(events)) => parse_watch_events(events)
+                    .ok()
+                    .map(|mask| (PathBuf::from(path), mask)),
+                (_, None) => None,
+            })
+            .collect()
+    }
+
     fn validate(&self) -> Result<(), String> {
+        if (self.dbus || self.dbus_only) && !cfg!(feature = "dbus") {
+            return Err(
+                "rspy was built without dbus support, rebuild with `--features dbus` to use --dbus or --dbus-only"
+                    .to_string(),
+            );
+        }
+
+        if self.dbus_sniff && !cfg!(feature = "dbus") {
+            return Err(
+                "rspy was built without dbus support, rebuild with `--features dbus` to use --dbus-sniff"
+                    .to_string(),
+            );
+        }
+
+        if self.polkit && !cfg!(feature = "dbus") {
+            return Err(
+                "rspy was built without dbus support, rebuild with `--features dbus` to use --polkit"
+                    .to_string(),
+            );
+        }
+
+        if self.show_unit && !cfg!(feature = "dbus") {
+            return Err(
+                "rspy was built without dbus support, rebuild with `--features dbus` to use --show-unit"
+                    .to_string(),
+            );
+        }
+
+        if self.sqlite.is_some() && !cfg!(feature = "sqlite") {
+            return Err(
+                "rspy was built without sqlite support, rebuild with `--features sqlite` to use --sqlite"
+                    .to_string(),
+            );
+        }
+
+        if self.webhook.is_some() && !cfg!(feature = "webhook") {
+            return Err(
+                "rspy was built without webhook support, rebuild with `--features webhook` to use --webhook"
+                    .to_string(),
+            );
+        }
+        if self.tls && !cfg!(feature = "tls") {
+            return Err(
+                "rspy was built without tls support, rebuild with `--features tls` to use --tls"
+                    .to_string(),
+            );
+        }
+        if self.async_runtime && !cfg!(feature = "tokio") {
+            return Err(
+                "rspy was built without tokio support, rebuild with `--features tokio` to use --async-runtime"
+                    .to_string(),
+            );
+        }
+        if self.async_runtime
+            && (self.fanotify
+                || self.detect_hidden
+                || self.network
+                || self.unix_sockets
+                || self.sessions)
+        {
+            return Err(
+                "--async-runtime cannot be used with --fanotify, --detect-hidden, --network, --unix-sockets or --sessions"
+                    .to_string(),
+            );
+        }
+        if self.async_runtime && (self.priority().is_some() || self.cpu_limit().is_some()) {
+            return Err(
+                "--async-runtime cannot be used with --nice, --cpu-limit or --low-resource"
+                    .to_string(),
+            );
+        }
+
+        if let Some(url) = &self.webhook
+            && !(url.starts_with("http://") || url.starts_with("https://"))
+        {
+            return Err(format!(
+                "--webhook must be an http:// or https:// url, got {}",
+                url
+            ));
+        }
+
+        if self.daemon && self.service {
+            return Err(
+                "--daemon and --service cannot be used together, service managers keep track of the process themselves"
+                    .to_string(),
+            );
+        }
+        if self.sandbox && self.exec_on_match.is_some() {
+            return Err(
+                "--sandbox cannot be used with --exec-on-match, the sandbox forbids running programs"
+                    .to_string(),
+            );
+        }
+        if self.stealth {
+            let writes = [
+                ("--record", self.record.is_some()),
+                ("--sqlite", self.sqlite.is_some()),
+                ("--pidfile", self.pidfile.is_some()),
+                ("--log-file", self.log_file.is_some()),
+                ("--preserve-exe", self.preserve_exe.is_some()),
+                ("--learn", self.learn.is_some()),
+                ("--control-socket", self.control_socket.is_some()),
+                (
+                    "--route",
+                    self.routes.iter().any(|route| route.path().is_some()),
+                ),
+            ];
+            if let Some((flag, _)) = writes.iter().find(|(_, given)| *given) {
+                return Err(format!(
+                    "--stealth cannot be used with {}, it writes to disk",
+                    flag
+                ));
+            }
+            if self.async_runtime {
+                return Err("--stealth cannot be used with --async-runtime".to_string());
+            }
+        }
+        if self.format_template.is_some() && self.output_format != OutputFormat::Text {
+            return Err("--format-template only applies to --output-format text".to_string());
+        }
+        if self.log_file.is_some() && !self.daemon {
+            return Err("--log-file needs --daemon".to_string());
+        }
+
+        if (self.tls || self.tls_ca.is_some() || self.tls_cert.is_some()) && self.remote.is_none() {
+            return Err("--tls, --tls-ca and --tls-cert need --remote".to_string());
+        }
+        if (self.tls_ca.is_some() || self.tls_cert.is_some()) && !self.tls {
+            return Err("--tls-ca and --tls-cert need --tls".to_string());
+        }
+        if let Some(address) = &self.remote
+            && !address
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+        {
+            return Err(format!("--remote must be host:port, got {}", address));
+        }
+
+        if let Some(Command::Replay { speed, .. }) = &self.command
+            && (!speed.is_finite() || *speed < 0.0)
+        {
+            return Err("--speed must be a non-negative number".to_string());
+        }
+
+        if self.duration.is_some_and(|duration| duration.is_zero()) {
+            return Err("--duration must be longer than zero".to_string());
+        }
+        if self.scan_threads && self.dbus_only {
+            return Err("--scan-threads cannot be used with --dbus-only".to_string());
+        }
+        if self.show_fds && self.dbus_only {
+            return Err("--show-fds cannot be used with --dbus-only".to_string());
+        }
+        if self.show_cwd && self.dbus_only {
+            return Err("--show-cwd cannot be used with --dbus-only".to_string());
+        }
+        if self.show_caps && self.dbus_only {
+            return Err("--show-caps cannot be used with --dbus-only".to_string());
+        }
+        if self.track_cmdline && self.dbus_only {
+            return Err("--track-cmdline cannot be used with --dbus-only".to_string());
+        }
+        if self.learn.is_some() && self.baseline.is_none() {
+            return Err("--learn needs a --baseline file to write to".to_string());
+        }
+        if self.learn.is_some_and(|learn| learn.is_zero()) {
+            return Err("--learn must be longer than zero".to_string());
+        }
+        if self.correlate.is_some_and(|window| window.is_zero()) {
+            return Err("--correlate must be longer than zero".to_string());
+        }
+        if self.lifetimes.is_some_and(|threshold| threshold.is_zero()) {
+            return Err("--lifetimes must be longer than zero".to_string());
+        }
+        if self.dedup_window.is_some_and(|window| window.is_zero()) {
+            return Err("--dedup-window must be longer than zero".to_string());
+        }
+        if self.max_events == Some(0) {
+            return Err("--max-events must be at least 1".to_string());
+        }
+        if self.max_watches == Some(0) {
+            return Err("--max-watches must be at least 1".to_string());
+        }
+        if self.analyze_writability == Some(None) && unsafe { libc::getuid() } == ROOT_UID {
+            return Err(
+                "root can write anywhere, name the uid to check as with --analyze-writability=1000"
+                    .to_string(),
+            );
+        }
+        if !self.diff_files.is_empty() && self.dbus_only {
+            return Err(
+                "--diff-files watches files, it cannot be used with --dbus-only".to_string(),
+            );
+        }
+        if self.persistence_watch && self.dbus_only {
+            return Err(
+                "--persistence-watch watches files, it cannot be used with --dbus-only".to_string(),
+            );
+        }
+        if self.strict_watches.is_some() && self.dbus_only {
+            return Err(
+                "--strict-watches counts filesystem watches, it cannot be used with --dbus-only"
+                    .to_string(),
+            );
+        }
+
+        for dir in self
+            .recursive_watch_dirs
+            .iter()
+            .chain(&self.direct_watch_dirs)
+        {
+            if let (_, Some(events)) = split_watch_dir(dir) {
+                parse_watch_events(events).map_err(|e| format!("{}: {}", dir, e))?;
+            }
+        }
+        for dir in &self.recursive_watch_dirs {
+            let path = Path::new(split_watch_dir(dir).0);
+            if path.exists() && !path.is_dir() {
+                return Err(format!(
+                    "{} is not a directory, watch single files with --direct-watch",
+                    path.display()
+                ));
+            }
+        }
+
         if self.low_resource {
+            // the stealth profile turns it on without it being on the command line
+            let flag = if self.profile == Some(Profile::Stealth) {
+                "--profile stealth"
+            } else {
+                "--low-resource"
+            };
             if !self.recursive_watch_dirs.is_empty() {
-                return Err(
-                    "--low-resource cannot be used with --recursive-watch directories".to_string(),
-                );
+                return Err(format!(
+                    "{} cannot be used with --recursive-watch directories",
+                    flag
+                ));
             }
             if !self.direct_watch_dirs.is_empty() {
-                return Err(
-                    "--low-resource cannot be used with --direct-watch directories".to_string(),
-                );
+                return Err(format!(
+                    "{} cannot be used with --direct-watch directories",
+                    flag
+                ));
             }
         }
 
@@ -120,3 +1109,87 @@ impl Config {
     }
 }
 
+// `-r /tmp:create,delete` watches /tmp with its own event mask, anything after the last
+// colon that does not look like an event list stays part of the path
+fn split_watch_dir(dir: &str) -> (&str, Option<&str>) {
+    match dir.rsplit_once(':') {
+        Some((path, events))
+            if !path.is_empty()
+                && !events.is_empty()
+                && events
+                    .bytes()
+                    .all(|b| b.is_ascii_alphabetic() || b == b'_' || b == b',') =>
+        {
+            (path, Some(events))
+        }
+        _ => (dir, None),
+    }
+}
+
+// --scan-interval is RSPY_SCAN_INTERVAL
+fn env_name(long: &str) -> String {
+    format!("RSPY_{}", long.replace('-', "_").to_uppercase())
+}
+
+// (long name, flag) for every entry of the --config file that neither the command line
+// nor the environment sets, lists give one flag per value
+fn config_file_flags(
+    command: &clap::Command,
+    matches: &clap::ArgMatches,
+    path: &Path,
+) -> Result<Vec<(String, OsString)>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let table: toml::Table = toml::from_str(&content).map_err(|e| e.to_string())?;
+
+    let mut flags = Vec::new();
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()) && long != "config")
+            .ok_or_else(|| format!("unknown option {}", key))?;
+        let source = matches.value_source(arg.get_id().as_str());
+        if matches!(
+            source,
+            Some(ValueSource::CommandLine) | Some(ValueSource::EnvVariable)
+        ) || std::env::var_os(env_name(&long)).is_some()
+        {
+            continue;
+        }
+
+        let scalar = |value: &toml::Value| match value {
+            toml::Value::String(value) => Ok(value.clone()),
+            toml::Value::Integer(value) => Ok(value.to_string()),
+            toml::Value::Float(value) => Ok(value.to_string()),
+            toml::Value::Boolean(value) => Ok(value.to_string()),
+            _ => Err(format!("{} takes a string, number or boolean", key)),
+        };
+        let takes_no_value = arg
+            .get_num_args()
+            .is_some_and(|range| range.min_values() == 0);
+        let values = match (&value, arg.get_action()) {
+            (toml::Value::Boolean(true), ArgAction::SetTrue) => vec![None],
+            (toml::Value::Boolean(false), ArgAction::SetTrue) => Vec::new(),
+            (toml::Value::Boolean(true), _) if takes_no_value => vec![None],
+            (toml::Value::Array(values), ArgAction::Append) => values
+                .iter()
+                .map(|value| scalar(value).map(Some))
+                .collect::<Result<_, _>>()?,
+            (toml::Value::Array(values), _) if arg.get_value_delimiter().is_some() => {
+                let delimiter = arg.get_value_delimiter().unwrap_or(',').to_string();
+                let values: Vec<String> = values.iter().map(scalar).collect::<Result<_, _>>()?;
+                vec![Some(values.join(&delimiter))]
+            }
+            (toml::Value::Array(_), _) => return Err(format!("{} takes a single value", key)),
+            (value, _) => vec![Some(scalar(value)?)],
+        };
+        flags.extend(values.into_iter().map(|value| {
+            let flag = match value {
+                Some(value) => format!("--{}={}", long, value),
+                None => format!("--{}", long),
+            };
+            (long.clone(), OsString::from(flag))
+        }));
+    }
+    Ok(flags)
+}
@@ -1,22 +1,208 @@
 pub const DEFAULT_SCAN_INTERVAL_MS: u64 = 100;
 
 pub const FS_WATCHER_POLL_INTERVAL_MS: u64 = 100;
+pub const INOTIFY_MAX_USER_WATCHES_FILE: &str = "/proc/sys/fs/inotify/max_user_watches";
+// `rspy check` suggests raising fs.inotify.max_user_watches below this, the default
+// recursive directories alone need tens of thousands on a desktop
+pub const CHECK_MIN_INOTIFY_WATCHES: usize = 65536;
+pub const YAMA_PTRACE_SCOPE_FILE: &str = "/proc/sys/kernel/yama/ptrace_scope";
 
 pub const SCANNER_MAX_TIMEOUT_SECS: u64 = 1;
 
+pub const DEFAULT_BURST_COUNT: u32 = 1;
+pub const DEFAULT_BURST_INTERVAL_MS: u64 = 2;
+
 pub const DEFAULT_NEW_PIDS_CAPACITY: usize = 32;
 
+pub const TREE_MAX_DEPTH: usize = 16;
+// how long `rspy tree` keeps showing a process it saw start after it exited
+pub const TREE_EXITED_LINGER_SECS: u64 = 5;
+// steps of one --correlate story that are spelled out
+pub const CORRELATE_MAX_STEPS: usize = 12;
+pub const FDS_MAX_PER_EVENT: usize = 8;
+
+pub const DELETED_EXE_SUFFIX: &str = " (deleted)";
+pub const EXE_MULTICALL_BINARIES: &[&str] = &["busybox", "toybox"];
+// --highlight-tmp-exec, whatever their mode, anything below a world-writable directory counts too
+pub const TMP_EXEC_DIRS: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+// commands of --exec-on-match and rules that may run at once, events past it are dropped
+pub const EXEC_MAX_RUNNING: usize = 32;
+// flagged when a new process has them in its environment
+pub const LOADER_ENV_VARIABLES: &[&str] = &["LD_PRELOAD", "LD_AUDIT", "LD_LIBRARY_PATH"];
+// files larger than this are only compared by size and mtime
+pub const CONTENT_MAX_BYTES: u64 = 1024 * 1024;
+pub const CONTENT_DIFF_CONTEXT: usize = 3;
+// always watched for changes unless --watch-loader=false, a directory stands for its files
+pub const LOADER_CONFIG_PATHS: &[&str] = &[
+    "/etc/ld.so.preload",
+    "/etc/ld.so.cache",
+    "/etc/ld.so.conf",
+    "/etc/ld.so.conf.d",
+];
+// --persistence-watch, places that start code at boot, login, on a schedule or for whoever
+// logs in with a key, a directory stands for its files
+pub const PERSISTENCE_PATHS: &[&str] = &[
+    "/etc/sudoers",
+    "/etc/sudoers.d",
+    "/etc/systemd/system",
+    "/etc/systemd/system/multi-user.target.wants",
+    "/etc/systemd/system/timers.target.wants",
+    "/etc/systemd/user",
+    "/etc/rc.local",
+    "/etc/profile",
+    "/etc/profile.d",
+    "/etc/bash.bashrc",
+    "/etc/environment",
+    "/etc/crontab",
+    "/etc/cron.d",
+    "/etc/cron.hourly",
+    "/etc/cron.daily",
+    "/etc/cron.weekly",
+    "/etc/cron.monthly",
+    "/var/spool/cron",
+    "/var/spool/cron/crontabs",
+];
+// the same below every home directory in passwd
+pub const PERSISTENCE_HOME_PATHS: &[&str] = &[
+    ".ssh/authorized_keys",
+    ".ssh/authorized_keys2",
+    ".bashrc",
+    ".bash_profile",
+    ".bash_login",
+    ".bash_logout",
+    ".profile",
+    ".zshrc",
+    ".zprofile",
+    ".config/systemd/user",
+];
+// --check-libraries, parsed dynamic sections of binaries and shared objects
+pub const LIBRARY_CACHE_CAPACITY: usize = 512;
+
 pub const DEFAULT_RECURSIVE_DIRS: &[&str] = &["/usr", "/tmp", "/etc", "/home", "/var", "/opt"];
 
 pub const LOW_RESOURCE_WATCH_DIRS: &[&str] = &["/etc/ld.so.cache"];
+pub const LOW_RESOURCE_NICE: i32 = 10;
+pub const LOW_RESOURCE_CPU_LIMIT_PERCENT: u32 = 5;
+
+// --stealth scans less often, each wait off by up to the jitter either way
+pub const STEALTH_SCAN_INTERVAL_MS: u64 = 1000;
+pub const STEALTH_SCAN_JITTER_PERCENT: u32 = 50;
+// what ps shows for rspy, the comm is the same without the brackets
+pub const STEALTH_PROCESS_NAME: &str = "[kworker/u8:1]";
+// the fd of the memfd holding the flags of a disguised rspy, separated by 0x1f, while it
+// executes itself again
+pub const STEALTH_ARGV_FD_ENV: &str = "RSPY_DISGUISED_ARGV_FD";
+
+pub const CPU_BUDGET_WINDOW_MS: u64 = 1000;
+pub const CPU_BUDGET_MAX_BACKOFF_SECS: u64 = 10;
 
 pub const DBUS_PROXY_TIMEOUT_SECS: u64 = 5;
 pub const DBUS_DEFAULT_SLEEP_MS: u64 = 100;
+// between attempts to reach the system bus again, doubled after each one that fails
+pub const DBUS_MIN_BACKOFF_MS: u64 = 500;
+pub const DBUS_MAX_BACKOFF_SECS: u64 = 30;
+pub const ROOT_SLICE: &str = "-.slice";
+// senders whose pid --dbus-sniff remembers, unique names are never reused
+pub const DBUS_SNIFF_SENDER_CAPACITY: usize = 4096;
+// CheckAuthorization calls still waiting for polkit's answer
+pub const POLKIT_PENDING_CAPACITY: usize = 1024;
+
+pub const HIDDEN_CHECK_INTERVAL_MS: u64 = 5000;
+pub const HIDDEN_TASK_TOLERANCE: usize = 8;
+pub const HIDDEN_MAX_PROBE_RANGE: u32 = 4096;
+
+pub const NETWORK_POLL_INTERVAL_MS: u64 = 250;
+
+pub const UTMP_FILE: &str = "/run/utmp";
+pub const SESSION_POLL_INTERVAL_MS: u64 = 1000;
 
 pub const UNKNOWN_UID_DISPLAY: &str = "???";
+// what --format-template can place in a process line
+pub const FORMAT_TEMPLATE_FIELDS: &[&str] =
+    &["time", "type", "uid", "user", "pid", "ppid", "cmd", "tags"];
 pub const UNKNOWN_COMMAND: &str = "<unknown command>";
 pub const UID_DISPLAY_WIDTH: usize = 5;
 pub const PID_DISPLAY_WIDTH: usize = 8;
 
 pub const ROOT_UID: u32 = 0;
-pub const USER_UID: u32 = 1000;
+// parent of every kernel thread
+pub const KTHREADD_PID: u32 = 2;
+// fallbacks when login.defs does not say otherwise
+pub const USER_UID_MIN: u32 = 1000;
+pub const USER_UID_MAX: u32 = 60000;
+
+pub const PASSWD_FILE: &str = "/etc/passwd";
+pub const GROUP_FILE: &str = "/etc/group";
+pub const LOGIN_DEFS_FILE: &str = "/etc/login.defs";
+pub const USER_DB_RECHECK_INTERVAL_MS: u64 = 1000;
+
+pub const SQLITE_BATCH_SIZE: usize = 512;
+pub const SQLITE_COMMIT_INTERVAL_MS: u64 = 1000;
+
+pub const WEBHOOK_QUEUE_CAPACITY: usize = 4096;
+pub const WEBHOOK_BATCH_SIZE: usize = 50;
+pub const WEBHOOK_BATCH_INTERVAL_MS: u64 = 2000;
+pub const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+pub const WEBHOOK_MAX_RETRIES: u32 = 5;
+pub const WEBHOOK_MAX_BACKOFF_SECS: u64 = 30;
+
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+pub const JOURNAL_MAX_VALUE_BYTES: usize = 64 * 1024;
+pub const JOURNAL_SEND_TIMEOUT_MS: u64 = 200;
+
+pub const REMOTE_QUEUE_CAPACITY: usize = 4096;
+// lines held in memory while the collector is unreachable, the oldest go first
+pub const REMOTE_BUFFER_CAPACITY: usize = 100_000;
+pub const REMOTE_TIMEOUT_SECS: u64 = 10;
+pub const REMOTE_MIN_BACKOFF_MS: u64 = 500;
+pub const REMOTE_MAX_BACKOFF_SECS: u64 = 30;
+
+pub const HASH_CACHE_CAPACITY: usize = 1024;
+pub const COMMAND_CACHE_CAPACITY: usize = 100_000;
+pub const PID_CACHE_CAPACITY: usize = 65_536;
+// a full cache drops this share of its entries at once
+pub const CACHE_EVICTION_PERCENT: usize = 10;
+// rough sizes of an entry including the map overhead, to turn --max-memory-mb into counts
+pub const COMMAND_CACHE_ENTRY_BYTES: u64 = 256;
+pub const HASH_CACHE_ENTRY_BYTES: u64 = 160;
+pub const PID_CACHE_ENTRY_BYTES: u64 = 32;
+pub const HASH_MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+// same short form as `docker ps`
+pub const CONTAINER_ID_DISPLAY_LEN: usize = 12;
+
+pub const PRESERVE_MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+
+pub const SYSTEM_CRONTAB: &str = "/etc/crontab";
+pub const CRON_DIRS: [&str; 1] = ["/etc/cron.d"];
+// debian keeps user crontabs in crontabs/, red hat directly in /var/spool/cron
+pub const CRON_SPOOL_DIRS: [&str; 2] = ["/var/spool/cron/crontabs", "/var/spool/cron"];
+pub const CRON_PERIODIC_DIRS: [(&str, &str); 4] = [
+    ("/etc/cron.hourly", "@hourly"),
+    ("/etc/cron.daily", "@daily"),
+    ("/etc/cron.weekly", "@weekly"),
+    ("/etc/cron.monthly", "@monthly"),
+];
+pub const ANACRONTAB: &str = "/etc/anacrontab";
+pub const AT_SPOOL_DIRS: [&str; 2] = ["/var/spool/cron/atjobs", "/var/spool/at"];
+pub const SYSTEMD_UNIT_DIRS: [&str; 4] = [
+    "/etc/systemd/system",
+    "/run/systemd/system",
+    "/usr/lib/systemd/system",
+    "/lib/systemd/system",
+];
+pub const SCHEDULER_COMMS: [&str; 4] = ["cron", "crond", "anacron", "atd"];
+pub const SCHEDULE_MAX_DEPTH: usize = 6;
+pub const SCHEDULE_RECHECK_INTERVAL_MS: u64 = 5000;
+pub const STATS_TOP_ENTRIES: usize = 10;
+// how often blocked worker threads look at the shutdown flag
+pub const WORKER_POLL_INTERVAL_MS: u64 = 200;
+pub const WORKER_JOIN_TIMEOUT_SECS: u64 = 5;
+pub const ASYNC_EVENT_QUEUE_SIZE: usize = 4096;
+
+pub const CONTROL_READ_TIMEOUT_SECS: u64 = 30;
+pub const CONTROL_REPLY_TIMEOUT_SECS: u64 = 5;
+// a scrape is one short GET, anything bigger or slower is cut off
+pub const METRICS_MAX_REQUEST_BYTES: u64 = 8192;
+pub const METRICS_REQUEST_TIMEOUT_SECS: u64 = 5;
+pub const METRICS_MAX_CONNECTIONS: usize = 4;
+pub const REACTOR_MAX_EVENTS: usize = 16;
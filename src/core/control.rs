@@ -0,0 +1,150 @@
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use super::constants::{CONTROL_READ_TIMEOUT_SECS, CONTROL_REPLY_TIMEOUT_SECS};
+use super::{error::Result, logger::Logger};
+
+const HELP: &str = "commands: add-watch <path>, set-filter [regex], pause, resume, stats";
+
+pub enum ControlCommand {
+    // a directory is watched recursively, anything else directly
+    AddWatch(PathBuf),
+    SetFilter(Option<Regex>),
+    Pause,
+    Resume,
+    Stats,
+}
+
+// answered by the event loop, which owns the filter, stats and collectors
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    pub reply: Sender<String>,
+}
+
+impl ControlCommand {
+    fn parse(line: &str) -> std::result::Result<Self, String> {
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+
+        match command {
+            "add-watch" if !argument.is_empty() => Ok(Self::AddWatch(PathBuf::from(argument))),
+            "add-watch" => Err("add-watch needs a path".to_string()),
+            "set-filter" if argument.is_empty() => Ok(Self::SetFilter(None)),
+            "set-filter" => Regex::new(argument)
+                .map(|pattern| Self::SetFilter(Some(pattern)))
+                .map_err(|e| format!("invalid regex: {}", e)),
+            "pause" => Ok(Self::Pause),
+            "resume" => Ok(Self::Resume),
+            "stats" => Ok(Self::Stats),
+            _ => Err(format!("unknown command {:?}, {}", command, HELP)),
+        }
+    }
+}
+
+// one command per line, answered with "ok ..." or "error: ...", e.g.
+// `echo 'add-watch /srv/app' | socat - UNIX-CONNECT:/run/rspy.sock`
+pub fn serve(path: &Path, requests: Sender<ControlRequest>) -> Result<()> {
+    // a socket left behind by a previous run that was killed
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    // the commands change what rspy reports, only the owner may send them. the socket is
+    // created 0600 rather than changed afterwards, which would leave a window open
+    let previous = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe {
+        libc::umask(previous);
+    }
+    let listener = listener?;
+    let owner = unsafe { libc::geteuid() };
+    Logger::info(format!("listening for commands on {}", path.display()));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    // the mode of the socket may have been changed since, or its directory
+                    // be shared with other users
+                    match peer_uid(&stream) {
+                        Ok(uid) if uid == owner => {}
+                        Ok(uid) => {
+                            Logger::debug(format!("refused a control connection from uid {}", uid));
+                            continue;
+                        }
+                        Err(e) => {
+                            Logger::debug(format!("control connection failed: {}", e));
+                            continue;
+                        }
+                    }
+                    if let Err(e) = respond(stream, &requests) {
+                        Logger::debug(format!("control connection failed: {}", e));
+                    }
+                }
+                Err(e) => Logger::debug(format!("control connection failed: {}", e)),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.uid)
+}
+
+fn respond(stream: UnixStream, requests: &Sender<ControlRequest>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(CONTROL_READ_TIMEOUT_SECS)))?;
+
+    let reader = BufReader::new(&stream);
+    let mut writer = &stream;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let reply = match ControlCommand::parse(line) {
+            Ok(command) => {
+                let (reply, answer) = mpsc::channel();
+                let request = ControlRequest { command, reply };
+                if requests.send(request).is_err() {
+                    "error: rspy is shutting down".to_string()
+                } else {
+                    answer
+                        .recv_timeout(Duration::from_secs(CONTROL_REPLY_TIMEOUT_SECS))
+                        .unwrap_or_else(|_| "error: no answer from the event loop".to_string())
+                }
+            }
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(writer, "{}", reply)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
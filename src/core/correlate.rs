@@ -0,0 +1,283 @@
+use rustc_hash::FxHashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use super::constants::{CORRELATE_MAX_STEPS, UNKNOWN_COMMAND};
+use super::event::{ConnectionEvent, Event, FsEvent, ProcessEvent};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Process,
+    File,
+    Network,
+}
+
+struct Story {
+    uid: Option<u32>,
+    steps: Vec<(Kind, String)>,
+    // steps past CORRELATE_MAX_STEPS are only counted
+    omitted: usize,
+    // the pids that joined, forgotten together with the story
+    members: Vec<u32>,
+    last: SystemTime,
+}
+
+impl Story {
+    fn new(uid: Option<u32>, at: SystemTime) -> Self {
+        Self {
+            uid,
+            steps: Vec::new(),
+            omitted: 0,
+            members: Vec::new(),
+            last: at,
+        }
+    }
+
+    fn push(&mut self, kind: Kind, step: String, at: SystemTime) {
+        if self.steps.len() < CORRELATE_MAX_STEPS {
+            self.steps.push((kind, step));
+        } else {
+            self.omitted += 1;
+        }
+        self.last = self.last.max(at);
+    }
+
+    // a process that only started others is nothing to tell
+    fn is_interesting(&self) -> bool {
+        let first = self.steps[0].0;
+        self.steps.iter().any(|(kind, _)| *kind != first)
+    }
+}
+
+// a story that nothing joined for a whole window
+pub struct Finished {
+    pub pid: u32,
+    pub uid: Option<u32>,
+    pub text: String,
+}
+
+// --correlate, follows a process, its children, the files they write and the sockets they
+// open for as long as one step follows the previous within the window, then tells it as
+// one line. files written by an unknown process (inotify) are joined by their path when
+// something runs them
+pub struct Correlator {
+    window: Duration,
+    // by the pid that started the story
+    stories: FxHashMap<u32, Story>,
+    // every pid in a story, to the pid that started it
+    owners: FxHashMap<u32, u32>,
+    // files written recently, with the story they were written in
+    written: FxHashMap<PathBuf, (Option<u32>, SystemTime)>,
+}
+
+impl Correlator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            stories: FxHashMap::default(),
+            owners: FxHashMap::default(),
+            written: FxHashMap::default(),
+        }
+    }
+
+    pub fn observe(&mut self, event: &Event) {
+        match event {
+            Event::Process(process) if process.thread.is_none() => self.observe_process(process),
+            Event::Fs(fs_event) => self.observe_file(fs_event),
+            Event::Connection(connection) => self.observe_connection(connection),
+            _ => {}
+        }
+    }
+
+    fn observe_process(&mut self, process: &ProcessEvent) {
+        let at = process.observed_at;
+        // something written within the window is run, by its exe or as an argument
+        let ran = process
+            .exe
+            .iter()
+            .cloned()
+            .chain(process.cmdline.split_whitespace().map(PathBuf::from))
+            .find_map(|path| {
+                let (writer, written_at) = *self.written.get(&path)?;
+                self.is_recent(written_at, at).then_some((path, writer))
+            });
+
+        if let Some((path, writer)) = ran {
+            let step = format!("PID {} ran {}", process.pid, process.cmdline);
+            match writer.and_then(|writer| self.owner(writer)) {
+                Some(owner) => self.join(owner, process.pid, Kind::Process, step, at),
+                None => {
+                    // inotify does not know who wrote it
+                    let story = self.open(process.pid, process.uid, at);
+                    story.push(Kind::File, format!("{} was written", path.display()), at);
+                    story.push(Kind::Process, step, at);
+                }
+            }
+            return;
+        }
+
+        let parent = process.ppid.and_then(|ppid| self.owner(ppid));
+        match parent {
+            Some(owner) => {
+                let step = format!("started {} (PID {})", process.cmdline, process.pid);
+                self.join(owner, process.pid, Kind::Process, step, at);
+            }
+            None => self.open(process.pid, process.uid, at).push(
+                Kind::Process,
+                format!("ran {}", process.cmdline),
+                at,
+            ),
+        }
+    }
+
+    fn observe_file(&mut self, fs_event: &FsEvent) {
+        let verb = if ["CREATE", "MODIFY", "CLOSE_WRITE", "MOVED_TO"]
+            .iter()
+            .any(|kind| fs_event.events.contains(kind))
+        {
+            "wrote"
+        } else if fs_event.events.contains("DELETE") {
+            "deleted"
+        } else {
+            // opening and reading is what everything does all the time
+            return;
+        };
+
+        let at = fs_event.observed_at;
+        let path = fs_event.full_path();
+        let owner = fs_event.pid.map(|pid| match self.owner(pid) {
+            Some(owner) => owner,
+            None => {
+                let process = fs_event.process.as_deref().unwrap_or(UNKNOWN_COMMAND);
+                self.open(pid, None, at)
+                    .push(Kind::Process, format!("ran {}", process), at);
+                pid
+            }
+        });
+        if let Some(owner) = owner
+            && let Some(story) = self.stories.get_mut(&owner)
+        {
+            // an editor writes the same file over and over
+            let step = format!("{} {}", verb, path.display());
+            if story.steps.last().map(|(_, last)| last) != Some(&step) {
+                story.push(Kind::File, step, at);
+            }
+        }
+        if verb == "wrote" {
+            self.written.insert(path, (owner, at));
+        }
+    }
+
+    fn observe_connection(&mut self, connection: &ConnectionEvent) {
+        let Some(pid) = connection.pid else {
+            return;
+        };
+        let at = connection.observed_at;
+        let step = match &connection.remote {
+            Some(remote) => format!("connected to {} ({})", remote, connection.protocol),
+            None => format!("listened on {} ({})", connection.local, connection.protocol),
+        };
+        let owner = match self.owner(pid) {
+            Some(owner) => owner,
+            None => {
+                let process = connection.process.as_deref().unwrap_or(UNKNOWN_COMMAND);
+                self.open(pid, connection.uid, at).push(
+                    Kind::Process,
+                    format!("ran {}", process),
+                    at,
+                );
+                pid
+            }
+        };
+        self.join(owner, pid, Kind::Network, step, at);
+    }
+
+    fn owner(&self, pid: u32) -> Option<u32> {
+        self.owners.get(&pid).copied()
+    }
+
+    fn open(&mut self, pid: u32, uid: Option<u32>, at: SystemTime) -> &mut Story {
+        // a recycled pid starts over
+        if let Some(owner) = self.owners.insert(pid, pid)
+            && owner != pid
+            && let Some(story) = self.stories.get_mut(&owner)
+        {
+            story.members.retain(|member| *member != pid);
+        }
+        let story = self
+            .stories
+            .entry(pid)
+            .or_insert_with(|| Story::new(uid, at));
+        if !story.members.contains(&pid) {
+            story.members.push(pid);
+        }
+        story
+    }
+
+    fn join(&mut self, owner: u32, pid: u32, kind: Kind, step: String, at: SystemTime) {
+        let Some(story) = self.stories.get_mut(&owner) else {
+            return;
+        };
+        if !story.members.contains(&pid) {
+            story.members.push(pid);
+        }
+        story.push(kind, step, at);
+        self.owners.insert(pid, owner);
+    }
+
+    fn is_recent(&self, earlier: SystemTime, later: SystemTime) -> bool {
+        later
+            .duration_since(earlier)
+            .map_or(true, |elapsed| elapsed <= self.window)
+    }
+
+    // ends the stories nothing joined within the window before `now`, or all of them, and
+    // returns the ones with more than one kind of step
+    pub fn expire(&mut self, now: Option<SystemTime>) -> Vec<Finished> {
+        let window = self.window;
+        let is_open = |last: SystemTime| {
+            now.is_some_and(|now| {
+                now.duration_since(last)
+                    .map_or(true, |elapsed| elapsed < window)
+            })
+        };
+        self.written.retain(|_, (_, at)| is_open(*at));
+
+        let ended: Vec<u32> = self
+            .stories
+            .iter()
+            .filter(|(_, story)| !is_open(story.last))
+            .map(|(pid, _)| *pid)
+            .collect();
+        let mut finished = Vec::new();
+        for pid in ended {
+            let Some(story) = self.stories.remove(&pid) else {
+                continue;
+            };
+            for member in &story.members {
+                if self.owners.get(member) == Some(&pid) {
+                    self.owners.remove(member);
+                }
+            }
+            if !story.is_interesting() {
+                continue;
+            }
+            let mut text = story
+                .steps
+                .iter()
+                .map(|(_, step)| step.as_str())
+                .collect::<Vec<_>>()
+                .join(" then ");
+            if story.omitted > 0 {
+                text.push_str(&format!(" (and {} more)", story.omitted));
+            }
+            finished.push(Finished {
+                pid,
+                uid: story.uid,
+                text,
+            });
+        }
+        finished.sort_by_key(|story| story.pid);
+        finished
+    }
+}
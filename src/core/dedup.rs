@@ -0,0 +1,129 @@
+use std::time::{Duration, SystemTime};
+
+use super::logger::Logger;
+use crate::utils::lru::Lru;
+
+// the least recently seen commands are forgotten once `capacity` are known, they are then
+// printed again the next time
+pub struct CommandDedup {
+    counts: Lru<(Option<u32>, String), u64>,
+}
+
+impl CommandDedup {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            counts: Lru::new("unique-cmd", capacity),
+        }
+    }
+
+    pub fn normalize(cmd: &str) -> String {
+        cmd.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    // returns how many times this (uid, cmdline) pair has been seen, including this one
+    pub fn record(&mut self, uid: Option<u32>, cmd: &str) -> u64 {
+        let key = (uid, Self::normalize(cmd));
+        if let Some(count) = self.counts.get_mut(&key) {
+            *count += 1;
+            return *count;
+        }
+        self.counts.insert(key, 1);
+        1
+    }
+
+    pub fn is_repeat(&mut self, uid: Option<u32>, cmd: &str) -> bool {
+        // kernel threads have no cmdline, collapsing them would hide distinct processes
+        if cmd.trim().is_empty() {
+            return false;
+        }
+
+        let count = self.record(uid, cmd);
+        if count > 1 {
+            Logger::debug(format!("suppressed repeated command (x{}): {}", count, cmd));
+            return true;
+        }
+        false
+    }
+
+    pub fn repeated(&self) -> Vec<(Option<u32>, &str, u64)> {
+        let mut repeated: Vec<_> = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|((uid, cmd), count)| (*uid, cmd.as_str(), *count))
+            .collect();
+        repeated.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(b.1)));
+        repeated
+    }
+}
+
+struct CommandWindow {
+    started: SystemTime,
+    repeats: u64,
+}
+
+// like CommandDedup but a command is printed again once its window is over, the repeats
+// in between are reported as a single line when the window closes
+pub struct WindowDedup {
+    window: Duration,
+    windows: Lru<(Option<u32>, String), CommandWindow>,
+}
+
+impl WindowDedup {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            windows: Lru::new("dedup-window", capacity),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    // `at` is the time the event was observed, so replays collapse the same way
+    pub fn is_repeat(&mut self, uid: Option<u32>, cmd: &str, at: SystemTime) -> bool {
+        if cmd.trim().is_empty() {
+            return false;
+        }
+
+        let key = (uid, CommandDedup::normalize(cmd));
+        if let Some(window) = self.windows.get_mut(&key)
+            && Self::is_open(window, self.window, at)
+        {
+            window.repeats += 1;
+            return true;
+        }
+        self.windows.insert(
+            key,
+            CommandWindow {
+                started: at,
+                repeats: 0,
+            },
+        );
+        false
+    }
+
+    fn is_open(window: &CommandWindow, length: Duration, at: SystemTime) -> bool {
+        at.duration_since(window.started)
+            .map_or(true, |elapsed| elapsed < length)
+    }
+
+    // closes the windows that are over at `now`, or all of them, and returns the
+    // commands that were repeated in them
+    pub fn expire(&mut self, now: Option<SystemTime>) -> Vec<(Option<u32>, String, u64)> {
+        let length = self.window;
+        let mut repeated = Vec::new();
+        self.windows.retain(|(uid, cmd), window| {
+            if now.is_some_and(|now| Self::is_open(window, length, now)) {
+                return true;
+            }
+            if window.repeats > 0 {
+                repeated.push((*uid, cmd.clone(), window.repeats));
+            }
+            false
+        });
+        repeated.sort_by(|a, b| a.1.cmp(&b.1));
+        repeated
+    }
+}
@@ -0,0 +1,117 @@
+use rustc_hash::FxHashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use super::{dedup::CommandDedup, error::Result, event::Event, logger::Logger, record};
+
+// what one recording saw, each with how often
+#[derive(Default)]
+struct Session {
+    commands: FxHashMap<(Option<u32>, String), u64>,
+    files: FxHashMap<PathBuf, u64>,
+    connections: FxHashMap<String, u64>,
+    events: usize,
+}
+
+impl Session {
+    fn load(path: &Path) -> Result<Self> {
+        let mut session = Self::default();
+        let events = record::replay(path, 0.0, |event| {
+            session.observe(&event);
+            true
+        })?;
+        session.events = events;
+        Ok(session)
+    }
+
+    fn observe(&mut self, event: &Event) {
+        match event {
+            Event::Process(process) if process.thread.is_none() => {
+                let cmd = CommandDedup::normalize(&process.cmdline);
+                if !cmd.is_empty() {
+                    *self.commands.entry((process.uid, cmd)).or_insert(0) += 1;
+                }
+            }
+            Event::Fs(fs_event) => *self.files.entry(fs_event.full_path()).or_insert(0) += 1,
+            Event::Connection(connection) => {
+                let endpoint = match &connection.remote {
+                    Some(remote) => format!("{} to {}", connection.protocol, remote),
+                    None => format!("{} listening on {}", connection.protocol, connection.local),
+                };
+                *self.connections.entry(endpoint).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+// `rspy diff`, the commands, files and connections only one of two recordings saw, e.g. a
+// quiet baseline against the window of an incident. counts are not compared, only presence
+pub fn run(first: &Path, second: &Path) -> Result<()> {
+    let a = Session::load(first)?;
+    let b = Session::load(second)?;
+    Logger::info(format!(
+        "comparing {} ({} events) with {} ({} events)",
+        first.display(),
+        a.events,
+        second.display(),
+        b.events
+    ));
+
+    let mut differences = 0;
+    for (path, only, other) in [(first, &a, &b), (second, &b, &a)] {
+        let mut lines = Vec::new();
+        section(
+            &mut lines,
+            "commands",
+            &only.commands,
+            &other.commands,
+            |(uid, cmd)| format!("UID={:<5} | {}", Logger::format_uid(*uid), cmd),
+        );
+        section(&mut lines, "files", &only.files, &other.files, |path| {
+            path.display().to_string()
+        });
+        section(
+            &mut lines,
+            "connections",
+            &only.connections,
+            &other.connections,
+            |endpoint| endpoint.clone(),
+        );
+        if lines.is_empty() {
+            continue;
+        }
+        differences += 1;
+        Logger::status(format!("only in {}:", path.display()));
+        for line in lines {
+            Logger::status(line);
+        }
+    }
+    if differences == 0 {
+        Logger::info("both recordings saw the same commands, files and connections");
+    }
+    Ok(())
+}
+
+fn section<K: Eq + Hash + Ord>(
+    lines: &mut Vec<String>,
+    title: &str,
+    only: &FxHashMap<K, u64>,
+    other: &FxHashMap<K, u64>,
+    describe: impl Fn(&K) -> String,
+) {
+    // most frequent first, ties by key so the output is stable between runs
+    let mut missing: Vec<(&K, u64)> = only
+        .iter()
+        .filter(|(key, _)| !other.contains_key(key))
+        .map(|(key, count)| (key, *count))
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+    missing.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    lines.push(format!("  {} ({}):", title, missing.len()));
+    for (key, count) in missing {
+        lines.push(format!("    {:>8}  {}", count, describe(key)));
+    }
+}
@@ -11,8 +11,13 @@ pub enum RsSpyError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[cfg(feature = "dbus")]
     #[error("dbus error: {0}")]
-    DBus(#[from] dbus::Error),
+    DBus(#[from] zbus::Error),
+
+    #[cfg(feature = "sqlite")]
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
 
     #[error("configuration error: {0}")]
     Config(String),
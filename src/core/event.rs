@@ -0,0 +1,489 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use super::constants::ROOT_UID;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProcessSource {
+    Procfs,
+    DBus,
+    Audit,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessEvent {
+    pub source: ProcessSource,
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    pub uid: Option<u32>,
+    // the effective uid, only audit records carry it
+    #[serde(default)]
+    pub euid: Option<u32>,
+    pub gid: Option<u32>,
+    pub cmdline: String,
+    pub exe: Option<PathBuf>,
+    pub exe_deleted: bool,
+    pub exe_mismatch: bool,
+    pub exe_sha256: Option<String>,
+    pub cwd: Option<PathBuf>,
+    pub start_time: Option<SystemTime>,
+    pub observed_at: SystemTime,
+    pub ancestors: Vec<(u32, String)>,
+    #[serde(default)]
+    pub cgroup: Option<Cgroup>,
+    // set when the process lives in a different pid namespace than rspy
+    #[serde(default)]
+    pub containerized: bool,
+    // the pid as seen inside its own namespace
+    #[serde(default)]
+    pub ns_pid: Option<u32>,
+    // set for threads found by --scan-threads, pid then is the process they belong to
+    #[serde(default)]
+    pub thread: Option<ThreadInfo>,
+    // set when an existing pid rewrote its argv or exec'd, with what it was before
+    #[serde(default)]
+    pub changed_from: Option<Box<ProcessImage>>,
+    // --show-fds, capped per event with the number left out
+    #[serde(default)]
+    pub fds: Vec<OpenFd>,
+    #[serde(default)]
+    pub omitted_fds: usize,
+    // --show-caps
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
+    // mode bits of the binary on disk
+    #[serde(default)]
+    pub exe_setuid: bool,
+    #[serde(default)]
+    pub exe_setgid: bool,
+    // --highlight-tmp-exec, see monitoring::process::exe_in_writable_dir
+    #[serde(default)]
+    pub exe_in_writable_dir: bool,
+    // LD_PRELOAD, LD_AUDIT and LD_LIBRARY_PATH as NAME=value
+    #[serde(default)]
+    pub loader_env: Vec<String>,
+    // --check-libraries, see utils::libraries::unexpected
+    #[serde(default)]
+    pub unexpected_libraries: Vec<PathBuf>,
+    // the file a shell, python, perl, php or ruby was started with
+    #[serde(default)]
+    pub script: Option<Script>,
+    // --analyze-writability, set by the runtime rather than the collectors
+    #[serde(default)]
+    pub writable: Option<WritablePaths>,
+    // --show-cwd, anything but "/" means a chroot
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    // --show-unit, the systemd unit the process belongs to
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+// CapEff and CapPrm from /proc/<pid>/status
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub effective: u64,
+    pub permitted: u64,
+}
+
+impl Capabilities {
+    // what the process has or can raise at will
+    pub fn held(&self) -> u64 {
+        self.effective | self.permitted
+    }
+}
+
+impl ProcessEvent {
+    // everything but what every collector knows left empty, collectors fill in the rest
+    // with struct update syntax
+    pub fn new(source: ProcessSource, pid: u32, cmdline: String) -> Self {
+        ProcessEvent {
+            source,
+            pid,
+            ppid: None,
+            uid: None,
+            euid: None,
+            gid: None,
+            cmdline,
+            exe: None,
+            exe_deleted: false,
+            exe_mismatch: false,
+            exe_sha256: None,
+            cwd: None,
+            start_time: None,
+            observed_at: SystemTime::now(),
+            ancestors: Vec::new(),
+            cgroup: None,
+            containerized: false,
+            ns_pid: None,
+            thread: None,
+            changed_from: None,
+            fds: Vec::new(),
+            omitted_fds: 0,
+            capabilities: None,
+            exe_setuid: false,
+            exe_setgid: false,
+            exe_in_writable_dir: false,
+            loader_env: Vec::new(),
+            unexpected_libraries: Vec::new(),
+            script: None,
+            writable: None,
+            root: None,
+            unit: None,
+        }
+    }
+
+    // capabilities held without being root, root holds them all anyway
+    pub fn non_root_capabilities(&self) -> u64 {
+        match (&self.capabilities, self.uid) {
+            (Some(capabilities), Some(uid)) if uid != ROOT_UID => capabilities.held(),
+            _ => 0,
+        }
+    }
+
+    // LD_PRELOAD or LD_AUDIT, both load code of their choice into the process
+    pub fn preloads(&self) -> bool {
+        self.loader_env
+            .iter()
+            .any(|entry| entry.starts_with("LD_PRELOAD=") || entry.starts_with("LD_AUDIT="))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Script {
+    pub path: PathBuf,
+    pub uid: u32,
+    pub gid: u32,
+    // permission bits only
+    pub mode: u32,
+}
+
+impl Script {
+    // someone besides root and `runner` can change what it does the next time it runs
+    pub fn writable_by_others(&self, runner: Option<u32>) -> bool {
+        let other_owner =
+            self.uid != ROOT_UID && Some(self.uid) != runner && self.mode & libc::S_IWUSR != 0;
+        // gid 0 rarely has members besides root
+        let group = self.gid != ROOT_UID && self.mode & libc::S_IWGRP != 0;
+        other_owner || group || self.mode & libc::S_IWOTH != 0
+    }
+}
+
+// what of a root process `uid` could change, see core::writability
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WritablePaths {
+    pub uid: u32,
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OpenFd {
+    pub fd: u32,
+    pub target: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessImage {
+    pub cmdline: String,
+    pub exe: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ThreadInfo {
+    pub tid: u32,
+    pub comm: String,
+}
+
+// where a process lives, derived from its cgroup path
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cgroup {
+    pub path: String,
+    pub container_id: Option<String>,
+    // kubernetes pod uid
+    pub pod: Option<String>,
+    pub unit: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExitEvent {
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    pub uid: Option<u32>,
+    pub cmdline: String,
+    pub lifetime: Option<Duration>,
+    pub observed_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FsEvent {
+    pub events: String,
+    pub path: PathBuf,
+    // the entry inside the watched directory `path` that changed, inotify only
+    #[serde(default)]
+    pub name: Option<String>,
+    // a change to a file watched on its own with -d
+    #[serde(default)]
+    pub file_modified: bool,
+    // only known to backends that report the accessing process, e.g. fanotify
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    // set for the files rspy keeps a copy of, see monitoring::contents
+    #[serde(default)]
+    pub content: Option<ContentChange>,
+    pub observed_at: SystemTime,
+}
+
+// why rspy keeps a copy of a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileCategory {
+    // ld.so.preload, ld.so.cache and ld.so.conf(.d), they decide what every process loads
+    Loader,
+    // --diff-files
+    Diff,
+    // --persistence-watch
+    Persistence,
+}
+
+impl FileCategory {
+    pub fn label(self) -> &'static str {
+        match self {
+            FileCategory::Loader => "LOADER CONFIG CHANGED",
+            FileCategory::Diff => "FILE CHANGED",
+            FileCategory::Persistence => "PERSISTENCE",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContentChange {
+    pub category: FileCategory,
+    // e.g. "created, 2 lines" or "binary, 10240 -> 10312 bytes"
+    pub summary: String,
+    // unified diff hunks, empty for binary files and ones too large to keep
+    pub diff: Vec<String>,
+}
+
+impl FsEvent {
+    pub fn full_path(&self) -> PathBuf {
+        match &self.name {
+            Some(name) => self.path.join(name),
+            None => self.path.clone(),
+        }
+    }
+}
+
+// a socket that appeared since the previous poll of /proc/net
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConnectionEvent {
+    pub protocol: String,
+    pub local: String,
+    // none for listening sockets
+    pub remote: Option<String>,
+    pub uid: Option<u32>,
+    // the owner, unknown when the socket was closed before it could be looked up
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    pub observed_at: SystemTime,
+}
+
+impl ConnectionEvent {
+    pub fn describe(&self) -> String {
+        match &self.remote {
+            Some(remote) => format!("{} {} -> {}", self.protocol, self.local, remote),
+            None => format!("{} listening on {}", self.protocol, self.local),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionKind {
+    Login,
+    Logout,
+}
+
+impl SessionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SessionKind::Login => "login",
+            SessionKind::Logout => "logout",
+        }
+    }
+}
+
+// a USER_PROCESS record that appeared in or left utmp
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub kind: SessionKind,
+    pub user: String,
+    // owner of the tty, utmp itself only has the name
+    pub uid: Option<u32>,
+    pub tty: String,
+    // empty for local logins
+    pub host: Option<String>,
+    // the login process, e.g. the sshd session or login
+    pub pid: Option<u32>,
+    pub observed_at: SystemTime,
+}
+
+impl SessionEvent {
+    pub fn describe(&self) -> String {
+        let action = self.kind.as_str();
+        match &self.host {
+            Some(host) => format!("{} of {} on {} from {}", action, self.user, self.tty, host),
+            None => format!("{} of {} on {}", action, self.user, self.tty),
+        }
+    }
+}
+
+// a kernel uevent for a device that was added or removed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceEvent {
+    pub action: String,
+    pub subsystem: Option<String>,
+    pub devtype: Option<String>,
+    pub devpath: String,
+    // the node under /dev, if the device has one
+    pub devname: Option<String>,
+    // network interfaces have no node but a name
+    pub interface: Option<String>,
+    // vendor/product/revision of usb devices
+    pub product: Option<String>,
+    pub observed_at: SystemTime,
+}
+
+impl DeviceEvent {
+    pub fn describe(&self) -> String {
+        let kind = match (&self.subsystem, &self.devtype) {
+            (Some(subsystem), Some(devtype)) => format!("{}/{}", subsystem, devtype),
+            (Some(subsystem), None) => subsystem.clone(),
+            (None, _) => "device".to_string(),
+        };
+        let target = match (&self.devname, &self.interface) {
+            (Some(devname), _) => format!("/dev/{}", devname),
+            (None, Some(interface)) => interface.clone(),
+            (None, None) => self.devpath.clone(),
+        };
+        match &self.product {
+            Some(product) => format!("{} {} {} (product {})", self.action, kind, target, product),
+            None => format!("{} {} {}", self.action, kind, target),
+        }
+    }
+}
+
+// a method call or signal on the system bus, seen with --dbus-sniff
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BusEvent {
+    // "method_call" or "signal"
+    pub kind: String,
+    // the unique name of the sending connection, e.g. ":1.42"
+    pub sender: Option<String>,
+    // the sender as the bus knows it, unknown once it disconnected
+    pub pid: Option<u32>,
+    pub uid: Option<u32>,
+    pub process: Option<String>,
+    // broadcast signals have none
+    pub destination: Option<String>,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub observed_at: SystemTime,
+}
+
+impl BusEvent {
+    pub fn describe(&self) -> String {
+        let member = match (&self.interface, &self.member) {
+            (Some(interface), Some(member)) => format!("{}.{}", interface, member),
+            (None, Some(member)) => member.clone(),
+            (_, None) => "?".to_string(),
+        };
+        let mut description = format!("{} {}", self.kind.replace('_', " "), member);
+        if let Some(path) = &self.path {
+            description.push_str(&format!(" on {}", path));
+        }
+        if let Some(destination) = &self.destination {
+            description.push_str(&format!(" to {}", destination));
+        }
+        description
+    }
+}
+
+// a polkit CheckAuthorization call together with its answer, seen with --polkit
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthorizationEvent {
+    // e.g. org.freedesktop.policykit.exec
+    pub action: String,
+    // the process the check is about
+    pub subject_pid: Option<u32>,
+    pub subject_uid: Option<u32>,
+    pub subject: Option<String>,
+    // the service that asked, e.g. pkexec or systemd
+    pub caller_pid: Option<u32>,
+    pub caller: Option<String>,
+    // the command pkexec was asked to run
+    pub command: Option<String>,
+    pub result: AuthorizationResult,
+    pub observed_at: SystemTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthorizationResult {
+    Authorized,
+    // would be authorized after authenticating, only when the caller did not allow it
+    Challenge,
+    NotAuthorized,
+    // the dbus error polkit answered with, e.g. when the authentication was cancelled
+    Error(String),
+}
+
+impl AuthorizationResult {
+    pub fn as_str(&self) -> &str {
+        match self {
+            AuthorizationResult::Authorized => "authorized",
+            AuthorizationResult::Challenge => "challenge",
+            AuthorizationResult::NotAuthorized => "not authorized",
+            AuthorizationResult::Error(name) => name,
+        }
+    }
+}
+
+impl AuthorizationEvent {
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} {}", self.action, self.result.as_str());
+        if let Some(command) = &self.command {
+            description.push_str(&format!(" to run {}", command));
+        }
+        if let Some(caller) = &self.caller {
+            description.push_str(&format!(", asked by {}", caller));
+        }
+        description
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Event {
+    Process(Box<ProcessEvent>),
+    Exit(ExitEvent),
+    Fs(FsEvent),
+    Connection(ConnectionEvent),
+    Session(SessionEvent),
+    Device(DeviceEvent),
+    Bus(BusEvent),
+    Authorization(AuthorizationEvent),
+}
+
+impl Event {
+    pub fn observed_at(&self) -> SystemTime {
+        match self {
+            Event::Process(process) => process.observed_at,
+            Event::Exit(exit) => exit.observed_at,
+            Event::Fs(fs_event) => fs_event.observed_at,
+            Event::Connection(connection) => connection.observed_at,
+            Event::Session(session) => session.observed_at,
+            Event::Device(device) => device.observed_at,
+            Event::Bus(bus) => bus.observed_at,
+            Event::Authorization(auth) => auth.observed_at,
+        }
+    }
+}
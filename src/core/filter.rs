@@ -0,0 +1,142 @@
+use regex::Regex;
+use rustc_hash::FxHashSet;
+use std::time::{Duration, SystemTime};
+
+use super::dedup::{CommandDedup, WindowDedup};
+use super::sinks::exec;
+
+#[derive(Default)]
+pub struct EventFilter {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+    uids: Vec<u32>,
+    excluded_uids: Vec<u32>,
+    unique_cmd: Option<CommandDedup>,
+    dedup_window: Option<WindowDedup>,
+}
+
+impl EventFilter {
+    pub fn new(
+        include: Option<Regex>,
+        exclude: Option<Regex>,
+        uids: Vec<u32>,
+        excluded_uids: Vec<u32>,
+        unique_cmd: Option<CommandDedup>,
+    ) -> Self {
+        Self {
+            include,
+            exclude,
+            uids,
+            excluded_uids,
+            unique_cmd,
+            dedup_window: None,
+        }
+    }
+
+    pub fn set_dedup_window(&mut self, window: Option<Duration>, capacity: usize) {
+        self.dedup_window = window.map(|window| WindowDedup::new(window, capacity));
+    }
+
+    pub fn set_include(&mut self, include: Option<Regex>) {
+        self.include = include;
+    }
+
+    // dedup runs last so filtered out commands never count as seen
+    pub fn allows(&mut self, uid: Option<u32>, cmd: &str, at: SystemTime) -> bool {
+        // an unknown uid can not satisfy an allow list but is never excluded
+        if !self.uids.is_empty() && !uid.is_some_and(|uid| self.uids.contains(&uid)) {
+            return false;
+        }
+
+        if uid.is_some_and(|uid| self.excluded_uids.contains(&uid)) {
+            return false;
+        }
+
+        if let Some(include) = &self.include
+            && !include.is_match(cmd)
+        {
+            return false;
+        }
+
+        if let Some(exclude) = &self.exclude
+            && exclude.is_match(cmd)
+        {
+            return false;
+        }
+
+        if self
+            .unique_cmd
+            .as_mut()
+            .is_some_and(|dedup| dedup.is_repeat(uid, cmd))
+        {
+            return false;
+        }
+
+        !self
+            .dedup_window
+            .as_mut()
+            .is_some_and(|dedup| dedup.is_repeat(uid, cmd, at))
+    }
+
+    pub fn dedup_window(&self) -> Option<Duration> {
+        self.dedup_window.as_ref().map(WindowDedup::window)
+    }
+
+    // commands collapsed in windows that are over at `now`, all of them for None
+    pub fn expired_repeats(&mut self, now: Option<SystemTime>) -> Vec<(Option<u32>, String, u64)> {
+        self.dedup_window
+            .as_mut()
+            .map(|dedup| dedup.expire(now))
+            .unwrap_or_default()
+    }
+
+    pub fn repeated_commands(&self) -> Vec<(Option<u32>, &str, u64)> {
+        self.unique_cmd
+            .as_ref()
+            .map(CommandDedup::repeated)
+            .unwrap_or_default()
+    }
+}
+
+// rspy itself, the commands it runs and --ignore-pid, together with their descendants
+pub struct ExcludedPids {
+    roots: FxHashSet<u32>,
+    own_commands: bool,
+    descendants: FxHashSet<u32>,
+}
+
+impl ExcludedPids {
+    pub fn new(ignore_self: bool, pids: &[u32]) -> Self {
+        let mut roots: FxHashSet<u32> = pids.iter().copied().collect();
+        if ignore_self {
+            roots.insert(std::process::id());
+        }
+        Self {
+            roots,
+            own_commands: ignore_self,
+            descendants: FxHashSet::default(),
+        }
+    }
+
+    // a pid that was excluded before and comes back with a parent that is not is a reused one
+    pub fn excludes_process(&mut self, pid: u32, ppid: Option<u32>) -> bool {
+        let excluded = self.roots.contains(&pid)
+            || ppid
+                .is_some_and(|ppid| self.roots.contains(&ppid) || self.descendants.contains(&ppid))
+            || (self.own_commands && exec::is_own_command(Some(pid), ppid));
+        if excluded {
+            self.descendants.insert(pid);
+        } else {
+            self.descendants.remove(&pid);
+        }
+        excluded
+    }
+
+    pub fn excludes(&self, pid: Option<u32>) -> bool {
+        pid.is_some_and(|pid| self.roots.contains(&pid) || self.descendants.contains(&pid))
+    }
+
+    pub fn forget(&mut self, pid: u32) {
+        self.descendants.remove(&pid);
+    }
+}
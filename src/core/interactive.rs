@@ -0,0 +1,224 @@
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::thread;
+
+use super::logger::Logger;
+
+struct DisplayFilter {
+    pattern: Option<Regex>,
+    uid: Option<u32>,
+    paused: bool,
+    prompting: bool,
+    seen_uids: BTreeSet<u32>,
+}
+
+impl DisplayFilter {
+    const fn new() -> Self {
+        Self {
+            pattern: None,
+            uid: None,
+            paused: false,
+            prompting: false,
+            seen_uids: BTreeSet::new(),
+        }
+    }
+
+    fn is_muted(&self) -> bool {
+        self.paused || self.prompting
+    }
+
+    fn next_uid(&self) -> Option<u32> {
+        match self.uid {
+            None => self.seen_uids.iter().next().copied(),
+            Some(current) => self.seen_uids.range(current + 1..).next().copied(),
+        }
+    }
+}
+
+static DISPLAY_FILTER: Mutex<DisplayFilter> = Mutex::new(DisplayFilter::new());
+static ORIGINAL_TERMIOS: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+// the display filter only decides what reaches the terminal, collectors keep running
+pub fn allows_process(uid: Option<u32>, cmd: &str) -> bool {
+    let Ok(mut filter) = DISPLAY_FILTER.lock() else {
+        return true;
+    };
+
+    if let Some(uid) = uid {
+        filter.seen_uids.insert(uid);
+    }
+
+    if filter.is_muted() {
+        return false;
+    }
+    if filter.uid.is_some() && filter.uid != uid {
+        return false;
+    }
+    filter
+        .pattern
+        .as_ref()
+        .is_none_or(|pattern| pattern.is_match(cmd))
+}
+
+pub fn allows_fs(message: &str) -> bool {
+    let Ok(filter) = DISPLAY_FILTER.lock() else {
+        return true;
+    };
+
+    !filter.is_muted()
+        && filter
+            .pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(message))
+}
+
+pub fn is_available() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+pub fn start() {
+    if let Err(e) = set_key_mode(true) {
+        Logger::error(format!(
+            "failed to configure terminal for keybindings: {}",
+            e
+        ));
+        return;
+    }
+
+    Logger::info("keys: [/] filter regex, [u] cycle uid, [p] pause, [c] clear".to_string());
+
+    thread::spawn(|| {
+        let mut stdin = io::stdin();
+        let mut key = [0u8; 1];
+
+        loop {
+            match stdin.read(&mut key) {
+                Ok(0) => break,
+                Ok(_) => handle_key(key[0]),
+                Err(e) => {
+                    Logger::error(format!("failed to read key: {}", e));
+                    break;
+                }
+            }
+        }
+    });
+}
+
+pub fn restore_terminal() {
+    let _ = set_key_mode(false);
+}
+
+fn handle_key(key: u8) {
+    match key {
+        b'/' => prompt_pattern(),
+        b'u' => {
+            let uid = with_filter(|filter| {
+                filter.uid = filter.next_uid();
+                filter.uid
+            });
+            match uid {
+                Some(uid) => Logger::info(format!("display filter: uid {}", uid)),
+                None => Logger::info("display filter: all uids".to_string()),
+            }
+        }
+        b'p' => {
+            let paused = with_filter(|filter| {
+                filter.paused = !filter.paused;
+                filter.paused
+            });
+            Logger::info(
+                if paused {
+                    "display paused"
+                } else {
+                    "display resumed"
+                }
+                .to_string(),
+            );
+        }
+        b'c' => {
+            with_filter(|filter| {
+                filter.pattern = None;
+                filter.uid = None;
+                filter.paused = false;
+            });
+            Logger::info("display filters cleared".to_string());
+        }
+        _ => {}
+    }
+}
+
+fn prompt_pattern() {
+    with_filter(|filter| filter.prompting = true);
+    let _ = set_key_mode(false);
+
+    print!("filter regex (empty to clear): ");
+    let _ = io::stdout().flush();
+
+    let mut input = String::new();
+    let read = io::stdin().read_line(&mut input);
+
+    let _ = set_key_mode(true);
+    with_filter(|filter| filter.prompting = false);
+
+    if read.is_err() {
+        return;
+    }
+
+    let input = input.trim();
+    if input.is_empty() {
+        with_filter(|filter| filter.pattern = None);
+        Logger::info("display filter: regex cleared".to_string());
+        return;
+    }
+
+    match Regex::new(input) {
+        Ok(pattern) => {
+            with_filter(|filter| filter.pattern = Some(pattern));
+            Logger::info(format!("display filter: regex {}", input));
+        }
+        Err(e) => Logger::error(format!("invalid filter regex: {}", e)),
+    }
+}
+
+fn with_filter<T>(f: impl FnOnce(&mut DisplayFilter) -> T) -> T {
+    let mut filter = DISPLAY_FILTER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut filter)
+}
+
+// keys are read unbuffered and without echo, isig stays on so ctrl-c still works
+fn set_key_mode(enabled: bool) -> io::Result<()> {
+    let mut original = ORIGINAL_TERMIOS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if original.is_none() {
+        if !enabled {
+            return Ok(());
+        }
+
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        *original = Some(termios);
+    }
+
+    let Some(mut termios) = *original else {
+        return Ok(());
+    };
+
+    if enabled {
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+    }
+
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,9 +1,33 @@
 use colored::*;
 use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use super::config::TimestampFormat;
 use super::constants::{
-    PID_DISPLAY_WIDTH, ROOT_UID, UID_DISPLAY_WIDTH, UNKNOWN_UID_DISPLAY, USER_UID,
+    CONTAINER_ID_DISPLAY_LEN, PID_DISPLAY_WIDTH, ROOT_UID, UID_DISPLAY_WIDTH, UNKNOWN_COMMAND,
+    UNKNOWN_UID_DISPLAY,
 };
+use super::event::{
+    AuthorizationEvent, AuthorizationResult, BusEvent, Cgroup, ConnectionEvent, DeviceEvent,
+    ExitEvent, FsEvent, ProcessEvent, ProcessSource, SessionEvent,
+};
+use super::interactive;
+use super::severity::Severity;
+use crate::utils::{
+    caps,
+    format::{Template, format_duration},
+    users,
+};
+
+// the format and the time rspy started, for relative timestamps
+static TIMESTAMP_FORMAT: OnceLock<(TimestampFormat, SystemTime)> = OnceLock::new();
+// --format-template, the layout of process and exit lines
+static TEMPLATE: OnceLock<Template> = OnceLock::new();
+// with --output-format json/cef/leef stdout carries only events, everything else goes to stderr
+static STRUCTURED: AtomicBool = AtomicBool::new(false);
 
 pub struct Logger;
 
@@ -19,81 +43,670 @@ impl Logger {
         log::set_max_level(level_filter);
     }
 
-    fn timestamp() -> ColoredString {
-        unsafe {
-            let mut t = 0;
-            libc::time(&mut t);
-            let tm = libc::localtime(&t);
-            format!(
-                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-                (*tm).tm_year + 1900,
-                (*tm).tm_mon + 1,
-                (*tm).tm_mday,
-                (*tm).tm_hour,
-                (*tm).tm_min,
-                (*tm).tm_sec
-            )
-            .green()
+    // applies to everything printed through `colored`, not only the logger
+    pub fn set_color(enabled: bool) {
+        colored::control::set_override(enabled);
+    }
+
+    pub fn set_template(template: Template) {
+        let _ = TEMPLATE.set(template);
+    }
+
+    pub fn set_structured(enabled: bool) {
+        STRUCTURED.store(enabled, Ordering::Relaxed);
+    }
+
+    // a line that is not an event
+    pub fn status<T: std::fmt::Display>(line: T) {
+        if STRUCTURED.load(Ordering::Relaxed) {
+            eprintln!("{}", line);
+            let _ = std::io::stderr().flush();
+        } else {
+            println!("{}", line);
+            let _ = std::io::stdout().flush();
         }
     }
 
-    pub fn info<T: Into<String>>(message: T) {
-        println!("{} [INFO] - {}", Self::timestamp(), message.into());
+    // an event already formatted by core::output
+    pub fn structured_event(line: &str) {
+        println!("{}", line);
         let _ = std::io::stdout().flush();
     }
 
+    fn timestamp() -> ColoredString {
+        Self::format_time(SystemTime::now())
+    }
+
+    pub fn set_timestamp_format(format: TimestampFormat) {
+        let _ = TIMESTAMP_FORMAT.set((format, SystemTime::now()));
+    }
+
+    // events carry their own time so replayed recordings show when things happened
+    fn format_time(time: SystemTime) -> ColoredString {
+        let (format, started) = TIMESTAMP_FORMAT
+            .get()
+            .copied()
+            .unwrap_or((TimestampFormat::Default, UNIX_EPOCH));
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let formatted = match format {
+            TimestampFormat::Epoch => format!(
+                "{}.{:03}",
+                since_epoch.as_secs(),
+                since_epoch.subsec_millis()
+            ),
+            TimestampFormat::Relative => match time.duration_since(started) {
+                Ok(elapsed) => format!("+{:.3}s", elapsed.as_secs_f64()),
+                // events observed before startup, e.g. from a replayed recording
+                Err(e) => format!("-{:.3}s", e.duration().as_secs_f64()),
+            },
+            _ => {
+                let secs = since_epoch.as_secs() as libc::time_t;
+                let tm = unsafe {
+                    let mut tm: libc::tm = std::mem::zeroed();
+                    libc::localtime_r(&secs, &mut tm);
+                    tm
+                };
+                let date = format!(
+                    "{:04}-{:02}-{:02}",
+                    tm.tm_year + 1900,
+                    tm.tm_mon + 1,
+                    tm.tm_mday
+                );
+                let clock = format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec);
+                let millis = since_epoch.subsec_millis();
+
+                match format {
+                    TimestampFormat::Ms => format!("{} {}.{:03}", date, clock, millis),
+                    TimestampFormat::Iso => {
+                        let offset = tm.tm_gmtoff / 60;
+                        format!(
+                            "{}T{}.{:03}{}{:02}:{:02}",
+                            date,
+                            clock,
+                            millis,
+                            if offset < 0 { '-' } else { '+' },
+                            offset.abs() / 60,
+                            offset.abs() % 60
+                        )
+                    }
+                    _ => format!("{} {}", date, clock),
+                }
+            }
+        };
+        formatted.green()
+    }
+
+    // the same without colors, for files
+    pub fn plain_time(time: SystemTime) -> String {
+        Self::format_time(time).clear().to_string()
+    }
+
+    pub fn info<T: Into<String>>(message: T) {
+        Self::status(format!("{} [INFO] - {}", Self::timestamp(), message.into()));
+    }
+
     pub fn error<T: Into<String>>(message: T) {
         eprintln!("{} [ERROR] - {}", Self::timestamp(), message.into().red());
         let _ = std::io::stderr().flush();
     }
 
-    fn format_uid(uid: Option<u32>) -> String {
+    pub fn alert<T: Into<String>>(message: T) {
+        Self::status(format!(
+            "{} {}",
+            Self::timestamp(),
+            format!("[ALERT] - {}", message.into()).red().bold()
+        ));
+    }
+
+    pub fn format_uid(uid: Option<u32>) -> String {
         uid.map_or(UNKNOWN_UID_DISPLAY.to_string(), |u| {
-            format!("{:<width$}", u, width = UID_DISPLAY_WIDTH)
+            let uid = match users::user_name(u) {
+                Some(name) => format!("{}({})", u, name),
+                None => u.to_string(),
+            };
+            format!("{:<width$}", uid, width = UID_DISPLAY_WIDTH)
         })
     }
 
-    fn colorize_by_uid(message: String, uid: Option<u32>) -> ColoredString {
+    pub fn colorize_by_uid(message: String, uid: Option<u32>) -> ColoredString {
         match uid {
             Some(ROOT_UID) => message.red(),
-            Some(USER_UID) => message.blue(),
+            Some(uid) if users::is_regular_user(uid) => message.blue(),
             None => message.yellow(),
             _ => message.normal(),
         }
     }
 
-    fn print_process_event(prefix: &str, uid: Option<u32>, pid: u32, cmd: &str) {
-        let message = format!(
-            "{}: UID={} PID={:<width$} | {}",
+    #[allow(clippy::too_many_arguments)]
+    fn print_process_event(
+        observed_at: SystemTime,
+        prefix: &str,
+        uid: Option<u32>,
+        pid: u32,
+        ppid: Option<u32>,
+        cmd: &str,
+        annotations: &[ColoredString],
+        details: &[String],
+    ) {
+        if !interactive::allows_process(uid, cmd) {
+            return;
+        }
+
+        let timestamp = Self::format_time(observed_at);
+        let ppid = ppid.map_or(UNKNOWN_UID_DISPLAY.to_string(), |p| p.to_string());
+        let template = TEMPLATE.get();
+        let message = match template {
+            // the annotations only where {tags} puts them, and without their own colors
+            Some(template) => template.render(|field| match field {
+                "time" => timestamp.clone().clear().to_string(),
+                "type" => prefix.trim().to_string(),
+                "uid" => uid.map_or(UNKNOWN_UID_DISPLAY.to_string(), |uid| uid.to_string()),
+                "user" => uid.map_or(UNKNOWN_UID_DISPLAY.to_string(), |uid| {
+                    users::user_name(uid).unwrap_or_else(|| uid.to_string())
+                }),
+                "pid" => pid.to_string(),
+                "ppid" => ppid.clone(),
+                "cmd" => cmd.to_string(),
+                "tags" => annotations
+                    .iter()
+                    .map(|annotation| annotation.clone().clear().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => String::new(),
+            }),
+            None => format!(
+                "{}: UID={} PID={:<width$} PPID={:<width$} | {}",
+                prefix,
+                Self::format_uid(uid),
+                pid,
+                ppid,
+                cmd,
+                width = PID_DISPLAY_WIDTH
+            ),
+        };
+        let message = match prefix {
+            // who runs which setuid binary stands out from the uid colors
+            "SUID" | "SGID" => message.bright_magenta().bold(),
+            _ => Self::colorize_by_uid(message, uid),
+        };
+        if template.is_some() {
+            print!("{}", message);
+        } else {
+            print!("{} {}", timestamp, message);
+            for annotation in annotations {
+                print!(" {}", annotation);
+            }
+        }
+        println!();
+
+        // further lines about the same process, e.g. its parent chain
+        for detail in details {
+            println!("{} {}", timestamp, detail.dimmed());
+        }
+        let _ = std::io::stdout().flush();
+    }
+
+    // tags come from matching rules and are printed after the command
+    pub fn process_event(event: &ProcessEvent, tags: &[ColoredString]) {
+        let prefix = match event.source {
+            _ if event.thread.is_some() => "THRD",
+            _ if event.exe_setuid => "SUID",
+            _ if event.exe_setgid => "SGID",
+            ProcessSource::Procfs => "CMD ",
+            ProcessSource::DBus => "DBUS",
+            ProcessSource::Audit => "AUDT",
+        };
+        let mut annotations = Self::exe_annotations(event);
+        annotations.extend(tags.iter().cloned());
+
+        let mut details = Vec::new();
+        if !event.ancestors.is_empty() {
+            let chain = event
+                .ancestors
+                .iter()
+                .map(|(pid, cmd)| format!("{} ({})", pid, cmd))
+                .collect::<Vec<_>>()
+                .join(" <- ");
+            details.push(format!("TREE: {}", chain));
+        }
+        if !event.fds.is_empty() {
+            let mut fds = event
+                .fds
+                .iter()
+                .map(|fd| format!("[{}] {}", fd.fd, fd.target))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if event.omitted_fds > 0 {
+                fds.push_str(&format!(" and {} more", event.omitted_fds));
+            }
+            details.push(format!("FDS : {}", fds));
+        }
+
+        Self::print_process_event(
+            event.observed_at,
             prefix,
-            Self::format_uid(uid),
-            pid,
-            cmd,
+            event.uid,
+            event.pid,
+            event.ppid,
+            &event.cmdline,
+            &annotations,
+            &details,
+        );
+    }
+
+    fn exe_annotations(event: &ProcessEvent) -> Vec<ColoredString> {
+        let mut annotations = Vec::new();
+        if let Some(thread) = &event.thread {
+            annotations.push(
+                format!("[TID {} {}]", thread.tid, thread.comm)
+                    .magenta()
+                    .bold(),
+            );
+        }
+        if let (Some(uid), Some(euid)) = (event.uid, event.euid)
+            && euid != uid
+        {
+            annotations.push(format!("[EUID {}]", euid).magenta().bold());
+        }
+        if let Some(previous) = &event.changed_from {
+            let annotation = if previous.exe.is_some() && previous.exe != event.exe {
+                format!("[EXEC IN SAME PID, WAS {}]", previous.cmdline)
+            } else {
+                format!("[CMDLINE CHANGED, WAS {}]", previous.cmdline)
+            };
+            annotations.push(annotation.yellow().bold());
+        }
+        if let Some(exe) = &event.exe {
+            if event.exe_deleted {
+                annotations.push(format!("[DELETED EXE {}]", exe.display()).red().bold());
+            } else if event.exe_mismatch {
+                annotations.push(format!("[EXE {}]", exe.display()).yellow().bold());
+            }
+            if event.exe_in_writable_dir {
+                annotations.push(format!("[WRITABLE DIR EXE {}]", exe.display()).red().bold());
+            }
+        }
+        if !event.loader_env.is_empty() {
+            let annotation = format!("[{}]", event.loader_env.join(" "));
+            // LD_LIBRARY_PATH on its own is common enough in wrappers and build trees
+            if event.preloads() {
+                annotations.push(annotation.red().bold());
+            } else {
+                annotations.push(annotation.yellow());
+            }
+        }
+        if !event.unexpected_libraries.is_empty() {
+            let paths: Vec<String> = event
+                .unexpected_libraries
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            annotations.push(
+                format!("[UNEXPECTED LIBS {}]", paths.join(", "))
+                    .red()
+                    .bold(),
+            );
+        }
+        if let Some(script) = &event.script {
+            let owner = |id: u32, name: Option<String>| name.unwrap_or_else(|| id.to_string());
+            let mut annotation = format!(
+                "[SCRIPT {} {}:{} {:04o}",
+                script.path.display(),
+                owner(script.uid, users::user_name(script.uid)),
+                owner(script.gid, users::group_name(script.gid)),
+                script.mode
+            );
+            if script.mode & libc::S_IWOTH != 0 {
+                annotation.push_str(" WORLD-WRITABLE");
+            } else if script.mode & libc::S_IWGRP != 0 {
+                annotation.push_str(" GROUP-WRITABLE");
+            }
+            annotation.push(']');
+            if script.writable_by_others(event.uid) {
+                annotations.push(annotation.red().bold());
+            } else {
+                annotations.push(annotation.dimmed());
+            }
+        }
+        if let Some(writable) = &event.writable {
+            let paths: Vec<String> = writable
+                .paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            annotations.push(
+                format!(
+                    "[WRITABLE BY {}: {}]",
+                    users::user_name(writable.uid).unwrap_or_else(|| writable.uid.to_string()),
+                    paths.join(", ")
+                )
+                .red()
+                .bold(),
+            );
+        }
+        let capabilities = event.non_root_capabilities();
+        if capabilities != 0 {
+            let annotation = format!("[CAPS {}]", caps::names(capabilities).join(","));
+            if caps::dangerous(capabilities) != 0 {
+                annotations.push(annotation.red().bold());
+            } else {
+                annotations.push(annotation.yellow());
+            }
+        }
+        // the root is only read with --show-cwd, which also asks for the cwd
+        if let Some(root) = &event.root {
+            if root != Path::new("/") {
+                annotations.push(format!("[CHROOT {}]", root.display()).red().bold());
+            }
+            if let Some(cwd) = &event.cwd {
+                annotations.push(format!("[CWD {}]", cwd.display()).dimmed());
+            }
+        }
+        if let Some(sha256) = &event.exe_sha256 {
+            annotations.push(format!("sha256={}", sha256).dimmed());
+        }
+        if let Some(ns_pid) = event.ns_pid {
+            annotations.push(format!("[NS PID {}]", ns_pid).cyan());
+        } else if event.containerized {
+            annotations.push("[NS]".cyan());
+        }
+        if let Some(cgroup) = &event.cgroup {
+            annotations.push(Self::cgroup_annotation(cgroup).dimmed());
+        }
+        // --show-cgroup often already names the same unit
+        if let Some(unit) = &event.unit
+            && event.cgroup.as_ref().and_then(|c| c.unit.as_ref()) != Some(unit)
+        {
+            annotations.push(format!("[unit {}]", unit).dimmed());
+        }
+        annotations
+    }
+
+    fn cgroup_annotation(cgroup: &Cgroup) -> String {
+        let mut parts = Vec::new();
+        if let Some(pod) = &cgroup.pod {
+            parts.push(format!("pod {}", pod));
+        }
+        if let Some(id) = &cgroup.container_id {
+            parts.push(format!(
+                "container {}",
+                &id[..id.len().min(CONTAINER_ID_DISPLAY_LEN)]
+            ));
+        }
+        if parts.is_empty()
+            && let Some(unit) = &cgroup.unit
+        {
+            parts.push(format!("unit {}", unit));
+        }
+        if parts.is_empty() {
+            parts.push(format!("cgroup {}", cgroup.path));
+        }
+        format!("[{}]", parts.join(" "))
+    }
+
+    pub fn exit_event(event: &ExitEvent, tags: &[ColoredString]) {
+        let cmd = format!(
+            "{} (ran for {})",
+            event.cmdline,
+            format_duration(event.lifetime)
+        );
+        Self::print_process_event(
+            event.observed_at,
+            "EXIT",
+            event.uid,
+            event.pid,
+            event.ppid,
+            &cmd,
+            tags,
+            &[],
+        );
+    }
+
+    pub fn repeat_summary(uid: Option<u32>, count: u64, cmd: &str) {
+        let message = format!("  x{:<6} UID={} | {}", count, Self::format_uid(uid), cmd);
+        Self::status(Self::colorize_by_uid(message, uid));
+    }
+
+    // info is the default and left unmarked
+    pub fn severity_tag(severity: Severity) -> Option<ColoredString> {
+        match severity {
+            Severity::Info => None,
+            Severity::Notice => Some("[NOTICE]".cyan()),
+            Severity::Warning => Some("[WARNING]".yellow().bold()),
+            Severity::Alert => Some("[ALERT]".red().bold().reversed()),
+        }
+    }
+
+    pub fn connection_event(event: &ConnectionEvent, tags: &[ColoredString]) {
+        let process = event.process.as_deref().unwrap_or(UNKNOWN_COMMAND);
+        if !interactive::allows_process(event.uid, process) {
+            return;
+        }
+
+        let message = format!(
+            "NET : UID={} PID={:<width$} | {} by {}",
+            Self::format_uid(event.uid),
+            event
+                .pid
+                .map_or(UNKNOWN_UID_DISPLAY.to_string(), |pid| pid.to_string()),
+            event.describe(),
+            process,
             width = PID_DISPLAY_WIDTH
         );
-        println!("{} {}", Self::timestamp(), Self::colorize_by_uid(message, uid));
+        print!(
+            "{} {}",
+            Self::format_time(event.observed_at),
+            Self::colorize_by_uid(message, event.uid)
+        );
+        for tag in tags {
+            print!(" {}", tag);
+        }
+        println!();
         let _ = std::io::stdout().flush();
     }
 
-    pub fn event(uid: Option<u32>, pid: u32, cmd: &str) {
-        Self::print_process_event("CMD ", uid, pid, cmd);
+    pub fn session_event(event: &SessionEvent, tags: &[ColoredString]) {
+        let message = format!(
+            "SESS: UID={} PID={:<width$} | {}",
+            Self::format_uid(event.uid),
+            event
+                .pid
+                .map_or(UNKNOWN_UID_DISPLAY.to_string(), |pid| pid.to_string()),
+            event.describe(),
+            width = PID_DISPLAY_WIDTH
+        );
+        print!(
+            "{} {}",
+            Self::format_time(event.observed_at),
+            Self::colorize_by_uid(message, event.uid)
+        );
+        for tag in tags {
+            print!(" {}", tag);
+        }
+        println!();
+        let _ = std::io::stdout().flush();
     }
 
-    pub fn fs<T: Into<String>>(message: T) {
-        println!("{} [FS] - {}", Self::timestamp(), message.into().white());
+    pub fn device_event(event: &DeviceEvent, tags: &[ColoredString]) {
+        let message = format!("DEV : {}", event.describe());
+        print!(
+            "{} {}",
+            Self::format_time(event.observed_at),
+            Self::colorize_by_uid(message, None)
+        );
+        for tag in tags {
+            print!(" {}", tag);
+        }
+        println!();
+        let _ = std::io::stdout().flush();
     }
 
-    pub fn debug<T: Into<String>>(message: T) {
-        if log::max_level() >= log::LevelFilter::Debug {
-            println!("{} [DEBUG] - {}", Self::timestamp(), message.into().cyan());
+    pub fn bus_event(event: &BusEvent, tags: &[ColoredString]) {
+        let process = event.process.as_deref().unwrap_or(UNKNOWN_COMMAND);
+        if !interactive::allows_process(event.uid, process) {
+            return;
+        }
+
+        let message = format!(
+            "BUS : UID={} PID={:<width$} | {} by {}",
+            Self::format_uid(event.uid),
+            event
+                .pid
+                .map_or(UNKNOWN_UID_DISPLAY.to_string(), |pid| pid.to_string()),
+            event.describe(),
+            process,
+            width = PID_DISPLAY_WIDTH
+        );
+        print!(
+            "{} {}",
+            Self::format_time(event.observed_at),
+            Self::colorize_by_uid(message, event.uid)
+        );
+        for tag in tags {
+            print!(" {}", tag);
+        }
+        println!();
+        let _ = std::io::stdout().flush();
+    }
+
+    pub fn authorization_event(event: &AuthorizationEvent, tags: &[ColoredString]) {
+        let subject = event.subject.as_deref().unwrap_or(UNKNOWN_COMMAND);
+        if !interactive::allows_process(event.subject_uid, subject) {
+            return;
         }
+
+        let message = format!(
+            "AUTH: UID={} PID={:<width$} | {} for {}",
+            Self::format_uid(event.subject_uid),
+            event
+                .subject_pid
+                .map_or(UNKNOWN_UID_DISPLAY.to_string(), |pid| pid.to_string()),
+            event.describe(),
+            subject,
+            width = PID_DISPLAY_WIDTH
+        );
+        let message = match event.result {
+            AuthorizationResult::Authorized => Self::colorize_by_uid(message, event.subject_uid),
+            _ => message.red(),
+        };
+        print!("{} {}", Self::format_time(event.observed_at), message);
+        for tag in tags {
+            print!(" {}", tag);
+        }
+        println!();
+        let _ = std::io::stdout().flush();
     }
 
-    pub fn dbus_event(pid: u32, cmd: &str) {
-        Self::dbus_event_with_uid(pid, cmd, None);
+    // what --correlate pieced together about one process
+    pub fn story(pid: u32, uid: Option<u32>, text: &str) {
+        if !interactive::allows_process(uid, text) {
+            return;
+        }
+
+        let message = format!(
+            "STRY: UID={} PID={:<width$} | {}",
+            Self::format_uid(uid),
+            pid,
+            text,
+            width = PID_DISPLAY_WIDTH
+        );
+        Self::status(format!(
+            "{} {}",
+            Self::timestamp(),
+            Self::colorize_by_uid(message, uid).bold()
+        ));
     }
 
-    pub fn dbus_event_with_uid(pid: u32, cmd: &str, uid: Option<u32>) {
-        Self::print_process_event("DBUS", uid, pid, cmd);
+    // the repeats of a command within one --dedup-window, the first run was printed as usual
+    pub fn repeated_command(uid: Option<u32>, count: u64, window: Duration, cmd: &str) {
+        if !interactive::allows_process(uid, cmd) {
+            return;
+        }
+
+        let message = format!(
+            "RPT : UID={} x{} within {} | {}",
+            Self::format_uid(uid),
+            count,
+            format_duration(Some(window)),
+            cmd
+        );
+        Self::status(format!(
+            "{} {}",
+            Self::timestamp(),
+            Self::colorize_by_uid(message, uid).dimmed()
+        ));
+    }
+
+    pub fn fs_event(event: &FsEvent, tags: &[ColoredString]) {
+        let mut message = match &event.name {
+            Some(name) => format!("events: {} {} in {:?}", event.events, name, event.path),
+            None => format!("events: {} on {:?}", event.events, event.path),
+        };
+        if let Some(pid) = event.pid {
+            message.push_str(&format!(
+                " by PID {} ({})",
+                pid,
+                event.process.as_deref().unwrap_or(UNKNOWN_COMMAND)
+            ));
+        }
+        if !interactive::allows_fs(&message) {
+            return;
+        }
+        if let Some(content) = &event.content {
+            print!(
+                "{} {}",
+                Self::format_time(event.observed_at),
+                format!(
+                    "[{}] - {} ({})",
+                    content.category.label(),
+                    event.full_path().display(),
+                    content.summary
+                )
+                .red()
+                .bold()
+            );
+        } else if event.file_modified {
+            print!(
+                "{} {}",
+                Self::format_time(event.observed_at),
+                format!("[FILE MODIFIED] - {}", message).red().bold()
+            );
+        } else {
+            print!(
+                "{} [FS] - {}",
+                Self::format_time(event.observed_at),
+                message.white()
+            );
+        }
+        for tag in tags {
+            print!(" {}", tag);
+        }
+        println!();
+
+        // the diff of a file rspy keeps a copy of, one line each
+        if let Some(content) = &event.content {
+            let timestamp = Self::format_time(event.observed_at);
+            for line in &content.diff {
+                let line = match line.chars().next() {
+                    Some('+') => line.green(),
+                    Some('-') => line.red(),
+                    Some('@') => line.cyan(),
+                    _ => line.dimmed(),
+                };
+                println!("{} {}", timestamp, line);
+            }
+        }
+        let _ = std::io::stdout().flush();
+    }
+
+    pub fn debug<T: Into<String>>(message: T) {
+        if log::max_level() >= log::LevelFilter::Debug {
+            Self::status(format!(
+                "{} [DEBUG] - {}",
+                Self::timestamp(),
+                message.into().cyan()
+            ));
+        }
     }
 }
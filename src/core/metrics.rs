@@ -0,0 +1,285 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{
+    constants::{METRICS_MAX_CONNECTIONS, METRICS_MAX_REQUEST_BYTES, METRICS_REQUEST_TIMEOUT_SECS},
+    error::Result,
+    logger::Logger,
+};
+
+pub struct Metrics {
+    processes_seen: AtomicU64,
+    processes_tracked: AtomicU64,
+    inotify_events: AtomicU64,
+    inotify_overflows: AtomicU64,
+    dbus_poll_failures: AtomicU64,
+    scans: AtomicU64,
+    scan_duration_micros: AtomicU64,
+    last_scan_duration_micros: AtomicU64,
+    inotify_watches: AtomicU64,
+    dropped_events: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics::new();
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            processes_seen: AtomicU64::new(0),
+            processes_tracked: AtomicU64::new(0),
+            inotify_events: AtomicU64::new(0),
+            inotify_overflows: AtomicU64::new(0),
+            dbus_poll_failures: AtomicU64::new(0),
+            scans: AtomicU64::new(0),
+            scan_duration_micros: AtomicU64::new(0),
+            last_scan_duration_micros: AtomicU64::new(0),
+            inotify_watches: AtomicU64::new(0),
+            dropped_events: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_scan(&self, duration: Duration, new_processes: usize, tracked: usize) {
+        let micros = duration.as_micros() as u64;
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        self.scan_duration_micros
+            .fetch_add(micros, Ordering::Relaxed);
+        self.last_scan_duration_micros
+            .store(micros, Ordering::Relaxed);
+        self.processes_seen
+            .fetch_add(new_processes as u64, Ordering::Relaxed);
+        self.processes_tracked
+            .store(tracked as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_inotify_events(&self, count: usize) {
+        self.inotify_events
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_inotify_overflow(&self) {
+        self.inotify_overflows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dbus_poll_failure(&self) {
+        self.dbus_poll_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_inotify_watches(&self, watches: usize) {
+        self.inotify_watches
+            .store(watches as u64, Ordering::Relaxed);
+    }
+
+    // events that reached the event loop and were thrown away, e.g. while paused
+    pub fn record_dropped_event(&self) {
+        self.dropped_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inotify_watches(&self) -> u64 {
+        self.inotify_watches.load(Ordering::Relaxed)
+    }
+
+    pub fn inotify_overflows(&self) -> u64 {
+        self.inotify_overflows.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    pub fn last_scan_duration(&self) -> Duration {
+        Duration::from_micros(self.last_scan_duration_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn scans(&self) -> u64 {
+        self.scans.load(Ordering::Relaxed)
+    }
+
+    pub fn scan_duration(&self) -> Duration {
+        Duration::from_micros(self.scan_duration_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn inotify_events(&self) -> u64 {
+        self.inotify_events.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, uptime: Duration) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let micros_to_secs = |micros: u64| micros as f64 / 1_000_000.0;
+
+        let metrics: [(&str, &str, &str, String); 11] = [
+            (
+                "rspy_uptime_seconds",
+                "gauge",
+                "seconds since rspy started",
+                format!("{:.3}", uptime.as_secs_f64()),
+            ),
+            (
+                "rspy_processes_seen_total",
+                "counter",
+                "new processes discovered by procfs scans, use rate() for processes per second",
+                load(&self.processes_seen).to_string(),
+            ),
+            (
+                "rspy_processes_tracked",
+                "gauge",
+                "live processes currently known to the procfs scanner",
+                load(&self.processes_tracked).to_string(),
+            ),
+            (
+                "rspy_inotify_events_total",
+                "counter",
+                "inotify events read, use rate() for events per second",
+                load(&self.inotify_events).to_string(),
+            ),
+            (
+                "rspy_inotify_overflows_total",
+                "counter",
+                "inotify queue overflows, each one means events were dropped",
+                load(&self.inotify_overflows).to_string(),
+            ),
+            (
+                "rspy_inotify_watches",
+                "gauge",
+                "directories and files currently watched with inotify",
+                load(&self.inotify_watches).to_string(),
+            ),
+            (
+                "rspy_dropped_events_total",
+                "counter",
+                "events thrown away by the event loop, e.g. while paused with SIGUSR1",
+                load(&self.dropped_events).to_string(),
+            ),
+            (
+                "rspy_dbus_poll_failures_total",
+                "counter",
+                "failed dbus process polls",
+                load(&self.dbus_poll_failures).to_string(),
+            ),
+            (
+                "rspy_scans_total",
+                "counter",
+                "completed procfs scans",
+                load(&self.scans).to_string(),
+            ),
+            (
+                "rspy_scan_duration_seconds_total",
+                "counter",
+                "cumulative time spent in procfs scans",
+                format!("{:.6}", micros_to_secs(load(&self.scan_duration_micros))),
+            ),
+            (
+                "rspy_last_scan_duration_seconds",
+                "gauge",
+                "duration of the most recent procfs scan",
+                format!(
+                    "{:.6}",
+                    micros_to_secs(load(&self.last_scan_duration_micros))
+                ),
+            ),
+        ];
+
+        metrics
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!(
+                    "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                    name, help, name, kind, name, value
+                )
+            })
+            .collect()
+    }
+}
+
+pub fn serve(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let started = Instant::now();
+    Logger::info(format!("serving metrics on http://{}/metrics", addr));
+
+    // each request on its own thread, a client that never finishes its request holds up
+    // no one but itself and only until the deadline
+    let connections = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    Logger::debug(format!("metrics connection failed: {}", e));
+                    continue;
+                }
+            };
+            if connections.fetch_add(1, Ordering::SeqCst) >= METRICS_MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                Logger::debug("too many metrics connections, dropping one".to_string());
+                continue;
+            }
+            let connections = Arc::clone(&connections);
+            thread::spawn(move || {
+                if let Err(e) = respond(stream, started) {
+                    Logger::debug(format!("metrics request failed: {}", e));
+                }
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+// the read timeout shrinks with every read, so a client trickling in a byte at a time
+// still runs out of time
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+fn respond(stream: TcpStream, started: Instant) -> io::Result<()> {
+    let timeout = Duration::from_secs(METRICS_REQUEST_TIMEOUT_SECS);
+    stream.set_write_timeout(Some(timeout))?;
+    let deadline = DeadlineReader {
+        stream: &stream,
+        deadline: Instant::now() + timeout,
+    };
+
+    let mut reader = BufReader::new(deadline.take(METRICS_MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // drain the headers, the body of a GET is empty
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = if path == "/metrics" || path == "/" {
+        ("200 OK", METRICS.render(started.elapsed()))
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
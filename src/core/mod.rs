@@ -1,4 +1,25 @@
+pub mod baseline;
+pub mod check;
 pub mod config;
 pub mod constants;
+pub mod control;
+pub mod correlate;
+pub mod dedup;
+pub mod diff;
 pub mod error;
+pub mod event;
+pub mod filter;
+pub mod interactive;
 pub mod logger;
+pub mod metrics;
+pub mod output;
+pub mod profiles;
+pub mod record;
+pub mod rules;
+pub mod sandbox;
+pub mod severity;
+pub mod sinks;
+pub mod snapshot;
+pub mod stats;
+pub mod tree;
+pub mod writability;
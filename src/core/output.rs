@@ -0,0 +1,346 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::event::Event;
+use super::severity::Severity;
+
+const VENDOR: &str = "rspy";
+const PRODUCT: &str = "rspy";
+// java SimpleDateFormat, how QRadar reads devTime
+const LEEF_TIME_FORMAT: &str = "yyyy-MM-dd'T'HH:mm:ss.SSSZ";
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    // colored lines for people
+    #[default]
+    Text,
+    // one object per line, the event as it is recorded plus severity and rules
+    Json,
+    // ArcSight Common Event Format
+    Cef,
+    // IBM QRadar Log Event Extended Format 2.0, tab separated
+    Leef,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    severity: &'static str,
+    rules: &'a [String],
+    event: &'a Event,
+}
+
+// None for the text format, which the logger renders itself
+pub fn format_event(
+    format: OutputFormat,
+    event: &Event,
+    severity: Severity,
+    rules: &[String],
+) -> Option<String> {
+    match format {
+        OutputFormat::Text => None,
+        OutputFormat::Json => serde_json::to_string(&JsonLine {
+            severity: severity.as_str(),
+            rules,
+            event,
+        })
+        .ok(),
+        OutputFormat::Cef => Some(cef(event, severity, rules)),
+        OutputFormat::Leef => Some(leef(event, severity, rules)),
+    }
+}
+
+// (signature id, name) of the header
+fn signature(event: &Event) -> (&'static str, &'static str) {
+    match event {
+        Event::Process(_) => ("process", "Process started"),
+        Event::Exit(_) => ("exit", "Process exited"),
+        Event::Fs(_) => ("file", "File system event"),
+        Event::Connection(_) => ("connection", "New socket"),
+        Event::Session(_) => ("session", "Login session"),
+        Event::Device(_) => ("device", "Device event"),
+        Event::Bus(_) => ("dbus", "DBus message"),
+        Event::Authorization(_) => ("polkit", "Authorization check"),
+    }
+}
+
+// 0-10 as both formats expect it
+fn numeric_severity(severity: Severity) -> u8 {
+    match severity {
+        Severity::Info => 1,
+        Severity::Notice => 3,
+        Severity::Warning => 6,
+        Severity::Alert => 9,
+    }
+}
+
+fn millis(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .to_string()
+}
+
+// the extension fields in CEF dictionary names, LEEF renames the few it has its own keys for
+fn fields(event: &Event, rules: &[String]) -> Vec<(&'static str, String)> {
+    let mut fields = Vec::new();
+    let mut push = |key: &'static str, value: Option<String>| {
+        if let Some(value) = value {
+            fields.push((key, value));
+        }
+    };
+
+    match event {
+        Event::Process(process) => {
+            push("rt", Some(millis(process.observed_at)));
+            push("spid", Some(process.pid.to_string()));
+            push("suid", process.uid.map(|uid| uid.to_string()));
+            push("cn1Label", process.ppid.map(|_| "ppid".to_string()));
+            push("cn1", process.ppid.map(|ppid| ppid.to_string()));
+            push(
+                "cs1Label",
+                process.unit.as_ref().map(|_| "unit".to_string()),
+            );
+            push("cs1", process.unit.clone());
+            push(
+                "sproc",
+                process.exe.as_ref().map(|exe| exe.display().to_string()),
+            );
+            push("msg", Some(process.cmdline.clone()));
+        }
+        Event::Exit(exit) => {
+            push("rt", Some(millis(exit.observed_at)));
+            push("spid", Some(exit.pid.to_string()));
+            push("suid", exit.uid.map(|uid| uid.to_string()));
+            push("msg", Some(exit.cmdline.clone()));
+        }
+        Event::Fs(fs_event) => {
+            push("rt", Some(millis(fs_event.observed_at)));
+            push("act", Some(fs_event.events.clone()));
+            push("filePath", Some(fs_event.full_path().display().to_string()));
+            push("spid", fs_event.pid.map(|pid| pid.to_string()));
+            push("sproc", fs_event.process.clone());
+        }
+        Event::Connection(connection) => {
+            push("rt", Some(millis(connection.observed_at)));
+            push("proto", Some(connection.protocol.clone()));
+            match connection.local.parse::<SocketAddr>() {
+                Ok(local) => {
+                    push("src", Some(local.ip().to_string()));
+                    push("spt", Some(local.port().to_string()));
+                }
+                // unix sockets have a path
+                Err(_) => push("filePath", Some(connection.local.clone())),
+            }
+            if let Some(Ok(remote)) = connection.remote.as_ref().map(|r| r.parse::<SocketAddr>()) {
+                push("dst", Some(remote.ip().to_string()));
+                push("dpt", Some(remote.port().to_string()));
+            }
+            push("spid", connection.pid.map(|pid| pid.to_string()));
+            push("suid", connection.uid.map(|uid| uid.to_string()));
+            push("sproc", connection.process.clone());
+        }
+        Event::Session(session) => {
+            push("rt", Some(millis(session.observed_at)));
+            push("act", Some(session.kind.as_str().to_string()));
+            push("suser", Some(session.user.clone()));
+            push("suid", session.uid.map(|uid| uid.to_string()));
+            push("shost", session.host.clone());
+            push("cs1Label", Some("tty".to_string()));
+            push("cs1", Some(session.tty.clone()));
+            push("spid", session.pid.map(|pid| pid.to_string()));
+        }
+        Event::Device(device) => {
+            push("rt", Some(millis(device.observed_at)));
+            push("act", Some(device.action.clone()));
+            push("msg", Some(device.describe()));
+        }
+        Event::Bus(bus) => {
+            push("rt", Some(millis(bus.observed_at)));
+            push("act", bus.member.clone());
+            push("spid", bus.pid.map(|pid| pid.to_string()));
+            push("suid", bus.uid.map(|uid| uid.to_string()));
+            push("sproc", bus.process.clone());
+            push("dproc", bus.destination.clone());
+            push("msg", Some(bus.describe()));
+        }
+        Event::Authorization(auth) => {
+            push("rt", Some(millis(auth.observed_at)));
+            push("act", Some(auth.action.clone()));
+            push("outcome", Some(auth.result.as_str().to_string()));
+            push("spid", auth.subject_pid.map(|pid| pid.to_string()));
+            push("suid", auth.subject_uid.map(|uid| uid.to_string()));
+            push("sproc", auth.subject.clone());
+            push("msg", Some(auth.describe()));
+        }
+    }
+
+    if !rules.is_empty() {
+        push("cs2Label", Some("rules".to_string()));
+        push("cs2", Some(rules.join(",")));
+    }
+    fields
+}
+
+fn cef(event: &Event, severity: Severity, rules: &[String]) -> String {
+    let (id, name) = signature(event);
+    let extension = fields(event, rules)
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, cef_escape_value(&value)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        id,
+        name,
+        numeric_severity(severity),
+        extension
+    )
+}
+
+fn cef_escape_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn leef(event: &Event, severity: Severity, rules: &[String]) -> String {
+    let (id, _) = signature(event);
+    let mut attributes = vec![
+        format!("devTime={}", leef_time(event.observed_at())),
+        format!("devTimeFormat={}", LEEF_TIME_FORMAT),
+        format!("sev={}", numeric_severity(severity)),
+    ];
+    for (key, value) in fields(event, rules) {
+        let key = match key {
+            "rt" => continue,
+            "suser" => "usrName",
+            "spt" => "srcPort",
+            "dpt" => "dstPort",
+            key => key,
+        };
+        // tabs separate the attributes, values can not contain them
+        attributes.push(format!("{}={}", key, value.replace(['\t', '\n'], " ")));
+    }
+    // the field after the event id names the delimiter, x09 is a tab
+    format!(
+        "LEEF:2.0|{}|{}|{}|{}|x09|{}",
+        VENDOR,
+        PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        id,
+        attributes.join("\t")
+    )
+}
+
+// LEEF_TIME_FORMAT in UTC
+fn leef_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as libc::time_t;
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::gmtime_r(&secs, &mut tm);
+        tm
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}+0000",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::{ProcessEvent, ProcessSource};
+    use std::time::Duration;
+
+    fn process(cmdline: &str) -> Event {
+        let mut process = ProcessEvent::new(ProcessSource::Procfs, 42, cmdline.to_string());
+        process.uid = Some(0);
+        process.observed_at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        Event::Process(Box::new(process))
+    }
+
+    #[test]
+    fn cef_escaping() {
+        assert_eq!(cef_escape_value("a=b"), "a\\=b");
+        assert_eq!(cef_escape_value("C:\\tmp\\x"), "C:\\\\tmp\\\\x");
+        assert_eq!(cef_escape_value("\\="), "\\\\\\=");
+        assert_eq!(cef_escape_value("one\r\ntwo"), "one\\r\\ntwo");
+        // only the header needs its pipes escaped
+        assert_eq!(cef_escape_value("ps | grep x"), "ps | grep x");
+    }
+
+    #[test]
+    fn cef_line() {
+        let line = cef(
+            &process("sh -c a=1|b\\c"),
+            Severity::Alert,
+            &["tmp".to_string()],
+        );
+        let header: Vec<&str> = line.splitn(8, '|').collect();
+        assert_eq!(
+            header[..7],
+            [
+                "CEF:0",
+                VENDOR,
+                PRODUCT,
+                env!("CARGO_PKG_VERSION"),
+                "process",
+                "Process started",
+                "9"
+            ]
+        );
+        assert_eq!(
+            header[7],
+            "rt=1700000000123 spid=42 suid=0 msg=sh -c a\\=1|b\\\\c cs2Label=rules cs2=tmp"
+        );
+    }
+
+    #[test]
+    fn leef_line() {
+        let line = leef(&process("printf a\tb=c|d\n"), Severity::Info, &[]);
+        let (header, attributes) = line.rsplit_once("|x09|").unwrap();
+        assert_eq!(
+            header,
+            format!(
+                "LEEF:2.0|{}|{}|{}|process",
+                VENDOR,
+                PRODUCT,
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert_eq!(
+            attributes.split('\t').collect::<Vec<_>>(),
+            [
+                "devTime=2023-11-14T22:13:20.123+0000",
+                "devTimeFormat=yyyy-MM-dd'T'HH:mm:ss.SSSZ",
+                "sev=1",
+                "spid=42",
+                "suid=0",
+                "msg=printf a b=c|d ",
+            ]
+        );
+    }
+
+    #[test]
+    fn leef_devtime() {
+        assert_eq!(leef_time(UNIX_EPOCH), "1970-01-01T00:00:00.000+0000");
+        assert_eq!(
+            leef_time(UNIX_EPOCH + Duration::from_millis(951_782_400_999)),
+            "2000-02-29T00:00:00.999+0000"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use clap::ValueEnum;
+
+// --profile, a set of flags for a common scenario. flags given on the command line or in
+// the environment win over the ones of the profile
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    // as little cpu, io and inotify watches as possible
+    Stealth,
+    // everything that can be learned about each process
+    Forensics,
+    // short lived jobs started from cron, at and systemd timers
+    CronHunt,
+    // who runs in which container, with which capabilities and sockets
+    ContainerHost,
+    // every monitor and enrichment that needs no extra feature or privilege
+    Verbose,
+}
+
+impl Profile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profile::Stealth => "stealth",
+            Profile::Forensics => "forensics",
+            Profile::CronHunt => "cron-hunt",
+            Profile::ContainerHost => "container-host",
+            Profile::Verbose => "verbose",
+        }
+    }
+
+    // written as on the command line, values always with an `=`
+    pub fn flags(self) -> &'static [&'static str] {
+        match self {
+            Profile::Stealth => &[
+                "--stealth",
+                "--low-resource",
+                "--plain",
+                "--nice=19",
+                "--cpu-limit=2",
+            ],
+            Profile::Forensics => &[
+                "--print-filesystem-events",
+                "--scan-interval=50ms",
+                "--tree",
+                "--show-exits",
+                "--show-fds",
+                "--show-cwd",
+                "--show-caps",
+                "--hash-exe",
+                "--track-cmdline",
+                "--scan-threads",
+                "--timestamp-format=ms",
+            ],
+            Profile::CronHunt => &[
+                "--schedules",
+                "--tree",
+                "--lifetimes=1s",
+                "--scan-interval=20ms",
+                "--recursive-watch=/etc",
+                "--recursive-watch=/var/spool",
+                "--recursive-watch=/tmp",
+                "--timestamp-format=ms",
+            ],
+            Profile::ContainerHost => &[
+                "--show-cgroup",
+                "--show-caps",
+                "--show-cwd",
+                "--network",
+                "--resolve-users",
+            ],
+            Profile::Verbose => &[
+                "--print-filesystem-events",
+                "--tree",
+                "--show-exits",
+                "--show-fds",
+                "--show-cwd",
+                "--show-caps",
+                "--show-cgroup",
+                "--track-cmdline",
+                "--scan-threads",
+                "--network",
+                "--unix-sockets",
+                "--sessions",
+                "--resolve-users",
+            ],
+        }
+    }
+}
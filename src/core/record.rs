@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::error::Result;
+use super::event::Event;
+use super::sinks::Sink;
+
+// one JSON object per line, `offset_us` is monotonic time since the recording started
+#[derive(Deserialize)]
+struct RecordedEvent {
+    offset_us: u64,
+    event: Event,
+}
+
+#[derive(Serialize)]
+struct RecordedEventRef<'a> {
+    offset_us: u64,
+    event: &'a Event,
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            started: Instant::now(),
+        })
+    }
+}
+
+impl Sink for Recorder {
+    fn name(&self) -> &'static str {
+        "record"
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        let line = serde_json::to_string(&RecordedEventRef {
+            offset_us: self.started.elapsed().as_micros() as u64,
+            event,
+        })
+        .map_err(|e| format!("failed to serialize event: {}", e))?;
+
+        writeln!(self.writer, "{}", line)?;
+        // flushed per event so a recording survives rspy being killed
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// plays a recording back, sleeping between events; a speed of 0 replays without delays
+pub fn replay(path: &Path, speed: f64, mut handle: impl FnMut(Event) -> bool) -> Result<usize> {
+    let reader = BufReader::new(File::open(path)?);
+    let started = Instant::now();
+    let mut replayed = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let recorded: RecordedEvent = serde_json::from_str(&line).map_err(|e| {
+            format!(
+                "{}:{}: invalid recorded event: {}",
+                path.display(),
+                index + 1,
+                e
+            )
+        })?;
+
+        if speed > 0.0 {
+            let due = Duration::from_micros(recorded.offset_us).div_f64(speed);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+
+        if !handle(recorded.event) {
+            break;
+        }
+        replayed += 1;
+    }
+
+    Ok(replayed)
+}
@@ -0,0 +1,361 @@
+use colored::{Color, ColoredString, Colorize};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+use super::error::Result;
+use super::event::Event;
+use super::severity::Severity;
+use super::sinks::exec::spawn_for_event;
+#[cfg(feature = "webhook")]
+use super::{
+    logger::Logger,
+    sinks::{Sink, webhook::WebhookSink},
+};
+use crate::utils::{caps, process::process_name};
+
+// a rules file is a list of rules, every condition given must match and every action given runs:
+//
+//   [[rule]]
+//   name = "shell spawned by the web server"
+//   cmdline = '^(/usr)?/bin/(ba|da)?sh'
+//   uid = 33
+//   parent = "nginx|apache2|php-fpm"
+//   capabilities = ["CAP_SYS_ADMIN", "CAP_SETUID"]
+//   highlight = "red"
+//   severity = "alert"
+//   run = "logger -t rspy \"$RSPY_RULE: $RSPY_CMD\""
+//
+// files ending in .yaml or .yml are read as YAML with the same fields
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule", alias = "rules")]
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    cmdline: Option<String>,
+    uid: Option<OneOrMany<u32>>,
+    parent: Option<String>,
+    path: Option<OneOrMany<String>>,
+    capabilities: Option<OneOrMany<String>>,
+    highlight: Option<String>,
+    severity: Option<Severity>,
+    webhook: Option<String>,
+    run: Option<String>,
+    #[serde(default)]
+    suppress: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+struct Rule {
+    name: String,
+    cmdline: Option<Regex>,
+    uids: Vec<u32>,
+    parent: Option<Regex>,
+    // matched against the exe and cwd of processes and the path of filesystem events
+    paths: Option<GlobSet>,
+    // any of these held, only known with --show-caps
+    capabilities: u64,
+    highlight: Option<Color>,
+    severity: Option<Severity>,
+    webhook: Option<String>,
+    run: Option<String>,
+    suppress: bool,
+}
+
+#[derive(Default)]
+pub struct RuleOutcome {
+    pub suppressed: bool,
+    pub tags: Vec<ColoredString>,
+    // the highest one set by a matching rule, it replaces the event's own
+    pub severity: Option<Severity>,
+    // names of the matching rules
+    pub rules: Vec<String>,
+}
+
+pub struct Rules {
+    rules: Vec<Rule>,
+    #[cfg(feature = "webhook")]
+    webhooks: Vec<(String, WebhookSink)>,
+}
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read rules file {}: {}", path.display(), e))?;
+
+        let is_yaml = path
+            .extension()
+            .is_some_and(|extension| extension == "yaml" || extension == "yml");
+        let file: RulesFile = if is_yaml {
+            serde_yaml::from_str(&content).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&content).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("invalid rules file {}: {}", path.display(), e))?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .map(Rule::compile)
+            .collect::<Result<Vec<_>>>()?;
+
+        #[cfg(feature = "webhook")]
+        let webhooks = {
+            let mut webhooks: Vec<(String, WebhookSink)> = Vec::new();
+            for url in rules.iter().filter_map(|rule| rule.webhook.as_ref()) {
+                if !webhooks.iter().any(|(existing, _)| existing == url) {
+                    webhooks.push((url.clone(), WebhookSink::new(url.clone())));
+                }
+            }
+            webhooks
+        };
+
+        Ok(Self {
+            rules,
+            #[cfg(feature = "webhook")]
+            webhooks,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    // --sandbox forbids execve, the names of the rules that lost their run action
+    pub fn drop_run_actions(&mut self) -> Vec<String> {
+        self.rules
+            .iter_mut()
+            .filter_map(|rule| rule.run.take().map(|_| rule.name.clone()))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn apply(&mut self, event: &Event) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+        let mut webhooks: Vec<&str> = Vec::new();
+
+        for rule in self.rules.iter().filter(|rule| rule.matches(event)) {
+            outcome.rules.push(rule.name.clone());
+            outcome.suppressed |= rule.suppress;
+            if rule.severity.is_some() {
+                outcome.severity = outcome.severity.max(rule.severity);
+            }
+            if let Some(color) = rule.highlight {
+                outcome
+                    .tags
+                    .push(format!("[{}]", rule.name).color(color).bold());
+            }
+            if let Some(url) = &rule.webhook
+                && !webhooks.contains(&url.as_str())
+            {
+                webhooks.push(url);
+            }
+            if let Some(command) = &rule.run {
+                spawn_for_event(command, event, Some(&rule.name));
+            }
+        }
+
+        #[cfg(feature = "webhook")]
+        for (url, sink) in &mut self.webhooks {
+            if webhooks.contains(&url.as_str())
+                && let Err(e) = sink.write(event)
+            {
+                Logger::error(format!("rule webhook {} failed: {}", url, e));
+            }
+        }
+
+        outcome
+    }
+}
+
+impl Rule {
+    fn compile(spec: RuleSpec) -> Result<Self> {
+        let name = spec.name;
+        let invalid = |what: &str, e: &dyn std::fmt::Display| -> String {
+            format!("rule {:?}: invalid {}: {}", name, what, e)
+        };
+
+        let cmdline = spec
+            .cmdline
+            .map(|pattern| Regex::new(&pattern))
+            .transpose()
+            .map_err(|e| invalid("cmdline regex", &e))?;
+        let parent = spec
+            .parent
+            .map(|pattern| Regex::new(&pattern))
+            .transpose()
+            .map_err(|e| invalid("parent regex", &e))?;
+
+        let paths = match spec.path {
+            Some(patterns) => {
+                let mut builder = GlobSetBuilder::new();
+                for pattern in patterns.into_vec() {
+                    let glob = GlobBuilder::new(&pattern)
+                        .literal_separator(true)
+                        .build()
+                        .map_err(|e| invalid("path pattern", &e))?;
+                    builder.add(glob);
+                }
+                Some(builder.build().map_err(|e| invalid("path pattern", &e))?)
+            }
+            None => None,
+        };
+
+        let highlight = spec
+            .highlight
+            .map(|color| color.parse::<Color>())
+            .transpose()
+            .map_err(|_| invalid("highlight color", &"unknown color"))?;
+
+        if spec.webhook.is_some() && !cfg!(feature = "webhook") {
+            return Err(invalid(
+                "action",
+                &"rspy was built without webhook support, rebuild with `--features webhook`",
+            )
+            .into());
+        }
+
+        let mut capabilities = 0;
+        for capability in spec
+            .capabilities
+            .map(OneOrMany::into_vec)
+            .unwrap_or_default()
+        {
+            capabilities |=
+                caps::parse(&capability).ok_or_else(|| invalid("capability", &capability))?;
+        }
+
+        let uids = spec.uid.map(OneOrMany::into_vec).unwrap_or_default();
+        if cmdline.is_none()
+            && parent.is_none()
+            && paths.is_none()
+            && uids.is_empty()
+            && capabilities == 0
+        {
+            return Err(invalid(
+                "conditions",
+                &"at least one of cmdline, uid, parent, path or capabilities is needed",
+            )
+            .into());
+        }
+        if highlight.is_none()
+            && spec.severity.is_none()
+            && spec.webhook.is_none()
+            && spec.run.is_none()
+            && !spec.suppress
+        {
+            return Err(invalid(
+                "actions",
+                &"at least one of highlight, severity, webhook, run or suppress is needed",
+            )
+            .into());
+        }
+
+        Ok(Self {
+            name,
+            cmdline,
+            uids,
+            parent,
+            paths,
+            capabilities,
+            highlight,
+            severity: spec.severity,
+            webhook: spec.webhook,
+            run: spec.run,
+            suppress: spec.suppress,
+        })
+    }
+
+    // a condition that can not be checked for an event, e.g. the uid of a filesystem
+    // event, counts as not matching
+    fn matches(&self, event: &Event) -> bool {
+        let (cmdline, uid, ppid) = match event {
+            Event::Process(process) => (Some(process.cmdline.as_str()), process.uid, process.ppid),
+            Event::Exit(exit) => (Some(exit.cmdline.as_str()), exit.uid, exit.ppid),
+            Event::Fs(fs_event) => (fs_event.process.as_deref(), None, None),
+            Event::Connection(connection) => (connection.process.as_deref(), connection.uid, None),
+            Event::Session(session) => (None, session.uid, None),
+            Event::Device(_) => (None, None, None),
+            Event::Bus(bus) => (bus.process.as_deref(), bus.uid, None),
+            Event::Authorization(auth) => (auth.subject.as_deref(), auth.subject_uid, None),
+        };
+
+        if let Some(pattern) = &self.cmdline
+            && !cmdline.is_some_and(|cmdline| pattern.is_match(cmdline))
+        {
+            return false;
+        }
+
+        if !self.uids.is_empty() && !uid.is_some_and(|uid| self.uids.contains(&uid)) {
+            return false;
+        }
+
+        if let Some(paths) = &self.paths {
+            let matched = match event {
+                Event::Process(process) => [&process.exe, &process.cwd]
+                    .into_iter()
+                    .flatten()
+                    .any(|path| paths.is_match(path)),
+                Event::Exit(_)
+                | Event::Connection(_)
+                | Event::Session(_)
+                | Event::Device(_)
+                | Event::Bus(_)
+                | Event::Authorization(_) => false,
+                Event::Fs(fs_event) => paths.is_match(fs_event.full_path()),
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        if self.capabilities != 0 {
+            let held = match event {
+                Event::Process(process) => process.capabilities.map(|caps| caps.held()),
+                _ => None,
+            };
+            if held.is_none_or(|held| held & self.capabilities == 0) {
+                return false;
+            }
+        }
+
+        // checked last, it may need to read the parent's command line from procfs
+        if let Some(pattern) = &self.parent {
+            let ancestors = match event {
+                Event::Process(process) => process.ancestors.first().map(|(_, cmd)| cmd.clone()),
+                _ => None,
+            };
+            let parent = ancestors.or_else(|| ppid.map(|ppid| process_name(ppid as i32)));
+            if !parent.is_some_and(|parent| pattern.is_match(&parent)) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,274 @@
+use std::io;
+
+use super::error::Result;
+
+// what rspy still needs once every monitor is running: reading /proc, its sockets,
+// sinks and sleeping. the point is what is missing, above all execve, ptrace, mount,
+// module loading and changes of privileges or namespaces. clone, kill and tgkill are
+// allowed further down, but only with harmless arguments
+const ALLOWED: &[libc::c_long] = &[
+    // files and /proc
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_lseek,
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_readlinkat,
+    libc::SYS_getdents64,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_getcwd,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat,
+    libc::SYS_ftruncate,
+    libc::SYS_fallocate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_flock,
+    libc::SYS_copy_file_range,
+    libc::SYS_sendfile,
+    libc::SYS_fchmod,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_rm_watch,
+    libc::SYS_fanotify_mark,
+    // memory
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    // threads, signals and time
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_set_tid_address,
+    libc::SYS_prctl,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_restart_syscall,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_gettimeofday,
+    libc::SYS_getrandom,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_wait4,
+    libc::SYS_waitid,
+    // ids and limits
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_getppid,
+    libc::SYS_getpgid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_uname,
+    libc::SYS_sysinfo,
+    libc::SYS_getrusage,
+    libc::SYS_prlimit64,
+    libc::SYS_getpriority,
+    // waiting for events
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    // netlink, dbus, the control socket and network sinks, which reconnect
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept4,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    // glibc's resolver sends the A and AAAA queries together
+    libc::SYS_sendmmsg,
+    libc::SYS_recvmmsg,
+    libc::SYS_shutdown,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+];
+
+// the older calls that aarch64 never had
+#[cfg(target_arch = "x86_64")]
+const ALLOWED_LEGACY: &[libc::c_long] = &[
+    libc::SYS_open,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_access,
+    libc::SYS_readlink,
+    libc::SYS_unlink,
+    libc::SYS_rename,
+    libc::SYS_mkdir,
+    libc::SYS_getdents,
+    libc::SYS_pipe,
+    libc::SYS_dup2,
+    libc::SYS_poll,
+    libc::SYS_select,
+    libc::SYS_epoll_wait,
+    libc::SYS_arch_prctl,
+    libc::SYS_time,
+    libc::SYS_accept,
+];
+#[cfg(not(target_arch = "x86_64"))]
+const ALLOWED_LEGACY: &[libc::c_long] = &[];
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: u32 = 0;
+
+// offsets into struct seccomp_data, of the arguments only the low half is looked at
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+const SECCOMP_DATA_ARG0: u32 = 16;
+const SECCOMP_DATA_ARG1: u32 = 24;
+
+const BPF_LD_W_ABS: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+const BPF_JEQ_K: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+const BPF_JSET_K: u16 = (libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K) as u16;
+const BPF_RET_K: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+
+// clone may start threads, never new namespaces
+const CLONE_NAMESPACES: u32 = (libc::CLONE_NEWNS
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWCGROUP) as u32;
+
+// every match of the allowlist jumps over the rest of it
+const _: () = assert!(ALLOWED.len() + ALLOWED_LEGACY.len() <= u8::MAX as usize);
+
+// installs the allowlist on every thread of rspy. anything else fails with EPERM instead of
+// killing rspy, so a forgotten call shows up as an error message. children inherit it
+pub fn install() -> Result<usize> {
+    if !cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+        return Err("--sandbox is only supported on x86_64 and aarch64".into());
+    }
+
+    let syscalls: Vec<u32> = ALLOWED
+        .iter()
+        .chain(ALLOWED_LEGACY)
+        .map(|&nr| nr as u32)
+        .collect();
+    let deny = errno(libc::EPERM);
+    let allow = statement(BPF_RET_K, libc::SECCOMP_RET_ALLOW);
+    let mut program = vec![
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        jump(BPF_JEQ_K, AUDIT_ARCH, 1, 0),
+        statement(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+        // its flags sit behind a pointer the filter cannot follow, ENOSYS makes glibc
+        // fall back to clone
+        jump(BPF_JEQ_K, libc::SYS_clone3 as u32, 0, 1),
+        errno(libc::ENOSYS),
+        jump(BPF_JEQ_K, libc::SYS_clone as u32, 0, 4),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARG0),
+        jump(BPF_JSET_K, CLONE_NAMESPACES, 0, 1),
+        deny,
+        allow,
+        // kill(pid, 0) tells whether a pid exists and sends nothing
+        jump(BPF_JEQ_K, libc::SYS_kill as u32, 0, 4),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARG1),
+        jump(BPF_JEQ_K, 0, 0, 1),
+        allow,
+        deny,
+        // tgkill is how a thread of rspy panics, for no other process
+        jump(BPF_JEQ_K, libc::SYS_tgkill as u32, 0, 4),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARG0),
+        jump(BPF_JEQ_K, std::process::id(), 0, 1),
+        allow,
+        deny,
+    ];
+    for (index, &nr) in syscalls.iter().enumerate() {
+        // on a match jump to the ALLOW at the very end
+        let remaining = (syscalls.len() - index) as u8;
+        program.push(jump(BPF_JEQ_K, nr, remaining, 0));
+    }
+    program.push(deny);
+    program.push(allow);
+
+    let fprog = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_mut_ptr(),
+    };
+    // required to install a filter without CAP_SYS_ADMIN, and it keeps setuid binaries
+    // from gaining privileges in any case
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(format!("failed to set no_new_privs: {}", io::Error::last_os_error()).into());
+    }
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &fprog as *const libc::sock_fprog,
+        )
+    };
+    match result {
+        // clone, kill and tgkill
+        0 => Ok(syscalls.len() + 3),
+        // with TSYNC a positive result is the id of a thread that could not be synchronized
+        tid if tid > 0 => Err(format!("thread {} could not be sandboxed", tid).into()),
+        _ => Err(format!(
+            "failed to install the seccomp filter: {}",
+            io::Error::last_os_error()
+        )
+        .into()),
+    }
+}
+
+fn statement(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn errno(errno: i32) -> libc::sock_filter {
+    statement(
+        BPF_RET_K,
+        libc::SECCOMP_RET_ERRNO | (errno as u32 & libc::SECCOMP_RET_DATA),
+    )
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
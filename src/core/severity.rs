@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use super::event::{AuthorizationResult, Event, FileCategory, ProcessEvent};
+use crate::utils::caps;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Notice,
+    Warning,
+    Alert,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Notice => "notice",
+            Severity::Warning => "warning",
+            Severity::Alert => "alert",
+        }
+    }
+
+    // what an event is worth on its own, rules can raise or lower it
+    pub fn of(event: &Event) -> Self {
+        match event {
+            Event::Process(process) => Self::of_process(process),
+            Event::Exit(_) => Severity::Info,
+            Event::Fs(fs_event)
+                if fs_event
+                    .content
+                    .as_ref()
+                    .is_some_and(|content| content.category == FileCategory::Loader) =>
+            {
+                Severity::Alert
+            }
+            Event::Fs(fs_event) if fs_event.content.is_some() => Severity::Warning,
+            Event::Fs(fs_event) if fs_event.file_modified => Severity::Notice,
+            Event::Fs(_) => Severity::Info,
+            Event::Connection(connection) if connection.remote.is_none() => Severity::Warning,
+            Event::Connection(_) | Event::Session(_) | Event::Device(_) => Severity::Notice,
+            Event::Bus(_) => Severity::Info,
+            Event::Authorization(auth) if auth.result == AuthorizationResult::Authorized => {
+                Severity::Notice
+            }
+            Event::Authorization(_) => Severity::Warning,
+        }
+    }
+
+    fn of_process(process: &ProcessEvent) -> Self {
+        let chrooted = process
+            .root
+            .as_ref()
+            .is_some_and(|root| root.as_os_str() != "/");
+        let capabilities = process.non_root_capabilities();
+        if process.exe_deleted
+            || caps::dangerous(capabilities) != 0
+            || process.writable.is_some()
+            || !process.unexpected_libraries.is_empty()
+        {
+            Severity::Alert
+        } else if process.exe_mismatch
+            || process.exe_setuid
+            || process.exe_setgid
+            || process.exe_in_writable_dir
+            || process.preloads()
+            || process
+                .script
+                .as_ref()
+                .is_some_and(|script| script.writable_by_others(process.uid))
+            || process.changed_from.is_some()
+            || chrooted
+        {
+            Severity::Warning
+        } else if process.thread.is_some() || capabilities != 0 || !process.loader_env.is_empty() {
+            Severity::Notice
+        } else {
+            Severity::Info
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use rustc_hash::FxHashSet;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use super::Sink;
+use crate::core::{
+    constants::EXEC_MAX_RUNNING, error::Result, event::Event, logger::Logger, metrics::METRICS,
+};
+use crate::utils::users;
+
+// process groups of commands we spawned, their descendants match filters and rules like
+// anything else and would otherwise trigger new commands forever
+static SPAWNED_GROUPS: Mutex<Option<FxHashSet<u32>>> = Mutex::new(None);
+// only the first event dropped at EXEC_MAX_RUNNING is reported
+static AT_CAPACITY: AtomicBool = AtomicBool::new(false);
+
+// runs a shell command for every event that passes the filters
+pub struct ExecSink {
+    command: String,
+}
+
+impl ExecSink {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+}
+
+impl Sink for ExecSink {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        spawn_for_event(&self.command, event, None);
+        Ok(())
+    }
+}
+
+pub fn is_own_command(pid: Option<u32>, ppid: Option<u32>) -> bool {
+    if ppid == Some(std::process::id()) {
+        return true;
+    }
+    let Ok(groups) = SPAWNED_GROUPS.lock() else {
+        return false;
+    };
+    let Some(groups) = groups.as_ref().filter(|groups| !groups.is_empty()) else {
+        return false;
+    };
+
+    // short-lived descendants are often gone before their event is handled, their
+    // parent is then the best hint left
+    if ppid.is_some_and(|ppid| groups.contains(&ppid)) {
+        return true;
+    }
+    pid.is_some_and(|pid| {
+        let pgid = unsafe { libc::getpgid(pid as libc::pid_t) };
+        pgid > 0 && groups.contains(&(pgid as u32))
+    })
+}
+
+// the event as RSPY_* variables, fields the event does not have are empty
+pub fn variables(event: &Event) -> Vec<(&'static str, String)> {
+    let mut env: Vec<(&'static str, String)> = Vec::new();
+    let mut set = |key, value: Option<String>| env.push((key, value.unwrap_or_default()));
+    let number = |value: Option<u32>| value.map(|v| v.to_string());
+
+    let (pid, observed_at) = match event {
+        Event::Process(process) => {
+            set("RSPY_EVENT", Some("process".to_string()));
+            set(
+                "RSPY_SOURCE",
+                Some(format!("{:?}", process.source).to_lowercase()),
+            );
+            set("RSPY_PPID", number(process.ppid));
+            set("RSPY_UID", number(process.uid));
+            set("RSPY_USER", process.uid.and_then(users::user_name));
+            set("RSPY_GID", number(process.gid));
+            set("RSPY_GROUP", process.gid.and_then(users::group_name));
+            set("RSPY_CMD", Some(process.cmdline.clone()));
+            set("RSPY_NS_PID", number(process.ns_pid));
+            set(
+                "RSPY_TID",
+                number(process.thread.as_ref().map(|thread| thread.tid)),
+            );
+            set(
+                "RSPY_PREVIOUS_CMD",
+                process
+                    .changed_from
+                    .as_ref()
+                    .map(|previous| previous.cmdline.clone()),
+            );
+            set(
+                "RSPY_THREAD",
+                process.thread.as_ref().map(|thread| thread.comm.clone()),
+            );
+            set(
+                "RSPY_EXE",
+                process.exe.as_ref().map(|p| p.display().to_string()),
+            );
+            set(
+                "RSPY_CWD",
+                process.cwd.as_ref().map(|p| p.display().to_string()),
+            );
+            set(
+                "RSPY_ROOT",
+                process.root.as_ref().map(|p| p.display().to_string()),
+            );
+            set("RSPY_UNIT", process.unit.clone());
+            (Some(process.pid), process.observed_at)
+        }
+        Event::Exit(exit) => {
+            set("RSPY_EVENT", Some("exit".to_string()));
+            set("RSPY_PPID", number(exit.ppid));
+            set("RSPY_UID", number(exit.uid));
+            set("RSPY_USER", exit.uid.and_then(users::user_name));
+            set("RSPY_CMD", Some(exit.cmdline.clone()));
+            (Some(exit.pid), exit.observed_at)
+        }
+        Event::Fs(fs_event) => {
+            set("RSPY_EVENT", Some("fs".to_string()));
+            set("RSPY_FS_EVENTS", Some(fs_event.events.clone()));
+            set(
+                "RSPY_PATH",
+                Some(fs_event.full_path().display().to_string()),
+            );
+            set("RSPY_NAME", fs_event.name.clone());
+            set("RSPY_CMD", fs_event.process.clone());
+            (fs_event.pid, fs_event.observed_at)
+        }
+        Event::Connection(connection) => {
+            set("RSPY_EVENT", Some("connection".to_string()));
+            set("RSPY_UID", number(connection.uid));
+            set("RSPY_USER", connection.uid.and_then(users::user_name));
+            set("RSPY_PROTOCOL", Some(connection.protocol.clone()));
+            set("RSPY_LOCAL", Some(connection.local.clone()));
+            set("RSPY_REMOTE", connection.remote.clone());
+            set("RSPY_CMD", connection.process.clone());
+            (connection.pid, connection.observed_at)
+        }
+        Event::Session(session) => {
+            set("RSPY_EVENT", Some(session.kind.as_str().to_string()));
+            set("RSPY_UID", number(session.uid));
+            set("RSPY_USER", Some(session.user.clone()));
+            set("RSPY_TTY", Some(session.tty.clone()));
+            set("RSPY_HOST", session.host.clone());
+            (session.pid, session.observed_at)
+        }
+        Event::Device(device) => {
+            set("RSPY_EVENT", Some("device".to_string()));
+            set("RSPY_ACTION", Some(device.action.clone()));
+            set("RSPY_SUBSYSTEM", device.subsystem.clone());
+            set("RSPY_DEVPATH", Some(device.devpath.clone()));
+            set("RSPY_DEVNAME", device.devname.clone());
+            (None, device.observed_at)
+        }
+        Event::Bus(bus) => {
+            set("RSPY_EVENT", Some(bus.kind.clone()));
+            set("RSPY_UID", number(bus.uid));
+            set("RSPY_CMD", bus.process.clone());
+            set("RSPY_SENDER", bus.sender.clone());
+            set("RSPY_DESTINATION", bus.destination.clone());
+            set("RSPY_PATH", bus.path.clone());
+            set("RSPY_INTERFACE", bus.interface.clone());
+            set("RSPY_MEMBER", bus.member.clone());
+            (bus.pid, bus.observed_at)
+        }
+        Event::Authorization(auth) => {
+            set("RSPY_EVENT", Some("polkit".to_string()));
+            set("RSPY_UID", number(auth.subject_uid));
+            set("RSPY_CMD", auth.subject.clone());
+            set("RSPY_ACTION", Some(auth.action.clone()));
+            set("RSPY_RESULT", Some(auth.result.as_str().to_string()));
+            set("RSPY_CALLER", auth.caller.clone());
+            set("RSPY_CALLER_PID", number(auth.caller_pid));
+            set("RSPY_COMMAND", auth.command.clone());
+            (auth.subject_pid, auth.observed_at)
+        }
+    };
+    set("RSPY_PID", number(pid));
+    set(
+        "RSPY_TIME",
+        observed_at
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs().to_string()),
+    );
+    env
+}
+
+// the command runs through /bin/sh with the event exposed as RSPY_* variables and is not
+// waited for, so it can still grab /proc/$RSPY_PID/* of short-lived processes
+pub fn spawn_for_event(command: &str, event: &Event, rule: Option<&str>) {
+    let (pid, ppid) = match event {
+        Event::Process(process) => (Some(process.pid), process.ppid),
+        Event::Exit(exit) => (Some(exit.pid), exit.ppid),
+        Event::Fs(fs_event) => (fs_event.pid, None),
+        Event::Connection(connection) => (connection.pid, None),
+        Event::Session(session) => (session.pid, None),
+        Event::Bus(bus) => (bus.pid, None),
+        Event::Authorization(auth) => (auth.subject_pid, None),
+        Event::Device(_) => (None, None),
+    };
+    if is_own_command(pid, ppid) {
+        return;
+    }
+
+    // a burst of events would otherwise fork a shell for each of them at once
+    let running = SPAWNED_GROUPS
+        .lock()
+        .map_or(0, |groups| groups.as_ref().map_or(0, FxHashSet::len));
+    if running >= EXEC_MAX_RUNNING {
+        METRICS.record_dropped_event();
+        if !AT_CAPACITY.swap(true, Ordering::Relaxed) {
+            Logger::error(format!(
+                "{} commands are still running, events are dropped until one finishes",
+                running
+            ));
+        }
+        return;
+    }
+    AT_CAPACITY.store(false, Ordering::Relaxed);
+
+    let mut env = variables(event);
+    if let Some(rule) = rule {
+        env.push(("RSPY_RULE", rule.to_string()));
+    }
+
+    let spawned = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .process_group(0)
+        .spawn();
+
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            Logger::error(format!("failed to run {:?}: {}", command, e));
+            return;
+        }
+    };
+
+    let group = child.id();
+    if let Ok(mut groups) = SPAWNED_GROUPS.lock() {
+        groups.get_or_insert_with(FxHashSet::default).insert(group);
+    }
+
+    // reaped from a thread so slow commands never hold up the event loop
+    std::thread::spawn(move || {
+        let _ = child.wait();
+        if let Ok(mut groups) = SPAWNED_GROUPS.lock()
+            && let Some(groups) = groups.as_mut()
+        {
+            groups.remove(&group);
+        }
+    });
+}
@@ -0,0 +1,102 @@
+use std::io::ErrorKind;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{Sink, exec, summary};
+use crate::core::{
+    constants::{JOURNAL_MAX_VALUE_BYTES, JOURNAL_SEND_TIMEOUT_MS, JOURNAL_SOCKET},
+    error::Result,
+    event::Event,
+    logger::Logger,
+    severity::Severity,
+};
+
+// sends events to the systemd journal over its native protocol, the RSPY_* fields are the
+// same as the variables of --exec-on-match, e.g. `journalctl -t rspy RSPY_UID=1000`
+pub struct JournaldSink {
+    socket: UnixDatagram,
+    dropped: u64,
+}
+
+impl JournaldSink {
+    pub fn open() -> Result<Self> {
+        if !Path::new(JOURNAL_SOCKET).exists() {
+            return Err(format!(
+                "{} does not exist, is systemd-journald running?",
+                JOURNAL_SOCKET
+            )
+            .into());
+        }
+        let socket = UnixDatagram::unbound()?;
+        // bursts wait for journald to catch up, a stalled one must not hold up the event loop
+        socket.set_write_timeout(Some(Duration::from_millis(JOURNAL_SEND_TIMEOUT_MS)))?;
+        Ok(Self { socket, dropped: 0 })
+    }
+}
+
+impl Sink for JournaldSink {
+    fn name(&self) -> &'static str {
+        "journald"
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        let severity = Severity::of(event);
+        let mut payload = Vec::new();
+        append_field(&mut payload, "MESSAGE", &summary(event));
+        append_field(&mut payload, "PRIORITY", priority(severity));
+        append_field(&mut payload, "SYSLOG_IDENTIFIER", "rspy");
+        append_field(&mut payload, "RSPY_SEVERITY", severity.as_str());
+        for (key, value) in exec::variables(event) {
+            if !value.is_empty() {
+                append_field(&mut payload, key, &value);
+            }
+        }
+
+        // sent to the path every time so a restarted journald is picked up again
+        match self.socket.send_to(&payload, JOURNAL_SOCKET) {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                self.dropped += 1;
+                if self.dropped.is_power_of_two() {
+                    Logger::error(format!(
+                        "journald is not keeping up, {} events dropped so far",
+                        self.dropped
+                    ));
+                }
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// syslog levels, alert is the same word in both
+fn priority(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "6",
+        Severity::Notice => "5",
+        Severity::Warning => "4",
+        Severity::Alert => "1",
+    }
+}
+
+// KEY=value, or KEY, a little endian length and the raw value for values with newlines
+fn append_field(payload: &mut Vec<u8>, key: &str, value: &str) {
+    // one datagram holds the whole entry, huge command lines are cut
+    let mut end = value.len().min(JOURNAL_MAX_VALUE_BYTES);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    let value = &value[..end];
+
+    payload.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value.as_bytes());
+    payload.push(b'\n');
+}
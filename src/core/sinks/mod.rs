@@ -0,0 +1,96 @@
+pub mod exec;
+pub mod journald;
+pub mod remote;
+pub mod route;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+use super::constants::{UNKNOWN_COMMAND, UNKNOWN_UID_DISPLAY};
+use super::error::Result;
+use super::event::{Event, FsEvent};
+
+pub trait Sink {
+    fn name(&self) -> &'static str;
+
+    fn write(&mut self, event: &Event) -> Result<()>;
+
+    // called periodically while the event loop is idle
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// one line for people, e.g. the text of a webhook post or a journal message
+pub fn summary(event: &Event) -> String {
+    let uid = |uid: Option<u32>| uid.map_or(UNKNOWN_UID_DISPLAY.to_string(), |u| u.to_string());
+
+    match event {
+        Event::Process(process) => format!(
+            "CMD: UID={} PID={} | {}",
+            uid(process.uid),
+            process.pid,
+            process.cmdline
+        ),
+        Event::Exit(exit) => format!(
+            "EXIT: UID={} PID={} | {}",
+            uid(exit.uid),
+            exit.pid,
+            exit.cmdline
+        ),
+        Event::Fs(
+            fs_event @ FsEvent {
+                content: Some(content),
+                ..
+            },
+        ) => format!(
+            "{}: {} ({})",
+            content.category.label(),
+            fs_event.full_path().display(),
+            content.summary
+        ),
+        Event::Fs(fs_event) if fs_event.file_modified => format!(
+            "FILE MODIFIED: {} on {}",
+            fs_event.events,
+            fs_event.path.display()
+        ),
+        Event::Fs(fs_event) => match &fs_event.name {
+            Some(name) => format!(
+                "FS: {} {} in {}",
+                fs_event.events,
+                name,
+                fs_event.path.display()
+            ),
+            None => format!("FS: {} on {}", fs_event.events, fs_event.path.display()),
+        },
+        Event::Connection(connection) => format!(
+            "NET: UID={} PID={} | {} by {}",
+            uid(connection.uid),
+            uid(connection.pid),
+            connection.describe(),
+            connection.process.as_deref().unwrap_or(UNKNOWN_COMMAND)
+        ),
+        Event::Session(session) => format!(
+            "SESS: UID={} PID={} | {}",
+            uid(session.uid),
+            uid(session.pid),
+            session.describe()
+        ),
+        Event::Device(device) => format!("DEV: {}", device.describe()),
+        Event::Bus(bus) => format!(
+            "BUS: UID={} PID={} | {} by {}",
+            uid(bus.uid),
+            uid(bus.pid),
+            bus.describe(),
+            bus.process.as_deref().unwrap_or(UNKNOWN_COMMAND)
+        ),
+        Event::Authorization(auth) => format!(
+            "AUTH: UID={} PID={} | {} for {}",
+            uid(auth.subject_uid),
+            uid(auth.subject_pid),
+            auth.describe(),
+            auth.subject.as_deref().unwrap_or(UNKNOWN_COMMAND)
+        ),
+    }
+}
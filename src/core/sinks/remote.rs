@@ -0,0 +1,318 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError, sync_channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::Sink;
+use crate::core::{
+    constants::{
+        REMOTE_BUFFER_CAPACITY, REMOTE_MAX_BACKOFF_SECS, REMOTE_MIN_BACKOFF_MS,
+        REMOTE_QUEUE_CAPACITY, REMOTE_TIMEOUT_SECS,
+    },
+    error::Result,
+    event::Event,
+    logger::Logger,
+};
+
+// the files given with --tls-ca, --tls-cert and --tls-key, without a ca the bundled
+// web pki roots are trusted
+#[derive(Clone, Default)]
+pub struct TlsFiles {
+    pub ca: Option<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+// one JSON object per line, the host tells apart several machines shipping to one collector
+#[derive(Serialize)]
+struct RemoteLine<'a> {
+    host: &'a str,
+    event: &'a Event,
+}
+
+enum Connector {
+    Tcp,
+    #[cfg(feature = "tls")]
+    Tls(std::sync::Arc<rustls::ClientConfig>),
+}
+
+// events are shipped from a background thread that owns the connection, lines wait in
+// memory while the collector is unreachable so nothing is written to disk
+pub struct RemoteSink {
+    host: String,
+    sender: Option<SyncSender<String>>,
+    worker: Option<JoinHandle<()>>,
+    dropped: u64,
+}
+
+impl RemoteSink {
+    pub fn new(address: String, tls: Option<&TlsFiles>) -> Result<Self> {
+        let connector = match tls {
+            None => Connector::Tcp,
+            #[cfg(feature = "tls")]
+            Some(files) => Connector::Tls(std::sync::Arc::new(tls_config(files)?)),
+            #[cfg(not(feature = "tls"))]
+            Some(_) => return Err("rspy was built without tls support".into()),
+        };
+
+        let (sender, receiver) = sync_channel(REMOTE_QUEUE_CAPACITY);
+        let worker = thread::spawn(move || ship(&address, &connector, receiver));
+        Ok(Self {
+            host: std::fs::read_to_string("/proc/sys/kernel/hostname")
+                .map(|host| host.trim().to_string())
+                .unwrap_or_default(),
+            sender: Some(sender),
+            worker: Some(worker),
+            dropped: 0,
+        })
+    }
+}
+
+impl Drop for RemoteSink {
+    // give the buffered lines a chance to go out, without hanging shutdown on a dead collector
+    fn drop(&mut self) {
+        self.sender.take();
+        let Some(worker) = self.worker.take() else {
+            return;
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(REMOTE_TIMEOUT_SECS);
+        while !worker.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        if worker.is_finished() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Sink for RemoteSink {
+    fn name(&self) -> &'static str {
+        "remote"
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        let mut line = serde_json::to_string(&RemoteLine {
+            host: &self.host,
+            event,
+        })
+        .map_err(|e| format!("failed to serialize event: {}", e))?;
+        line.push('\n');
+
+        let Some(sender) = &self.sender else {
+            return Ok(());
+        };
+
+        match sender.try_send(line) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                if self.dropped.is_power_of_two() {
+                    Logger::error(format!(
+                        "remote queue is full, {} events dropped so far",
+                        self.dropped
+                    ));
+                }
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err("remote thread stopped".into()),
+        }
+    }
+}
+
+fn ship(address: &str, connector: &Connector, receiver: Receiver<String>) {
+    let mut pending: VecDeque<String> = VecDeque::new();
+    let mut connection: Option<Box<dyn Write>> = None;
+    let mut backoff = Duration::from_millis(REMOTE_MIN_BACKOFF_MS);
+    let mut retry_at = Instant::now();
+    // only the first failure of an outage is reported
+    let mut reachable = true;
+    let mut discarded: u64 = 0;
+    let mut open = true;
+
+    loop {
+        let wait = if pending.is_empty() {
+            Duration::from_secs(REMOTE_TIMEOUT_SECS)
+        } else {
+            retry_at.saturating_duration_since(Instant::now())
+        };
+        if open {
+            match receiver.recv_timeout(wait) {
+                Ok(line) => pending.push_back(line),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => open = false,
+            }
+            pending.extend(receiver.try_iter());
+        }
+        if pending.len() > REMOTE_BUFFER_CAPACITY {
+            let excess = pending.len() - REMOTE_BUFFER_CAPACITY;
+            pending.drain(..excess);
+            discarded += excess as u64;
+        }
+        if pending.is_empty() {
+            if open {
+                continue;
+            }
+            return;
+        }
+
+        // on shutdown there is one more attempt, not a wait for the backoff
+        if connection.is_none() && (Instant::now() >= retry_at || !open) {
+            match connect(address, connector) {
+                Ok(stream) => {
+                    Logger::info(format!("connected to remote collector {}", address));
+                    connection = Some(stream);
+                    reachable = true;
+                    backoff = Duration::from_millis(REMOTE_MIN_BACKOFF_MS);
+                }
+                Err(e) => {
+                    if reachable {
+                        Logger::error(format!(
+                            "remote collector {} is unreachable, buffering events: {}",
+                            address, e
+                        ));
+                        reachable = false;
+                    }
+                    retry_at = Instant::now() + backoff;
+                    backoff = (backoff * 2).min(Duration::from_secs(REMOTE_MAX_BACKOFF_SECS));
+                }
+            }
+        }
+
+        if let Some(stream) = connection.as_mut()
+            && let Err(e) = send_pending(stream.as_mut(), &mut pending)
+        {
+            Logger::error(format!(
+                "lost connection to remote collector {}: {}",
+                address, e
+            ));
+            connection = None;
+            reachable = false;
+            retry_at = Instant::now() + backoff;
+        }
+
+        if !open && connection.is_none() {
+            Logger::error(format!(
+                "{} events were not shipped to {}",
+                pending.len() as u64 + discarded,
+                address
+            ));
+            return;
+        }
+        if discarded > 0 && connection.is_some() {
+            Logger::error(format!(
+                "remote buffer overflowed while {} was unreachable, {} events were dropped",
+                address, discarded
+            ));
+            discarded = 0;
+        }
+    }
+}
+
+// a line that was partly written before an error is sent again in full after reconnecting
+fn send_pending(stream: &mut dyn Write, pending: &mut VecDeque<String>) -> std::io::Result<()> {
+    while let Some(line) = pending.front() {
+        stream.write_all(line.as_bytes())?;
+        pending.pop_front();
+    }
+    stream.flush()
+}
+
+fn connect(address: &str, connector: &Connector) -> std::io::Result<Box<dyn Write>> {
+    let timeout = Duration::from_secs(REMOTE_TIMEOUT_SECS);
+    // resolved again on every attempt, the collector may have moved
+    let mut last_error = None;
+    let mut stream = None;
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(connected) => {
+                stream = Some(connected);
+                break;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let stream = match (stream, last_error) {
+        (Some(stream), _) => stream,
+        (None, Some(e)) => return Err(e),
+        (None, None) => {
+            return Err(std::io::Error::other("the address did not resolve"));
+        }
+    };
+    // a stalled collector must not block the thread forever
+    stream.set_write_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+
+    match connector {
+        Connector::Tcp => Ok(Box::new(stream)),
+        #[cfg(feature = "tls")]
+        Connector::Tls(config) => {
+            let host = server_name(address);
+            let name = rustls_pki_types::ServerName::try_from(host.to_string())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            let mut client = rustls::ClientConnection::new(std::sync::Arc::clone(config), name)
+                .map_err(std::io::Error::other)?;
+            let mut stream = stream;
+            // a rejected certificate is a failed connect, not a connection that is lost later
+            while client.is_handshaking() {
+                client.complete_io(&mut stream)?;
+            }
+            Ok(Box::new(rustls::StreamOwned::new(client, stream)))
+        }
+    }
+}
+
+// host:port, [v6]:port
+#[cfg(feature = "tls")]
+fn server_name(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+#[cfg(feature = "tls")]
+fn tls_config(files: &TlsFiles) -> Result<rustls::ClientConfig> {
+    use rustls_pki_types::pem::PemObject;
+    use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+
+    let mut roots = rustls::RootCertStore::empty();
+    match &files.ca {
+        Some(ca) => {
+            let certs = CertificateDer::pem_file_iter(ca)
+                .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+                .map_err(|e| format!("failed to read --tls-ca {}: {}", ca.display(), e))?;
+            roots.add_parsable_certificates(certs);
+            if roots.is_empty() {
+                return Err(
+                    format!("--tls-ca {} contains no usable certificate", ca.display()).into(),
+                );
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let builder = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| format!("failed to set up tls: {}", e))?
+    .with_root_certificates(roots);
+
+    let config = match (&files.cert, &files.key) {
+        (Some(cert), Some(key)) => {
+            let chain = CertificateDer::pem_file_iter(cert)
+                .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+                .map_err(|e| format!("failed to read --tls-cert {}: {}", cert.display(), e))?;
+            let key = PrivateKeyDer::from_pem_file(key)
+                .map_err(|e| format!("failed to read --tls-key {}: {}", key.display(), e))?;
+            builder
+                .with_client_auth_cert(chain, key)
+                .map_err(|e| format!("invalid client certificate: {}", e))?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+    Ok(config)
+}
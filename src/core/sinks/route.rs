@@ -0,0 +1,238 @@
+use rustc_hash::FxHashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::summary;
+use crate::core::{
+    error::Result,
+    event::{Event, ProcessSource},
+    logger::Logger,
+    output::{self, OutputFormat},
+    severity::Severity,
+};
+
+// where an event came from, as named on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Source {
+    // procfs scans, and the exits they notice
+    Proc,
+    Audit,
+    // processes found over dbus and --dbus-sniff messages
+    Dbus,
+    Polkit,
+    Fs,
+    Net,
+    Session,
+    Device,
+}
+
+const SOURCES: [(&str, Source); 8] = [
+    ("proc", Source::Proc),
+    ("audit", Source::Audit),
+    ("dbus", Source::Dbus),
+    ("polkit", Source::Polkit),
+    ("fs", Source::Fs),
+    ("net", Source::Net),
+    ("session", Source::Session),
+    ("device", Source::Device),
+];
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        SOURCES
+            .iter()
+            .find(|(_, source)| *source == self)
+            .map_or("?", |(name, _)| name)
+    }
+
+    pub fn of(event: &Event) -> Self {
+        match event {
+            Event::Process(process) => match process.source {
+                ProcessSource::Procfs => Source::Proc,
+                ProcessSource::DBus => Source::Dbus,
+                ProcessSource::Audit => Source::Audit,
+            },
+            Event::Exit(_) => Source::Proc,
+            Event::Fs(_) => Source::Fs,
+            Event::Connection(_) => Source::Net,
+            Event::Session(_) => Source::Session,
+            Event::Device(_) => Source::Device,
+            Event::Bus(_) => Source::Dbus,
+            Event::Authorization(_) => Source::Polkit,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    // printed as without --route
+    Stdout,
+    // dropped
+    Null,
+    // the one line summaries the journal and webhooks get, with a timestamp
+    Text(PathBuf),
+    Json(PathBuf),
+}
+
+// --route fs=file:/tmp/fs.log
+#[derive(Clone, Debug)]
+pub struct Route {
+    source: Source,
+    destination: Destination,
+}
+
+impl Route {
+    pub fn parse(spec: &str) -> std::result::Result<Self, String> {
+        let (source, destination) = spec.split_once('=').ok_or_else(|| {
+            format!(
+                "expected source=destination, e.g. fs=file:/tmp/fs.log, got {:?}",
+                spec
+            )
+        })?;
+        let source = SOURCES
+            .iter()
+            .find(|(name, _)| *name == source)
+            .map(|(_, source)| *source)
+            .ok_or_else(|| {
+                format!(
+                    "unknown source {:?}, use {}",
+                    source,
+                    SOURCES.map(|(name, _)| name).join(", ")
+                )
+            })?;
+        let destination = match (destination, destination.split_once(':')) {
+            ("stdout", _) => Destination::Stdout,
+            ("null", _) => Destination::Null,
+            (_, Some(("file", path))) if !path.is_empty() => Destination::Text(PathBuf::from(path)),
+            (_, Some(("json", path))) if !path.is_empty() => Destination::Json(PathBuf::from(path)),
+            _ => {
+                return Err(format!(
+                    "unknown destination {:?}, use stdout, null, file:<path> or json:<path>",
+                    destination
+                ));
+            }
+        };
+        Ok(Self {
+            source,
+            destination,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match &self.destination {
+            Destination::Text(path) | Destination::Json(path) => Some(path),
+            Destination::Stdout | Destination::Null => None,
+        }
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = self.source.name();
+        match &self.destination {
+            Destination::Stdout => write!(f, "{} events to stdout", source),
+            Destination::Null => write!(f, "{} events nowhere", source),
+            Destination::Text(path) => write!(f, "{} events to {}", source, path.display()),
+            Destination::Json(path) => {
+                write!(f, "{} events as json to {}", source, path.display())
+            }
+        }
+    }
+}
+
+struct Output {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+// sends each source to its routes, sources without one are printed as usual
+pub struct Router {
+    routes: Vec<Route>,
+    // by path, several sources may share a file
+    outputs: FxHashMap<PathBuf, Output>,
+}
+
+impl Router {
+    pub fn open(routes: &[Route]) -> Result<Self> {
+        let mut outputs = FxHashMap::default();
+        for path in routes.iter().filter_map(Route::path) {
+            if outputs.contains_key(path) {
+                continue;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+            outputs.insert(
+                path.to_path_buf(),
+                Output {
+                    path: path.to_path_buf(),
+                    writer: BufWriter::new(file),
+                },
+            );
+        }
+        Ok(Self {
+            routes: routes.to_vec(),
+            outputs,
+        })
+    }
+
+    // writes the event to the files of its source, true when it should still be printed
+    pub fn route(&mut self, event: &Event, severity: Severity, rules: &[String]) -> bool {
+        let source = Source::of(event);
+        let mut routed = false;
+        let mut print = false;
+        for route in self.routes.iter().filter(|route| route.source == source) {
+            routed = true;
+            let (path, line) = match &route.destination {
+                Destination::Stdout => {
+                    print = true;
+                    continue;
+                }
+                Destination::Null => continue,
+                Destination::Text(path) => (
+                    path,
+                    Some(format!(
+                        "{} {}",
+                        Logger::plain_time(event.observed_at()),
+                        summary(event)
+                    )),
+                ),
+                Destination::Json(path) => (
+                    path,
+                    output::format_event(OutputFormat::Json, event, severity, rules),
+                ),
+            };
+            let (Some(output), Some(line)) = (self.outputs.get_mut(path), line) else {
+                continue;
+            };
+            if let Err(e) = writeln!(output.writer, "{}", line) {
+                Logger::error(format!(
+                    "failed to write to {}, dropping its routes: {}",
+                    output.path.display(),
+                    e
+                ));
+                self.outputs.remove(path);
+            }
+        }
+        !routed || print
+    }
+
+    pub fn flush(&mut self) {
+        self.outputs
+            .retain(|_, output| match output.writer.flush() {
+                Ok(()) => true,
+                Err(e) => {
+                    Logger::error(format!(
+                        "failed to write to {}, dropping its routes: {}",
+                        output.path.display(),
+                        e
+                    ));
+                    false
+                }
+            });
+    }
+}
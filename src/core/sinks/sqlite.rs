@@ -0,0 +1,354 @@
+use rusqlite::{Connection, params};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::Sink;
+use crate::core::{
+    constants::{SQLITE_BATCH_SIZE, SQLITE_COMMIT_INTERVAL_MS},
+    error::Result,
+    event::{
+        AuthorizationEvent, BusEvent, ConnectionEvent, DeviceEvent, Event, ExitEvent, FsEvent,
+        ProcessEvent, SessionEvent,
+    },
+};
+
+// timestamps are unix seconds, e.g. `datetime(observed_at, 'unixepoch', 'localtime')`
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS process_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    start_time REAL,
+    source TEXT NOT NULL,
+    pid INTEGER NOT NULL,
+    ppid INTEGER,
+    uid INTEGER,
+    gid INTEGER,
+    cmdline TEXT NOT NULL,
+    exe TEXT,
+    exe_deleted INTEGER NOT NULL,
+    exe_mismatch INTEGER NOT NULL,
+    exe_sha256 TEXT,
+    cwd TEXT
+);
+CREATE INDEX IF NOT EXISTS process_events_observed_at ON process_events (observed_at);
+CREATE INDEX IF NOT EXISTS process_events_uid ON process_events (uid, observed_at);
+CREATE INDEX IF NOT EXISTS process_events_pid ON process_events (pid);
+CREATE INDEX IF NOT EXISTS process_events_exe ON process_events (exe);
+
+CREATE TABLE IF NOT EXISTS exit_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    pid INTEGER NOT NULL,
+    ppid INTEGER,
+    uid INTEGER,
+    cmdline TEXT NOT NULL,
+    lifetime REAL
+);
+CREATE INDEX IF NOT EXISTS exit_events_pid ON exit_events (pid);
+
+CREATE TABLE IF NOT EXISTS fs_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    events TEXT NOT NULL,
+    path TEXT NOT NULL,
+    pid INTEGER,
+    process TEXT
+);
+CREATE INDEX IF NOT EXISTS fs_events_observed_at ON fs_events (observed_at);
+CREATE INDEX IF NOT EXISTS fs_events_path ON fs_events (path);
+
+CREATE TABLE IF NOT EXISTS connection_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    protocol TEXT NOT NULL,
+    local TEXT NOT NULL,
+    remote TEXT,
+    uid INTEGER,
+    pid INTEGER,
+    process TEXT
+);
+CREATE INDEX IF NOT EXISTS connection_events_observed_at ON connection_events (observed_at);
+
+CREATE TABLE IF NOT EXISTS session_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    kind TEXT NOT NULL,
+    user TEXT NOT NULL,
+    uid INTEGER,
+    tty TEXT NOT NULL,
+    host TEXT,
+    pid INTEGER
+);
+CREATE INDEX IF NOT EXISTS session_events_observed_at ON session_events (observed_at);
+
+CREATE TABLE IF NOT EXISTS device_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    action TEXT NOT NULL,
+    subsystem TEXT,
+    devtype TEXT,
+    devpath TEXT NOT NULL,
+    devname TEXT,
+    interface TEXT,
+    product TEXT
+);
+CREATE INDEX IF NOT EXISTS device_events_observed_at ON device_events (observed_at);
+
+CREATE TABLE IF NOT EXISTS bus_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    kind TEXT NOT NULL,
+    sender TEXT,
+    pid INTEGER,
+    uid INTEGER,
+    process TEXT,
+    destination TEXT,
+    path TEXT,
+    interface TEXT,
+    member TEXT
+);
+CREATE INDEX IF NOT EXISTS bus_events_observed_at ON bus_events (observed_at);
+
+CREATE TABLE IF NOT EXISTS authorization_events (
+    id INTEGER PRIMARY KEY,
+    observed_at REAL NOT NULL,
+    action TEXT NOT NULL,
+    result TEXT NOT NULL,
+    subject_pid INTEGER,
+    subject_uid INTEGER,
+    subject TEXT,
+    caller_pid INTEGER,
+    caller TEXT,
+    command TEXT
+);
+CREATE INDEX IF NOT EXISTS authorization_events_observed_at ON authorization_events (observed_at);
+";
+
+pub struct SqliteSink {
+    connection: Connection,
+    pending: usize,
+    last_commit: Instant,
+}
+
+impl SqliteSink {
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        // inserts are batched into transactions, committing each one is far too slow for fs events
+        connection.execute_batch("BEGIN")?;
+
+        Ok(Self {
+            connection,
+            pending: 0,
+            last_commit: Instant::now(),
+        })
+    }
+
+    fn insert_process(&self, event: &ProcessEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO process_events (observed_at, start_time, source, pid, ppid, uid, gid,
+                 cmdline, exe, exe_deleted, exe_mismatch, exe_sha256, cwd)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.start_time.map(unix_secs),
+                format!("{:?}", event.source).to_lowercase(),
+                event.pid,
+                event.ppid,
+                event.uid,
+                event.gid,
+                event.cmdline,
+                event.exe.as_ref().map(|exe| exe.to_string_lossy()),
+                event.exe_deleted,
+                event.exe_mismatch,
+                event.exe_sha256,
+                event.cwd.as_ref().map(|cwd| cwd.to_string_lossy()),
+            ])?;
+        Ok(())
+    }
+
+    fn insert_exit(&self, event: &ExitEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO exit_events (observed_at, pid, ppid, uid, cmdline, lifetime)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.pid,
+                event.ppid,
+                event.uid,
+                event.cmdline,
+                event.lifetime.map(|lifetime| lifetime.as_secs_f64()),
+            ])?;
+        Ok(())
+    }
+
+    fn insert_fs(&self, event: &FsEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO fs_events (observed_at, events, path, pid, process)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.events,
+                event.full_path().to_string_lossy(),
+                event.pid,
+                event.process,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_connection(&self, event: &ConnectionEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO connection_events (observed_at, protocol, local, remote, uid, pid, process)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.protocol,
+                event.local,
+                event.remote,
+                event.uid,
+                event.pid,
+                event.process,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_session(&self, event: &SessionEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO session_events (observed_at, kind, user, uid, tty, host, pid)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.kind.as_str(),
+                event.user,
+                event.uid,
+                event.tty,
+                event.host,
+                event.pid,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_device(&self, event: &DeviceEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO device_events (observed_at, action, subsystem, devtype, devpath, devname, interface, product)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.action,
+                event.subsystem,
+                event.devtype,
+                event.devpath,
+                event.devname,
+                event.interface,
+                event.product,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_bus(&self, event: &BusEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO bus_events (observed_at, kind, sender, pid, uid, process, destination, path, interface, member)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.kind,
+                event.sender,
+                event.pid,
+                event.uid,
+                event.process,
+                event.destination,
+                event.path,
+                event.interface,
+                event.member,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_authorization(&self, event: &AuthorizationEvent) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO authorization_events (observed_at, action, result, subject_pid, subject_uid, subject, caller_pid, caller, command)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(params![
+                unix_secs(event.observed_at),
+                event.action,
+                event.result.as_str(),
+                event.subject_pid,
+                event.subject_uid,
+                event.subject,
+                event.caller_pid,
+                event.caller,
+                event.command,
+            ])?;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.connection.execute_batch("COMMIT; BEGIN")?;
+        self.pending = 0;
+        self.last_commit = Instant::now();
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        match event {
+            Event::Process(process) => self.insert_process(process)?,
+            Event::Exit(exit) => self.insert_exit(exit)?,
+            Event::Fs(fs_event) => self.insert_fs(fs_event)?,
+            Event::Connection(connection) => self.insert_connection(connection)?,
+            Event::Session(session) => self.insert_session(session)?,
+            Event::Device(device) => self.insert_device(device)?,
+            Event::Bus(bus) => self.insert_bus(bus)?,
+            Event::Authorization(auth) => self.insert_authorization(auth)?,
+        }
+
+        self.pending += 1;
+        if self.pending >= SQLITE_BATCH_SIZE {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.pending > 0
+            && self.last_commit.elapsed() >= Duration::from_millis(SQLITE_COMMIT_INTERVAL_MS)
+        {
+            self.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        let _ = self.connection.execute_batch("COMMIT");
+    }
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
@@ -0,0 +1,155 @@
+use serde_json::{Value, json};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError, sync_channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{Sink, summary};
+use crate::core::{
+    constants::{
+        WEBHOOK_BATCH_INTERVAL_MS, WEBHOOK_BATCH_SIZE, WEBHOOK_MAX_BACKOFF_SECS,
+        WEBHOOK_MAX_RETRIES, WEBHOOK_QUEUE_CAPACITY, WEBHOOK_TIMEOUT_SECS,
+    },
+    error::Result,
+    event::Event,
+    logger::Logger,
+};
+
+// events are posted from a background thread so a slow endpoint never stalls the event loop
+pub struct WebhookSink {
+    sender: Option<SyncSender<Value>>,
+    worker: Option<JoinHandle<()>>,
+    dropped: u64,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        let (sender, receiver) = sync_channel(WEBHOOK_QUEUE_CAPACITY);
+        let worker = thread::spawn(move || deliver(&url, receiver));
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+            dropped: 0,
+        }
+    }
+}
+
+impl Drop for WebhookSink {
+    // give the last batch a chance to go out, without hanging shutdown on a dead endpoint
+    fn drop(&mut self) {
+        self.sender.take();
+        let Some(worker) = self.worker.take() else {
+            return;
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(WEBHOOK_TIMEOUT_SECS);
+        while !worker.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        if worker.is_finished() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        let value = json!({
+            "summary": summary(event),
+            "event": event,
+        });
+
+        let Some(sender) = &self.sender else {
+            return Ok(());
+        };
+
+        match sender.try_send(value) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                // the endpoint is down or too slow, shed load instead of growing without bound
+                self.dropped += 1;
+                if self.dropped.is_power_of_two() {
+                    Logger::error(format!(
+                        "webhook queue is full, {} events dropped so far",
+                        self.dropped
+                    ));
+                }
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err("webhook thread stopped".into()),
+        }
+    }
+}
+
+fn deliver(url: &str, receiver: Receiver<Value>) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+        .build();
+
+    loop {
+        let Ok(first) = receiver.recv() else {
+            return;
+        };
+
+        let mut batch = vec![first];
+        let deadline = Instant::now() + Duration::from_millis(WEBHOOK_BATCH_INTERVAL_MS);
+        while batch.len() < WEBHOOK_BATCH_SIZE {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(value) => batch.push(value),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    post_with_retry(&agent, url, batch);
+                    return;
+                }
+            }
+        }
+
+        post_with_retry(&agent, url, batch);
+    }
+}
+
+fn post_with_retry(agent: &ureq::Agent, url: &str, batch: Vec<Value>) {
+    // `text` makes the payload readable by slack-style incoming webhooks as is
+    let text = batch
+        .iter()
+        .filter_map(|value| value["summary"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let events: Vec<&Value> = batch.iter().map(|value| &value["event"]).collect();
+    let body = json!({ "text": text, "events": events });
+
+    let mut backoff = Duration::from_millis(500);
+    for attempt in 1..=WEBHOOK_MAX_RETRIES {
+        match agent.post(url).send_json(&body) {
+            Ok(_) => return,
+            Err(ureq::Error::Status(status, _)) if status != 429 && status < 500 => {
+                Logger::error(format!(
+                    "webhook rejected {} events with status {}, not retrying",
+                    batch.len(),
+                    status
+                ));
+                return;
+            }
+            Err(e) => {
+                Logger::debug(format!(
+                    "webhook post failed (attempt {}/{}): {}",
+                    attempt, WEBHOOK_MAX_RETRIES, e
+                ));
+            }
+        }
+
+        if attempt < WEBHOOK_MAX_RETRIES {
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(Duration::from_secs(WEBHOOK_MAX_BACKOFF_SECS));
+        }
+    }
+
+    Logger::error(format!(
+        "webhook post failed after {} attempts, {} events were dropped",
+        WEBHOOK_MAX_RETRIES,
+        batch.len()
+    ));
+}
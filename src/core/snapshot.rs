@@ -0,0 +1,105 @@
+use colored::Colorize;
+use std::sync::mpsc;
+
+use super::{
+    config::Config,
+    error::Result,
+    event::{Event, ProcessEvent},
+    filter::{EventFilter, ExcludedPids},
+    logger::Logger,
+    output,
+    severity::Severity,
+};
+use crate::monitoring::process::ProcessScanner;
+use crate::utils::process::process_name;
+
+// `rspy snapshot`, one scan of the procfs scanner with everything it can tell about a
+// process turned on, printed as a table or in the --output-format and then done
+pub fn run(config: &Config) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut scanner = ProcessScanner::new(sender);
+    scanner.set_show_cwd(true);
+    scanner.set_show_fds(true);
+    scanner.set_highlight_tmp_exec(config.highlight_tmp_exec);
+    scanner.set_check_libraries(config.check_libraries);
+    scanner.scan_processes()?;
+    drop(scanner);
+
+    let mut processes: Vec<Box<ProcessEvent>> = receiver
+        .try_iter()
+        .filter_map(|event| match event {
+            Event::Process(process) => Some(process),
+            _ => None,
+        })
+        .collect();
+    // parents first, so --ignore-pid takes their descendants along
+    processes.sort_by_key(|process| (process.start_time, process.pid));
+
+    let mut filter = EventFilter::new(
+        config.filter.clone(),
+        config.exclude.clone(),
+        config.uids.clone(),
+        config.excluded_uids.clone(),
+        None,
+    );
+    let mut excluded_pids = ExcludedPids::new(config.ignore_self, &config.ignore_pids);
+    processes.retain(|process| {
+        !excluded_pids.excludes_process(process.pid, process.ppid)
+            && filter.allows(process.uid, &process.cmdline, process.observed_at)
+    });
+    processes.sort_by_key(|process| process.pid);
+
+    let structured = config.output_format != output::OutputFormat::Text;
+    if !structured {
+        Logger::status(format!("{:>8} {:>8}  {:<14}CMD", "PID", "PPID", "UID").bold());
+    }
+    for process in processes {
+        if !structured {
+            print_process(&process);
+            continue;
+        }
+        let event = Event::Process(process);
+        if let Some(line) =
+            output::format_event(config.output_format, &event, Severity::of(&event), &[])
+        {
+            Logger::structured_event(&line);
+        }
+    }
+    Ok(())
+}
+
+fn print_process(process: &ProcessEvent) {
+    let cmdline = if process.cmdline.is_empty() {
+        process_name(process.pid as i32)
+    } else {
+        process.cmdline.clone()
+    };
+    Logger::status(format!(
+        "{:>8} {:>8}  {:<14}{}",
+        process.pid,
+        process
+            .ppid
+            .map_or_else(|| "?".to_string(), |ppid| ppid.to_string()),
+        Logger::format_uid(process.uid),
+        cmdline
+    ));
+
+    let indent = " ".repeat(33);
+    let mut details = Vec::new();
+    // kernel threads have no exe and every one of them sits in /
+    if let Some(exe) = &process.exe {
+        details.push(format!("exe {}", exe.display()));
+        if let Some(cwd) = &process.cwd {
+            details.push(format!("cwd {}", cwd.display()));
+        }
+    }
+    if !details.is_empty() {
+        Logger::status(format!("{}{}", indent, details.join(", ")).dimmed());
+    }
+    for fd in &process.fds {
+        Logger::status(format!("{}fd {}: {}", indent, fd.fd, fd.target));
+    }
+    if process.omitted_fds > 0 {
+        Logger::status(format!("{}... and {} more", indent, process.omitted_fds));
+    }
+}
@@ -0,0 +1,336 @@
+use rustc_hash::FxHashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{
+    constants::STATS_TOP_ENTRIES, dedup::CommandDedup, event::Event, logger::Logger,
+    metrics::METRICS, sinks::route::Source,
+};
+use crate::utils::format::format_duration;
+
+// aggregates everything that came through the event loop, printed once rspy stops
+pub struct Stats {
+    started: Instant,
+    processes_by_uid: FxHashMap<Option<u32>, u64>,
+    commands: FxHashMap<String, u64>,
+    fs_events_by_directory: FxHashMap<PathBuf, u64>,
+    threads: u64,
+    connections: u64,
+    sessions: u64,
+    devices: u64,
+    bus_messages: u64,
+    authorizations: u64,
+    lifetimes: Option<Lifetimes>,
+    events_by_source: FxHashMap<Source, u64>,
+    // when the live statistics were last asked for and the counts then, for the rates
+    last_report: Option<(Instant, FxHashMap<Source, u64>)>,
+}
+
+// --lifetimes, how long the processes that exited lived. the scanner notices an exit one
+// scan late at worst, so the lifetimes of short jobs are rounded up to about the interval
+struct Lifetimes {
+    threshold: Duration,
+    interval: Option<Duration>,
+    lifetimes: Vec<Duration>,
+    transient_commands: FxHashMap<String, u64>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            processes_by_uid: FxHashMap::default(),
+            commands: FxHashMap::default(),
+            fs_events_by_directory: FxHashMap::default(),
+            threads: 0,
+            connections: 0,
+            sessions: 0,
+            devices: 0,
+            bus_messages: 0,
+            authorizations: 0,
+            lifetimes: None,
+            events_by_source: FxHashMap::default(),
+            last_report: None,
+        }
+    }
+
+    pub fn track_lifetimes(&mut self, threshold: Duration, interval: Option<Duration>) {
+        self.lifetimes = Some(Lifetimes {
+            threshold,
+            interval,
+            lifetimes: Vec::new(),
+            transient_commands: FxHashMap::default(),
+        });
+    }
+
+    // counts events before any filtering, the summary describes the host, not the output
+    pub fn record(&mut self, event: &Event) {
+        *self.events_by_source.entry(Source::of(event)).or_insert(0) += 1;
+        match event {
+            Event::Process(process) if process.thread.is_some() => self.threads += 1,
+            Event::Process(process) => {
+                *self.processes_by_uid.entry(process.uid).or_insert(0) += 1;
+                let cmd = CommandDedup::normalize(&process.cmdline);
+                if !cmd.is_empty() {
+                    *self.commands.entry(cmd).or_insert(0) += 1;
+                }
+            }
+            Event::Exit(exit) => {
+                if let Some(lifetimes) = self.lifetimes.as_mut()
+                    && let Some(lifetime) = exit.lifetime
+                {
+                    lifetimes.lifetimes.push(lifetime);
+                    if lifetime < lifetimes.threshold {
+                        let cmd = CommandDedup::normalize(&exit.cmdline);
+                        if !cmd.is_empty() {
+                            *lifetimes.transient_commands.entry(cmd).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
+            Event::Connection(_) => self.connections += 1,
+            Event::Session(_) => self.sessions += 1,
+            Event::Device(_) => self.devices += 1,
+            Event::Bus(_) => self.bus_messages += 1,
+            Event::Authorization(_) => self.authorizations += 1,
+            Event::Fs(fs_event) => {
+                let directory = if fs_event.path.is_dir() {
+                    fs_event.path.as_path()
+                } else {
+                    fs_event.path.parent().unwrap_or(Path::new("/"))
+                };
+                *self
+                    .fs_events_by_directory
+                    .entry(directory.to_path_buf())
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    // SIGQUIT and `stats` on the control socket, whether a long running rspy still sees
+    // events, keeps up and stays small. rates are since the previous report
+    pub fn print_live(&mut self) {
+        let lines = self.live_lines();
+        Logger::info(lines[0].clone());
+        for line in &lines[1..] {
+            Logger::status(line);
+        }
+    }
+
+    pub fn live_summary(&mut self) -> String {
+        self.live_lines().join("\n")
+    }
+
+    fn live_lines(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let (since, previous) = self
+            .last_report
+            .take()
+            .unwrap_or_else(|| (self.started, FxHashMap::default()));
+        let elapsed = now.duration_since(since).as_secs_f64().max(0.001);
+
+        let mut lines = vec![format!(
+            "live statistics after {}:",
+            format_duration(Some(self.started.elapsed()))
+        )];
+        lines.push(format!(
+            "  events per second over the last {}:",
+            format_duration(Some(now.duration_since(since)))
+        ));
+        let mut sources: Vec<(&Source, &u64)> = self.events_by_source.iter().collect();
+        sources.sort_by_key(|(source, _)| source.name());
+        if sources.is_empty() {
+            lines.push("    none yet".to_string());
+        }
+        for (source, total) in sources {
+            let recent = total - previous.get(source).copied().unwrap_or(0);
+            lines.push(format!(
+                "    {:<8}{:>10.1}  ({} in total)",
+                source.name(),
+                recent as f64 / elapsed,
+                total
+            ));
+        }
+
+        lines.push(format!("  inotify watches: {}", METRICS.inotify_watches()));
+        if let Some(rss) = resident_memory() {
+            lines.push(format!(
+                "  resident memory: {:.1} MiB",
+                rss as f64 / 1024.0 / 1024.0
+            ));
+        }
+        lines.push(format!(
+            "  dropped events: {} ({} inotify queue overflows, {} while paused)",
+            METRICS.inotify_overflows() + METRICS.dropped_events(),
+            METRICS.inotify_overflows(),
+            METRICS.dropped_events()
+        ));
+        let scans = METRICS.scans();
+        if scans > 0 {
+            lines.push(format!(
+                "  procfs scans: {}, the last took {}",
+                scans,
+                format_duration(Some(METRICS.last_scan_duration()))
+            ));
+        }
+
+        self.last_report = Some((now, self.events_by_source.clone()));
+        lines
+    }
+
+    pub fn print(&self) {
+        Logger::info(self.header());
+        for line in self.lines() {
+            Logger::status(line);
+        }
+    }
+
+    // the same summary as one block of text, e.g. for the control socket
+    pub fn summary(&self) -> String {
+        let mut summary = self.header();
+        for line in self.lines() {
+            summary.push('\n');
+            summary.push_str(&line);
+        }
+        summary
+    }
+
+    fn header(&self) -> String {
+        format!(
+            "summary after {}:",
+            format_duration(Some(self.started.elapsed()))
+        )
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        let processes: u64 = self.processes_by_uid.values().sum();
+        lines.push(format!("  new processes: {}", processes));
+        for (uid, count) in top(&self.processes_by_uid) {
+            lines.push(format!(
+                "    {:>8}  UID={}",
+                count,
+                Logger::format_uid(*uid)
+            ));
+        }
+
+        if self.threads > 0 {
+            lines.push(format!("  new threads: {}", self.threads));
+        }
+
+        if !self.commands.is_empty() {
+            lines.push("  most frequent commands:".to_string());
+            for (cmd, count) in top(&self.commands) {
+                lines.push(format!("    {:>8}  {}", count, cmd));
+            }
+        }
+
+        let fs_events: u64 = self.fs_events_by_directory.values().sum();
+        lines.push(format!("  file system events: {}", fs_events));
+        for (directory, count) in top(&self.fs_events_by_directory) {
+            lines.push(format!("    {:>8}  {}", count, directory.display()));
+        }
+        if self.fs_events_by_directory.len() > STATS_TOP_ENTRIES {
+            lines.push(format!(
+                "    ... and {} more directories",
+                self.fs_events_by_directory.len() - STATS_TOP_ENTRIES
+            ));
+        }
+
+        if let Some(lifetimes) = &self.lifetimes {
+            lines.extend(lifetimes.lines());
+        }
+
+        if self.connections > 0 {
+            lines.push(format!("  new connections: {}", self.connections));
+        }
+        if self.sessions > 0 {
+            lines.push(format!("  logins and logouts: {}", self.sessions));
+        }
+        if self.devices > 0 {
+            lines.push(format!("  device events: {}", self.devices));
+        }
+        if self.bus_messages > 0 {
+            lines.push(format!("  dbus messages: {}", self.bus_messages));
+        }
+        if self.authorizations > 0 {
+            lines.push(format!("  polkit checks: {}", self.authorizations));
+        }
+
+        let scans = METRICS.scans();
+        if scans > 0 {
+            lines.push(format!(
+                "  procfs scans: {} ({} total, {} on average)",
+                scans,
+                format_duration(Some(METRICS.scan_duration())),
+                format_duration(Some(METRICS.scan_duration().div_f64(scans as f64)))
+            ));
+        }
+        let inotify_events = METRICS.inotify_events();
+        if inotify_events > 0 {
+            lines.push(format!("  inotify events read: {}", inotify_events));
+        }
+        lines
+    }
+}
+
+impl Lifetimes {
+    fn lines(&self) -> Vec<String> {
+        let mut sorted = self.lifetimes.clone();
+        sorted.sort();
+        let Some(longest) = sorted.last() else {
+            return vec!["  process lifetimes: no process exited".to_string()];
+        };
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        let mut lines = vec![format!(
+            "  process lifetimes: {} exited, median {}, 90th percentile {}, longest {}",
+            sorted.len(),
+            format_duration(Some(percentile(50))),
+            format_duration(Some(percentile(90))),
+            format_duration(Some(*longest))
+        )];
+
+        let transient = sorted.partition_point(|lifetime| *lifetime < self.threshold);
+        lines.push(format!(
+            "    {:>8}  transient (under {})",
+            transient,
+            format_duration(Some(self.threshold))
+        ));
+        // these were only caught because a scan happened to fall into their short life
+        if let Some(interval) = self.interval {
+            let missable = sorted.partition_point(|lifetime| *lifetime <= interval);
+            lines.push(format!(
+                "    {:>8}  lived no longer than the scan interval ({})",
+                missable,
+                format_duration(Some(interval))
+            ));
+        }
+        for (cmd, count) in top(&self.transient_commands) {
+            lines.push(format!("    {:>8}  transient: {}", count, cmd));
+        }
+        lines
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// highest counts first, ties broken by key so the summary is stable between runs
+fn top<K: Ord>(counts: &FxHashMap<K, u64>) -> Vec<(&K, u64)> {
+    let mut entries: Vec<(&K, u64)> = counts.iter().map(|(key, count)| (key, *count)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    entries.truncate(STATS_TOP_ENTRIES);
+    entries
+}
+
+// VmRSS of rspy itself, in bytes
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
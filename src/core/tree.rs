@@ -0,0 +1,242 @@
+use colored::Colorize;
+use rustc_hash::FxHashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{
+    constants::{KTHREADD_PID, TREE_EXITED_LINGER_SECS},
+    error::Result,
+    event::Event,
+    logger::Logger,
+};
+use crate::monitoring::process::ProcessScanner;
+use crate::utils::{process::process_name, shutdown};
+
+struct Node {
+    ppid: Option<u32>,
+    uid: Option<u32>,
+    cmdline: String,
+    // when a scan after the first one found it
+    spawned: Option<SystemTime>,
+    exited: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Tree {
+    nodes: FxHashMap<u32, Node>,
+    spawned: usize,
+}
+
+// `rspy tree`, the process tree kept up to date by procfs scans and drawn again after each
+// scan that changed it, processes started while it runs are marked
+pub fn run(interval: Duration) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = Arc::clone(&running);
+    ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst))
+        .map_err(|e| format!("error setting Ctrl-C handler: {}", e))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut scanner = ProcessScanner::new(sender);
+    scanner.set_show_exits(true);
+    let started = SystemTime::now();
+    let mut tree = Tree::default();
+    let mut initial = true;
+    while running.load(Ordering::SeqCst) {
+        scanner.scan_processes()?;
+        let mut changed = tree.expire();
+        for event in receiver.try_iter() {
+            changed |= tree.apply(event, initial);
+        }
+        initial = false;
+        if changed {
+            tree.draw(started)?;
+        }
+        shutdown::sleep(&running, interval);
+    }
+    Ok(())
+}
+
+impl Tree {
+    fn apply(&mut self, event: Event, initial: bool) -> bool {
+        match event {
+            Event::Process(process) if process.thread.is_none() => {
+                // an exec keeps the node where it is
+                if process.changed_from.is_some()
+                    && let Some(node) = self.nodes.get_mut(&process.pid)
+                {
+                    node.cmdline = process.cmdline;
+                    return true;
+                }
+                let spawned = (!initial).then_some(process.observed_at);
+                if spawned.is_some() {
+                    self.spawned += 1;
+                }
+                self.nodes.insert(
+                    process.pid,
+                    Node {
+                        ppid: process.ppid,
+                        uid: process.uid,
+                        cmdline: process.cmdline,
+                        spawned,
+                        exited: None,
+                    },
+                );
+                true
+            }
+            Event::Exit(exit) => {
+                // the ones started meanwhile stay a little longer, a burst would be gone
+                // before anyone could read it
+                match self.nodes.get_mut(&exit.pid) {
+                    Some(node) if node.spawned.is_some() => {
+                        node.exited = Some(Instant::now());
+                    }
+                    Some(_) => {
+                        self.nodes.remove(&exit.pid);
+                    }
+                    None => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expire(&mut self) -> bool {
+        let linger = Duration::from_secs(TREE_EXITED_LINGER_SECS);
+        let before = self.nodes.len();
+        self.nodes
+            .retain(|_, node| node.exited.is_none_or(|exited| exited.elapsed() < linger));
+        self.nodes.len() != before
+    }
+
+    fn draw(&self, started: SystemTime) -> Result<()> {
+        let mut children: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+        let mut roots = Vec::new();
+        for (&pid, node) in &self.nodes {
+            // kernel threads say nothing about what runs on the machine
+            if pid == KTHREADD_PID || node.ppid == Some(KTHREADD_PID) {
+                continue;
+            }
+            match node.ppid {
+                Some(ppid) if self.nodes.contains_key(&ppid) => {
+                    children.entry(ppid).or_default().push(pid)
+                }
+                _ => roots.push(pid),
+            }
+        }
+        roots.sort_unstable();
+        for pids in children.values_mut() {
+            pids.sort_unstable();
+        }
+
+        let mut lines = Vec::new();
+        for (i, &root) in roots.iter().enumerate() {
+            self.lines(root, "", i + 1 == roots.len(), true, &children, &mut lines);
+        }
+
+        let tty = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+        let (rows, columns) = if tty { terminal_size() } else { (None, None) };
+        let mut out = io::stdout().lock();
+        if tty {
+            // home and clear, each draw replaces the previous one
+            write!(out, "\x1b[H\x1b[2J")?;
+        }
+        writeln!(
+            out,
+            "{}",
+            format!(
+                "{} processes, {} started since {}",
+                lines.len(),
+                self.spawned,
+                Logger::plain_time(started)
+            )
+            .bold()
+        )?;
+        let room = rows.map_or(usize::MAX, |rows| rows.saturating_sub(2));
+        for (prefix, line, node) in lines.iter().take(room) {
+            // cut before coloring, the escape codes take no room on screen
+            let line = match columns {
+                Some(columns) => {
+                    let width = columns.saturating_sub(prefix.chars().count());
+                    line.chars().take(width).collect::<String>()
+                }
+                None => line.clone(),
+            };
+            let line = match (node.spawned, node.exited) {
+                (_, Some(_)) => line.dimmed(),
+                (Some(_), None) => Logger::colorize_by_uid(line, node.uid).bold(),
+                (None, None) => Logger::colorize_by_uid(line, node.uid),
+            };
+            writeln!(out, "{}{}", prefix, line)?;
+        }
+        if lines.len() > room {
+            writeln!(
+                out,
+                "{}",
+                format!("... and {} more", lines.len() - room).dimmed()
+            )?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    // (prefix, line, node) for the node and everything below it
+    fn lines<'a>(
+        &'a self,
+        pid: u32,
+        indent: &str,
+        last: bool,
+        root: bool,
+        children: &FxHashMap<u32, Vec<u32>>,
+        lines: &mut Vec<(String, String, &'a Node)>,
+    ) {
+        let Some(node) = self.nodes.get(&pid) else {
+            return;
+        };
+        let branch = match (root, last) {
+            (true, _) => "",
+            (false, true) => "└─ ",
+            (false, false) => "├─ ",
+        };
+        let cmdline = if node.cmdline.is_empty() {
+            process_name(pid as i32)
+        } else {
+            node.cmdline.clone()
+        };
+        // one line per node, whatever the arguments hold
+        let cmdline = cmdline.replace(|c: char| c.is_control(), " ");
+        let mut line = format!("{} {}", pid, cmdline);
+        if let Some(spawned) = node.spawned {
+            line = format!("+ {} (started {})", line, Logger::plain_time(spawned));
+        }
+        if node.exited.is_some() {
+            line.push_str(" exited");
+        }
+        lines.push((format!("{}{}", indent, branch), line, node));
+
+        let Some(below) = children.get(&pid) else {
+            return;
+        };
+        let indent = match (root, last) {
+            (true, _) => indent.to_string(),
+            (false, true) => format!("{}   ", indent),
+            (false, false) => format!("{}│  ", indent),
+        };
+        for (i, &child) in below.iter().enumerate() {
+            self.lines(child, &indent, i + 1 == below.len(), false, children, lines);
+        }
+    }
+}
+
+// (rows, columns) of the terminal on stdout
+fn terminal_size() -> (Option<usize>, Option<usize>) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return (None, None);
+    }
+    let nonzero = |value: u16| (value > 0).then_some(value as usize);
+    (nonzero(size.ws_row), nonzero(size.ws_col))
+}
@@ -0,0 +1,119 @@
+use std::ffi::CString;
+use std::fs::Metadata;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use super::{
+    constants::{DELETED_EXE_SUFFIX, ROOT_UID},
+    event::{ProcessEvent, WritablePaths},
+};
+use crate::utils::users;
+
+// --analyze-writability, which files that root executes a less privileged user could change,
+// either the file itself or a directory above it that lets them replace it
+pub struct WritabilityAnalyzer {
+    uid: u32,
+    // None tests the current user with access(2), which also knows about ACLs and
+    // read-only mounts
+    groups: Option<Vec<u32>>,
+}
+
+impl WritabilityAnalyzer {
+    pub fn new(uid: Option<u32>) -> Self {
+        match uid {
+            Some(uid) => Self {
+                uid,
+                groups: Some(users::groups_of(uid)),
+            },
+            None => Self {
+                uid: unsafe { libc::getuid() },
+                groups: None,
+            },
+        }
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn analyze(&self, process: &ProcessEvent) -> Option<WritablePaths> {
+        if process.uid != Some(ROOT_UID) {
+            return None;
+        }
+
+        let mut paths = Vec::new();
+        for path in executed_paths(process) {
+            if let Some(writable) = self.writable_part(&path)
+                && !paths.contains(&writable)
+            {
+                paths.push(writable);
+            }
+        }
+        (!paths.is_empty()).then_some(WritablePaths {
+            uid: self.uid,
+            paths,
+        })
+    }
+
+    // the file, or the deepest directory above it whose entries the uid may rename
+    fn writable_part(&self, path: &Path) -> Option<PathBuf> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if self.can_write(path, &metadata) {
+            return Some(path.to_path_buf());
+        }
+        path.ancestors().skip(1).find_map(|dir| {
+            let metadata = std::fs::metadata(dir).ok()?;
+            // in /tmp and the like only the owner may rename what is inside
+            let sticky = metadata.mode() & libc::S_ISVTX != 0;
+            (self.can_write(dir, &metadata) && (!sticky || metadata.uid() == self.uid))
+                .then(|| dir.to_path_buf())
+        })
+    }
+
+    fn can_write(&self, path: &Path, metadata: &Metadata) -> bool {
+        let Some(groups) = &self.groups else {
+            let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+                return false;
+            };
+            return unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0;
+        };
+        let mode = metadata.mode();
+        if self.uid == ROOT_UID {
+            true
+        } else if metadata.uid() == self.uid {
+            mode & libc::S_IWUSR != 0
+        } else if groups.contains(&metadata.gid()) {
+            mode & libc::S_IWGRP != 0
+        } else {
+            mode & libc::S_IWOTH != 0
+        }
+    }
+}
+
+// the binary, the script an interpreter runs and argv[0] when it names a path relative to
+// the working directory
+fn executed_paths(process: &ProcessEvent) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    // a deleted binary can not be replaced any more
+    if let Some(exe) = &process.exe
+        && !exe.to_string_lossy().ends_with(DELETED_EXE_SUFFIX)
+    {
+        paths.push(exe.clone());
+    }
+    if let Some(script) = &process.script {
+        paths.push(script.path.clone());
+    }
+    let argv0 = process
+        .cmdline
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    if argv0.contains('/')
+        && !argv0.starts_with('/')
+        && let Some(cwd) = &process.cwd
+    {
+        paths.push(cwd.join(argv0));
+    }
+    paths
+}
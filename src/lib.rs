@@ -0,0 +1,958 @@
+pub mod core;
+pub mod monitoring;
+#[cfg(feature = "tokio")]
+pub mod stream;
+pub mod utils;
+
+use globset::Glob;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::core::config::Config;
+use crate::core::constants::{
+    DEFAULT_BURST_COUNT, DEFAULT_BURST_INTERVAL_MS, DEFAULT_RECURSIVE_DIRS,
+    DEFAULT_SCAN_INTERVAL_MS, HIDDEN_CHECK_INTERVAL_MS, LOADER_CONFIG_PATHS,
+    NETWORK_POLL_INTERVAL_MS, PERSISTENCE_HOME_PATHS, PERSISTENCE_PATHS, SESSION_POLL_INTERVAL_MS,
+    UTMP_FILE,
+};
+use crate::core::error::{Result, RsSpyError};
+use crate::core::event::{Event, FileCategory};
+use crate::core::logger::Logger;
+#[cfg(feature = "dbus")]
+use crate::monitoring::dbus::{self, BusSniffer, DBusScanner};
+#[cfg(feature = "dbus")]
+use crate::monitoring::polkit::PolkitMonitor;
+use crate::monitoring::{
+    audit::AuditListener,
+    contents::ContentWatcher,
+    fanotify::FanotifyWatcher,
+    filesystem::{FsWatcher, WatchReport},
+    hidden::HiddenProcessDetector,
+    network::NetworkMonitor,
+    process::ProcessScanner,
+    reactor::{Reactor, Waker},
+    scanner::Scanner,
+    sessions::SessionMonitor,
+    udev::UdevListener,
+};
+use crate::utils::lru::CacheLimits;
+use crate::utils::preserve::ExePreserver;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::throttle::Priority;
+use crate::utils::users;
+
+// sets up the collectors (procfs scanner, inotify or fanotify, dbus, audit) and hands out
+// everything they see as one stream of events, the defaults match the rspy binary
+pub struct RspyBuilder {
+    recursive_dirs: Vec<PathBuf>,
+    direct_dirs: Vec<PathBuf>,
+    excluded_paths: Vec<PathBuf>,
+    watch_exclude: Vec<Glob>,
+    max_watches: Option<usize>,
+    strict_watches: Option<u8>,
+    watch_events: Option<u32>,
+    watch_masks: Vec<(PathBuf, u32)>,
+    filesystem_events: bool,
+    low_resource: bool,
+    priority: Option<Priority>,
+    cpu_limit: Option<u32>,
+    scan_jitter: Option<u32>,
+    cache_limits: CacheLimits,
+    debug: bool,
+    fanotify: bool,
+    audit: bool,
+    udev: bool,
+    watch_loader: bool,
+    diff_files: Vec<PathBuf>,
+    persistence_watch: bool,
+    dbus: bool,
+    dbus_only: bool,
+    dbus_interval: Option<Duration>,
+    dbus_sniff: bool,
+    polkit: bool,
+    scan_interval: Option<Duration>,
+    burst_count: u32,
+    burst_interval: Duration,
+    show_tree: bool,
+    show_exits: bool,
+    hash_exe: bool,
+    show_fds: bool,
+    show_cgroup: bool,
+    show_caps: bool,
+    show_cwd: bool,
+    show_unit: bool,
+    highlight_tmp_exec: bool,
+    check_libraries: bool,
+    scan_threads: bool,
+    track_cmdline: bool,
+    preserver: Option<ExePreserver>,
+    detect_hidden: bool,
+    network: bool,
+    unix_sockets: bool,
+    sessions: bool,
+    async_runtime: bool,
+    running: Arc<AtomicBool>,
+}
+
+impl RspyBuilder {
+    pub fn new() -> Self {
+        Self {
+            recursive_dirs: DEFAULT_RECURSIVE_DIRS.iter().map(PathBuf::from).collect(),
+            direct_dirs: Vec::new(),
+            excluded_paths: Vec::new(),
+            watch_exclude: Vec::new(),
+            max_watches: None,
+            strict_watches: None,
+            watch_events: None,
+            watch_masks: Vec::new(),
+            filesystem_events: true,
+            low_resource: false,
+            priority: None,
+            cpu_limit: None,
+            scan_jitter: None,
+            cache_limits: CacheLimits::default(),
+            debug: false,
+            fanotify: false,
+            audit: false,
+            udev: false,
+            watch_loader: true,
+            diff_files: Vec::new(),
+            persistence_watch: false,
+            dbus: false,
+            dbus_only: false,
+            dbus_interval: None,
+            dbus_sniff: false,
+            polkit: false,
+            scan_interval: Some(Duration::from_millis(DEFAULT_SCAN_INTERVAL_MS)),
+            burst_count: DEFAULT_BURST_COUNT,
+            burst_interval: Duration::from_millis(DEFAULT_BURST_INTERVAL_MS),
+            show_tree: false,
+            show_exits: false,
+            hash_exe: false,
+            show_fds: false,
+            show_cgroup: false,
+            show_caps: false,
+            show_cwd: false,
+            show_unit: false,
+            highlight_tmp_exec: true,
+            check_libraries: false,
+            scan_threads: false,
+            track_cmdline: false,
+            preserver: None,
+            detect_hidden: false,
+            network: false,
+            unix_sockets: false,
+            sessions: false,
+            async_runtime: false,
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new()
+            .recursive_dirs(
+                config
+                    .get_recursive_watch_dirs()
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            )
+            .direct_dirs(
+                config
+                    .get_direct_watch_dirs()
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            )
+            .watch_exclude(config.watch_exclude.clone())
+            .max_watches(config.max_watches)
+            .strict_watches(config.strict_watches)
+            .watch_events(config.watch_events())
+            .watch_masks(config.watch_masks())
+            .filesystem_events(config.emit_filesystem_events())
+            .low_resource(config.low_resource)
+            .priority(config.priority())
+            .cpu_limit(config.cpu_limit())
+            .scan_jitter(config.scan_jitter())
+            .cache_limits(config.cache_limits())
+            .debug(config.debug)
+            .fanotify(config.fanotify)
+            .audit(config.audit)
+            .udev(config.udev)
+            .watch_loader(config.watch_loader)
+            .diff_files(config.diff_files.clone())
+            .persistence_watch(config.persistence_watch)
+            .dbus(config.dbus)
+            .dbus_only(config.dbus_only)
+            .dbus_interval(config.dbus_interval())
+            .dbus_sniff(config.dbus_sniff)
+            .polkit(config.polkit)
+            .scan_interval(config.scan_interval())
+            .burst(config.burst_count(), config.burst_interval())
+            .show_tree(config.tree)
+            .show_exits(config.track_exits())
+            .hash_exe(config.hash_exe)
+            .show_fds(config.show_fds)
+            .show_cgroup(config.show_cgroup)
+            .show_caps(config.show_caps)
+            .show_cwd(config.show_cwd)
+            .show_unit(config.show_unit)
+            .highlight_tmp_exec(config.highlight_tmp_exec)
+            .check_libraries(config.check_libraries)
+            .scan_threads(config.scan_threads)
+            .track_cmdline(config.track_cmdline)
+            .detect_hidden(config.detect_hidden)
+            .network(config.network)
+            .unix_sockets(config.unix_sockets)
+            .sessions(config.sessions)
+            .async_runtime(config.async_runtime)
+    }
+
+    pub fn recursive_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.recursive_dirs = dirs;
+        self
+    }
+
+    pub fn direct_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.direct_dirs = dirs;
+        self
+    }
+
+    // files the embedding program writes itself, watching them would feed back into scans
+    pub fn excluded_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.excluded_paths = paths;
+        self
+    }
+
+    // directories matching these are not watched and events on matching paths are dropped
+    pub fn watch_exclude(mut self, globs: Vec<Glob>) -> Self {
+        self.watch_exclude = globs;
+        self
+    }
+
+    // directories beyond it, the deepest first, are not watched
+    pub fn max_watches(mut self, max_watches: Option<usize>) -> Self {
+        self.max_watches = max_watches;
+        self
+    }
+
+    // fails start() when fewer percent of the directories could be watched
+    pub fn strict_watches(mut self, minimum: Option<u8>) -> Self {
+        self.strict_watches = minimum;
+        self
+    }
+
+    // inotify mask for every watch, see monitoring::filesystem::parse_watch_events
+    pub fn watch_events(mut self, mask: Option<u32>) -> Self {
+        self.watch_events = mask;
+        self
+    }
+
+    // masks for single directories and their subdirectories, taking precedence over
+    // watch_events
+    pub fn watch_masks(mut self, masks: Vec<(PathBuf, u32)>) -> Self {
+        self.watch_masks = masks;
+        self
+    }
+
+    // without it the watches only trigger scans and the only Event::Fs produced are changes
+    // to single watched files
+    pub fn filesystem_events(mut self, enabled: bool) -> Self {
+        self.filesystem_events = enabled;
+        self
+    }
+
+    pub fn low_resource(mut self, enabled: bool) -> Self {
+        self.low_resource = enabled;
+        self
+    }
+
+    // niceness and io priority of the collector threads, the thread that reads the events
+    // is left alone
+    pub fn priority(mut self, priority: Option<Priority>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // percentage of one cpu, scans are held off while rspy as a whole uses more
+    pub fn cpu_limit(mut self, percent: Option<u32>) -> Self {
+        self.cpu_limit = percent;
+        self
+    }
+
+    // entries kept by the exe hash cache and the dbus scanner before the least recently
+    // used are evicted
+    pub fn cache_limits(mut self, limits: CacheLimits) -> Self {
+        self.cache_limits = limits;
+        self
+    }
+
+    pub fn debug(mut self, enabled: bool) -> Self {
+        self.debug = enabled;
+        self
+    }
+
+    pub fn fanotify(mut self, enabled: bool) -> Self {
+        self.fanotify = enabled;
+        self
+    }
+
+    pub fn audit(mut self, enabled: bool) -> Self {
+        self.audit = enabled;
+        self
+    }
+
+    pub fn udev(mut self, enabled: bool) -> Self {
+        self.udev = enabled;
+        self
+    }
+
+    pub fn watch_loader(mut self, enabled: bool) -> Self {
+        self.watch_loader = enabled;
+        self
+    }
+
+    pub fn diff_files(mut self, paths: Vec<PathBuf>) -> Self {
+        self.diff_files = paths;
+        self
+    }
+
+    pub fn persistence_watch(mut self, enabled: bool) -> Self {
+        self.persistence_watch = enabled;
+        self
+    }
+
+    pub fn dbus(mut self, enabled: bool) -> Self {
+        self.dbus = enabled;
+        self
+    }
+
+    pub fn dbus_only(mut self, enabled: bool) -> Self {
+        self.dbus_only = enabled;
+        self
+    }
+
+    pub fn dbus_interval(mut self, interval: Option<Duration>) -> Self {
+        self.dbus_interval = interval;
+        self
+    }
+
+    pub fn dbus_sniff(mut self, enabled: bool) -> Self {
+        self.dbus_sniff = enabled;
+        self
+    }
+
+    pub fn polkit(mut self, enabled: bool) -> Self {
+        self.polkit = enabled;
+        self
+    }
+
+    // None only scans when the filesystem watches fire
+    pub fn scan_interval(mut self, interval: Option<Duration>) -> Self {
+        self.scan_interval = interval;
+        self
+    }
+
+    // percent of the interval each wait between scans is moved by at random
+    pub fn scan_jitter(mut self, percent: Option<u32>) -> Self {
+        self.scan_jitter = percent;
+        self
+    }
+
+    pub fn burst(mut self, count: u32, interval: Duration) -> Self {
+        self.burst_count = count;
+        self.burst_interval = interval;
+        self
+    }
+
+    pub fn show_tree(mut self, enabled: bool) -> Self {
+        self.show_tree = enabled;
+        self
+    }
+
+    pub fn show_exits(mut self, enabled: bool) -> Self {
+        self.show_exits = enabled;
+        self
+    }
+
+    pub fn hash_exe(mut self, enabled: bool) -> Self {
+        self.hash_exe = enabled;
+        self
+    }
+
+    pub fn show_fds(mut self, enabled: bool) -> Self {
+        self.show_fds = enabled;
+        self
+    }
+
+    pub fn show_cgroup(mut self, enabled: bool) -> Self {
+        self.show_cgroup = enabled;
+        self
+    }
+
+    pub fn show_caps(mut self, enabled: bool) -> Self {
+        self.show_caps = enabled;
+        self
+    }
+
+    pub fn show_cwd(mut self, enabled: bool) -> Self {
+        self.show_cwd = enabled;
+        self
+    }
+
+    pub fn show_unit(mut self, enabled: bool) -> Self {
+        self.show_unit = enabled;
+        self
+    }
+
+    // flags binaries below /tmp, /dev/shm or any world-writable directory
+    pub fn highlight_tmp_exec(mut self, enabled: bool) -> Self {
+        self.highlight_tmp_exec = enabled;
+        self
+    }
+
+    // compares the mapped libraries of new processes with what their binary needs
+    pub fn check_libraries(mut self, enabled: bool) -> Self {
+        self.check_libraries = enabled;
+        self
+    }
+
+    pub fn scan_threads(mut self, enabled: bool) -> Self {
+        self.scan_threads = enabled;
+        self
+    }
+
+    pub fn track_cmdline(mut self, enabled: bool) -> Self {
+        self.track_cmdline = enabled;
+        self
+    }
+
+    pub fn preserver(mut self, preserver: Option<ExePreserver>) -> Self {
+        self.preserver = preserver;
+        self
+    }
+
+    pub fn detect_hidden(mut self, enabled: bool) -> Self {
+        self.detect_hidden = enabled;
+        self
+    }
+
+    pub fn network(mut self, enabled: bool) -> Self {
+        self.network = enabled;
+        self
+    }
+
+    pub fn unix_sockets(mut self, enabled: bool) -> Self {
+        self.unix_sockets = enabled;
+        self
+    }
+
+    pub fn sessions(mut self, enabled: bool) -> Self {
+        self.sessions = enabled;
+        self
+    }
+
+    // runs the collectors as tasks on one tokio thread, see stream::EventStream
+    pub fn async_runtime(mut self, enabled: bool) -> Self {
+        self.async_runtime = enabled;
+        self
+    }
+
+    // shares a stop flag with the caller, e.g. one that a signal handler clears
+    pub fn running(mut self, running: Arc<AtomicBool>) -> Self {
+        self.running = running;
+        self
+    }
+
+    // without dbus both --dbus and --dbus-only carry on with procfs scans alone
+    fn check_dbus(&mut self, backends: &mut Vec<Backend>) {
+        if !self.dbus && !self.dbus_only {
+            return;
+        }
+        #[cfg(feature = "dbus")]
+        let error = (!DBusScanner::is_available()).then_some("the system bus is not available");
+        #[cfg(not(feature = "dbus"))]
+        let error = Some("rspy was built without dbus support");
+        let Some(error) = error else {
+            backends.push(Backend::active("dbus"));
+            return;
+        };
+        Logger::error(format!("{}, continuing with procfs scans", error));
+        backends.push(Backend::failed("dbus", error));
+        self.dbus = false;
+        self.dbus_only = false;
+    }
+
+    // the files the content watcher keeps a copy of, none with --dbus-only
+    fn content_files(&self) -> Vec<(PathBuf, FileCategory)> {
+        if self.dbus_only {
+            return Vec::new();
+        }
+        let mut files = Vec::new();
+        if self.watch_loader {
+            files.extend(
+                LOADER_CONFIG_PATHS
+                    .iter()
+                    .map(|path| (PathBuf::from(path), FileCategory::Loader)),
+            );
+        }
+        if self.persistence_watch {
+            let homes = users::home_directories();
+            let below_homes = homes.iter().flat_map(|home| {
+                PERSISTENCE_HOME_PATHS
+                    .iter()
+                    .map(move |path| home.join(path))
+            });
+            files.extend(
+                PERSISTENCE_PATHS
+                    .iter()
+                    .map(PathBuf::from)
+                    .chain(below_homes)
+                    .map(|path| (path, FileCategory::Persistence)),
+            );
+        }
+        files.extend(
+            self.diff_files
+                .iter()
+                .map(|path| (path.clone(), FileCategory::Diff)),
+        );
+        files
+    }
+
+    // only the event channel and epoll are required, a collector that fails to start is
+    // logged and listed in Rspy::backends while the others keep running
+    pub fn start(mut self) -> Result<Rspy> {
+        if self.async_runtime {
+            #[cfg(feature = "tokio")]
+            return stream::start_on_thread(self);
+            #[cfg(not(feature = "tokio"))]
+            return Err("rspy was built without the tokio feature".into());
+        }
+        let mut backends = Vec::new();
+        self.check_dbus(&mut backends);
+        let content_files = self.content_files();
+
+        let (tx, rx) = mpsc::channel();
+        let (trigger_tx, trigger_rx) = mpsc::channel();
+        let dbus_trigger = trigger_tx.clone();
+        let mut workers = Vec::new();
+        // inotify, the audit and the uevent socket share one epoll thread
+        let mut reactor = Reactor::new()?;
+
+        let mut fanotify_watcher = if self.fanotify && !self.dbus_only {
+            match FanotifyWatcher::new(
+                tx.clone(),
+                trigger_tx.clone(),
+                self.recursive_dirs.clone(),
+                self.direct_dirs.clone(),
+                self.filesystem_events,
+                self.low_resource,
+                self.debug,
+            )
+            .and_then(|mut watcher| {
+                watcher.set_excluded_paths(&self.excluded_paths);
+                watcher.set_watch_exclude(&self.watch_exclude)?;
+                Ok(watcher)
+            }) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    Logger::error(format!(
+                        "fanotify is not available ({}), falling back to inotify",
+                        e
+                    ));
+                    backends.push(Backend::failed("fanotify", e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(watcher) = fanotify_watcher.as_mut()
+            && let Err(e) = watcher.setup_marks()
+        {
+            Logger::error(format!(
+                "failed to setup fanotify marks ({}), falling back to inotify",
+                e
+            ));
+            backends.push(Backend::failed("fanotify", e));
+            fanotify_watcher = None;
+        }
+        if let Some(watcher) = fanotify_watcher.as_ref() {
+            check_watch_coverage(self.strict_watches, Some(watcher.watch_report()))?;
+        }
+
+        let mut fs_watcher = if !self.dbus_only && fanotify_watcher.is_none() {
+            let watcher = FsWatcher::new(
+                tx.clone(),
+                trigger_tx,
+                self.recursive_dirs,
+                self.direct_dirs,
+                self.filesystem_events,
+                self.low_resource,
+                self.debug,
+            )
+            .and_then(|mut watcher| {
+                watcher.set_excluded_paths(&self.excluded_paths);
+                watcher.set_watch_exclude(&self.watch_exclude)?;
+                watcher.set_max_watches(self.max_watches);
+                watcher.set_watch_events(self.watch_events);
+                watcher.set_watch_masks(self.watch_masks);
+                watcher.setup_watches()?;
+                Ok(watcher)
+            });
+            let watcher = match watcher {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    Logger::error(format!(
+                        "failed to setup filesystem watches ({}), continuing with procfs scans alone",
+                        e
+                    ));
+                    backends.push(Backend::failed("inotify", e));
+                    None
+                }
+            };
+            check_watch_coverage(
+                self.strict_watches,
+                watcher.as_ref().map(FsWatcher::watch_report),
+            )?;
+            watcher
+        } else {
+            None
+        };
+
+        if self.audit {
+            match AuditListener::new(tx.clone()) {
+                Ok(mut listener) => {
+                    listener.set_highlight_tmp_exec(self.highlight_tmp_exec);
+                    listener.set_check_libraries(self.check_libraries);
+                    reactor.register(Box::new(listener))?;
+                    backends.push(Backend::active("audit"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "audit netlink is not available ({}), continuing without it",
+                        e
+                    ));
+                    backends.push(Backend::failed("audit", e));
+                }
+            }
+        }
+
+        if self.udev {
+            match UdevListener::new(tx.clone()) {
+                Ok(listener) => {
+                    reactor.register(Box::new(listener))?;
+                    backends.push(Backend::active("udev"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "uevent netlink is not available ({}), continuing without it",
+                        e
+                    ));
+                    backends.push(Backend::failed("udev", e));
+                }
+            }
+        }
+
+        if !content_files.is_empty() {
+            match ContentWatcher::new(tx.clone(), &content_files) {
+                Ok(watcher) => {
+                    reactor.register(Box::new(watcher))?;
+                    backends.push(Backend::active("contents"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "failed to watch the contents of {} files ({}), continuing without it",
+                        content_files.len(),
+                        e
+                    ));
+                    backends.push(Backend::failed("contents", e));
+                }
+            }
+        }
+
+        #[cfg(feature = "dbus")]
+        if self.dbus_sniff {
+            match BusSniffer::new(tx.clone()).start(Arc::clone(&self.running), self.priority) {
+                Ok(worker) => {
+                    workers.push(worker);
+                    backends.push(Backend::active("dbus-sniff"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "the system bus can not be monitored ({}), continuing without --dbus-sniff",
+                        e
+                    ));
+                    backends.push(Backend::failed("dbus-sniff", e));
+                }
+            }
+        }
+
+        #[cfg(feature = "dbus")]
+        if self.polkit {
+            match PolkitMonitor::new(tx.clone()).start(Arc::clone(&self.running), self.priority) {
+                Ok(worker) => {
+                    workers.push(worker);
+                    backends.push(Backend::active("polkit"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "polkit can not be monitored ({}), continuing without --polkit",
+                        e
+                    ));
+                    backends.push(Backend::failed("polkit", e));
+                }
+            }
+        }
+
+        if self.network || self.unix_sockets {
+            let mut monitor =
+                NetworkMonitor::new(tx.clone(), Duration::from_millis(NETWORK_POLL_INTERVAL_MS));
+            monitor.set_inet(self.network);
+            monitor.set_unix_sockets(self.unix_sockets);
+            workers.push(monitor.start(Arc::clone(&self.running), self.priority));
+            // one monitor polls both tables, each is listed on its own
+            if self.network {
+                backends.push(Backend::active("network"));
+            }
+            if self.unix_sockets {
+                backends.push(Backend::active("unix-sockets"));
+            }
+        }
+
+        if self.sessions {
+            let monitor = SessionMonitor::new(
+                tx.clone(),
+                PathBuf::from(UTMP_FILE),
+                Duration::from_millis(SESSION_POLL_INTERVAL_MS),
+            );
+            workers.push(monitor.start(Arc::clone(&self.running), self.priority));
+            backends.push(Backend::active("sessions"));
+        }
+
+        let mut process_scanner = ProcessScanner::new(tx.clone());
+        process_scanner.set_show_tree(self.show_tree);
+        process_scanner.set_show_exits(self.show_exits);
+        process_scanner.set_hash_exe(self.hash_exe, self.cache_limits.hashes);
+        process_scanner.set_show_fds(self.show_fds);
+        process_scanner.set_show_cgroup(self.show_cgroup);
+        process_scanner.set_show_caps(self.show_caps);
+        process_scanner.set_show_cwd(self.show_cwd);
+        process_scanner.set_highlight_tmp_exec(self.highlight_tmp_exec);
+        process_scanner.set_check_libraries(self.check_libraries);
+        process_scanner.set_scan_threads(self.scan_threads);
+        process_scanner.set_track_cmdline(self.track_cmdline);
+        process_scanner.set_preserver(self.preserver);
+        #[cfg(feature = "dbus")]
+        let units = dbus::unit_resolver(self.show_unit);
+        #[cfg(feature = "dbus")]
+        process_scanner.set_units(units.clone());
+
+        let mut scanner = Scanner::new(
+            self.scan_interval,
+            trigger_rx,
+            self.dbus_only,
+            self.dbus,
+            self.dbus_interval,
+            tx,
+            process_scanner,
+        );
+        scanner.set_burst(self.burst_count, self.burst_interval);
+        scanner.set_jitter(self.scan_jitter);
+        scanner.set_cpu_limit(self.cpu_limit);
+        scanner.set_max_dbus_pids(self.cache_limits.pids);
+        scanner.set_dbus_show_exits(self.show_exits);
+        scanner.set_dbus_highlight_tmp_exec(self.highlight_tmp_exec);
+        scanner.set_dbus_check_libraries(self.check_libraries);
+        scanner.set_dbus_trigger(dbus_trigger);
+        #[cfg(feature = "dbus")]
+        scanner.set_dbus_units(units);
+        scanner.set_active(true);
+        let scanning = scanner.activity();
+        workers.extend(scanner.start(Arc::clone(&self.running), self.priority));
+        if !self.dbus_only {
+            backends.push(Backend::active("procfs"));
+        }
+
+        if self.detect_hidden {
+            let detector = HiddenProcessDetector::new(
+                Duration::from_millis(HIDDEN_CHECK_INTERVAL_MS),
+                self.dbus || self.dbus_only,
+            );
+            workers.push(detector.start(Arc::clone(&self.running), self.priority));
+            backends.push(Backend::active("hidden"));
+        }
+
+        let mut watch_requests = fs_watcher.as_mut().map(FsWatcher::watch_requests);
+        if let Some(watcher) = fs_watcher {
+            match reactor.register(Box::new(watcher)) {
+                Ok(()) => backends.push(Backend::active("inotify")),
+                Err(e) => {
+                    Logger::error(format!(
+                        "failed to start filesystem watcher ({}), continuing with procfs scans alone",
+                        e
+                    ));
+                    backends.push(Backend::failed("inotify", e));
+                    watch_requests = None;
+                }
+            }
+        }
+
+        let mut wakers = Vec::new();
+        if !reactor.is_empty() {
+            wakers.push(reactor.waker());
+            workers.push(reactor.start(Arc::clone(&self.running), self.priority));
+        }
+
+        if let Some(watcher) = fanotify_watcher {
+            match watcher.start_watching(Arc::clone(&self.running), self.priority) {
+                Ok(worker) => {
+                    workers.push(worker);
+                    backends.push(Backend::active("fanotify"));
+                }
+                Err(e) => {
+                    Logger::error(format!("failed to start fanotify watcher: {}", e));
+                    backends.push(Backend::failed("fanotify", e));
+                }
+            }
+        }
+
+        Ok(Rspy {
+            events: rx,
+            running: self.running,
+            workers,
+            wakers,
+            watch_requests,
+            scanning: Some(scanning),
+            backends,
+        })
+    }
+}
+
+// --strict-watches, nothing is covered when the watcher did not start
+fn check_watch_coverage(minimum: Option<u8>, report: Option<WatchReport>) -> Result<()> {
+    let Some(minimum) = minimum else {
+        return Ok(());
+    };
+    let coverage = report.map_or(0.0, |report| report.coverage());
+    if coverage < f64::from(minimum) {
+        return Err(RsSpyError::Filesystem(format!(
+            "only {:.1}% of the directories to watch are watched, --strict-watches needs {}%",
+            coverage, minimum
+        )));
+    }
+    Ok(())
+}
+
+impl Default for RspyBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// a collector asked for and whether it came up
+#[derive(Clone, Debug)]
+pub struct Backend {
+    pub name: &'static str,
+    // why it is not running, None when it is
+    pub error: Option<String>,
+}
+
+impl Backend {
+    pub(crate) fn active(name: &'static str) -> Self {
+        Self { name, error: None }
+    }
+
+    pub(crate) fn failed(name: &'static str, error: impl std::fmt::Display) -> Self {
+        Self {
+            name,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+// running collectors, iterating blocks for the next event and ends after stop()
+pub struct Rspy {
+    events: Receiver<Event>,
+    running: Arc<AtomicBool>,
+    workers: Vec<Worker>,
+    // collectors blocked in epoll_wait only see the flag once woken
+    wakers: Vec<Waker>,
+    watch_requests: Option<Sender<PathBuf>>,
+    // the procfs scanner's switch
+    scanning: Option<Arc<AtomicBool>>,
+    backends: Vec<Backend>,
+}
+
+impl Rspy {
+    pub fn builder() -> RspyBuilder {
+        RspyBuilder::new()
+    }
+
+    pub fn events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> std::result::Result<Event, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    // stops and restarts procfs scans, the processes already seen stay known so the first
+    // scan after resuming only reports what started meanwhile and still runs. the other
+    // collectors keep delivering, the rspy binary drops their events while paused
+    pub fn set_paused(&self, paused: bool) {
+        if let Some(scanning) = &self.scanning {
+            scanning.store(!paused, Ordering::Relaxed);
+        }
+    }
+
+    // in the order they were started
+    pub fn backends(&self) -> &[Backend] {
+        &self.backends
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    // watches a directory (recursively) or file from now on, only with the inotify watcher
+    pub fn add_watch(&self, path: PathBuf) -> Result<()> {
+        let Some(requests) = &self.watch_requests else {
+            return Err("adding watches needs the inotify watcher".into());
+        };
+        requests
+            .send(path)
+            .map_err(|_| "the inotify watcher is not running")?;
+        for waker in &self.wakers {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    // the collectors notice within a poll interval, ones stuck in a slow call are left behind
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for waker in &self.wakers {
+            waker.wake();
+        }
+        shutdown::join(std::mem::take(&mut self.workers));
+    }
+}
+
+impl Iterator for Rspy {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for Rspy {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,476 @@
+use libc::{self, AF_NETLINK, NETLINK_AUDIT, SOCK_CLOEXEC, SOCK_RAW, nlmsghdr, sockaddr_nl};
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::{
+    error::Result,
+    event::{Event, ProcessEvent, ProcessSource},
+    logger::Logger,
+};
+use crate::monitoring::process::{exe_anomalies, exe_in_writable_dir, exe_setid_bits, script_of};
+use crate::monitoring::reactor::Source;
+use crate::utils::libraries;
+use crate::utils::process::Environ;
+
+const BUFFER_SIZE: usize = 16384;
+const MAX_PENDING_RECORDS: usize = 1024;
+
+// read-only multicast group, joining it does not interfere with a running auditd
+const AUDIT_NLGRP_READLOG: u32 = 1;
+
+const AUDIT_SYSCALL: u16 = 1300;
+const AUDIT_CWD: u16 = 1307;
+const AUDIT_EXECVE: u16 = 1309;
+const AUDIT_EOE: u16 = 1320;
+
+#[derive(Default)]
+struct PendingExec {
+    observed_at: Option<SystemTime>,
+    pid: Option<u32>,
+    ppid: Option<u32>,
+    uid: Option<u32>,
+    euid: Option<u32>,
+    gid: Option<u32>,
+    exe: Option<PathBuf>,
+    success: bool,
+    cwd: Option<PathBuf>,
+    // the fields of every EXECVE record, a long argv is spread over several of them
+    execve: Option<FxHashMap<String, String>>,
+}
+
+pub struct AuditListener {
+    fd: RawFd,
+    sender: Sender<Event>,
+    // by serial, which only grows, so the first entry is the oldest
+    pending: BTreeMap<u64, PendingExec>,
+    buffer: Vec<u8>,
+    highlight_tmp_exec: bool,
+    check_libraries: bool,
+}
+
+impl AuditListener {
+    // needs CAP_AUDIT_READ, execve records also need an audit rule such as
+    // `auditctl -a always,exit -F arch=b64 -S execve`
+    pub fn new(sender: Sender<Event>) -> Result<Self> {
+        let fd = unsafe { libc::socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_AUDIT) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut addr: sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = AF_NETLINK as u16;
+        addr.nl_groups = AUDIT_NLGRP_READLOG;
+
+        let result = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<sockaddr_nl>() as u32,
+            )
+        };
+        if result == -1 {
+            let err = io::Error::last_os_error();
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(err.into());
+        }
+
+        Ok(Self {
+            fd,
+            sender,
+            pending: BTreeMap::new(),
+            buffer: vec![0; BUFFER_SIZE],
+            highlight_tmp_exec: false,
+            check_libraries: false,
+        })
+    }
+
+    pub fn set_highlight_tmp_exec(&mut self, highlight_tmp_exec: bool) {
+        self.highlight_tmp_exec = highlight_tmp_exec;
+    }
+
+    pub fn set_check_libraries(&mut self, check_libraries: bool) {
+        self.check_libraries = check_libraries;
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // reads and handles one datagram, recv flags allow non-blocking reads from async callers
+    pub fn receive(&mut self, flags: libc::c_int) -> io::Result<()> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        let result = self.receive_into(&mut buffer, flags);
+        self.buffer = buffer;
+        result
+    }
+
+    fn receive_into(&mut self, buffer: &mut [u8], flags: libc::c_int) -> io::Result<()> {
+        let read_size = unsafe {
+            libc::recv(
+                self.fd,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                flags,
+            )
+        };
+        if read_size < 0 {
+            let err = io::Error::last_os_error();
+            // the socket buffer overflowed, some records were lost
+            if err.raw_os_error() == Some(libc::ENOBUFS) {
+                Logger::debug("audit netlink buffer overflowed, records were dropped");
+                return Ok(());
+            }
+            return Err(err);
+        }
+
+        self.handle_datagram(&buffer[..read_size as usize]);
+        Ok(())
+    }
+
+    // kauditd sends one record per datagram and sets nlmsg_len to the payload length
+    // only (see kauditd_send_multicast_skb), so the header length is not trusted here
+    fn handle_datagram(&mut self, datagram: &[u8]) {
+        let header_size = std::mem::size_of::<nlmsghdr>();
+        if datagram.len() <= header_size {
+            return;
+        }
+
+        let header = unsafe { std::ptr::read_unaligned(datagram.as_ptr() as *const nlmsghdr) };
+        self.handle_record(header.nlmsg_type, &datagram[header_size..]);
+    }
+
+    fn handle_record(&mut self, record_type: u16, raw: &[u8]) {
+        if !matches!(
+            record_type,
+            AUDIT_SYSCALL | AUDIT_CWD | AUDIT_EXECVE | AUDIT_EOE
+        ) {
+            return;
+        }
+
+        let text = String::from_utf8_lossy(raw);
+        let text = text.trim_end_matches('\0').trim();
+        let Some((observed_at, serial, body)) = parse_header(text) else {
+            return;
+        };
+
+        if record_type == AUDIT_EOE {
+            if let Some(pending) = self.pending.remove(&serial) {
+                self.emit(pending);
+            }
+            return;
+        }
+
+        if !self.pending.contains_key(&serial) && self.pending.len() >= MAX_PENDING_RECORDS {
+            // records whose EOE never arrived, the oldest serials go first
+            self.pending.pop_first();
+        }
+
+        let fields = parse_fields(body);
+        let pending = self.pending.entry(serial).or_default();
+        pending.observed_at = Some(observed_at);
+
+        match record_type {
+            AUDIT_SYSCALL => {
+                pending.pid = field_number(&fields, "pid");
+                pending.ppid = field_number(&fields, "ppid");
+                pending.uid = field_number(&fields, "uid");
+                pending.euid = field_number(&fields, "euid");
+                pending.gid = field_number(&fields, "gid");
+                pending.exe = field_string(&fields, "exe").map(PathBuf::from);
+                pending.success = fields.get("success").is_some_and(|v| *v == "yes");
+            }
+            AUDIT_CWD => {
+                pending.cwd = field_string(&fields, "cwd").map(PathBuf::from);
+            }
+            AUDIT_EXECVE => {
+                pending.execve.get_or_insert_default().extend(
+                    fields
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string())),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn emit(&self, pending: PendingExec) {
+        let (Some(pid), Some(execve), true) = (pending.pid, pending.execve, pending.success) else {
+            return;
+        };
+        let argv = parse_argv(&execve);
+
+        // the process may be gone already, then this knows nothing
+        let environ = Environ::read(pid);
+        let (exe_deleted, exe_mismatch) = exe_anomalies(
+            pending.exe.as_deref(),
+            Some(&argv),
+            pending.cwd.as_deref(),
+            environ.as_ref(),
+        );
+        // the process may be gone by now, the binary usually is not
+        let (exe_setuid, exe_setgid) = pending
+            .exe
+            .as_deref()
+            .map_or((false, false), exe_setid_bits);
+        let exe_in_writable_dir =
+            self.highlight_tmp_exec && exe_in_writable_dir(pending.exe.as_deref());
+        let script = script_of(Some(&argv), pending.cwd.as_deref());
+        let loader_env = libraries::loader_env(environ.as_ref());
+        let unexpected_libraries = if self.check_libraries {
+            libraries::unexpected(pid)
+        } else {
+            Vec::new()
+        };
+
+        let event = ProcessEvent {
+            ppid: pending.ppid,
+            uid: pending.uid,
+            euid: pending.euid,
+            gid: pending.gid,
+            exe: pending.exe,
+            exe_deleted,
+            exe_mismatch,
+            cwd: pending.cwd,
+            observed_at: pending.observed_at.unwrap_or_else(SystemTime::now),
+            exe_setuid,
+            exe_setgid,
+            exe_in_writable_dir,
+            loader_env,
+            unexpected_libraries,
+            script,
+            ..ProcessEvent::new(ProcessSource::Audit, pid, argv.join(" "))
+        };
+
+        if let Err(e) = self.sender.send(Event::Process(Box::new(event))) {
+            Logger::error(format!("failed to send audit event: {}", e));
+        }
+    }
+}
+
+// e.g. "audit(1700000000.123:456): argc=2 a0="ls" a1="-la""
+fn parse_header(text: &str) -> Option<(SystemTime, u64, &str)> {
+    let rest = text.strip_prefix("audit(")?;
+    let (stamp, body) = rest.split_once("):")?;
+    let (time, serial) = stamp.split_once(':')?;
+    let (secs, fraction) = time.split_once('.').unwrap_or((time, ""));
+
+    let observed_at = UNIX_EPOCH
+        + Duration::from_secs(secs.parse().ok()?)
+        + Duration::from_nanos(parse_fraction(fraction).unwrap_or(0));
+    Some((observed_at, serial.parse().ok()?, body))
+}
+
+// the digits after the dot as nanoseconds, the kernel prints milliseconds today
+fn parse_fraction(fraction: &str) -> Option<u64> {
+    let digits = fraction.get(..9).unwrap_or(fraction);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value: u64 = digits.parse().ok()?;
+    Some(value * 10u64.pow(9 - digits.len() as u32))
+}
+
+// quoted values never contain spaces, anything unusual is hex encoded instead
+fn parse_fields(body: &str) -> FxHashMap<&str, &str> {
+    body.split_whitespace()
+        .filter_map(|field| field.split_once('='))
+        .collect()
+}
+
+fn field_number(fields: &FxHashMap<&str, &str>, key: &str) -> Option<u32> {
+    fields.get(key).and_then(|value| value.parse().ok())
+}
+
+fn field_string(fields: &FxHashMap<&str, &str>, key: &str) -> Option<String> {
+    fields.get(key).and_then(|value| decode_value(value))
+}
+
+fn decode_value(value: &str) -> Option<String> {
+    if let Some(quoted) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return Some(quoted.to_string());
+    }
+    if value == "(null)" {
+        return None;
+    }
+    decode_hex(value)
+}
+
+fn decode_hex(value: &str) -> Option<String> {
+    if !value.is_ascii() || !value.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn parse_argv(fields: &FxHashMap<String, String>) -> Vec<String> {
+    let argc: usize = fields
+        .get("argc")
+        .and_then(|argc| argc.parse().ok())
+        .unwrap_or(0);
+
+    (0..argc)
+        .map(|i| {
+            if let Some(value) = fields.get(format!("a{}", i).as_str()) {
+                return decode_value(value).unwrap_or_default();
+            }
+
+            // long arguments are split into hex chunks: a1_len=20000 a1[0]=... a1[1]=...
+            let mut hex = String::new();
+            let mut chunk = 0;
+            while let Some(part) = fields.get(format!("a{}[{}]", i, chunk).as_str()) {
+                hex.push_str(part);
+                chunk += 1;
+            }
+            decode_hex(&hex).unwrap_or_default()
+        })
+        .collect()
+}
+
+impl Source for AuditListener {
+    fn name(&self) -> &'static str {
+        "audit listener"
+    }
+
+    fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    fn on_readable(&mut self) -> io::Result<()> {
+        loop {
+            match self.receive(0) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for AuditListener {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // how handle_record collects the EXECVE records of one serial
+    fn execve_fields(records: &[&str]) -> FxHashMap<String, String> {
+        let mut fields = FxHashMap::default();
+        for record in records {
+            fields.extend(
+                parse_fields(record)
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string())),
+            );
+        }
+        fields
+    }
+
+    #[test]
+    fn header() {
+        let (observed_at, serial, body) =
+            parse_header("audit(1700000000.123:456): argc=1 a0=\"ls\"").unwrap();
+        assert_eq!(
+            observed_at,
+            UNIX_EPOCH + Duration::new(1_700_000_000, 123_000_000)
+        );
+        assert_eq!(serial, 456);
+        assert_eq!(body, " argc=1 a0=\"ls\"");
+
+        let (observed_at, serial, _) = parse_header("audit(1700000000:7): ").unwrap();
+        assert_eq!(observed_at, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        assert_eq!(serial, 7);
+
+        assert!(parse_header("type=EXECVE msg=audit(1:2): argc=0").is_none());
+        assert!(parse_header("audit(1700000000.123): argc=0").is_none());
+        assert!(parse_header("audit(x.123:456): argc=0").is_none());
+    }
+
+    #[test]
+    fn fraction_digits() {
+        let expected = [
+            ("1", 100_000_000),
+            ("12", 120_000_000),
+            ("123", 123_000_000),
+            ("1234", 123_400_000),
+            ("12345", 123_450_000),
+            ("123456", 123_456_000),
+            ("1234567", 123_456_700),
+            ("12345678", 123_456_780),
+            ("123456789", 123_456_789),
+            ("000000001", 1),
+        ];
+        for (fraction, nanos) in expected {
+            assert_eq!(parse_fraction(fraction), Some(nanos), "{}", fraction);
+        }
+    }
+
+    #[test]
+    fn fraction_truncated_or_invalid() {
+        assert_eq!(parse_fraction("1234567891"), Some(123_456_789));
+        assert_eq!(parse_fraction(""), None);
+        assert_eq!(parse_fraction("12a"), None);
+        assert_eq!(parse_fraction("-1"), None);
+        assert_eq!(parse_fraction("12345678\u{e9}"), None);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(
+            decode_hex("2F746D702F61206220").as_deref(),
+            Some("/tmp/a b ")
+        );
+        assert_eq!(decode_hex("").as_deref(), Some(""));
+        assert_eq!(decode_hex("414"), None);
+        assert_eq!(decode_hex("4G"), None);
+        assert_eq!(decode_hex("\u{e9}\u{e9}"), None);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(decode_value("\"/bin/ls\"").as_deref(), Some("/bin/ls"));
+        assert_eq!(decode_value("2F62696E"), Some("/bin".to_string()));
+        assert_eq!(decode_value("(null)"), None);
+    }
+
+    #[test]
+    fn argv() {
+        let fields = execve_fields(&[" argc=3 a0=\"sh\" a1=\"-c\" a2=6563686F2068690A"]);
+        assert_eq!(parse_argv(&fields), ["sh", "-c", "echo hi\n"]);
+    }
+
+    #[test]
+    fn argv_split_across_records() {
+        // a long argument comes in chunks and the chunks in several records
+        let fields = execve_fields(&[
+            " argc=3 a0=\"cat\" a1_len=10 a1[0]=2F746D70",
+            " a1[1]=2F61626364",
+            " a1[2]=65 a2=\"-n\"",
+        ]);
+        assert_eq!(parse_argv(&fields), ["cat", "/tmp/abcde", "-n"]);
+    }
+
+    #[test]
+    fn argv_missing_arguments() {
+        let fields = execve_fields(&[" argc=2 a0=\"ls\""]);
+        assert_eq!(parse_argv(&fields), ["ls", ""]);
+        assert!(parse_argv(&execve_fields(&[" a0=\"ls\""])).is_empty());
+    }
+}
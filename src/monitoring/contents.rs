@@ -0,0 +1,379 @@
+use libc::{self, inotify_add_watch, inotify_init1};
+use rustc_hash::FxHashMap;
+use sha2::{Digest, Sha256};
+use std::ffi::{CString, OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+
+use crate::core::{
+    constants::{CONTENT_DIFF_CONTEXT, CONTENT_MAX_BYTES},
+    error::Result,
+    event::{ContentChange, Event, FileCategory, FsEvent},
+    logger::Logger,
+};
+use crate::monitoring::filesystem::{
+    BUFFER_SIZE, FsWatcher, IN_CLOSE_WRITE, IN_CREATE, IN_DELETE, IN_MOVED_FROM, IN_MOVED_TO,
+    IN_Q_OVERFLOW, InotifyRecords, read_events,
+};
+use crate::monitoring::reactor::Source;
+use crate::utils::textdiff;
+
+// a file is written in place or replaced by a rename, either way its directory sees it
+// and a symlink shows up with no more than IN_CREATE, e.g. a unit enabled by systemctl
+const CONTENT_MASK: u32 = IN_CLOSE_WRITE | IN_MOVED_TO | IN_MOVED_FROM | IN_DELETE | IN_CREATE;
+
+// what is remembered of a file between two changes
+#[derive(PartialEq)]
+enum Snapshot {
+    Missing,
+    Text(String),
+    Binary { size: u64, digest: [u8; 32] },
+    // only compared by size and mtime
+    Large { size: u64, mtime: i64 },
+}
+
+impl Snapshot {
+    fn read(path: &Path) -> Self {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return Snapshot::Missing;
+        };
+        if !metadata.is_file() {
+            return Snapshot::Missing;
+        }
+        if metadata.len() > CONTENT_MAX_BYTES {
+            return Snapshot::Large {
+                size: metadata.len(),
+                mtime: metadata.mtime(),
+            };
+        }
+        let Ok(bytes) = std::fs::read(path) else {
+            return Snapshot::Missing;
+        };
+        if !bytes.contains(&0)
+            && let Ok(text) = std::str::from_utf8(&bytes)
+        {
+            return Snapshot::Text(text.to_string());
+        }
+        Snapshot::Binary {
+            size: bytes.len() as u64,
+            digest: Sha256::digest(&bytes).into(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Snapshot::Missing => "missing".to_string(),
+            Snapshot::Text(text) => match text.lines().count() {
+                1 => "1 line".to_string(),
+                lines => format!("{} lines", lines),
+            },
+            Snapshot::Binary { size, .. } | Snapshot::Large { size, .. } => {
+                format!("{} bytes", size)
+            }
+        }
+    }
+}
+
+// (summary, diff) of what changed, None when nothing did
+fn compare(old: &Snapshot, new: &Snapshot) -> Option<(String, Vec<String>)> {
+    if old == new {
+        return None;
+    }
+    let text = |snapshot: &Snapshot| match snapshot {
+        Snapshot::Text(text) => Some(text.clone()),
+        Snapshot::Missing => Some(String::new()),
+        _ => None,
+    };
+    let diff = match (text(old), text(new)) {
+        (Some(old), Some(new)) => textdiff::unified(&old, &new, CONTENT_DIFF_CONTEXT),
+        _ => Vec::new(),
+    };
+    let summary = match (old, new) {
+        (Snapshot::Missing, new) => format!("created, {}", new.describe()),
+        (old, Snapshot::Missing) => format!("deleted, was {}", old.describe()),
+        (Snapshot::Text(_), Snapshot::Text(_)) => {
+            let count = |prefix: char| diff.iter().filter(|line| line.starts_with(prefix)).count();
+            format!("{} added, {} removed", count('+'), count('-'))
+        }
+        (old, new) => format!("{} -> {}", old.describe(), new.describe()),
+    };
+    Some((summary, diff))
+}
+
+struct WatchedDirectory {
+    path: PathBuf,
+    // the entries kept and why, a None name stands for every file in the directory
+    entries: Vec<(Option<OsString>, FileCategory)>,
+}
+
+impl WatchedDirectory {
+    fn names(&self, name: &OsStr) -> bool {
+        self.entries
+            .iter()
+            .any(|(entry, _)| entry.as_deref() == Some(name))
+    }
+
+    fn category(&self, name: &OsStr) -> Option<FileCategory> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry.as_deref() == Some(name))
+            .or_else(|| self.entries.iter().find(|(entry, _)| entry.is_none()))
+            .map(|(_, category)| *category)
+    }
+}
+
+// keeps a copy of a few small files and reports how each change altered them, with an
+// inotify instance of its own so it works whatever the filesystem backend watches
+pub struct ContentWatcher {
+    fd: RawFd,
+    sender: Sender<Event>,
+    directories: FxHashMap<i32, WatchedDirectory>,
+    snapshots: FxHashMap<PathBuf, Snapshot>,
+    // files whose directory does not exist yet, e.g. authorized_keys without a ~/.ssh,
+    // and the closest existing directories above them that are watched until it does
+    pending: Vec<(PathBuf, FileCategory)>,
+    ancestors: FxHashMap<i32, PathBuf>,
+    buffer: Vec<u8>,
+}
+
+impl ContentWatcher {
+    // a directory among `files` stands for every file directly in it
+    pub fn new(sender: Sender<Event>, files: &[(PathBuf, FileCategory)]) -> Result<Self> {
+        let fd = unsafe { inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut watcher = Self {
+            fd,
+            sender,
+            directories: FxHashMap::default(),
+            snapshots: FxHashMap::default(),
+            pending: Vec::new(),
+            ancestors: FxHashMap::default(),
+            buffer: vec![0; BUFFER_SIZE],
+        };
+        for (path, category) in files {
+            watcher.add(path, *category, false);
+        }
+        if watcher.directories.is_empty() && watcher.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "none of the files can be watched",
+            )
+            .into());
+        }
+        Ok(watcher)
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // `appeared` reports the files found as created, their directory was made after startup
+    fn add(&mut self, path: &Path, category: FileCategory, appeared: bool) {
+        let (directory, name) = if path.is_dir() {
+            (path, None)
+        } else {
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => (parent, Some(name.to_os_string())),
+                _ => return,
+            }
+        };
+        if !directory.is_dir() {
+            self.watch_ancestor(path, category);
+            return;
+        }
+        let Ok(directory_str) = CString::new(directory.as_os_str().as_bytes()) else {
+            return;
+        };
+        let wd = unsafe { inotify_add_watch(self.fd, directory_str.as_ptr(), CONTENT_MASK) };
+        if wd == -1 {
+            let err = io::Error::last_os_error();
+            let message = format!("failed to watch {} for changes: {}", path.display(), err);
+            // the loader and persistence files are looked for on every system, not all of
+            // them exist or are readable
+            if category == FileCategory::Diff {
+                Logger::error(message);
+            } else {
+                Logger::debug(message);
+            }
+            return;
+        }
+
+        let files: Vec<PathBuf> = if name.is_some() {
+            vec![path.to_path_buf()]
+        } else {
+            std::fs::read_dir(directory)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect()
+        };
+        for file in files {
+            if appeared {
+                self.snapshots.insert(file.clone(), Snapshot::Missing);
+                self.report(file, category, IN_CREATE);
+            } else {
+                let snapshot = Snapshot::read(&file);
+                self.snapshots.insert(file, snapshot);
+            }
+        }
+        // watching a directory again hands out the same wd
+        let entries = &mut self
+            .directories
+            .entry(wd)
+            .or_insert_with(|| WatchedDirectory {
+                path: directory.to_path_buf(),
+                entries: Vec::new(),
+            })
+            .entries;
+        if !entries.contains(&(name.clone(), category)) {
+            entries.push((name, category));
+        }
+    }
+
+    fn watch_ancestor(&mut self, path: &Path, category: FileCategory) {
+        let Some(ancestor) = path.ancestors().skip(1).find(|ancestor| ancestor.is_dir()) else {
+            return;
+        };
+        let Ok(ancestor_str) = CString::new(ancestor.as_os_str().as_bytes()) else {
+            return;
+        };
+        let wd = unsafe { inotify_add_watch(self.fd, ancestor_str.as_ptr(), CONTENT_MASK) };
+        if wd == -1 {
+            Logger::debug(format!(
+                "failed to watch {} for {} to appear: {}",
+                ancestor.display(),
+                path.display(),
+                io::Error::last_os_error()
+            ));
+            return;
+        }
+        self.ancestors.insert(wd, ancestor.to_path_buf());
+        self.pending.push((path.to_path_buf(), category));
+    }
+
+    // a directory was made somewhere above the pending files, each is watched again from
+    // wherever it now gets to
+    fn retry_pending(&mut self) {
+        for (path, category) in std::mem::take(&mut self.pending) {
+            self.add(&path, category, true);
+        }
+    }
+
+    // reads and handles whatever is queued, WouldBlock when nothing is
+    pub fn read_batch(&mut self) -> io::Result<()> {
+        let read_size = read_events(self.fd, &mut self.buffer)?;
+        let records: Vec<(i32, u32, OsString)> = InotifyRecords {
+            buffer: &self.buffer[..read_size],
+        }
+        .map(|record| (record.wd, record.mask, record.name.to_os_string()))
+        .collect();
+        for (wd, mask, name) in records {
+            if mask & IN_Q_OVERFLOW != 0 {
+                Logger::debug("content watcher queue overflowed, changes may be missed");
+                continue;
+            }
+            self.handle(wd, mask, &name);
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, wd: i32, mask: u32, name: &OsStr) {
+        if name.is_empty() {
+            return;
+        }
+        let created = mask & (IN_CREATE | IN_MOVED_TO) != 0;
+        if created
+            && let Some(ancestor) = self.ancestors.get(&wd)
+            && ancestor.join(name).is_dir()
+        {
+            self.retry_pending();
+        }
+        let Some(directory) = self.directories.get(&wd) else {
+            return;
+        };
+        let Some(category) = directory.category(name) else {
+            return;
+        };
+        let path = directory.path.join(name);
+        // a watched name that was missing at startup and came back as a directory, e.g.
+        // /etc/cron.weekly, stands for its files from now on
+        if created && directory.names(name) && path.is_dir() {
+            self.add(&path, category, true);
+            return;
+        }
+        // a new regular file is reported once it is written and closed
+        if mask & IN_CREATE != 0 && !path.is_symlink() {
+            return;
+        }
+        self.report(path, category, mask);
+    }
+
+    fn report(&mut self, path: PathBuf, category: FileCategory, mask: u32) {
+        let (Some(directory), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        let (directory, name) = (directory.to_path_buf(), name.to_string_lossy().into_owned());
+        let current = Snapshot::read(&path);
+        let previous = self.snapshots.get(&path).unwrap_or(&Snapshot::Missing);
+        let Some((summary, diff)) = compare(previous, &current) else {
+            return;
+        };
+        self.snapshots.insert(path, current);
+
+        let event = FsEvent {
+            events: FsWatcher::get_event_string(mask),
+            path: directory,
+            name: Some(name),
+            file_modified: true,
+            pid: None,
+            process: None,
+            content: Some(ContentChange {
+                category,
+                summary,
+                diff,
+            }),
+            observed_at: SystemTime::now(),
+        };
+        if let Err(e) = self.sender.send(Event::Fs(event)) {
+            Logger::error(format!("failed to send event: {}", e));
+        }
+    }
+}
+
+impl Source for ContentWatcher {
+    fn name(&self) -> &'static str {
+        "content watcher"
+    }
+
+    fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    fn on_readable(&mut self) -> io::Result<()> {
+        loop {
+            match self.read_batch() {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for ContentWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
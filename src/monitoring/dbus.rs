@@ -1,93 +1,682 @@
-use dbus::blocking::Connection;
 use procfs::process::Process;
 use rustc_hash::FxHashSet;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use zbus::blocking::{Connection, MessageIterator, Proxy, connection, fdo::DBusProxy};
+use zbus::message::{Message, Type};
+use zbus::names::BusName;
+use zbus::zvariant::OwnedObjectPath;
 
 use crate::core::{
-    constants::{DBUS_DEFAULT_SLEEP_MS, DBUS_PROXY_TIMEOUT_SECS},
+    constants::{
+        DBUS_DEFAULT_SLEEP_MS, DBUS_MAX_BACKOFF_SECS, DBUS_MIN_BACKOFF_MS, DBUS_PROXY_TIMEOUT_SECS,
+        DBUS_SNIFF_SENDER_CAPACITY, PID_CACHE_CAPACITY, ROOT_SLICE, WORKER_POLL_INTERVAL_MS,
+    },
     error::Result,
+    event::{BusEvent, Event, ExitEvent, ProcessEvent, ProcessSource},
     logger::Logger,
+    metrics::METRICS,
 };
+use crate::monitoring::process::{exe_anomalies, exe_in_writable_dir, exe_setid_bits, script_of};
+use crate::utils::libraries;
+use crate::utils::lru::Lru;
+use crate::utils::process::Environ;
+use crate::utils::procparse;
+use crate::utils::procparse::ProcReader;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::throttle::{self, Priority};
 
-pub struct DBusScanner {
-    printed_processes: FxHashSet<u32>,
-    interval: Option<Duration>,
+// what a pid was when it was reported, to tell a reused pid from the same process
+struct PrintedProcess {
+    // from /proc/<pid>/stat, the command line has to do when procfs is not readable
+    starttime: Option<u64>,
+    cmdline: String,
+    uid: Option<u32>,
+    ppid: Option<u32>,
+    first_seen: Instant,
 }
 
-fn lookup_uid(pid: u32) -> Option<u32> {
-    Process::new(pid as i32)
-        .ok()?
-        .status()
-        .ok()
-        .map(|s| s.ruid)
+impl PrintedProcess {
+    fn is_same(&self, starttime: Option<u64>, cmdline: &str) -> bool {
+        match (self.starttime, starttime) {
+            (Some(known), Some(current)) => known == current,
+            _ => self.cmdline == cmdline,
+        }
+    }
+}
+
+pub struct DBusScanner {
+    // reconciled against every listing, the limit only matters for huge process tables
+    printed_processes: Lru<u32, PrintedProcess>,
+    interval: Option<Duration>,
+    sender: Sender<Event>,
+    reader: ProcReader,
+    show_exits: bool,
+    highlight_tmp_exec: bool,
+    check_libraries: bool,
+    units: Option<UnitResolver>,
+    // the procfs scanner's, so a process dbus found is looked at in full right away
+    trigger: Option<Sender<()>>,
 }
 
 impl DBusScanner {
-    pub fn new(interval: Option<Duration>) -> Self {
+    pub fn new(interval: Option<Duration>, sender: Sender<Event>) -> Self {
         DBusScanner {
-            printed_processes: FxHashSet::default(),
+            printed_processes: Lru::new("dbus pid", PID_CACHE_CAPACITY),
             interval,
+            sender,
+            reader: ProcReader::new(),
+            show_exits: false,
+            highlight_tmp_exec: false,
+            check_libraries: false,
+            units: None,
+            trigger: None,
+        }
+    }
+
+    pub fn set_show_exits(&mut self, show_exits: bool) {
+        self.show_exits = show_exits;
+    }
+
+    pub fn set_highlight_tmp_exec(&mut self, highlight_tmp_exec: bool) {
+        self.highlight_tmp_exec = highlight_tmp_exec;
+    }
+
+    pub fn set_check_libraries(&mut self, check_libraries: bool) {
+        self.check_libraries = check_libraries;
+    }
+
+    pub fn set_trigger(&mut self, trigger: Option<Sender<()>>) {
+        self.trigger = trigger;
+    }
+
+    pub fn set_units(&mut self, units: Option<UnitResolver>) {
+        self.units = units;
+    }
+
+    pub fn set_max_pids(&mut self, capacity: usize) {
+        self.printed_processes = Lru::new("dbus pid", capacity);
+    }
+
+    // dbus only knows pid and cmdline, the rest is best effort from procfs
+    fn lookup_process_event(&self, pid: u32, cmdline: String) -> ProcessEvent {
+        let process = Process::new(pid as i32).ok();
+        let status = process.as_ref().and_then(|p| p.status().ok());
+        let exe = process.as_ref().and_then(|p| p.exe().ok());
+        let cwd = process.as_ref().and_then(|p| p.cwd().ok());
+        let argv = process.as_ref().and_then(|p| p.cmdline().ok());
+        let environ = Environ::read(pid);
+        let (exe_deleted, exe_mismatch) = exe_anomalies(
+            exe.as_deref(),
+            argv.as_deref(),
+            cwd.as_deref(),
+            environ.as_ref(),
+        );
+        let (exe_setuid, exe_setgid) =
+            exe_setid_bits(&Path::new("/proc").join(pid.to_string()).join("exe"));
+        let exe_in_writable_dir = self.highlight_tmp_exec && exe_in_writable_dir(exe.as_deref());
+        let script = script_of(argv.as_deref(), cwd.as_deref());
+        let loader_env = libraries::loader_env(environ.as_ref());
+        let unexpected_libraries = if self.check_libraries {
+            libraries::unexpected(pid)
+        } else {
+            Vec::new()
+        };
+
+        ProcessEvent {
+            ppid: status.as_ref().map(|s| s.ppid as u32),
+            uid: status.as_ref().map(|s| s.ruid),
+            gid: status.as_ref().map(|s| s.rgid),
+            exe,
+            exe_deleted,
+            exe_mismatch,
+            cwd,
+            exe_setuid,
+            exe_setgid,
+            exe_in_writable_dir,
+            loader_env,
+            unexpected_libraries,
+            script,
+            ..ProcessEvent::new(ProcessSource::DBus, pid, cmdline)
         }
     }
 
+    // systemd only answers on the system bus, a session bus is no use here
     pub fn is_available() -> bool {
-        match Connection::new_system() {
+        match Connection::system() {
             Ok(_) => true,
             Err(e) => {
                 Logger::debug(format!("failed to connect to system bus: {}", e));
-                match Connection::new_session() {
-                    Ok(_) => true,
+                false
+            }
+        }
+    }
+
+    // dbus restarts, e.g. during upgrades, end the connection, it is made again with
+    // an exponential backoff until rspy stops
+    pub fn start_listening(&mut self, running: &AtomicBool) -> Result<()> {
+        let sleep_duration = self.interval();
+        let mut proxy = None;
+        let mut backoff = Duration::from_millis(DBUS_MIN_BACKOFF_MS);
+        // only the first failure of an outage is reported
+        let mut reachable = true;
+
+        Logger::debug("starting dbus monitoring loop...".to_string());
+        while running.load(Ordering::SeqCst) {
+            let Some(manager) = &proxy else {
+                match connect_manager() {
+                    Ok(manager) => {
+                        if !reachable {
+                            Logger::info("reconnected to the system dbus".to_string());
+                        }
+                        proxy = Some(manager);
+                        reachable = true;
+                        backoff = Duration::from_millis(DBUS_MIN_BACKOFF_MS);
+                    }
                     Err(e) => {
-                        Logger::debug(format!("failed to connect to session bus: {}", e));
-                        false
+                        if reachable {
+                            Logger::error(format!(
+                                "failed to connect to system dbus, retrying: {}",
+                                e
+                            ));
+                        }
+                        reachable = false;
+                        shutdown::sleep(running, backoff);
+                        backoff = (backoff * 2).min(Duration::from_secs(DBUS_MAX_BACKOFF_SECS));
                     }
                 }
+                continue;
+            };
+
+            Logger::debug("polling dbus for processes...".to_string());
+            match slice_processes(manager) {
+                Ok(listing) => {
+                    if self.handle_processes(listing) > 0
+                        && let Some(trigger) = &self.trigger
+                        && trigger.send(()).is_err()
+                    {
+                        Logger::debug("process scanner is gone, no more dbus triggers".to_string());
+                        self.trigger = None;
+                    }
+                }
+                Err(e) => {
+                    METRICS.record_dbus_poll_failure();
+                    Logger::error(format!(
+                        "failed to get processes from dbus, reconnecting: {}",
+                        e
+                    ));
+                    proxy = None;
+                    reachable = false;
+                }
             }
+
+            shutdown::sleep(running, sleep_duration);
         }
+        Ok(())
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+            .unwrap_or(Duration::from_millis(DBUS_DEFAULT_SLEEP_MS))
     }
 
-    pub fn start_listening(&mut self) -> Result<()> {
-        Logger::debug("attempting to connect to system dbus...".to_string());
-        let conn = Connection::new_system().map_err(|e| {
-            Logger::error(format!("failed to connect to system dbus: {}", e));
-            e
-        })?;
+    // returns how many were new
+    pub fn handle_processes(&mut self, listing: SliceProcesses) -> usize {
+        Logger::debug(format!(
+            "retrieved {} processes from dbus",
+            listing.processes.len()
+        ));
+
+        let mut listed = FxHashSet::default();
+        let mut reported = 0;
+        for (_name, pid, cmdline) in listing.processes {
+            listed.insert(pid);
+            let starttime = self
+                .reader
+                .stat(pid as i32, None)
+                .ok()
+                .map(|stat| stat.starttime);
+            if let Some(known) = self.printed_processes.get_mut(&pid) {
+                if known.is_same(starttime, &cmdline) {
+                    continue;
+                }
+                // the pid was reused between two polls
+                if let Some(previous) = self.printed_processes.remove(&pid) {
+                    self.report_exit(pid, previous);
+                }
+            }
 
-        let sleep_duration = self
-            .interval
-            .unwrap_or(Duration::from_millis(DBUS_DEFAULT_SLEEP_MS));
-        let proxy_timeout = Duration::from_secs(DBUS_PROXY_TIMEOUT_SECS);
+            let mut event = self.lookup_process_event(pid, cmdline);
+            event.unit = self.units.as_ref().and_then(|units| units.unit_of(pid));
+            self.printed_processes.insert(
+                pid,
+                PrintedProcess {
+                    starttime,
+                    cmdline: event.cmdline.clone(),
+                    uid: event.uid,
+                    ppid: event.ppid,
+                    first_seen: Instant::now(),
+                },
+            );
+            if let Err(e) = self.sender.send(Event::Process(Box::new(event))) {
+                Logger::error(format!("failed to send dbus event: {}", e));
+            }
+            reported += 1;
+        }
 
-        Logger::debug("creating dbus proxy...".to_string());
-        // thanks jkr
-        let proxy = conn.with_proxy(
+        // a slice that failed to answer would make all of its processes look gone
+        if listing.partial {
+            return reported;
+        }
+        let vanished: Vec<u32> = self
+            .printed_processes
+            .iter()
+            .map(|(pid, _)| *pid)
+            .filter(|pid| !listed.contains(pid))
+            .collect();
+        for pid in vanished {
+            if let Some(previous) = self.printed_processes.remove(&pid) {
+                self.report_exit(pid, previous);
+            }
+        }
+        reported
+    }
+
+    // the lifetime counts from the first poll that listed the process
+    fn report_exit(&self, pid: u32, process: PrintedProcess) {
+        if !self.show_exits {
+            return;
+        }
+        let event = Event::Exit(ExitEvent {
+            pid,
+            ppid: process.ppid,
+            uid: process.uid,
+            cmdline: process.cmdline,
+            lifetime: Some(process.first_seen.elapsed()),
+            observed_at: SystemTime::now(),
+        });
+        if let Err(e) = self.sender.send(event) {
+            Logger::error(format!("failed to send dbus exit event: {}", e));
+        }
+    }
+}
+
+// the systemd unit a process belongs to, e.g. cron.service or user@1000.service
+#[derive(Clone)]
+pub struct UnitResolver {
+    manager: Proxy<'static>,
+}
+
+impl UnitResolver {
+    pub fn connect() -> Result<Self> {
+        let conn = connection::Builder::system()?
+            .method_timeout(Duration::from_secs(DBUS_PROXY_TIMEOUT_SECS))
+            .build()?;
+        let manager = Proxy::new(
+            &conn,
             "org.freedesktop.systemd1",
-            "/org/freedesktop/systemd1/unit/_2d_2eslice",
-            proxy_timeout,
-        );
+            "/org/freedesktop/systemd1",
+            "org.freedesktop.systemd1.Manager",
+        )?;
+        Ok(UnitResolver { manager })
+    }
 
-        Logger::debug("starting dbus monitoring loop...".to_string());
-        loop {
-            Logger::debug("polling dbus for processes...".to_string());
-            match proxy.method_call("org.freedesktop.systemd1.Slice", "GetProcesses", ()) {
-                Ok(result) => {
-                    let (processes,): (Vec<(String, u32, String)>,) = result;
-                    Logger::debug(format!("retrieved {} processes from dbus", processes.len()));
-
-                    for (_name, pid, cmdline) in processes {
-                        if self.printed_processes.insert(pid) {
-                            let uid = lookup_uid(pid);
-                            Logger::dbus_event_with_uid(pid, &cmdline, uid);
+    // None when the process already exited or systemd does not know it
+    pub fn unit_of(&self, pid: u32) -> Option<String> {
+        match self
+            .manager
+            .call::<_, _, OwnedObjectPath>("GetUnitByPID", &(pid,))
+        {
+            Ok(path) => unit_name(path.as_str()),
+            Err(e) => {
+                Logger::debug(format!("no systemd unit for pid {}: {}", pid, e));
+                None
+            }
+        }
+    }
+}
+
+// a monitoring connection is handed every message that matches its rules, which needs root
+// with the default bus policy. it can not send anything anymore, so pids are looked up over
+// a second connection
+pub struct BusMonitor {
+    bus: DBusProxy<'static>,
+    // of both connections, what the bus says about them is not reported
+    own_names: Vec<String>,
+    messages: Receiver<Message>,
+    // by unique name, None when the sender was gone before it could be asked
+    senders: Lru<String, Option<u32>>,
+}
+
+impl BusMonitor {
+    pub fn become_monitor(rules: &[&str]) -> Result<Self> {
+        let lookup = connection::Builder::system()?
+            .method_timeout(Duration::from_secs(DBUS_PROXY_TIMEOUT_SECS))
+            .build()?;
+        let bus = DBusProxy::new(&lookup)?;
+
+        let monitor = Connection::system()?;
+        let own_names = [&lookup, &monitor]
+            .into_iter()
+            .filter_map(|connection| connection.unique_name().map(|name| name.to_string()))
+            .collect();
+        // created first so nothing sent right after BecomeMonitor is missed
+        let iterator = MessageIterator::from(&monitor);
+        monitor.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus.Monitoring"),
+            "BecomeMonitor",
+            &(rules, 0u32),
+        )?;
+
+        // the iterator blocks without a timeout, so it gets a thread of its own that is
+        // simply left behind on shutdown
+        let (messages_tx, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for message in iterator {
+                match message {
+                    Ok(message) => {
+                        if messages_tx.send(message).is_err() {
+                            break;
                         }
                     }
-                }
-                Err(e) => {
-                    Logger::error(format!("failed to get processes from dbus: {}", e));
-                    return Err(e.into());
+                    Err(e) => {
+                        Logger::error(format!("dbus monitor stopped: {}", e));
+                        break;
+                    }
                 }
             }
+        });
+
+        Ok(BusMonitor {
+            bus,
+            own_names,
+            messages,
+            senders: Lru::new("dbus sender", DBUS_SNIFF_SENDER_CAPACITY),
+        })
+    }
+
+    // the next message that is not about rspy itself, None once stopped or disconnected
+    pub fn next(&self, running: &AtomicBool) -> Option<Message> {
+        while running.load(Ordering::SeqCst) {
+            match self
+                .messages
+                .recv_timeout(Duration::from_millis(WORKER_POLL_INTERVAL_MS))
+            {
+                Ok(message) if !self.is_own(&message) => return Some(message),
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        None
+    }
+
+    // our own pid lookups and what the bus tells our connections about their names
+    fn is_own(&self, message: &Message) -> bool {
+        let header = message.header();
+        [
+            header.sender().map(|name| name.as_str()),
+            header.destination().map(|name| name.as_str()),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|name| self.own_names.iter().any(|own| own == name))
+    }
+
+    pub fn pid_of(&mut self, name: &str) -> Option<u32> {
+        if let Some(pid) = self.senders.get_mut(&name.to_string()) {
+            return *pid;
+        }
+        let pid = BusName::try_from(name)
+            .ok()
+            .and_then(|bus_name| self.bus.get_connection_unix_process_id(bus_name).ok());
+        self.senders.insert(name.to_string(), pid);
+        pid
+    }
+}
+
+// --dbus-sniff, every method call and signal on the system bus
+pub struct BusSniffer {
+    sender: Sender<Event>,
+    reader: ProcReader,
+}
+
+impl BusSniffer {
+    pub fn new(sender: Sender<Event>) -> Self {
+        BusSniffer {
+            sender,
+            reader: ProcReader::new(),
+        }
+    }
+
+    pub fn start(mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Result<Worker> {
+        let mut monitor = BusMonitor::become_monitor(&["type='method_call'", "type='signal'"])?;
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            while let Some(message) = monitor.next(&running) {
+                self.handle(&mut monitor, &message);
+            }
+        });
+        Ok(("dbus sniffer", worker))
+    }
+
+    fn handle(&mut self, monitor: &mut BusMonitor, message: &Message) {
+        let header = message.header();
+        let kind = match header.message_type() {
+            Type::MethodCall => "method_call",
+            Type::Signal => "signal",
+            // the reply to BecomeMonitor
+            _ => return,
+        };
+        let sender = header.sender().map(|name| name.to_string());
+        let pid = sender.as_deref().and_then(|name| monitor.pid_of(name));
+        let (uid, process) = describe_pid(&mut self.reader, pid);
+
+        let event = Event::Bus(BusEvent {
+            kind: kind.to_string(),
+            sender,
+            pid,
+            uid,
+            process,
+            destination: header.destination().map(|name| name.to_string()),
+            path: header.path().map(|path| path.to_string()),
+            interface: header.interface().map(|interface| interface.to_string()),
+            member: header.member().map(|member| member.to_string()),
+            observed_at: SystemTime::now(),
+        });
+        if let Err(e) = self.sender.send(event) {
+            Logger::error(format!("failed to send dbus message event: {}", e));
+        }
+    }
+}
+
+// uid and cmdline of a process that may already be gone
+pub fn describe_pid(reader: &mut ProcReader, pid: Option<u32>) -> (Option<u32>, Option<String>) {
+    let Some(pid) = pid else {
+        return (None, None);
+    };
+    let uid = reader.status(pid as i32).ok().map(|status| status.ruid);
+    let process = reader
+        .cmdline(pid as i32)
+        .ok()
+        .map(procparse::split_cmdline)
+        .filter(|argv| !argv.is_empty())
+        .map(|argv| argv.join(" "));
+    (uid, process)
+}
 
-            std::thread::sleep(sleep_duration);
+// --show-unit carries on without units when systemd can not be reached
+pub fn unit_resolver(enabled: bool) -> Option<UnitResolver> {
+    if !enabled {
+        return None;
+    }
+    UnitResolver::connect()
+        .map_err(|e| {
+            Logger::error(format!(
+                "systemd is not reachable over dbus ({}), continuing without --show-unit",
+                e
+            ))
+        })
+        .ok()
+}
+
+// unit object paths are the escaped unit name, every byte that is not alphanumeric
+// becomes _xx, so asking the unit for its Id is not needed
+fn unit_name(path: &str) -> Option<String> {
+    let escaped = path.strip_prefix("/org/freedesktop/systemd1/unit/")?;
+    let bytes = escaped.as_bytes();
+    let mut name = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'_' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            name.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            name.push(bytes[i]);
+            i += 1;
         }
     }
+    String::from_utf8(name).ok()
+}
+
+// (name, description, load state, active state, sub state, followed unit, object path,
+// job id, job type, job path) as returned by ListUnits
+type UnitStatus = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    OwnedObjectPath,
+    u32,
+    String,
+    OwnedObjectPath,
+);
+
+// GetUnitProcesses includes the slices below, so of the slices that are up only those
+// whose parent is not are asked. -.slice is asked along with its children, on some
+// distros the user sessions are missing from it
+fn active_slices(units: Vec<UnitStatus>) -> Vec<String> {
+    let active: FxHashSet<String> = units
+        .into_iter()
+        .filter(|unit| unit.0.ends_with(".slice") && unit.3 == "active")
+        .map(|unit| unit.0)
+        .collect();
+    let mut slices: Vec<String> = active
+        .iter()
+        .filter(|slice| {
+            parent_slice(slice)
+                .is_none_or(|parent| parent == ROOT_SLICE || !active.contains(parent.as_str()))
+        })
+        .cloned()
+        .collect();
+    slices.sort();
+    slices
+}
+
+// user-1000.slice is below user.slice, which is below -.slice
+fn parent_slice(slice: &str) -> Option<String> {
+    if slice == ROOT_SLICE {
+        return None;
+    }
+    let name = slice.strip_suffix(".slice")?;
+    Some(match name.rsplit_once('-') {
+        Some((parent, _)) => format!("{}.slice", parent),
+        None => ROOT_SLICE.to_string(),
+    })
+}
+
+// a process is listed again by every slice above its own, only the first listing is kept
+#[derive(Default)]
+pub struct SliceProcesses {
+    seen: FxHashSet<u32>,
+    // (cgroup, pid, cmdline) as returned by GetUnitProcesses
+    pub processes: Vec<(String, u32, String)>,
+    // some slice could not be listed, whatever ran there is missing
+    pub partial: bool,
+}
+
+impl SliceProcesses {
+    fn add(&mut self, slice: &str, listed: zbus::Result<Vec<(String, u32, String)>>) {
+        match listed {
+            Ok(listed) => self.processes.extend(
+                listed
+                    .into_iter()
+                    .filter(|(_, pid, _)| self.seen.insert(*pid)),
+            ),
+            // the slice went away since ListUnits
+            Err(e) => {
+                Logger::debug(format!("failed to list the processes of {}: {}", slice, e));
+                self.partial = true;
+            }
+        }
+    }
+}
+
+pub fn connect_manager() -> Result<Proxy<'static>> {
+    let proxy_timeout = Duration::from_secs(DBUS_PROXY_TIMEOUT_SECS);
+
+    Logger::debug("attempting to connect to system dbus...".to_string());
+    let conn = connection::Builder::system()
+        .and_then(|builder| builder.method_timeout(proxy_timeout).build())?;
+
+    Logger::debug("creating dbus proxy...".to_string());
+    let proxy = Proxy::new(
+        &conn,
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        "org.freedesktop.systemd1.Manager",
+    )?;
+    Ok(proxy)
+}
+
+// for `rspy check`, how many processes systemd lists
+pub fn probe_systemd() -> Result<usize> {
+    Ok(slice_processes(&connect_manager()?)?.processes.len())
+}
+
+#[cfg(feature = "tokio")]
+pub async fn connect_manager_async() -> Result<zbus::Proxy<'static>> {
+    let conn = zbus::connection::Builder::system()?
+        .method_timeout(Duration::from_secs(DBUS_PROXY_TIMEOUT_SECS))
+        .build()
+        .await?;
+    let proxy = zbus::Proxy::new(
+        &conn,
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        "org.freedesktop.systemd1.Manager",
+    )
+    .await?;
+    Ok(proxy)
+}
+
+// every process in any slice
+pub fn slice_processes(manager: &Proxy) -> Result<SliceProcesses> {
+    let slices = active_slices(manager.call("ListUnits", &())?);
+    let mut merged = SliceProcesses::default();
+    for slice in &slices {
+        merged.add(slice, manager.call("GetUnitProcesses", &(slice.as_str(),)));
+    }
+    Ok(merged)
+}
+
+#[cfg(feature = "tokio")]
+pub async fn slice_processes_async(manager: &zbus::Proxy<'_>) -> Result<SliceProcesses> {
+    let slices = active_slices(manager.call("ListUnits", &()).await?);
+    let mut merged = SliceProcesses::default();
+    for slice in &slices {
+        merged.add(
+            slice,
+            manager.call("GetUnitProcesses", &(slice.as_str(),)).await,
+        );
+    }
+    Ok(merged)
 }
@@ -0,0 +1,404 @@
+use globset::{Glob, GlobSet};
+use libc::{
+    self, FAN_ACCESS, FAN_CLASS_NOTIF, FAN_CLOEXEC, FAN_CLOSE_NOWRITE, FAN_CLOSE_WRITE,
+    FAN_EVENT_ON_CHILD, FAN_MARK_ADD, FAN_MARK_MOUNT, FAN_MODIFY, FAN_NOFD, FAN_OPEN,
+    FAN_OPEN_EXEC, FAN_Q_OVERFLOW, FANOTIFY_METADATA_VERSION, fanotify_event_metadata,
+    fanotify_init, fanotify_mark,
+};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::SystemTime;
+
+use crate::core::{
+    error::Result,
+    event::{Event, FsEvent},
+    logger::Logger,
+};
+use crate::monitoring::filesystem::{WatchReport, canonical_output, watch_exclude_sets};
+use crate::utils::process::process_name;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::throttle::{self, Priority};
+
+const BUFFER_SIZE: usize = 8192;
+
+pub struct FanotifyWatcher {
+    fd: RawFd,
+    sender: Sender<Event>,
+    trigger_sender: Sender<()>,
+    recursive_directories: Vec<PathBuf>,
+    direct_directories: Vec<PathBuf>,
+    excluded_paths: Vec<PathBuf>,
+    // --watch-exclude, patterns with a slash match the whole path, others the entry name
+    excluded_globs: GlobSet,
+    excluded_names: GlobSet,
+    print_events: bool,
+    low_resource: bool,
+    debug: bool,
+    report: WatchReport,
+}
+
+impl FanotifyWatcher {
+    fn get_event_string(mask: u64) -> String {
+        let mut events = Vec::new();
+
+        if mask & FAN_ACCESS != 0 {
+            events.push("ACCESS");
+        }
+        if mask & FAN_MODIFY != 0 {
+            events.push("MODIFY");
+        }
+        if mask & FAN_CLOSE_WRITE != 0 {
+            events.push("CLOSE_WRITE");
+        }
+        if mask & FAN_CLOSE_NOWRITE != 0 {
+            events.push("CLOSE_NOWRITE");
+        }
+        if mask & FAN_OPEN != 0 {
+            events.push("OPEN");
+        }
+        if mask & FAN_OPEN_EXEC != 0 {
+            events.push("OPEN_EXEC");
+        }
+
+        events.join("|")
+    }
+
+    // fails without CAP_SYS_ADMIN, callers are expected to fall back to inotify
+    pub fn new(
+        sender: Sender<Event>,
+        trigger_sender: Sender<()>,
+        recursive_directories: Vec<PathBuf>,
+        direct_directories: Vec<PathBuf>,
+        print_events: bool,
+        low_resource: bool,
+        debug: bool,
+    ) -> Result<Self> {
+        let fd = unsafe {
+            fanotify_init(
+                FAN_CLASS_NOTIF | FAN_CLOEXEC,
+                (libc::O_RDONLY | libc::O_LARGEFILE | libc::O_CLOEXEC) as u32,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // event paths come from readlink and are always canonical
+        let canonicalize = |directories: Vec<PathBuf>| -> Vec<PathBuf> {
+            directories
+                .into_iter()
+                .map(|directory| directory.canonicalize().unwrap_or(directory))
+                .collect()
+        };
+
+        Ok(Self {
+            fd,
+            sender,
+            trigger_sender,
+            recursive_directories: canonicalize(recursive_directories),
+            direct_directories: canonicalize(direct_directories),
+            excluded_paths: Vec::new(),
+            excluded_globs: GlobSet::empty(),
+            excluded_names: GlobSet::empty(),
+            print_events,
+            low_resource,
+            debug,
+            report: WatchReport::default(),
+        })
+    }
+
+    // rspy's own outputs, a mount mark cannot leave them out so their events are dropped
+    pub fn set_excluded_paths(&mut self, paths: &[PathBuf]) {
+        self.excluded_paths = paths
+            .iter()
+            .filter_map(|path| canonical_output(path).ok())
+            .collect();
+    }
+
+    pub fn set_watch_exclude(&mut self, globs: &[Glob]) -> Result<()> {
+        (self.excluded_globs, self.excluded_names) = watch_exclude_sets(globs)?;
+        Ok(())
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.excluded_paths
+            .iter()
+            .any(|excluded| path.starts_with(excluded))
+            || self.excluded_globs.is_match(path)
+            || path
+                .file_name()
+                .is_some_and(|name| self.excluded_names.is_match(name))
+    }
+
+    fn event_mask(&self) -> u64 {
+        if self.low_resource {
+            FAN_OPEN
+        } else {
+            FAN_OPEN | FAN_CLOSE_WRITE | FAN_OPEN_EXEC
+        }
+    }
+
+    pub fn setup_marks(&mut self) -> Result<()> {
+        // fanotify has no recursive inode marks, so recursive roots mark their whole
+        // mount (plus any mounts below them) and events are filtered by path prefix
+        let mut mount_points: Vec<PathBuf> = Vec::new();
+        for directory in &self.recursive_directories {
+            for mount_point in std::iter::once(directory.clone()).chain(mounts_below(directory)) {
+                if !self.is_excluded(&mount_point) && !mount_points.contains(&mount_point) {
+                    mount_points.push(mount_point);
+                }
+            }
+        }
+
+        for mount_point in mount_points {
+            self.add_mark(
+                &mount_point,
+                FAN_MARK_ADD | FAN_MARK_MOUNT,
+                self.event_mask(),
+            )?;
+        }
+
+        let direct_dirs: Vec<PathBuf> = self
+            .direct_directories
+            .iter()
+            .filter(|directory| !self.is_excluded(directory))
+            .cloned()
+            .collect();
+        for directory in direct_dirs {
+            self.add_mark(
+                &directory,
+                FAN_MARK_ADD,
+                self.event_mask() | FAN_EVENT_ON_CHILD,
+            )?;
+        }
+
+        // failures below --debug are not printed one by one, this is the coverage
+        Logger::info(self.report.to_string());
+        Ok(())
+    }
+
+    // of setup_marks, a mount mark counts as one directory
+    pub fn watch_report(&self) -> WatchReport {
+        self.report
+    }
+
+    fn add_mark(&mut self, path: &Path, flags: u32, mask: u64) -> Result<()> {
+        let path_str = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| format!("failed to create CString for path {:?}: {}", path, e))?;
+
+        let mut result =
+            unsafe { fanotify_mark(self.fd, flags, mask, libc::AT_FDCWD, path_str.as_ptr()) };
+        // FAN_OPEN_EXEC needs linux 5.0, older kernels reject the whole mask
+        if result == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+            result = unsafe {
+                fanotify_mark(
+                    self.fd,
+                    flags,
+                    mask & !FAN_OPEN_EXEC,
+                    libc::AT_FDCWD,
+                    path_str.as_ptr(),
+                )
+            };
+        }
+
+        if result == -1 {
+            let err = io::Error::last_os_error();
+            // a directory that does not exist needs no mark, as with inotify
+            match err.kind() {
+                io::ErrorKind::NotFound => {}
+                io::ErrorKind::PermissionDenied => self.report.denied += 1,
+                _ => self.report.failed += 1,
+            }
+            if self.debug
+                || !matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                )
+            {
+                Logger::error(format!("failed to fanotify-mark {:?}: {}", path, err));
+            }
+        } else {
+            self.report.watched += 1;
+            if self.debug {
+                Logger::debug(format!("fanotify marked: {:?} (flags={:x})", path, flags));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        self.recursive_directories
+            .iter()
+            .any(|root| path.starts_with(root))
+            || self
+                .direct_directories
+                .iter()
+                .any(|directory| path == directory || path.parent() == Some(directory))
+    }
+
+    pub fn start_watching(
+        self,
+        running: Arc<AtomicBool>,
+        priority: Option<Priority>,
+    ) -> Result<Worker> {
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            // our own reads (procfs lookups, hashing, log files) would otherwise trigger scans forever
+            let own_pid = std::process::id() as i32;
+            let mut buffer = [0u8; BUFFER_SIZE];
+
+            loop {
+                match shutdown::wait_readable(self.fd, &running) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => {
+                        Logger::error(format!("error waiting for fanotify events: {}", e));
+                        break;
+                    }
+                }
+
+                let read_size = unsafe {
+                    libc::read(
+                        self.fd,
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                    )
+                };
+                if read_size < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    Logger::error(format!("error reading fanotify events: {}", err));
+                    break;
+                }
+
+                let read_size = read_size as usize;
+                let metadata_size = std::mem::size_of::<fanotify_event_metadata>();
+                let mut offset = 0;
+                let mut has_events = false;
+
+                while offset + metadata_size <= read_size {
+                    let metadata = unsafe {
+                        std::ptr::read_unaligned(
+                            buffer.as_ptr().add(offset) as *const fanotify_event_metadata
+                        )
+                    };
+                    if metadata.vers != FANOTIFY_METADATA_VERSION
+                        || (metadata.event_len as usize) < metadata_size
+                    {
+                        Logger::error("unexpected fanotify event format, stopping watcher");
+                        return;
+                    }
+                    offset += metadata.event_len as usize;
+
+                    if metadata.mask & FAN_Q_OVERFLOW != 0 {
+                        Logger::debug("fanotify queue overflowed, events were dropped");
+                        has_events = true;
+                        continue;
+                    }
+                    if metadata.fd == FAN_NOFD {
+                        continue;
+                    }
+
+                    let path = std::fs::read_link(format!("/proc/self/fd/{}", metadata.fd));
+                    unsafe {
+                        libc::close(metadata.fd);
+                    }
+
+                    let Ok(path) = path else {
+                        continue;
+                    };
+                    if metadata.pid == own_pid || !self.is_watched(&path) || self.is_excluded(&path)
+                    {
+                        continue;
+                    }
+
+                    has_events = true;
+
+                    if self.print_events {
+                        let fs_event = FsEvent {
+                            events: Self::get_event_string(metadata.mask),
+                            path: path.clone(),
+                            name: None,
+                            file_modified: false,
+                            pid: Some(metadata.pid as u32),
+                            process: Some(process_name(metadata.pid)),
+                            content: None,
+                            observed_at: SystemTime::now(),
+                        };
+                        if let Err(e) = self.sender.send(Event::Fs(fs_event)) {
+                            Logger::error(format!("failed to send event: {}", e));
+                        }
+                    }
+
+                    if self.debug {
+                        Logger::debug(format!(
+                            "fanotify event: mask={:x} ({}) on {:?} by pid {}",
+                            metadata.mask,
+                            Self::get_event_string(metadata.mask),
+                            path,
+                            metadata.pid
+                        ));
+                    }
+                }
+
+                // send only one trigger per batch of events to avoid flooding
+                if has_events && let Err(e) = self.trigger_sender.send(()) {
+                    Logger::error(format!("failed to send trigger: {}", e));
+                }
+            }
+        });
+
+        Ok(("fanotify watcher", worker))
+    }
+}
+
+fn mounts_below(directory: &Path) -> Vec<PathBuf> {
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return Vec::new();
+    };
+
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .map(|mount_point| PathBuf::from(unescape_mount_point(mount_point)))
+        .filter(|mount_point| mount_point != directory && mount_point.starts_with(directory))
+        .collect()
+}
+
+// mountinfo escapes space, tab, newline and backslash as \ooo octal
+fn unescape_mount_point(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let octal: String = chars.by_ref().take(3).collect();
+            match u8::from_str_radix(&octal, 8) {
+                Ok(byte) => result.push(byte as char),
+                Err(_) => {
+                    result.push(c);
+                    result.push_str(&octal);
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+impl Drop for FanotifyWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
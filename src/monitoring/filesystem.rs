@@ -1,38 +1,179 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use libc::{self, IN_ALL_EVENTS, IN_OPEN, inotify_add_watch, inotify_init1};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::ffi::OsStr;
+use std::fmt;
 use std::io;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::Sender;
-use std::thread;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::SystemTime;
 use walkdir::WalkDir;
 
-use crate::core::{error::Result, logger::Logger};
-
-const BUFFER_SIZE: usize = 1024;
+use crate::core::{
+    constants::INOTIFY_MAX_USER_WATCHES_FILE,
+    error::Result,
+    event::{Event, FsEvent},
+    logger::Logger,
+    metrics::METRICS,
+};
+use crate::monitoring::reactor::Source;
+
+// every record is an inotify_event header followed by up to NAME_MAX + 1 bytes of name,
+// sized so one read can return BUFFER_EVENTS records even with the longest names
+const EVENT_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+const BUFFER_EVENTS: usize = 64;
+pub(crate) const BUFFER_SIZE: usize = (EVENT_SIZE + libc::NAME_MAX as usize + 1) * BUFFER_EVENTS;
 
 const IN_ACCESS: u32 = 0x00000001;
 const IN_MODIFY: u32 = 0x00000002;
 const IN_ATTRIB: u32 = 0x00000004;
-const IN_CLOSE_WRITE: u32 = 0x00000008;
+pub(crate) const IN_CLOSE_WRITE: u32 = 0x00000008;
 const IN_CLOSE_NOWRITE: u32 = 0x00000010;
-const IN_MOVED_FROM: u32 = 0x00000040;
-const IN_MOVED_TO: u32 = 0x00000080;
-const IN_CREATE: u32 = 0x00000100;
-const IN_DELETE: u32 = 0x00000200;
-
-#[repr(C)]
-struct InotifyEvent {
-    wd: i32,
-    mask: u32,
-    cookie: u32,
-    len: u32,
-    name: [u8; 0],
+pub(crate) const IN_MOVED_FROM: u32 = 0x00000040;
+pub(crate) const IN_MOVED_TO: u32 = 0x00000080;
+pub(crate) const IN_CREATE: u32 = 0x00000100;
+pub(crate) const IN_DELETE: u32 = 0x00000200;
+const IN_DELETE_SELF: u32 = 0x00000400;
+const IN_MOVE_SELF: u32 = 0x00000800;
+pub(crate) const IN_Q_OVERFLOW: u32 = 0x00004000;
+const IN_IGNORED: u32 = 0x00008000;
+const IN_ISDIR: u32 = 0x40000000;
+
+// changes to a watched file that are reported as FILE MODIFIED
+const FILE_MODIFIED_MASK: u32 =
+    IN_MODIFY | IN_CLOSE_WRITE | IN_ATTRIB | IN_DELETE_SELF | IN_MOVE_SELF;
+
+// names accepted by --watch-events and -r/-d dir:events
+const WATCH_EVENT_NAMES: &[(&str, u32)] = &[
+    ("access", IN_ACCESS),
+    ("modify", IN_MODIFY),
+    ("attrib", IN_ATTRIB),
+    ("close_write", IN_CLOSE_WRITE),
+    ("close_nowrite", IN_CLOSE_NOWRITE),
+    ("close", IN_CLOSE_WRITE | IN_CLOSE_NOWRITE),
+    ("open", IN_OPEN),
+    ("moved_from", IN_MOVED_FROM),
+    ("moved_to", IN_MOVED_TO),
+    ("move", IN_MOVED_FROM | IN_MOVED_TO),
+    ("create", IN_CREATE),
+    ("delete", IN_DELETE),
+    ("delete_self", IN_DELETE_SELF),
+    ("move_self", IN_MOVE_SELF),
+    ("all", IN_ALL_EVENTS),
+];
+
+pub fn parse_watch_events(spec: &str) -> std::result::Result<u32, String> {
+    let mut mask = 0;
+    for name in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let Some((_, bits)) = WATCH_EVENT_NAMES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        else {
+            let known: Vec<&str> = WATCH_EVENT_NAMES.iter().map(|(known, _)| *known).collect();
+            return Err(format!(
+                "unknown inotify event {:?}, expected one of {}",
+                name,
+                known.join(", ")
+            ));
+        };
+        mask |= bits;
+    }
+    if mask == 0 {
+        return Err("no inotify events given".to_string());
+    }
+    Ok(mask)
+}
+
+// the kernel writes inotify_event headers at aligned offsets of the read buffer
+#[repr(C, align(4))]
+struct EventBuffer([u8; BUFFER_SIZE]);
+
+pub(crate) struct InotifyRecord<'a> {
+    pub(crate) wd: i32,
+    pub(crate) mask: u32,
+    pub(crate) name: &'a OsStr,
+}
+
+// walks the records of one read(), a truncated record ends the iteration
+pub(crate) struct InotifyRecords<'a> {
+    pub(crate) buffer: &'a [u8],
+}
+
+impl<'a> Iterator for InotifyRecords<'a> {
+    type Item = InotifyRecord<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.len() < EVENT_SIZE {
+            return None;
+        }
+        let header =
+            unsafe { std::ptr::read_unaligned(self.buffer.as_ptr() as *const libc::inotify_event) };
+        let name = self
+            .buffer
+            .get(EVENT_SIZE..EVENT_SIZE + header.len as usize)?;
+        self.buffer = &self.buffer[EVENT_SIZE + name.len()..];
+
+        // the name is padded with NULs up to len, directories watched themselves have none
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Some(InotifyRecord {
+            wd: header.wd,
+            mask: header.mask,
+            name: OsStr::from_bytes(&name[..end]),
+        })
+    }
+}
+
+// how many of the directories found at startup got a watch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WatchReport {
+    pub watched: usize,
+    pub denied: usize,
+    // beyond --max-watches or fs.inotify.max_user_watches
+    pub over_limit: usize,
+    pub failed: usize,
+}
+
+impl WatchReport {
+    // percent of the directories that needed a watch, 100 when none did
+    pub fn coverage(&self) -> f64 {
+        let total = self.watched + self.denied + self.over_limit + self.failed;
+        if total == 0 {
+            return 100.0;
+        }
+        self.watched as f64 * 100.0 / total as f64
+    }
+}
+
+impl fmt::Display for WatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "watching {} directories, {} skipped (permission), {} skipped (limit)",
+            self.watched, self.denied, self.over_limit
+        )?;
+        if self.failed > 0 {
+            write!(f, ", {} failed", self.failed)?;
+        }
+        Ok(())
+    }
+}
+
+enum Watch {
+    Added,
+    Denied,
+    OverLimit,
+    Failed,
 }
 
 pub struct FsWatcher {
     fd: RawFd,
-    sender: Sender<String>,
+    sender: Sender<Event>,
     trigger_sender: Sender<()>,
     recursive_directories: Vec<PathBuf>,
     direct_directories: Vec<PathBuf>,
@@ -40,10 +181,29 @@ pub struct FsWatcher {
     low_resource: bool,
     debug: bool,
     wd_to_path: FxHashMap<i32, PathBuf>,
+    recursive_wds: FxHashSet<i32>,
+    excluded_paths: Vec<PathBuf>,
+    // --watch-exclude, patterns with a slash match the whole path, others the entry name
+    excluded_globs: GlobSet,
+    excluded_names: GlobSet,
+    watch_events: Option<u32>,
+    watch_masks: Vec<(PathBuf, u32)>,
+    // the events asked for on each watch, the kernel mask can hold more
+    wd_masks: FxHashMap<i32, u32>,
+    // -d targets that are files rather than directories
+    file_wds: FxHashSet<i32>,
+    max_watches: Option<usize>,
+    watch_budget: usize,
+    watch_limit_reached: bool,
+    report: WatchReport,
+    // paths to start watching while running, e.g. from the control socket
+    watch_requests: Option<Receiver<PathBuf>>,
+    // handed out while the records read into it are handled
+    buffer: Option<Box<EventBuffer>>,
 }
 
 impl FsWatcher {
-    fn get_event_string(mask: u32) -> String {
+    pub(crate) fn get_event_string(mask: u32) -> String {
         let mut events = Vec::new();
 
         if mask & IN_ACCESS != 0 {
@@ -76,12 +236,18 @@ impl FsWatcher {
         if mask & IN_DELETE != 0 {
             events.push("DELETE");
         }
+        if mask & IN_DELETE_SELF != 0 {
+            events.push("DELETE_SELF");
+        }
+        if mask & IN_MOVE_SELF != 0 {
+            events.push("MOVE_SELF");
+        }
 
         events.join("|")
     }
 
     pub fn new(
-        sender: Sender<String>,
+        sender: Sender<Event>,
         trigger_sender: Sender<()>,
         recursive_directories: Vec<PathBuf>,
         direct_directories: Vec<PathBuf>,
@@ -104,152 +270,506 @@ impl FsWatcher {
             low_resource,
             debug,
             wd_to_path: FxHashMap::default(),
+            recursive_wds: FxHashSet::default(),
+            excluded_paths: Vec::new(),
+            excluded_globs: GlobSet::empty(),
+            excluded_names: GlobSet::empty(),
+            watch_events: None,
+            watch_masks: Vec::new(),
+            wd_masks: FxHashMap::default(),
+            file_wds: FxHashSet::default(),
+            max_watches: None,
+            watch_budget: usize::MAX,
+            watch_limit_reached: false,
+            report: WatchReport::default(),
+            watch_requests: None,
+            buffer: Some(Box::new(EventBuffer([0; BUFFER_SIZE]))),
         })
     }
 
-    pub fn setup_watches(&mut self) -> Result<()> {
-        let recursive_dirs = self.recursive_directories.clone();
-        let direct_dirs = self.direct_directories.clone();
-
-        for directory in recursive_dirs {
-            self.add_watch(&directory, true)?;
+    // rspy's own outputs are dropped from the watch set, otherwise every line we
+    // write produces an event that triggers another scan and another line
+    pub fn set_excluded_paths(&mut self, paths: &[PathBuf]) {
+        let roots: Vec<PathBuf> = self
+            .recursive_directories
+            .iter()
+            .chain(self.direct_directories.iter())
+            .cloned()
+            .collect();
+
+        self.excluded_paths.clear();
+        for path in paths {
+            let Ok(canonical) = canonical_output(path) else {
+                continue;
+            };
+
+            for root in &roots {
+                let Ok(canonical_root) = root.canonicalize() else {
+                    continue;
+                };
+                if let Ok(relative) = canonical.strip_prefix(&canonical_root) {
+                    let resolved = root.join(relative);
+                    if self.excluded_paths.contains(&resolved) {
+                        continue;
+                    }
+                    if self.debug {
+                        Logger::debug(format!(
+                            "excluding own output {:?} from watch root {:?}",
+                            resolved, root
+                        ));
+                    }
+                    self.excluded_paths.push(resolved);
+                }
+            }
         }
+    }
+
+    // replaces IN_ALL_EVENTS (or IN_OPEN with --low-resource) on every watch
+    pub fn set_watch_events(&mut self, mask: Option<u32>) {
+        self.watch_events = mask;
+    }
+
+    // per-directory masks, subdirectories of a recursive watch inherit them
+    pub fn set_watch_masks(&mut self, masks: Vec<(PathBuf, u32)>) {
+        self.watch_masks = masks;
+    }
+
+    fn watch_mask(&self, path: &Path) -> u32 {
+        self.watch_masks
+            .iter()
+            .filter(|(directory, _)| path.starts_with(directory))
+            .max_by_key(|(directory, _)| directory.components().count())
+            .map(|(_, mask)| *mask)
+            .or(self.watch_events)
+            .unwrap_or(if self.low_resource {
+                IN_OPEN
+            } else {
+                IN_ALL_EVENTS
+            })
+    }
 
-        for directory in direct_dirs {
-            self.add_watch(&directory, false)?;
+    pub fn set_watch_exclude(&mut self, globs: &[Glob]) -> Result<()> {
+        (self.excluded_globs, self.excluded_names) = watch_exclude_sets(globs)?;
+        Ok(())
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.excluded_paths
+            .iter()
+            .any(|excluded| path.starts_with(excluded))
+            || self.excluded_globs.is_match(path)
+            || path
+                .file_name()
+                .is_some_and(|name| self.excluded_names.is_match(name))
+    }
+
+    // the requests are picked up when the reactor is woken
+    pub fn watch_requests(&mut self) -> Sender<PathBuf> {
+        let (tx, rx) = mpsc::channel();
+        self.watch_requests = Some(rx);
+        tx
+    }
+
+    pub fn set_max_watches(&mut self, max_watches: Option<usize>) {
+        self.max_watches = max_watches;
+    }
+
+    pub fn setup_watches(&mut self) -> Result<()> {
+        let mut directories = Vec::new();
+        for directory in &self.recursive_directories {
+            directories.extend(self.walk(directory, true));
+        }
+        for directory in &self.direct_directories {
+            directories.extend(self.walk(directory, false));
+        }
+        // stable, so a cut drops the deepest directories of every root first and always
+        // the same ones
+        directories.sort_by_key(|(depth, _, _)| *depth);
+        let mut report = WatchReport::default();
+
+        let kernel_limit = max_user_watches();
+        self.watch_budget = self
+            .max_watches
+            .unwrap_or(usize::MAX)
+            .min(kernel_limit.unwrap_or(usize::MAX));
+
+        if directories.len() > self.watch_budget {
+            let skipped = &directories[self.watch_budget..];
+            let message = format!(
+                "{} directories need inotify watches, only the {} least nested are watched, {} are not (e.g. {:?})",
+                directories.len(),
+                self.watch_budget,
+                skipped.len(),
+                skipped[0].1
+            );
+            if self.max_watches.is_some_and(|max| max == self.watch_budget) {
+                Logger::info(format!("--max-watches: {}", message));
+            } else {
+                Logger::error(format!(
+                    "fs.inotify.max_user_watches is {}: {}, raise it with sysctl or narrow the watched directories",
+                    self.watch_budget, message
+                ));
+            }
+            report.over_limit = skipped.len();
+            directories.truncate(self.watch_budget);
+        } else if self.debug {
+            Logger::debug(format!(
+                "{} directories to watch, inotify limit {:?}",
+                directories.len(),
+                kernel_limit
+            ));
         }
 
+        for (_, directory, is_recursive) in directories {
+            match self.add_watch_single(&directory, is_recursive)? {
+                Watch::Added => report.watched += 1,
+                Watch::Denied => report.denied += 1,
+                Watch::OverLimit => report.over_limit += 1,
+                Watch::Failed => report.failed += 1,
+            }
+        }
+        // failures below --debug are not printed one by one, this is the coverage
+        Logger::info(report.to_string());
+        self.report = report;
         Ok(())
     }
 
+    // of setup_watches, the watches added while running are not counted
+    pub fn watch_report(&self) -> WatchReport {
+        self.report
+    }
+
+    // (depth, path, recursive) for every directory that needs a watch, excluded directories
+    // are not descended into so their subtrees cost no watches
+    fn walk(&self, path: &Path, is_recursive: bool) -> Vec<(usize, PathBuf, bool)> {
+        if self.is_excluded(path) {
+            return Vec::new();
+        }
+        if !is_recursive {
+            return vec![(0, path.to_path_buf(), false)];
+        }
+        WalkDir::new(path)
+            .follow_links(true)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| !self.is_excluded(e.path()))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_dir())
+            .map(|e| (e.depth(), e.into_path(), true))
+            .collect()
+    }
+
+    // subtrees that show up while running, they share the budget of the initial watches
     fn add_watch(&mut self, path: &Path, is_recursive: bool) -> Result<()> {
-        if is_recursive {
-            for entry in WalkDir::new(path)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.file_type().is_dir())
-            {
-                self.add_watch_single(entry.path())?;
+        for (_, directory, is_recursive) in self.walk(path, is_recursive) {
+            if self.wd_to_path.len() >= self.watch_budget {
+                if !self.watch_limit_reached {
+                    self.watch_limit_reached = true;
+                    Logger::error(format!(
+                        "inotify watch budget of {} used up, {:?} and later new directories are not watched",
+                        self.watch_budget, directory
+                    ));
+                }
+                break;
             }
-        } else {
-            self.add_watch_single(path)?;
+            self.add_watch_single(&directory, is_recursive)?;
         }
         Ok(())
     }
 
-    fn add_watch_single(&mut self, path: &Path) -> Result<()> {
+    fn add_watch_single(&mut self, path: &Path, is_recursive: bool) -> Result<Watch> {
         let path_str = match path.to_str() {
             Some(s) => std::ffi::CString::new(s)
                 .map_err(|e| format!("failed to create CString for path {:?}: {}", path, e))?,
             None => {
                 Logger::error(format!("path contains invalid UTF-8: {:?}", path));
-                return Ok(());
+                return Ok(Watch::Failed);
             }
         };
 
-        let wd = unsafe {
-            inotify_add_watch(
-                self.fd,
-                path_str.as_ptr(),
-                if self.low_resource {
-                    IN_OPEN
-                } else {
-                    IN_ALL_EVENTS
-                },
-            )
+        let mask = self.watch_mask(path);
+        // new subdirectories have to be seen to be watched and moved ones to be dropped,
+        // whatever the mask says
+        let kernel_mask = if is_recursive {
+            mask | IN_CREATE | IN_MOVED_TO | IN_MOVE_SELF
+        } else {
+            mask | IN_MOVE_SELF
         };
+        let wd = unsafe { inotify_add_watch(self.fd, path_str.as_ptr(), kernel_mask) };
 
         if wd != -1 {
             self.wd_to_path.insert(wd, path.to_path_buf());
+            METRICS.set_inotify_watches(self.wd_to_path.len());
+            self.wd_masks.insert(wd, mask);
+            if is_recursive {
+                self.recursive_wds.insert(wd);
+            }
+            if !path.is_dir() {
+                self.file_wds.insert(wd);
+            }
             if self.debug {
                 Logger::debug(format!("watching: {:?} (wd={})", path, wd));
             }
+            return Ok(Watch::Added);
+        }
+
+        let err = io::Error::last_os_error();
+        // watches of other programs count against the same per-user limit
+        if err.raw_os_error() == Some(libc::ENOSPC) {
+            if !self.watch_limit_reached {
+                self.watch_limit_reached = true;
+                Logger::error(format!(
+                    "inotify watch limit reached at {:?}, further directories are not watched (fs.inotify.max_user_watches)",
+                    path
+                ));
+            }
+            return Ok(Watch::OverLimit);
+        }
+        if self.debug || err.kind() != io::ErrorKind::PermissionDenied {
+            Logger::error(format!("failed to monitor {:?}: {}", path, err));
+        }
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            Ok(Watch::Denied)
         } else {
-            let err = io::Error::last_os_error();
-            if self.debug || err.kind() != io::ErrorKind::PermissionDenied {
-                Logger::error(format!("failed to monitor {:?}: {}", path, err));
+            Ok(Watch::Failed)
+        }
+    }
+
+    fn forget_watch(&mut self, wd: i32) {
+        if let Some(path) = self.wd_to_path.remove(&wd)
+            && self.debug
+        {
+            Logger::debug(format!("watch removed: {:?} (wd={})", path, wd));
+        }
+        METRICS.set_inotify_watches(self.wd_to_path.len());
+        self.wd_masks.remove(&wd);
+        self.recursive_wds.remove(&wd);
+        self.file_wds.remove(&wd);
+    }
+
+    // the watches below a directory that moved out of the watched trees would report
+    // events under paths that no longer exist
+    fn drop_moved_watches(&mut self, wd: i32) {
+        let Some(path) = self.wd_to_path.get(&wd).cloned() else {
+            return;
+        };
+        if path.exists() {
+            // already walked again at its new place, or replaced by a new directory
+            return;
+        }
+
+        let stale: Vec<i32> = self
+            .wd_to_path
+            .iter()
+            .filter(|(_, watched)| watched.starts_with(&path))
+            .map(|(wd, _)| *wd)
+            .collect();
+        for wd in stale {
+            unsafe {
+                libc::inotify_rm_watch(self.fd, wd);
             }
+            self.forget_watch(wd);
         }
-        Ok(())
     }
 
-    pub fn start_watching(self) -> Result<()> {
-        let sender = self.sender.clone();
-        let trigger_sender = self.trigger_sender.clone();
-        let wd_to_path = self.wd_to_path.clone();
-        let print_events = self.print_events;
-        let fd = self.fd;
-        let debug = self.debug;
-
-        thread::spawn(move || {
-            let _watcher = self;
-            let mut buffer = [0u8; BUFFER_SIZE];
-
-            loop {
-                let read_result = read_events(fd, &mut buffer);
-
-                match read_result {
-                    Ok(read_size) => {
-                        let mut offset = 0;
-                        let mut has_events = false;
-
-                        while offset < read_size {
-                            let event =
-                                unsafe { &*(buffer.as_ptr().add(offset) as *const InotifyEvent) };
-
-                            has_events = true;
-
-                            if print_events
-                                && let Some(path) = wd_to_path.get(&event.wd)
-                            {
-                                let event_str = format!(
-                                    "events: {} on {:?}",
-                                    Self::get_event_string(event.mask),
-                                    path
-                                );
-                                if let Err(e) = sender.send(event_str) {
-                                    Logger::error(format!("failed to send event: {}", e));
-                                }
-                            }
-
-                            if debug && let Some(path) = wd_to_path.get(&event.wd) {
-                                Logger::debug(format!(
-                                    "inotify event: mask={:x} ({}) on {:?}",
-                                    event.mask,
-                                    Self::get_event_string(event.mask),
-                                    path
-                                ));
-                            }
-
-                            offset += std::mem::size_of::<InotifyEvent>() + event.len as usize;
-                        }
-
-                        // send only one trigger per batch of events to avoid flooding
-                        if has_events {
-                            if let Err(e) = trigger_sender.send(()) {
-                                Logger::error(format!("failed to send trigger: {}", e));
-                            } else if debug {
-                                Logger::debug(
-                                    "sent process scan trigger due to filesystem events"
-                                        .to_string(),
-                                );
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        Logger::error(format!("error reading events: {}", e));
-                        break;
-                    }
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // reads and handles whatever is queued, a non-blocking fd gives WouldBlock when empty
+    pub fn read_batch(&mut self) -> io::Result<()> {
+        let mut buffer = self
+            .buffer
+            .take()
+            .unwrap_or_else(|| Box::new(EventBuffer([0; BUFFER_SIZE])));
+        let result = read_events(self.fd, &mut buffer.0);
+        if let Ok(read_size) = result {
+            self.handle_events(InotifyRecords {
+                buffer: &buffer.0[..read_size],
+            });
+        }
+        self.buffer = Some(buffer);
+        result.map(|_| ())
+    }
+
+    fn handle_events(&mut self, records: InotifyRecords) {
+        let mut has_events = false;
+        let mut new_directories = Vec::new();
+        let mut moved_directories = Vec::new();
+        let mut replaced_files = Vec::new();
+
+        for event in records {
+            let name = event.name;
+
+            METRICS.record_inotify_events(1);
+            if event.mask & IN_Q_OVERFLOW != 0 {
+                METRICS.record_inotify_overflow();
+                Logger::debug("inotify queue overflowed, events were dropped");
+            }
+
+            // the kernel removed the watch, its directory was deleted, unmounted or the
+            // watch was dropped below
+            if event.mask & IN_IGNORED != 0 {
+                if self.file_wds.contains(&event.wd)
+                    && let Some(path) = self.wd_to_path.get(&event.wd)
+                {
+                    replaced_files.push((event.wd, path.clone()));
+                }
+                self.forget_watch(event.wd);
+                continue;
+            }
+            if event.mask & IN_MOVE_SELF != 0 {
+                if let Some(path) = self.wd_to_path.get(&event.wd)
+                    && self.file_wds.contains(&event.wd)
+                {
+                    replaced_files.push((event.wd, path.clone()));
+                } else {
+                    moved_directories.push(event.wd);
                 }
             }
-        });
 
-        Ok(())
+            let path = self.wd_to_path.get(&event.wd);
+            if let Some(path) = path
+                && self.is_excluded(&path.join(name))
+            {
+                continue;
+            }
+
+            let wanted = self
+                .wd_masks
+                .get(&event.wd)
+                .is_none_or(|mask| event.mask & mask != 0);
+            if wanted {
+                has_events = true;
+            }
+
+            let Some(path) = path else {
+                continue;
+            };
+
+            // subtrees created after startup would otherwise never be watched
+            if event.mask & IN_ISDIR != 0
+                && event.mask & (IN_CREATE | IN_MOVED_TO) != 0
+                && self.recursive_wds.contains(&event.wd)
+            {
+                new_directories.push(path.join(name));
+            }
+
+            if !wanted {
+                continue;
+            }
+
+            // changes to a file watched on its own are always reported, reads only with -f
+            let file_modified =
+                self.file_wds.contains(&event.wd) && event.mask & FILE_MODIFIED_MASK != 0;
+            if self.print_events || file_modified {
+                let fs_event = FsEvent {
+                    events: Self::get_event_string(event.mask),
+                    path: path.clone(),
+                    name: (!name.is_empty()).then(|| name.to_string_lossy().into_owned()),
+                    file_modified,
+                    pid: None,
+                    process: None,
+                    content: None,
+                    observed_at: SystemTime::now(),
+                };
+                if let Err(e) = self.sender.send(Event::Fs(fs_event)) {
+                    Logger::error(format!("failed to send event: {}", e));
+                }
+            }
+
+            if self.debug {
+                Logger::debug(format!(
+                    "inotify event: mask={:x} ({}) on {:?}",
+                    event.mask,
+                    Self::get_event_string(event.mask),
+                    path.join(name)
+                ));
+            }
+        }
+
+        for wd in moved_directories {
+            self.drop_moved_watches(wd);
+        }
+
+        // editors and tools like passwd replace a file by renaming a new one over it, the
+        // watch stays with the old inode and has to follow the path instead
+        for (wd, path) in replaced_files {
+            if self.wd_to_path.contains_key(&wd) {
+                unsafe {
+                    libc::inotify_rm_watch(self.fd, wd);
+                }
+                self.forget_watch(wd);
+            }
+            if path.exists()
+                && let Err(e) = self.add_watch_single(&path, false)
+            {
+                Logger::error(format!("failed to watch replaced file {:?}: {}", path, e));
+            }
+        }
+
+        // a directory renamed inside a recursive watch is walked again, its subdirectories
+        // keep their watch descriptors and only get their new paths
+        for directory in new_directories {
+            if let Err(e) = self.add_watch(&directory, true) {
+                Logger::error(format!(
+                    "failed to watch new directory {:?}: {}",
+                    directory, e
+                ));
+            }
+        }
+
+        // send only one trigger per batch of events to avoid flooding
+        if has_events {
+            if let Err(e) = self.trigger_sender.send(()) {
+                Logger::error(format!("failed to send trigger: {}", e));
+            } else if self.debug {
+                Logger::debug("sent process scan trigger due to filesystem events".to_string());
+            }
+        }
     }
 }
 
-fn read_events(fd: RawFd, buffer: &mut [u8]) -> io::Result<usize> {
+pub fn max_user_watches() -> Option<usize> {
+    std::fs::read_to_string(INOTIFY_MAX_USER_WATCHES_FILE)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+// files like sqlite journals may only be created later, resolve their directory instead
+pub(crate) fn canonical_output(path: &Path) -> io::Result<PathBuf> {
+    path.canonicalize()
+        .or_else(|_| match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+                std::env::current_dir().map(|cwd| cwd.join(name))
+            }
+            (Some(parent), Some(name)) => parent.canonicalize().map(|parent| parent.join(name)),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        })
+}
+
+// --watch-exclude as (patterns matching the whole path, patterns matching the entry name)
+pub(crate) fn watch_exclude_sets(globs: &[Glob]) -> Result<(GlobSet, GlobSet)> {
+    let mut paths = GlobSetBuilder::new();
+    let mut names = GlobSetBuilder::new();
+    for glob in globs {
+        if glob.glob().contains('/') {
+            paths.add(glob.clone());
+        } else {
+            names.add(glob.clone());
+        }
+    }
+    let build = |builder: GlobSetBuilder| {
+        builder
+            .build()
+            .map_err(|e| format!("invalid --watch-exclude pattern: {}", e))
+    };
+    Ok((build(paths)?, build(names)?))
+}
+
+pub(crate) fn read_events(fd: RawFd, buffer: &mut [u8]) -> io::Result<usize> {
     let read_size =
         unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
 
@@ -260,6 +780,41 @@ fn read_events(fd: RawFd, buffer: &mut [u8]) -> io::Result<usize> {
     }
 }
 
+impl Source for FsWatcher {
+    fn name(&self) -> &'static str {
+        "inotify watcher"
+    }
+
+    fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    fn on_wake(&mut self) {
+        let Some(requests) = self.watch_requests.as_ref() else {
+            return;
+        };
+        let paths: Vec<PathBuf> = requests.try_iter().collect();
+        for path in paths {
+            let is_recursive = path.is_dir();
+            match self.add_watch(&path, is_recursive) {
+                Ok(()) => Logger::info(format!("added watch on {}", path.display())),
+                Err(e) => Logger::error(format!("failed to watch {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    fn on_readable(&mut self) -> io::Result<()> {
+        loop {
+            match self.read_batch() {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
 impl Drop for FsWatcher {
     fn drop(&mut self) {
         unsafe {
@@ -267,3 +822,85 @@ impl Drop for FsWatcher {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one record as the kernel lays it out, the name NUL padded to len bytes
+    fn record(buffer: &mut Vec<u8>, wd: i32, mask: u32, name: &[u8], len: u32) {
+        buffer.extend_from_slice(&wd.to_ne_bytes());
+        buffer.extend_from_slice(&mask.to_ne_bytes());
+        buffer.extend_from_slice(&0u32.to_ne_bytes());
+        buffer.extend_from_slice(&len.to_ne_bytes());
+        buffer.extend_from_slice(name);
+        buffer.resize(buffer.len() + len as usize - name.len(), 0);
+    }
+
+    #[test]
+    fn records() {
+        let mut buffer = Vec::new();
+        record(&mut buffer, 1, IN_CREATE, b"new file", 16);
+        record(&mut buffer, 2, IN_DELETE_SELF, b"", 0);
+        record(&mut buffer, 3, IN_MOVED_TO | IN_ISDIR, b"dir", 4);
+
+        let records: Vec<_> = InotifyRecords { buffer: &buffer }
+            .map(|record| (record.wd, record.mask, record.name.to_os_string()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (1, IN_CREATE, "new file".into()),
+                (2, IN_DELETE_SELF, "".into()),
+                (3, IN_MOVED_TO | IN_ISDIR, "dir".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn longest_name() {
+        let name = vec![b'a'; libc::NAME_MAX as usize];
+        let mut buffer = Vec::new();
+        record(
+            &mut buffer,
+            1,
+            IN_CLOSE_WRITE,
+            &name,
+            libc::NAME_MAX as u32 + 1,
+        );
+
+        let mut records = InotifyRecords { buffer: &buffer };
+        assert_eq!(records.next().unwrap().name.as_bytes(), name);
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn truncated_record() {
+        let mut buffer = Vec::new();
+        record(&mut buffer, 1, IN_CREATE, b"complete", 16);
+        record(&mut buffer, 2, IN_CREATE, b"truncated", 16);
+
+        // the second record loses part of its name, all of it, then part of its header
+        for cut in [4, 16, EVENT_SIZE + 12] {
+            let mut records = InotifyRecords {
+                buffer: &buffer[..buffer.len() - cut],
+            };
+            assert_eq!(records.next().unwrap().name, "complete");
+            assert!(records.next().is_none());
+        }
+    }
+
+    #[test]
+    fn watch_events() {
+        assert_eq!(
+            parse_watch_events("create, MOVED_TO,,"),
+            Ok(IN_CREATE | IN_MOVED_TO)
+        );
+        assert_eq!(
+            parse_watch_events("close"),
+            Ok(IN_CLOSE_WRITE | IN_CLOSE_NOWRITE)
+        );
+        assert!(parse_watch_events("created").is_err());
+        assert!(parse_watch_events(" , ").is_err());
+    }
+}
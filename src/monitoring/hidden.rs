@@ -0,0 +1,247 @@
+use rustc_hash::FxHashSet;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::core::{
+    constants::{HIDDEN_MAX_PROBE_RANGE, HIDDEN_TASK_TOLERANCE},
+    error::{Result, RsSpyError},
+    logger::Logger,
+};
+#[cfg(feature = "dbus")]
+use crate::monitoring::dbus;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::throttle::{self, Priority};
+
+struct ProcListing {
+    pids: FxHashSet<u32>,
+    tasks: usize,
+}
+
+struct LoadAvg {
+    total_tasks: usize,
+    last_pid: u32,
+}
+
+pub struct HiddenProcessDetector {
+    interval: Duration,
+    #[cfg(feature = "dbus")]
+    use_dbus: bool,
+    reported_pids: FxHashSet<u32>,
+    last_pid: Option<u32>,
+    task_gap_streak: u32,
+    task_gap_reported: bool,
+}
+
+impl HiddenProcessDetector {
+    pub fn new(interval: Duration, use_dbus: bool) -> Self {
+        #[cfg(not(feature = "dbus"))]
+        let _ = use_dbus;
+
+        Self {
+            interval,
+            #[cfg(feature = "dbus")]
+            use_dbus,
+            reported_pids: FxHashSet::default(),
+            last_pid: None,
+            task_gap_streak: 0,
+            task_gap_reported: false,
+        }
+    }
+
+    pub fn start(mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Worker {
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            #[cfg(feature = "dbus")]
+            let proxy = if self.use_dbus {
+                dbus::connect_manager()
+                    .map_err(|e| {
+                        Logger::error(format!(
+                            "hidden process detection continues without dbus: {}",
+                            e
+                        ))
+                    })
+                    .ok()
+            } else {
+                None
+            };
+
+            // the kernel task count is global, a child pid namespace only sees its own tasks
+            let compare_task_count = !in_child_pid_namespace();
+            if !compare_task_count {
+                Logger::debug(
+                    "running in a child pid namespace, skipping /proc/loadavg task count comparison"
+                        .to_string(),
+                );
+            }
+
+            while running.load(Ordering::SeqCst) {
+                #[cfg(feature = "dbus")]
+                let dbus_pids = proxy.as_ref().and_then(|proxy| {
+                    dbus::slice_processes(proxy)
+                        .map_err(|e| {
+                            Logger::debug(format!("hidden process check: dbus poll failed: {}", e))
+                        })
+                        .ok()
+                        .map(|listing| {
+                            listing
+                                .processes
+                                .into_iter()
+                                .map(|(_, pid, _)| pid)
+                                .collect()
+                        })
+                });
+                #[cfg(not(feature = "dbus"))]
+                let dbus_pids: Option<Vec<u32>> = None;
+
+                if let Err(e) = self.check(dbus_pids, compare_task_count) {
+                    Logger::debug(format!("hidden process check failed: {}", e));
+                }
+
+                shutdown::sleep(&running, self.interval);
+            }
+        });
+        ("hidden process detector", worker)
+    }
+
+    fn check(&mut self, dbus_pids: Option<Vec<u32>>, compare_task_count: bool) -> Result<()> {
+        let before = list_proc()?;
+        let loadavg = read_loadavg()?;
+
+        let mut candidates: Vec<(u32, &'static str)> = Vec::new();
+
+        if let Some(dbus_pids) = dbus_pids {
+            candidates.extend(
+                dbus_pids
+                    .into_iter()
+                    .filter(|pid| !before.pids.contains(pid))
+                    .map(|pid| (pid, "systemd slice")),
+            );
+        }
+
+        // pids allocated since the last check should either be listed, be threads, or be gone
+        if let Some(previous) = self.last_pid
+            && loadavg.last_pid > previous
+            && loadavg.last_pid - previous <= HIDDEN_MAX_PROBE_RANGE
+        {
+            candidates.extend(
+                (previous + 1..=loadavg.last_pid)
+                    .filter(|pid| !before.pids.contains(pid))
+                    .map(|pid| (pid, "pid allocation")),
+            );
+        }
+        self.last_pid = Some(loadavg.last_pid);
+
+        let candidates: Vec<(u32, &'static str, &'static str)> = candidates
+            .into_iter()
+            .filter(|(pid, _)| !self.reported_pids.contains(pid))
+            .filter_map(|(pid, source)| probe_pid(pid).map(|evidence| (pid, source, evidence)))
+            .collect();
+
+        let after = list_proc()?;
+
+        for (pid, source, evidence) in candidates {
+            if after.pids.contains(&pid) || !self.reported_pids.insert(pid) {
+                continue;
+            }
+            Logger::alert(format!(
+                "possible hidden process: PID {} seen via {} and {} but missing from /proc listing",
+                pid, source, evidence
+            ));
+        }
+
+        if compare_task_count {
+            let listed_tasks = before.tasks.max(after.tasks);
+            if loadavg.total_tasks > listed_tasks + HIDDEN_TASK_TOLERANCE {
+                self.task_gap_streak += 1;
+                // require two consecutive mismatches so a fork burst between reads is not reported
+                if self.task_gap_streak >= 2 && !self.task_gap_reported {
+                    self.task_gap_reported = true;
+                    Logger::alert(format!(
+                        "possible hidden tasks: kernel reports {} tasks in /proc/loadavg but only {} are listed in /proc",
+                        loadavg.total_tasks, listed_tasks
+                    ));
+                }
+            } else {
+                self.task_gap_streak = 0;
+                self.task_gap_reported = false;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn list_proc() -> Result<ProcListing> {
+    let mut pids = FxHashSet::default();
+    let mut tasks = 0;
+
+    for entry in fs::read_dir("/proc")?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        pids.insert(pid);
+        tasks += fs::read_dir(entry.path().join("task"))
+            .map(|tasks| tasks.count())
+            .unwrap_or(1);
+    }
+
+    Ok(ProcListing { pids, tasks })
+}
+
+fn read_loadavg() -> Result<LoadAvg> {
+    // e.g. "0.36 0.32 0.15 2/74 7016"
+    let content = fs::read_to_string("/proc/loadavg")?;
+    let mut fields = content.split_whitespace().skip(3);
+
+    let total_tasks = fields
+        .next()
+        .and_then(|field| field.split('/').nth(1))
+        .and_then(|total| total.parse().ok());
+    let last_pid = fields.next().and_then(|field| field.parse().ok());
+
+    match (total_tasks, last_pid) {
+        (Some(total_tasks), Some(last_pid)) => Ok(LoadAvg {
+            total_tasks,
+            last_pid,
+        }),
+        _ => Err(RsSpyError::Scanner(format!(
+            "unexpected /proc/loadavg format: {}",
+            content.trim()
+        ))),
+    }
+}
+
+// returns how the pid was confirmed to be a live process (not a thread), if it is one
+fn probe_pid(pid: u32) -> Option<&'static str> {
+    if let Ok(status) = fs::read_to_string(format!("/proc/{}/status", pid)) {
+        let tgid = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Tgid:"))
+            .and_then(|tgid| tgid.trim().parse::<u32>().ok());
+        return (tgid == Some(pid)).then_some("a reachable /proc entry");
+    }
+
+    let alive = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    alive.then_some("kill(0) without a /proc entry")
+}
+
+fn in_child_pid_namespace() -> bool {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("NSpid:"))
+                .map(|pids| pids.split_whitespace().count() > 1)
+        })
+        .unwrap_or(false)
+}
@@ -1,4 +1,16 @@
+pub mod audit;
+pub mod contents;
+#[cfg(feature = "dbus")]
 pub mod dbus;
+pub mod fanotify;
 pub mod filesystem;
+pub mod hidden;
+pub mod network;
+#[cfg(feature = "dbus")]
+pub mod polkit;
 pub mod process;
+pub mod reactor;
 pub mod scanner;
+pub mod schedulers;
+pub mod sessions;
+pub mod udev;
@@ -0,0 +1,173 @@
+use rustc_hash::FxHashSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::core::event::{ConnectionEvent, Event};
+use crate::core::logger::Logger;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::sockets::{self, InetSocket, TCP_ESTABLISHED, TCP_LISTEN, UnixSocket};
+use crate::utils::throttle::{self, Priority};
+use crate::utils::{fds, process::process_name};
+
+const TCP_SYN_SENT: u8 = 0x02;
+const TCP_SYN_RECV: u8 = 0x03;
+
+// a reportable socket before its owner has been looked up
+struct Observed {
+    inode: u64,
+    protocol: String,
+    local: String,
+    remote: Option<String>,
+    uid: Option<u32>,
+}
+
+// diffs the socket tables of rspy's network namespace, sockets that exist when it starts
+// are not reported
+pub struct NetworkMonitor {
+    sender: Sender<Event>,
+    interval: Duration,
+    inet: bool,
+    unix: bool,
+    // only sockets that were reportable, one that is bound now and listens later is
+    // reported once it listens
+    known: FxHashSet<u64>,
+    own_pid: u32,
+}
+
+impl NetworkMonitor {
+    pub fn new(sender: Sender<Event>, interval: Duration) -> Self {
+        Self {
+            sender,
+            interval,
+            inet: true,
+            unix: false,
+            known: FxHashSet::default(),
+            own_pid: std::process::id(),
+        }
+    }
+
+    pub fn set_inet(&mut self, enabled: bool) {
+        self.inet = enabled;
+    }
+
+    pub fn set_unix_sockets(&mut self, enabled: bool) {
+        self.unix = enabled;
+    }
+
+    pub fn start(mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Worker {
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            self.known = self.observe().iter().map(|socket| socket.inode).collect();
+
+            while shutdown::sleep(&running, self.interval) {
+                if let Err(e) = self.poll() {
+                    Logger::error(format!("network monitor stopped: {}", e));
+                    break;
+                }
+            }
+        });
+        ("network monitor", worker)
+    }
+
+    fn observe(&self) -> Vec<Observed> {
+        let net = Path::new("/proc/net");
+        let mut observed = Vec::new();
+        if self.inet {
+            observed.extend(
+                sockets::inet_sockets(net)
+                    .into_iter()
+                    .filter(is_reportable)
+                    .map(|socket| {
+                        let listening = socket.is_listening();
+                        Observed {
+                            inode: socket.inode,
+                            protocol: socket.protocol.to_string(),
+                            local: socket.local.to_string(),
+                            remote: (!listening).then(|| socket.remote.to_string()),
+                            uid: Some(socket.uid),
+                        }
+                    }),
+            );
+        }
+        if self.unix {
+            observed.extend(
+                sockets::unix_sockets(net)
+                    .into_iter()
+                    .filter(|socket| socket.listening && socket.inode != 0)
+                    .filter_map(|socket: UnixSocket| {
+                        Some(Observed {
+                            inode: socket.inode,
+                            protocol: "unix".to_string(),
+                            local: socket.path?,
+                            remote: None,
+                            // /proc/net/unix has no uid column, it is the owner's
+                            uid: None,
+                        })
+                    }),
+            );
+        }
+        observed
+    }
+
+    fn poll(&mut self) -> std::result::Result<(), String> {
+        let observed = self.observe();
+        let current: FxHashSet<u64> = observed.iter().map(|socket| socket.inode).collect();
+        let new: Vec<Observed> = observed
+            .into_iter()
+            .filter(|socket| !self.known.contains(&socket.inode))
+            .collect();
+        self.known = current;
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        let inodes = new.iter().map(|socket| socket.inode).collect();
+        let owners = fds::socket_owners(&inodes);
+        for socket in new {
+            let pid = owners.get(&socket.inode).map(|&pid| pid as u32);
+            if pid == Some(self.own_pid) {
+                continue;
+            }
+
+            let uid = socket.uid.or_else(|| {
+                let pid = pid?;
+                std::fs::metadata(format!("/proc/{}", pid))
+                    .ok()
+                    .map(|metadata| metadata.uid())
+            });
+            let event = Event::Connection(ConnectionEvent {
+                protocol: socket.protocol,
+                local: socket.local,
+                remote: socket.remote,
+                uid,
+                pid,
+                process: pid.map(|pid| process_name(pid as i32)),
+                observed_at: SystemTime::now(),
+            });
+            self.sender
+                .send(event)
+                .map_err(|e| format!("failed to send connection event: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+// sockets in TIME_WAIT and similar states have no inode and no owner left, new tcp
+// sockets that are closing already were never interesting on their own
+fn is_reportable(socket: &InetSocket) -> bool {
+    if socket.inode == 0 {
+        return false;
+    }
+    match socket.protocol {
+        "tcp" => matches!(
+            socket.state,
+            TCP_ESTABLISHED | TCP_SYN_SENT | TCP_SYN_RECV | TCP_LISTEN
+        ),
+        _ => true,
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::SystemTime;
+use zbus::message::{Message, Type};
+use zbus::zvariant::OwnedValue;
+
+use crate::core::{
+    constants::POLKIT_PENDING_CAPACITY,
+    error::Result,
+    event::{AuthorizationEvent, AuthorizationResult, Event},
+    logger::Logger,
+};
+use crate::monitoring::dbus::{BusMonitor, describe_pid};
+use crate::utils::lru::Lru;
+use crate::utils::procparse::ProcReader;
+use crate::utils::shutdown::Worker;
+use crate::utils::throttle::{self, Priority};
+
+const POLKIT_RULES: [&str; 3] = [
+    "type='method_call',destination='org.freedesktop.PolicyKit1',interface='org.freedesktop.PolicyKit1.Authority',member='CheckAuthorization'",
+    "type='method_return',sender='org.freedesktop.PolicyKit1'",
+    "type='error',sender='org.freedesktop.PolicyKit1'",
+];
+
+// ((kind, details), action id, details, flags, cancellation id)
+type CheckAuthorization = (
+    (String, HashMap<String, OwnedValue>),
+    String,
+    HashMap<String, String>,
+    u32,
+    String,
+);
+
+// (is authorized, is challenge, details)
+type AuthorizationReply = (bool, bool, HashMap<String, String>);
+
+// what is known when the check is asked, the subject is often gone by the answer
+struct PendingCheck {
+    action: String,
+    subject_pid: Option<u32>,
+    subject_uid: Option<u32>,
+    subject: Option<String>,
+    caller_pid: Option<u32>,
+    caller: Option<String>,
+    command: Option<String>,
+}
+
+// --polkit, watches CheckAuthorization calls and polkit's answers to them. with user
+// interaction allowed the answer only comes once the password prompt is done
+pub struct PolkitMonitor {
+    sender: Sender<Event>,
+    reader: ProcReader,
+    // by (caller, serial of the call), which the answer refers to
+    pending: Lru<(String, u32), PendingCheck>,
+}
+
+impl PolkitMonitor {
+    pub fn new(sender: Sender<Event>) -> Self {
+        PolkitMonitor {
+            sender,
+            reader: ProcReader::new(),
+            pending: Lru::new("polkit check", POLKIT_PENDING_CAPACITY),
+        }
+    }
+
+    pub fn start(mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Result<Worker> {
+        let mut monitor = BusMonitor::become_monitor(&POLKIT_RULES)?;
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            while let Some(message) = monitor.next(&running) {
+                match message.header().message_type() {
+                    Type::MethodCall => self.handle_check(&mut monitor, &message),
+                    Type::MethodReturn | Type::Error => self.handle_answer(&message),
+                    Type::Signal => {}
+                }
+            }
+        });
+        Ok(("polkit monitor", worker))
+    }
+
+    fn handle_check(&mut self, monitor: &mut BusMonitor, message: &Message) {
+        let header = message.header();
+        let Some(caller_name) = header.sender().map(|name| name.to_string()) else {
+            return;
+        };
+        let serial = header.primary().serial_num().get();
+        let ((kind, subject), action, details, _, _): CheckAuthorization =
+            match message.body().deserialize() {
+                Ok(body) => body,
+                Err(e) => {
+                    Logger::debug(format!("malformed CheckAuthorization call: {}", e));
+                    return;
+                }
+            };
+
+        let subject_pid = match kind.as_str() {
+            "unix-process" => subject
+                .get("pid")
+                .and_then(|pid| pid.downcast_ref::<u32>().ok()),
+            "system-bus-name" => subject
+                .get("name")
+                .and_then(|name| name.downcast_ref::<String>().ok())
+                .and_then(|name| monitor.pid_of(&name)),
+            _ => None,
+        };
+        let (proc_uid, subject_cmdline) = describe_pid(&mut self.reader, subject_pid);
+        // -1 when the caller did not pass it
+        let subject_uid = subject
+            .get("uid")
+            .and_then(|uid| uid.downcast_ref::<i32>().ok())
+            .and_then(|uid| u32::try_from(uid).ok())
+            .or(proc_uid);
+        let caller_pid = monitor.pid_of(&caller_name);
+        let (_, caller) = describe_pid(&mut self.reader, caller_pid);
+
+        self.pending.insert(
+            (caller_name, serial),
+            PendingCheck {
+                action,
+                subject_pid,
+                subject_uid,
+                subject: subject_cmdline,
+                caller_pid,
+                caller,
+                command: details.get("command_line").cloned(),
+            },
+        );
+    }
+
+    fn handle_answer(&mut self, message: &Message) {
+        let header = message.header();
+        let (Some(caller), Some(serial)) = (header.destination(), header.reply_serial()) else {
+            return;
+        };
+        let Some(check) = self.pending.remove(&(caller.to_string(), serial.get())) else {
+            return;
+        };
+
+        let result = match header.message_type() {
+            Type::Error => AuthorizationResult::Error(
+                header
+                    .error_name()
+                    .map_or_else(|| "error".to_string(), |name| name.to_string()),
+            ),
+            _ => match message.body().deserialize::<AuthorizationReply>() {
+                Ok((true, _, _)) => AuthorizationResult::Authorized,
+                Ok((false, true, _)) => AuthorizationResult::Challenge,
+                Ok((false, false, _)) => AuthorizationResult::NotAuthorized,
+                Err(e) => AuthorizationResult::Error(e.to_string()),
+            },
+        };
+
+        let event = Event::Authorization(AuthorizationEvent {
+            action: check.action,
+            subject_pid: check.subject_pid,
+            subject_uid: check.subject_uid,
+            subject: check.subject,
+            caller_pid: check.caller_pid,
+            caller: check.caller,
+            command: check.command,
+            result,
+            observed_at: SystemTime::now(),
+        });
+        if let Err(e) = self.sender.send(event) {
+            Logger::error(format!("failed to send polkit event: {}", e));
+        }
+    }
+}
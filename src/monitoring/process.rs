@@ -1,83 +1,894 @@
-use procfs::process::{Process, all_processes};
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::core::{
-    constants::{DEFAULT_NEW_PIDS_CAPACITY, UNKNOWN_COMMAND},
+    constants::{
+        DEFAULT_NEW_PIDS_CAPACITY, DELETED_EXE_SUFFIX, EXE_MULTICALL_BINARIES, FDS_MAX_PER_EVENT,
+        ROOT_UID, TMP_EXEC_DIRS, TREE_MAX_DEPTH, UNKNOWN_COMMAND,
+    },
     error::Result,
+    event::{
+        Capabilities, Event, ExitEvent, ProcessEvent, ProcessImage, ProcessSource, Script,
+        ThreadInfo,
+    },
     logger::Logger,
+    metrics::METRICS,
 };
+#[cfg(feature = "dbus")]
+use crate::monitoring::dbus::UnitResolver;
+use crate::utils::{
+    cgroup, fds,
+    hashcache::HashCache,
+    libraries,
+    preserve::ExePreserver,
+    process::{self, Environ},
+    procparse::{self, ProcReader},
+};
+
+struct TrackedProcess {
+    uid: u32,
+    ppid: u32,
+    cmdline: String,
+    starttime: u64,
+    start_secs: f64,
+}
+
+// (pid, tid, start time of the process in ticks since boot), a process itself is
+// (pid, pid, start) and its other threads only show up with --scan-threads. the start time
+// tells a recycled pid apart from the process that had it before
+type TaskKey = (i32, i32, u64);
 
 pub struct ProcessScanner {
-    seen_pids: FxHashSet<i32>,
-    current_pids: FxHashSet<i32>,
-    new_pids: Vec<i32>,
+    sender: Sender<Event>,
+    reader: ProcReader,
+    seen_tasks: FxHashSet<TaskKey>,
+    current_tasks: FxHashSet<TaskKey>,
+    new_pids: Vec<(i32, u64)>,
+    new_threads: Vec<(i32, i32)>,
+    scan_threads: bool,
+    // (start time, cmdline and exe) per pid with --track-cmdline
+    images: Option<FxHashMap<i32, (u64, ProcessImage)>>,
+    changed_pids: Vec<(i32, u64, ProcessImage)>,
+    show_tree: bool,
+    tracked: Option<FxHashMap<i32, TrackedProcess>>,
+    hash_cache: Option<HashCache>,
+    preserver: Option<ExePreserver>,
+    first_scan: bool,
+    show_cgroup: bool,
+    show_caps: bool,
+    show_cwd: bool,
+    show_fds: bool,
+    // --highlight-tmp-exec, costs a stat per directory of the exe path
+    highlight_tmp_exec: bool,
+    // --check-libraries, reads the maps and parses the ELF headers of every library
+    check_libraries: bool,
+    // --show-unit, asks systemd over dbus
+    #[cfg(feature = "dbus")]
+    units: Option<UnitResolver>,
+    pid_namespace: Option<u64>,
+    own_uid: u32,
+    // processes of other users the first scan could read, none means /proc hides them
+    foreign_processes: usize,
 }
 
 impl ProcessScanner {
-    pub fn new() -> Self {
+    pub fn new(sender: Sender<Event>) -> Self {
         Self {
-            seen_pids: FxHashSet::default(),
-            current_pids: FxHashSet::default(),
+            sender,
+            reader: ProcReader::new(),
+            seen_tasks: FxHashSet::default(),
+            current_tasks: FxHashSet::default(),
             new_pids: Vec::new(),
+            new_threads: Vec::new(),
+            scan_threads: false,
+            images: None,
+            changed_pids: Vec::new(),
+            show_tree: false,
+            tracked: None,
+            hash_cache: None,
+            preserver: None,
+            first_scan: true,
+            show_cgroup: false,
+            show_caps: false,
+            show_cwd: false,
+            show_fds: false,
+            highlight_tmp_exec: false,
+            check_libraries: false,
+            #[cfg(feature = "dbus")]
+            units: None,
+            pid_namespace: pid_namespace("self"),
+            own_uid: unsafe { libc::geteuid() },
+            foreign_processes: 0,
         }
     }
 
+    pub fn set_show_exits(&mut self, show_exits: bool) {
+        self.tracked = show_exits.then(FxHashMap::default);
+    }
+
+    pub fn set_hash_exe(&mut self, hash_exe: bool, capacity: usize) {
+        self.hash_cache = hash_exe.then(|| HashCache::with_capacity(capacity));
+    }
+
+    pub fn set_preserver(&mut self, preserver: Option<ExePreserver>) {
+        self.preserver = preserver;
+    }
+
+    pub fn set_show_cgroup(&mut self, show_cgroup: bool) {
+        self.show_cgroup = show_cgroup;
+    }
+
+    pub fn set_show_caps(&mut self, show_caps: bool) {
+        self.show_caps = show_caps;
+    }
+
+    pub fn set_show_cwd(&mut self, show_cwd: bool) {
+        self.show_cwd = show_cwd;
+    }
+
+    pub fn set_highlight_tmp_exec(&mut self, highlight_tmp_exec: bool) {
+        self.highlight_tmp_exec = highlight_tmp_exec;
+    }
+
+    pub fn set_check_libraries(&mut self, check_libraries: bool) {
+        self.check_libraries = check_libraries;
+    }
+
+    #[cfg(feature = "dbus")]
+    pub fn set_units(&mut self, units: Option<UnitResolver>) {
+        self.units = units;
+    }
+
+    pub fn set_scan_threads(&mut self, scan_threads: bool) {
+        self.scan_threads = scan_threads;
+    }
+
+    pub fn set_track_cmdline(&mut self, track_cmdline: bool) {
+        self.images = track_cmdline.then(FxHashMap::default);
+    }
+
+    pub fn set_show_fds(&mut self, show_fds: bool) {
+        self.show_fds = show_fds;
+    }
+
+    pub fn set_show_tree(&mut self, show_tree: bool) {
+        self.show_tree = show_tree;
+    }
+
     pub fn scan_processes(&mut self) -> Result<usize> {
-        let processes = all_processes()?;
+        let started = Instant::now();
+        let entries = std::fs::read_dir("/proc")?;
 
-        self.current_pids.clear();
-        self.current_pids.reserve(processes.len());
+        self.current_tasks.clear();
+        self.current_tasks.reserve(self.seen_tasks.len());
         self.new_pids.clear();
         self.new_pids.reserve(DEFAULT_NEW_PIDS_CAPACITY);
+        self.new_threads.clear();
 
-        for process in processes {
-            let pid = process.pid();
-            self.current_pids.insert(pid);
+        for entry in entries.flatten() {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            else {
+                continue;
+            };
+            // only the start time is needed to tell whether the process is known, the
+            // rest is read for new processes alone
+            let start = match self.reader.stat(pid, None) {
+                Ok(stat) => stat.starttime,
+                // exited since the directory was listed
+                Err(_) => continue,
+            };
+            self.current_tasks.insert((pid, pid, start));
 
-            if self.seen_pids.insert(pid) {
-                self.new_pids.push(pid);
+            let is_new = self.seen_tasks.insert((pid, pid, start));
+            if is_new {
+                self.new_pids.push((pid, start));
+            } else if self.images.is_some() {
+                self.check_image(pid, start);
+            }
+            if self.scan_threads {
+                self.collect_threads(pid, start, is_new);
             }
         }
 
+        let new_pids = std::mem::take(&mut self.new_pids);
         let mut new_count = 0;
-        for &pid in &self.new_pids {
-            match self.process_new_pid(pid) {
+        for &(pid, start) in &new_pids {
+            match self.process_new_pid(pid, start, None) {
                 Ok(()) => new_count += 1,
                 Err(e) => {
                     Logger::debug(format!("failed to process pid {}: {}", pid, e));
-                    self.seen_pids.remove(&pid);
+                    self.seen_tasks.remove(&(pid, pid, start));
                     continue;
                 }
             }
         }
+        self.new_pids = new_pids;
 
-        self.seen_pids.retain(|pid| self.current_pids.contains(pid));
+        for (pid, start, previous) in std::mem::take(&mut self.changed_pids) {
+            if let Err(e) = self.process_new_pid(pid, start, Some(previous)) {
+                Logger::debug(format!("failed to process changed pid {}: {}", pid, e));
+            }
+        }
+
+        let new_threads = std::mem::take(&mut self.new_threads);
+        for &(pid, tid) in &new_threads {
+            if let Err(e) = self.process_new_thread(pid, tid) {
+                Logger::debug(format!(
+                    "failed to process thread {} of pid {}: {}",
+                    tid, pid, e
+                ));
+            }
+        }
+        self.new_threads = new_threads;
 
+        if self.tracked.is_some() {
+            self.report_exits();
+        }
+
+        self.seen_tasks
+            .retain(|task| self.current_tasks.contains(task));
+        if let Some(images) = self.images.as_mut() {
+            images.retain(|pid, (start, _)| self.current_tasks.contains(&(*pid, *pid, *start)));
+        }
+        if self.first_scan {
+            self.check_visibility(new_count);
+        }
+        self.first_scan = false;
+
+        METRICS.record_scan(started.elapsed(), new_count, self.get_process_count());
         Ok(new_count)
     }
 
-    fn process_new_pid(&self, pid: i32) -> Result<()> {
-        let process = Process::new(pid)?;
+    fn report_exits(&mut self) {
+        let Some(tracked) = self.tracked.as_mut() else {
+            return;
+        };
+
+        let exited: Vec<i32> = tracked
+            .iter()
+            .filter(|(pid, process)| {
+                !self
+                    .current_tasks
+                    .contains(&(**pid, **pid, process.starttime))
+            })
+            .map(|(pid, _)| *pid)
+            .collect();
+        if exited.is_empty() {
+            return;
+        }
+
+        let exited: Vec<(i32, TrackedProcess)> = exited
+            .into_iter()
+            .filter_map(|pid| tracked.remove(&pid).map(|process| (pid, process)))
+            .collect();
+        let now_secs = uptime_secs();
+        for (pid, process) in exited {
+            self.send_exit(pid, process, now_secs);
+        }
+    }
+
+    // the exit happened at some point since the previous scan, so the lifetime is an upper
+    // bound
+    fn send_exit(&self, pid: i32, process: TrackedProcess, now_secs: Option<f64>) {
+        let lifetime =
+            now_secs.map(|now| Duration::from_secs_f64((now - process.start_secs).max(0.0)));
+        let event = Event::Exit(ExitEvent {
+            pid: pid as u32,
+            ppid: Some(process.ppid),
+            uid: Some(process.uid),
+            cmdline: process.cmdline,
+            lifetime,
+            observed_at: SystemTime::now(),
+        });
+        if let Err(e) = self.sender.send(event) {
+            Logger::error(format!("failed to send exit event: {}", e));
+        }
+    }
+
+    // compares an already reported process with what it looked like then
+    fn check_image(&mut self, pid: i32, start: u64) {
+        let Some((image_start, image)) = self.images.as_ref().and_then(|images| images.get(&pid))
+        else {
+            return;
+        };
+        // the pid was reused by an unrelated process between two scans
+        if *image_start != start {
+            return;
+        }
+
+        // exiting processes briefly have an empty cmdline and an unreadable exe
+        let same_cmdline = match self.reader.cmdline(pid) {
+            Ok(raw) if !raw.is_empty() => procparse::cmdline_matches(raw, &image.cmdline),
+            _ => return,
+        };
+        let exe = std::fs::read_link(Path::new("/proc").join(pid.to_string()).join("exe")).ok();
+        let exe_changed = exe.is_some() && exe != image.exe;
+        if same_cmdline && !exe_changed {
+            return;
+        }
+        self.changed_pids.push((pid, start, image.clone()));
+    }
 
-        let cmdline = process
-            .cmdline()
-            .unwrap_or_else(|_| vec![UNKNOWN_COMMAND.to_string()])
-            .join(" ");
+    // threads of a process that just appeared belong to its start and threads that were
+    // there before the first scan to the baseline, only later ones are reported
+    fn collect_threads(&mut self, pid: i32, start: u64, is_new: bool) {
+        let Ok(tasks) = std::fs::read_dir(Path::new("/proc").join(pid.to_string()).join("task"))
+        else {
+            return;
+        };
+
+        for entry in tasks.flatten() {
+            let Some(tid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            else {
+                continue;
+            };
+            if tid == pid {
+                continue;
+            }
+            self.current_tasks.insert((pid, tid, start));
+            if self.seen_tasks.insert((pid, tid, start)) && !is_new && !self.first_scan {
+                self.new_threads.push((pid, tid));
+            }
+        }
+    }
+
+    fn process_new_thread(&mut self, pid: i32, tid: i32) -> Result<()> {
+        let stat = self.reader.stat(pid, Some(tid))?;
+        let comm = stat.comm.into_owned();
+        let start_secs = secs_since_boot(stat.starttime);
+        let status = self.reader.status(pid)?;
+        let (ppid, uid, gid) = (status.ppid, status.ruid, status.rgid);
+
+        let cmdline = self.reader.cmdline(pid).map_or_else(
+            |_| UNKNOWN_COMMAND.to_string(),
+            |raw| procparse::split_cmdline(raw).join(" "),
+        );
+        let proc_dir = Path::new("/proc").join(pid.to_string());
+
+        let event = ProcessEvent {
+            ppid: Some(ppid as u32),
+            uid: Some(uid),
+            gid: Some(gid),
+            exe: std::fs::read_link(proc_dir.join("exe")).ok(),
+            cwd: std::fs::read_link(proc_dir.join("cwd")).ok(),
+            start_time: start_secs.and_then(boot_relative_time),
+            cgroup: if self.show_cgroup {
+                cgroup::read(pid)
+            } else {
+                None
+            },
+            thread: Some(ThreadInfo {
+                tid: tid as u32,
+                comm,
+            }),
+            ..ProcessEvent::new(ProcessSource::Procfs, pid as u32, cmdline)
+        };
+
+        self.sender
+            .send(Event::Process(Box::new(event)))
+            .map_err(|e| format!("failed to send thread event: {}", e))?;
+        Ok(())
+    }
+
+    fn process_new_pid(
+        &mut self,
+        pid: i32,
+        start: u64,
+        changed_from: Option<ProcessImage>,
+    ) -> Result<()> {
+        let status = self.reader.status(pid)?;
+
+        // first thing, droppers tend to delete themselves and exit right away; processes
+        // that were already running at startup are left alone
+        if !self.first_scan
+            && let Some(preserver) = self.preserver.as_mut()
+        {
+            match preserver.preserve(pid, &status.name) {
+                Ok(Some(evidence)) => Logger::info(format!(
+                    "preserved exe of pid {} in {}",
+                    pid,
+                    evidence.display()
+                )),
+                Ok(None) => {}
+                Err(e) => Logger::debug(format!("failed to preserve exe of pid {}: {}", pid, e)),
+            }
+        }
 
-        let status = process.status()?;
         let uid = status.ruid;
+        let gid = status.rgid;
+        if self.first_scan && uid != self.own_uid {
+            self.foreign_processes += 1;
+        }
+        let ppid = status.ppid;
+        let ns_pid = status.ns_pid.map(|ns_pid| ns_pid as u32);
+        let capabilities = self.show_caps.then_some(Capabilities {
+            effective: status.capeff,
+            permitted: status.capprm,
+        });
+
+        let proc_dir = Path::new("/proc").join(pid.to_string());
+        let argv = self.reader.cmdline(pid).ok().map(procparse::split_cmdline);
+        let cmdline = argv
+            .as_ref()
+            .map_or_else(|| UNKNOWN_COMMAND.to_string(), |argv| argv.join(" "));
+        let exe = std::fs::read_link(proc_dir.join("exe")).ok();
+        let cwd = std::fs::read_link(proc_dir.join("cwd")).ok();
+        let environ = Environ::read(pid as u32);
+        let (exe_deleted, exe_mismatch) = exe_anomalies(
+            exe.as_deref(),
+            argv.as_deref(),
+            cwd.as_deref(),
+            environ.as_ref(),
+        );
+        let exe_sha256 = match (self.hash_cache.as_mut(), exe.is_some()) {
+            (Some(cache), true) => cache
+                .sha256(&proc_dir.join("exe"))
+                .map_err(|e| Logger::debug(format!("failed to hash exe of pid {}: {}", pid, e)))
+                .ok(),
+            _ => None,
+        };
 
-        Logger::event(Some(uid), pid as u32, &cmdline);
+        let containerized = ns_pid.is_some()
+            || matches!(
+                (self.pid_namespace, pid_namespace(&pid.to_string())),
+                (Some(own), Some(theirs)) if own != theirs
+            );
+
+        let start_secs = secs_since_boot(start);
+
+        if let Some(images) = self.images.as_mut() {
+            let image = ProcessImage {
+                cmdline: cmdline.clone(),
+                exe: exe.clone(),
+            };
+            images.insert(pid, (start, image));
+        }
+
+        if let Some(tracked) = self.tracked.as_mut()
+            && let Some(start_secs) = start_secs
+        {
+            let previous = tracked.insert(
+                pid,
+                TrackedProcess {
+                    uid,
+                    ppid: ppid as u32,
+                    cmdline: cmdline.clone(),
+                    starttime: start,
+                    start_secs,
+                },
+            );
+            // the pid was recycled since the last scan, its previous owner is gone
+            if let Some(previous) = previous
+                && previous.starttime != start
+            {
+                self.send_exit(pid, previous, uptime_secs());
+            }
+        }
+
+        let ancestors = if self.show_tree {
+            self.ancestor_chain(ppid)
+        } else {
+            Vec::new()
+        };
+        let (open_fds, omitted_fds) = if self.show_fds {
+            fds::interesting(pid, Some(ppid as u32), FDS_MAX_PER_EVENT)
+        } else {
+            (Vec::new(), 0)
+        };
+        let root = if self.show_cwd {
+            std::fs::read_link(proc_dir.join("root")).ok()
+        } else {
+            None
+        };
+        let (exe_setuid, exe_setgid) = exe_setid_bits(&proc_dir.join("exe"));
+        let exe_in_writable_dir = self.highlight_tmp_exec && exe_in_writable_dir(exe.as_deref());
+        let script = script_of(argv.as_deref(), cwd.as_deref());
+        let loader_env = libraries::loader_env(environ.as_ref());
+        let unexpected_libraries = if self.check_libraries {
+            libraries::unexpected(pid as u32)
+        } else {
+            Vec::new()
+        };
+        #[cfg(feature = "dbus")]
+        let unit = self
+            .units
+            .as_ref()
+            .and_then(|units| units.unit_of(pid as u32));
+        #[cfg(not(feature = "dbus"))]
+        let unit = None;
+
+        let event = ProcessEvent {
+            ppid: Some(ppid as u32),
+            uid: Some(uid),
+            gid: Some(gid),
+            exe,
+            exe_deleted,
+            exe_mismatch,
+            exe_sha256,
+            cwd,
+            start_time: start_secs.and_then(boot_relative_time),
+            ancestors,
+            cgroup: if self.show_cgroup {
+                cgroup::read(pid)
+            } else {
+                None
+            },
+            containerized,
+            ns_pid,
+            changed_from: changed_from.map(Box::new),
+            fds: open_fds,
+            omitted_fds,
+            capabilities,
+            exe_setuid,
+            exe_setgid,
+            exe_in_writable_dir,
+            loader_env,
+            unexpected_libraries,
+            script,
+            root,
+            unit,
+            ..ProcessEvent::new(ProcessSource::Procfs, pid as u32, cmdline)
+        };
+
+        self.sender
+            .send(Event::Process(Box::new(event)))
+            .map_err(|e| format!("failed to send process event: {}", e))?;
         Ok(())
     }
 
+    // any host runs something as root, seeing nothing but our own processes means the
+    // scanner is blind to everyone else and would silently stay quiet about them
+    fn check_visibility(&self, processes: usize) {
+        if self.own_uid == ROOT_UID || self.foreign_processes > 0 {
+            return;
+        }
+        let cause = match process::proc_mount().and_then(|mount| mount.hidepid) {
+            Some(hidepid) => format!("/proc is mounted with hidepid={}", hidepid),
+            None => "something hides the others, e.g. a pid namespace".to_string(),
+        };
+        Logger::error(format!(
+            "only the {} processes of uid {} are visible, {}. run as root, or add --dbus to learn about every process from systemd and -f to see file activity",
+            processes, self.own_uid, cause
+        ));
+    }
+
+    fn ancestor_chain(&mut self, ppid: i32) -> Vec<(u32, String)> {
+        let mut ancestors = Vec::new();
+        let mut current = ppid;
+
+        while current > 0 && ancestors.len() < TREE_MAX_DEPTH {
+            let Ok(stat) = self.reader.stat(current, None) else {
+                break;
+            };
+            let parent = stat.ppid;
+            let comm = format!("[{}]", stat.comm);
+
+            let cmd = match self.reader.cmdline(current) {
+                Ok(raw) if !raw.is_empty() => procparse::split_cmdline(raw).join(" "),
+                _ => comm,
+            };
+            ancestors.push((current as u32, cmd));
+            current = parent;
+        }
+
+        ancestors
+    }
+
     pub fn get_process_count(&self) -> usize {
-        self.seen_pids.len()
+        self.seen_tasks
+            .iter()
+            .filter(|(pid, tid, _)| pid == tid)
+            .count()
     }
 }
 
-impl Default for ProcessScanner {
-    fn default() -> Self {
-        Self::new()
+fn secs_since_boot(starttime: u64) -> Option<f64> {
+    procfs::ticks_per_second()
+        .ok()
+        .map(|ticks| starttime as f64 / ticks as f64)
+}
+
+fn uptime_secs() -> Option<f64> {
+    std::fs::read_to_string("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn boot_relative_time(secs_since_boot: f64) -> Option<SystemTime> {
+    let boot_secs = procfs::boot_time_secs().ok()?;
+    UNIX_EPOCH.checked_add(Duration::from_secs_f64(boot_secs as f64 + secs_since_boot))
+}
+
+// the inode of /proc/<pid>/ns/pid identifies the namespace, e.g. "pid:[4026531836]"
+fn pid_namespace(pid: &str) -> Option<u64> {
+    std::fs::metadata(Path::new("/proc").join(pid).join("ns").join("pid"))
+        .ok()
+        .map(|metadata| metadata.ino())
+}
+
+// returns (setuid, setgid) of the binary, /proc/<pid>/exe stats the file it points to
+pub fn exe_setid_bits(exe: &Path) -> (bool, bool) {
+    match std::fs::metadata(exe) {
+        Ok(metadata) => (
+            metadata.mode() & libc::S_ISUID != 0,
+            metadata.mode() & libc::S_ISGID != 0,
+        ),
+        Err(_) => (false, false),
+    }
+}
+
+// the binary lies below /tmp, /var/tmp, /dev/shm or a directory anyone may write to, where
+// any user could have put or replaced it
+pub fn exe_in_writable_dir(exe: Option<&Path>) -> bool {
+    let Some(exe) = exe else {
+        return false;
+    };
+    let exe_str = exe.to_string_lossy();
+    let exe = Path::new(exe_str.strip_suffix(DELETED_EXE_SUFFIX).unwrap_or(&exe_str));
+    if TMP_EXEC_DIRS.iter().any(|dir| exe.starts_with(dir)) {
+        return true;
+    }
+    exe.ancestors().skip(1).any(|dir| {
+        std::fs::metadata(dir)
+            .is_ok_and(|metadata| metadata.is_dir() && metadata.mode() & libc::S_IWOTH != 0)
+    })
+}
+
+// (interpreter, short options that run inline code, short options that take a value, the
+// option naming the script), versioned names like python3.11 or php8.2 count as well
+const SCRIPT_INTERPRETERS: &[(&str, &str, &str, Option<char>)] = &[
+    ("sh", "cs", "oO", None),
+    ("bash", "cs", "oO", None),
+    ("dash", "cs", "oO", None),
+    ("zsh", "cs", "oO", None),
+    ("ksh", "cs", "oO", None),
+    ("ash", "cs", "oO", None),
+    ("python", "cm", "WX", None),
+    ("perl", "eE", "", None),
+    ("php", "rBRE", "dcz", Some('f')),
+    ("ruby", "e", "Ir", None),
+];
+
+// the script an interpreter was started with, stat'ed for who may change it
+pub fn script_of(argv: Option<&[String]>, cwd: Option<&Path>) -> Option<Script> {
+    let argument = Path::new(script_argument(argv?)?);
+    let path = match cwd {
+        Some(cwd) => cwd.join(argument),
+        None if argument.is_absolute() => argument.to_path_buf(),
+        None => return None,
+    };
+    let metadata = std::fs::metadata(&path)
+        .ok()
+        .filter(|metadata| metadata.is_file())?;
+    Some(Script {
+        path: path.canonicalize().unwrap_or(path),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        mode: metadata.mode() & 0o7777,
+    })
+}
+
+// None for inline code (bash -c, python -m, perl -e) and scripts read from stdin
+fn script_argument(argv: &[String]) -> Option<&str> {
+    // login shells are started as -bash
+    let name = Path::new(argv.first()?)
+        .file_name()?
+        .to_str()?
+        .trim_start_matches('-');
+    let &(_, inline, with_value, script_option) =
+        SCRIPT_INTERPRETERS.iter().find(|(interpreter, ..)| {
+            name.strip_prefix(interpreter)
+                .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        })?;
+
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return args.next().map(String::as_str);
+        }
+        if arg == "-" {
+            return None;
+        }
+        // shells take +o and friends as well
+        let Some(options) = arg.strip_prefix('-').or_else(|| arg.strip_prefix('+')) else {
+            return Some(arg);
+        };
+        // long options, none of the ones that take a value are common enough to matter
+        if options.starts_with('-') {
+            continue;
+        }
+        for (i, option) in options.char_indices() {
+            if inline.contains(option) {
+                return None;
+            }
+            let rest = &options[i + option.len_utf8()..];
+            if script_option == Some(option) {
+                return if rest.is_empty() {
+                    args.next().map(String::as_str)
+                } else {
+                    Some(rest)
+                };
+            }
+            if with_value.contains(option) {
+                // the value follows directly or is the next argument
+                if rest.is_empty() {
+                    args.next();
+                }
+                break;
+            }
+        }
+    }
+    None
+}
+
+// returns (deleted, mismatch): the binary was unlinked or memfd-backed, or argv[0] names another program
+pub fn exe_anomalies(
+    exe: Option<&Path>,
+    argv: Option<&[String]>,
+    cwd: Option<&Path>,
+    environ: Option<&Environ>,
+) -> (bool, bool) {
+    let Some(exe) = exe else {
+        return (false, false);
+    };
+
+    let exe_str = exe.to_string_lossy();
+    if exe_str.ends_with(DELETED_EXE_SUFFIX) {
+        return (true, false);
+    }
+    (false, argv0_mismatch(exe, argv, cwd, environ))
+}
+
+fn argv0_mismatch(
+    exe: &Path,
+    argv: Option<&[String]>,
+    cwd: Option<&Path>,
+    environ: Option<&Environ>,
+) -> bool {
+    let Some(argv0) = argv.and_then(|argv| argv.first()) else {
+        return false;
+    };
+
+    // setproctitle() rewrites argv[0] to things like "sshd: root@pts/0", only the program part counts
+    let program = argv0
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches('-');
+    if program.is_empty() {
+        return false;
+    }
+
+    let exe_name = exe
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let program_name = Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    if EXE_MULTICALL_BINARIES.contains(&exe_name.as_ref()) {
+        return false;
+    }
+
+    // versioned interpreters, e.g. argv[0] "python3" running /usr/bin/python3.11
+    if exe_name.starts_with(program_name.as_ref()) || program_name.starts_with(exe_name.as_ref()) {
+        return false;
+    }
+
+    // only names that differ are followed, e.g. "vi" being a symlink to vim.basic
+    resolve_program(program, cwd, environ).is_none_or(|resolved| resolved != exe)
+}
+
+// a bare name is looked up in the PATH of the process itself, without it only the names
+// are compared
+fn resolve_program(
+    program: &str,
+    cwd: Option<&Path>,
+    environ: Option<&Environ>,
+) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = match cwd {
+            Some(cwd) => cwd.join(program),
+            None => PathBuf::from(program),
+        };
+        return path.canonicalize().ok();
+    }
+
+    let paths = environ?.var("PATH")?;
+    std::env::split_paths(paths).find_map(|dir| {
+        // relative entries like "." are relative to the working directory of the process
+        let dir = match cwd {
+            Some(cwd) => cwd.join(dir),
+            None if dir.is_absolute() => dir,
+            None => return None,
+        };
+        dir.join(program).canonicalize().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(argv: &[&str]) -> Option<String> {
+        let argv: Vec<String> = argv.iter().map(|arg| arg.to_string()).collect();
+        script_argument(&argv).map(str::to_string)
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            script(&["/bin/sh", "/opt/backup.sh"]).as_deref(),
+            Some("/opt/backup.sh")
+        );
+        assert_eq!(
+            script(&["bash", "-x", "run.sh", "-c"]).as_deref(),
+            Some("run.sh")
+        );
+        assert_eq!(
+            script(&["bash", "+o", "posix", "run.sh"]).as_deref(),
+            Some("run.sh")
+        );
+        assert_eq!(
+            script(&["bash", "--norc", "run.sh"]).as_deref(),
+            Some("run.sh")
+        );
+        assert_eq!(
+            script(&["bash", "--", "-run.sh"]).as_deref(),
+            Some("-run.sh")
+        );
+        assert_eq!(script(&["-bash", "run.sh"]).as_deref(), Some("run.sh"));
+        assert_eq!(
+            script(&["python3.11", "-u", "-W", "ignore", "job.py"]).as_deref(),
+            Some("job.py")
+        );
+        assert_eq!(
+            script(&["python3", "-Werror", "job.py"]).as_deref(),
+            Some("job.py")
+        );
+        assert_eq!(script(&["perl", "-w", "job.pl"]).as_deref(), Some("job.pl"));
+        assert_eq!(
+            script(&["ruby", "-I", "lib", "job.rb"]).as_deref(),
+            Some("job.rb")
+        );
+        assert_eq!(
+            script(&["php8.2", "-d", "x=1", "-f", "job.php"]).as_deref(),
+            Some("job.php")
+        );
+        assert_eq!(script(&["php", "-fjob.php"]).as_deref(), Some("job.php"));
+    }
+
+    #[test]
+    fn inline_code_and_stdin() {
+        assert_eq!(script(&["sh", "-c", "id"]), None);
+        assert_eq!(script(&["bash", "-xc", "id"]), None);
+        assert_eq!(script(&["bash", "-s", "arg"]), None);
+        assert_eq!(script(&["bash", "-", "arg"]), None);
+        assert_eq!(script(&["bash"]), None);
+        assert_eq!(script(&["python3", "-m", "http.server"]), None);
+        assert_eq!(script(&["perl", "-e", "print 1"]), None);
+        assert_eq!(script(&["php", "-r", "echo 1;"]), None);
+        assert_eq!(script(&["php", "-f"]), None);
+    }
+
+    #[test]
+    fn not_interpreters() {
+        assert_eq!(script(&["/bin/ls", "run.sh"]), None);
+        assert_eq!(script(&["bashful", "run.sh"]), None);
+        assert_eq!(script(&["python3-config", "run.py"]), None);
+        assert_eq!(script(&[]), None);
     }
 }
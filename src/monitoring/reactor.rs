@@ -0,0 +1,183 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::core::constants::REACTOR_MAX_EVENTS;
+use crate::core::logger::Logger;
+use crate::utils::shutdown::Worker;
+use crate::utils::throttle::{self, Priority};
+
+// the eventfd uses the token right after the last possible source index
+const WAKE_TOKEN: u64 = u64::MAX;
+
+// an fd-based collector, inotify and netlink sockets so far
+pub trait Source: Send {
+    fn name(&self) -> &'static str;
+
+    fn fd(&self) -> RawFd;
+
+    // called when the fd is readable, the fd is non-blocking so reading until WouldBlock
+    // drains it, an error drops the source from the reactor
+    fn on_readable(&mut self) -> io::Result<()>;
+
+    // the reactor was woken, e.g. to hand over requests queued for the source
+    fn on_wake(&mut self) {}
+}
+
+// one epoll thread for all fd-based collectors instead of a blocking read loop each
+pub struct Reactor {
+    epoll: OwnedFd,
+    wake: Arc<OwnedFd>,
+    sources: Vec<Option<Box<dyn Source>>>,
+}
+
+// wakes the reactor thread so it notices a stop or queued requests right away
+#[derive(Clone)]
+pub struct Waker {
+    wake: Arc<OwnedFd>,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        let value: u64 = 1;
+        unsafe {
+            libc::write(
+                self.wake.as_raw_fd(),
+                &value as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl Reactor {
+    pub fn new() -> io::Result<Self> {
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let epoll = unsafe { OwnedFd::from_raw_fd(epoll) };
+
+        let wake = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if wake < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let wake = unsafe { OwnedFd::from_raw_fd(wake) };
+
+        let reactor = Self {
+            epoll,
+            wake: Arc::new(wake),
+            sources: Vec::new(),
+        };
+        reactor.add(reactor.wake.as_raw_fd(), WAKE_TOKEN)?;
+        Ok(reactor)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn waker(&self) -> Waker {
+        Waker {
+            wake: Arc::clone(&self.wake),
+        }
+    }
+
+    pub fn register(&mut self, source: Box<dyn Source>) -> io::Result<()> {
+        set_nonblocking(source.fd())?;
+        self.add(source.fd(), self.sources.len() as u64)?;
+        self.sources.push(Some(source));
+        Ok(())
+    }
+
+    fn add(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        let result =
+            unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, token: usize) {
+        if let Some(source) = self.sources[token].take() {
+            unsafe {
+                libc::epoll_ctl(
+                    self.epoll.as_raw_fd(),
+                    libc::EPOLL_CTL_DEL,
+                    source.fd(),
+                    std::ptr::null_mut(),
+                );
+            }
+        }
+    }
+
+    pub fn start(mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Worker {
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            let mut events = [libc::epoll_event { events: 0, u64: 0 }; REACTOR_MAX_EVENTS];
+
+            while running.load(Ordering::SeqCst) {
+                let ready = unsafe {
+                    libc::epoll_wait(
+                        self.epoll.as_raw_fd(),
+                        events.as_mut_ptr(),
+                        REACTOR_MAX_EVENTS as libc::c_int,
+                        -1,
+                    )
+                };
+                if ready < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    Logger::error(format!("error waiting for events: {}", err));
+                    break;
+                }
+
+                for event in &events[..ready as usize] {
+                    let token = event.u64;
+                    if token == WAKE_TOKEN {
+                        // a stop is noticed by the loop condition, anything else by the sources
+                        let mut value: u64 = 0;
+                        unsafe {
+                            libc::read(
+                                self.wake.as_raw_fd(),
+                                &mut value as *mut u64 as *mut libc::c_void,
+                                std::mem::size_of::<u64>(),
+                            );
+                        }
+                        for source in self.sources.iter_mut().flatten() {
+                            source.on_wake();
+                        }
+                        continue;
+                    }
+
+                    let token = token as usize;
+                    let Some(source) = self.sources[token].as_mut() else {
+                        continue;
+                    };
+                    if let Err(e) = source.on_readable() {
+                        Logger::error(format!("{} stopped: {}", source.name(), e));
+                        self.remove(token);
+                    }
+                }
+            }
+        });
+        ("reactor", worker)
+    }
+}
+
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,23 +1,36 @@
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use crate::core::{
-    constants::{DEFAULT_SCAN_INTERVAL_MS, SCANNER_MAX_TIMEOUT_SECS},
+    constants::{
+        DEFAULT_BURST_COUNT, DEFAULT_BURST_INTERVAL_MS, DEFAULT_SCAN_INTERVAL_MS,
+        SCANNER_MAX_TIMEOUT_SECS,
+    },
+    event::Event,
     logger::Logger,
 };
-use crate::monitoring::{dbus::DBusScanner, process::ProcessScanner};
+#[cfg(feature = "dbus")]
+use crate::monitoring::dbus::{DBusScanner, UnitResolver};
+use crate::monitoring::process::ProcessScanner;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::throttle::{self, CpuBudget, Priority};
 
 pub struct Scanner {
     interval: Option<Duration>,
     dbus_interval: Option<Duration>,
+    burst_count: u32,
+    burst_interval: Duration,
+    jitter: Option<u32>,
+    cpu_limit: Option<u32>,
     trigger_rx: Option<Receiver<()>>,
     is_active: Arc<AtomicBool>,
     dbus_only: bool,
+    #[cfg(feature = "dbus")]
     dbus_scanner: Option<DBusScanner>,
-    process_scanner: ProcessScanner,
+    process_scanner: Option<ProcessScanner>,
 }
 
 impl Scanner {
@@ -27,47 +40,145 @@ impl Scanner {
         dbus_only: bool,
         dbus_enabled: bool,
         dbus_interval: Option<Duration>,
+        sender: Sender<Event>,
+        process_scanner: ProcessScanner,
     ) -> Self {
+        #[cfg(feature = "dbus")]
         let dbus_scanner = if dbus_only || dbus_enabled {
-            Some(DBusScanner::new(dbus_interval))
+            Some(DBusScanner::new(dbus_interval, sender))
         } else {
             None
         };
+        #[cfg(not(feature = "dbus"))]
+        let _ = (dbus_enabled, sender);
 
         Self {
             interval,
             dbus_interval,
+            burst_count: DEFAULT_BURST_COUNT,
+            burst_interval: Duration::from_millis(DEFAULT_BURST_INTERVAL_MS),
+            jitter: None,
+            cpu_limit: None,
             trigger_rx: Some(trigger_rx),
             is_active: Arc::new(AtomicBool::new(false)),
             dbus_only,
+            #[cfg(feature = "dbus")]
             dbus_scanner,
-            process_scanner: ProcessScanner::new(),
+            process_scanner: Some(process_scanner),
         }
     }
 
-    pub fn start(&mut self) {
+    // a single triggered scan misses helpers that live for a few milliseconds, a burst
+    // keeps rescanning while the process that touched the filesystem is still forking
+    pub fn set_burst(&mut self, count: u32, interval: Duration) {
+        self.burst_count = count.max(1);
+        self.burst_interval = interval;
+    }
+
+    // percent of the interval each wait is moved by at random, see throttle::jitter
+    pub fn set_jitter(&mut self, percent: Option<u32>) {
+        self.jitter = percent;
+    }
+
+    pub fn set_cpu_limit(&mut self, percent: Option<u32>) {
+        self.cpu_limit = percent;
+    }
+
+    pub fn set_dbus_show_exits(&mut self, show_exits: bool) {
+        #[cfg(feature = "dbus")]
+        if let Some(dbus_scanner) = self.dbus_scanner.as_mut() {
+            dbus_scanner.set_show_exits(show_exits);
+        }
+        #[cfg(not(feature = "dbus"))]
+        let _ = show_exits;
+    }
+
+    pub fn set_dbus_highlight_tmp_exec(&mut self, highlight_tmp_exec: bool) {
+        #[cfg(feature = "dbus")]
+        if let Some(dbus_scanner) = self.dbus_scanner.as_mut() {
+            dbus_scanner.set_highlight_tmp_exec(highlight_tmp_exec);
+        }
+        #[cfg(not(feature = "dbus"))]
+        let _ = highlight_tmp_exec;
+    }
+
+    pub fn set_dbus_check_libraries(&mut self, check_libraries: bool) {
+        #[cfg(feature = "dbus")]
+        if let Some(dbus_scanner) = self.dbus_scanner.as_mut() {
+            dbus_scanner.set_check_libraries(check_libraries);
+        }
+        #[cfg(not(feature = "dbus"))]
+        let _ = check_libraries;
+    }
+
+    // new processes seen over dbus start a procfs scan, which knows far more about them
+    pub fn set_dbus_trigger(&mut self, trigger: Sender<()>) {
+        #[cfg(feature = "dbus")]
+        if !self.dbus_only
+            && let Some(dbus_scanner) = self.dbus_scanner.as_mut()
+        {
+            dbus_scanner.set_trigger(Some(trigger));
+        }
+        #[cfg(not(feature = "dbus"))]
+        let _ = trigger;
+    }
+
+    #[cfg(feature = "dbus")]
+    pub fn set_dbus_units(&mut self, units: Option<UnitResolver>) {
+        if let Some(dbus_scanner) = self.dbus_scanner.as_mut() {
+            dbus_scanner.set_units(units);
+        }
+    }
+
+    pub fn set_max_dbus_pids(&mut self, capacity: usize) {
+        #[cfg(feature = "dbus")]
+        if let Some(dbus_scanner) = self.dbus_scanner.as_mut() {
+            dbus_scanner.set_max_pids(capacity);
+        }
+        #[cfg(not(feature = "dbus"))]
+        let _ = capacity;
+    }
+
+    pub fn start(&mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Vec<Worker> {
         self.set_active(true);
+        let mut workers = Vec::new();
 
+        #[cfg(feature = "dbus")]
         if let Some(mut dbus_scanner) = self.dbus_scanner.take() {
-            thread::spawn(move || {
-                if let Err(e) = dbus_scanner.start_listening() {
+            let running = Arc::clone(&running);
+            let worker = thread::spawn(move || {
+                throttle::enter_collector_thread(priority);
+                if let Err(e) = dbus_scanner.start_listening(&running) {
                     Logger::error(format!("dbus scanner error: {}", e));
                 }
             });
+            workers.push(("dbus scanner", worker));
         }
 
         if self.dbus_only {
-            return;
+            return workers;
         }
 
         let is_active = Arc::clone(&self.is_active);
         let interval = self.interval;
         let dbus_interval = self.dbus_interval;
-        let mut process_scanner = std::mem::take(&mut self.process_scanner);
-
-        if let Some(trigger_rx) = self.trigger_rx.take() {
-            thread::spawn(move || {
+        let burst_count = self.burst_count;
+        let burst_interval = self.burst_interval;
+        let jitter = self.jitter;
+        let mut cpu_budget = self.cpu_limit.map(CpuBudget::new);
+        if let (Some(trigger_rx), Some(mut process_scanner)) =
+            (self.trigger_rx.take(), self.process_scanner.take())
+        {
+            let worker = thread::spawn(move || {
+                throttle::enter_collector_thread(priority);
                 let mut last_process_scan = Instant::now();
+                let next_wait = || {
+                    interval.map(|interval| match jitter {
+                        Some(percent) => throttle::jitter(interval, percent),
+                        None => interval,
+                    })
+                };
+                let mut wait = next_wait();
                 let min_between_scans =
                     interval.unwrap_or(Duration::from_millis(DEFAULT_SCAN_INTERVAL_MS));
 
@@ -79,18 +190,32 @@ impl Scanner {
                     (None, None) => Duration::from_millis(DEFAULT_SCAN_INTERVAL_MS),
                 };
 
-                loop {
+                while running.load(Ordering::SeqCst) {
                     if !is_active.load(Ordering::Relaxed) {
-                        thread::sleep(inactive_sleep_duration);
+                        shutdown::sleep(&running, inactive_sleep_duration);
                         continue;
                     }
 
+                    // over the cpu budget, triggers that arrive meanwhile are drained by
+                    // the next scan
+                    if let Some(budget) = cpu_budget.as_mut() {
+                        let backoff = budget.backoff();
+                        if !backoff.is_zero() {
+                            Logger::debug(format!(
+                                "over the cpu limit, holding off scans for {:?}",
+                                backoff
+                            ));
+                            shutdown::sleep(&running, backoff);
+                            continue;
+                        }
+                    }
+
                     let now = Instant::now();
                     let time_since_last_process = now.duration_since(last_process_scan);
 
                     // calc next process scan time if applicable
                     let next_process_scan =
-                        interval.map(|interval_duration| last_process_scan + interval_duration);
+                        wait.map(|interval_duration| last_process_scan + interval_duration);
 
                     let timeout = if let Some(next_scan_time) = next_process_scan {
                         if now >= next_scan_time {
@@ -121,6 +246,7 @@ impl Scanner {
                             }
                         }
                         last_process_scan = Instant::now();
+                        wait = next_wait();
                         continue;
                     }
 
@@ -156,7 +282,31 @@ impl Scanner {
                                         Logger::error(format!("triggered scan failed: {}", e));
                                     }
                                 }
+
+                                for burst_scan in 1..burst_count {
+                                    if !shutdown::sleep(&running, burst_interval) {
+                                        break;
+                                    }
+                                    if cpu_budget.as_ref().is_some_and(CpuBudget::exceeded) {
+                                        break;
+                                    }
+                                    match process_scanner.scan_processes() {
+                                        Ok(new_count) => {
+                                            Logger::debug(format!(
+                                                "burst scan {}/{} completed. Found {} new processes",
+                                                burst_scan + 1,
+                                                burst_count,
+                                                new_count
+                                            ));
+                                        }
+                                        Err(e) => {
+                                            Logger::error(format!("burst scan failed: {}", e));
+                                            break;
+                                        }
+                                    }
+                                }
                                 last_process_scan = Instant::now();
+                                wait = next_wait();
                             } else {
                                 Logger::debug(format!(
                                     "ignoring trigger - only {:?} since last scan (min: {:?})",
@@ -174,7 +324,14 @@ impl Scanner {
                     }
                 }
             });
+            workers.push(("process scanner", worker));
         }
+        workers
+    }
+
+    // shared with Rspy::set_paused, cleared the scans stop until it is set again
+    pub fn activity(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.is_active)
     }
 
     pub fn set_active(&self, active: bool) {
@@ -0,0 +1,429 @@
+use rustc_hash::FxHashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::core::{
+    constants::{
+        ANACRONTAB, AT_SPOOL_DIRS, CRON_DIRS, CRON_PERIODIC_DIRS, CRON_SPOOL_DIRS,
+        SCHEDULE_MAX_DEPTH, SCHEDULE_RECHECK_INTERVAL_MS, SCHEDULER_COMMS, SYSTEM_CRONTAB,
+        SYSTEMD_UNIT_DIRS,
+    },
+    event::ProcessEvent,
+    logger::Logger,
+};
+use crate::utils::{
+    cgroup,
+    process::{parent_and_comm, process_name},
+};
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ScheduledJob {
+    // the file the job was found in
+    pub source: PathBuf,
+    // e.g. "*/5 * * * *", "@daily", "OnCalendar=daily"
+    pub schedule: String,
+    pub user: Option<String>,
+    pub command: String,
+    // the service a systemd timer starts
+    unit: Option<String>,
+}
+
+impl ScheduledJob {
+    pub fn describe(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{} {} ({})", self.source.display(), self.schedule, user),
+            None => format!("{} {}", self.source.display(), self.schedule),
+        }
+    }
+
+    fn matches(&self, cmdline: &str) -> bool {
+        if self.command.is_empty() {
+            return false;
+        }
+        // cron runs jobs through `/bin/sh -c <command>`, scripts show up as `<interpreter> <path>`
+        if cmdline.contains(&self.command) {
+            return true;
+        }
+        self.command
+            .split_whitespace()
+            .next()
+            .filter(|program| program.starts_with('/'))
+            .is_some_and(|program| cmdline.split_whitespace().any(|arg| arg == program))
+    }
+}
+
+pub enum ScheduleMatch<'a> {
+    Expected(&'a ScheduledJob),
+    // started by a scheduler (named here) without matching any known job
+    Unexpected(String),
+}
+
+pub struct ScheduleIndex {
+    jobs: Vec<ScheduledJob>,
+    // files and directories the jobs were read from, with their mtime at that point
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant,
+}
+
+impl ScheduleIndex {
+    pub fn load() -> Self {
+        let mut index = Self {
+            jobs: Vec::new(),
+            sources: Vec::new(),
+            last_check: Instant::now(),
+        };
+        index.scan();
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn classify(&mut self, event: &ProcessEvent) -> Option<ScheduleMatch<'_>> {
+        self.refresh();
+
+        // a job's own children (tar under backup.sh, ...) are expected as well, so the
+        // ancestors are checked for a job, but only count once a scheduler shows up above it
+        let mut job = None;
+        let mut cmdline = event.cmdline.clone();
+        let mut ppid = event.ppid;
+        for depth in 0..SCHEDULE_MAX_DEPTH {
+            if job.is_none() {
+                job = self.jobs.iter().position(|job| job.matches(&cmdline));
+            }
+
+            let Some(parent) = ppid.filter(|&ppid| ppid > 1) else {
+                break;
+            };
+            let Some((grandparent, comm)) = parent_and_comm(parent) else {
+                break;
+            };
+            if SCHEDULER_COMMS.contains(&comm.as_str()) {
+                // cron forks a copy of itself per job, that copy is not a job
+                let (_, own_comm) = parent_and_comm(event.pid).unwrap_or_default();
+                if depth == 0 && own_comm == comm {
+                    return None;
+                }
+                return Some(match job {
+                    Some(index) => ScheduleMatch::Expected(&self.jobs[index]),
+                    None => ScheduleMatch::Unexpected(comm),
+                });
+            }
+
+            cmdline = process_name(parent as i32);
+            ppid = Some(grandparent);
+        }
+
+        // services started by a timer run in their own cgroup named after the unit
+        let unit = cgroup::read(event.pid as i32)?.unit?;
+        self.jobs
+            .iter()
+            .find(|job| job.unit.as_deref() == Some(unit.as_str()))
+            .map(ScheduleMatch::Expected)
+    }
+
+    // schedules are reread when one of their files or directories changed, new jobs are
+    // exactly what cron hunting is after so they are reported
+    pub fn refresh(&mut self) {
+        if self.last_check.elapsed() < Duration::from_millis(SCHEDULE_RECHECK_INTERVAL_MS) {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        if self
+            .sources
+            .iter()
+            .all(|(path, mtime)| modified(path) == *mtime)
+        {
+            return;
+        }
+
+        let known: FxHashSet<ScheduledJob> = self.jobs.drain(..).collect();
+        self.sources.clear();
+        self.scan();
+        for job in self.jobs.iter().filter(|job| !known.contains(job)) {
+            Logger::alert(format!(
+                "new scheduled job in {}: {}",
+                job.describe(),
+                job.command
+            ));
+        }
+    }
+
+    fn scan(&mut self) {
+        self.read_crontab(Path::new(SYSTEM_CRONTAB), None);
+        for directory in CRON_DIRS {
+            for file in self.list(Path::new(directory)) {
+                self.read_crontab(&file, None);
+            }
+        }
+        for directory in CRON_SPOOL_DIRS {
+            for file in self.list(Path::new(directory)) {
+                // per-user crontabs are named after the user and have no user column
+                let user = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+                self.read_crontab(&file, user);
+            }
+        }
+        for (directory, schedule) in CRON_PERIODIC_DIRS {
+            for file in self.list(Path::new(directory)) {
+                self.jobs.push(ScheduledJob {
+                    source: PathBuf::from(directory),
+                    schedule: schedule.to_string(),
+                    user: None,
+                    command: file.display().to_string(),
+                    unit: None,
+                });
+            }
+        }
+        self.read_anacrontab(Path::new(ANACRONTAB));
+        for directory in AT_SPOOL_DIRS {
+            for file in self.list(Path::new(directory)) {
+                self.read_at_job(&file);
+            }
+        }
+        // like systemd, the first directory that has a unit wins (/lib is often /usr/lib)
+        let mut timers = FxHashSet::default();
+        for directory in SYSTEMD_UNIT_DIRS {
+            for file in self.list(Path::new(directory)) {
+                if file
+                    .extension()
+                    .is_some_and(|extension| extension == "timer")
+                    && timers.insert(file.file_name().unwrap_or_default().to_os_string())
+                {
+                    self.read_timer(&file);
+                }
+            }
+        }
+    }
+
+    fn track(&mut self, path: &Path) {
+        if !self.sources.iter().any(|(source, _)| source == path) {
+            self.sources.push((path.to_path_buf(), modified(path)));
+        }
+    }
+
+    fn read(&mut self, path: &Path) -> Option<String> {
+        self.track(path);
+        std::fs::read_to_string(path).ok()
+    }
+
+    fn list(&mut self, directory: &Path) -> Vec<PathBuf> {
+        self.track(directory);
+        let Ok(entries) = std::fs::read_dir(directory) else {
+            return Vec::new();
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            // package manager leftovers and placeholders that cron itself skips
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                !name.starts_with('.') && !name.contains(".dpkg-") && !name.ends_with('~')
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    // "m h dom mon dow [user] command" or "@daily [user] command"; the system crontab and
+    // cron.d have the user column, per-user crontabs do not
+    fn read_crontab(&mut self, path: &Path, owner: Option<String>) {
+        let Some(content) = self.read(path) else {
+            return;
+        };
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || is_assignment(line) {
+                continue;
+            }
+
+            let mut rest = line;
+            let mut take = || {
+                let trimmed = rest.trim_start();
+                let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+                let (field, tail) = trimmed.split_at(end);
+                rest = tail;
+                field
+            };
+
+            let first = take();
+            let schedule = if first.starts_with('@') {
+                first.to_string()
+            } else {
+                let fields: Vec<&str> = std::iter::once(first)
+                    .chain((0..4).map(|_| take()))
+                    .collect();
+                fields.join(" ")
+            };
+            let user = match &owner {
+                Some(owner) => Some(owner.clone()),
+                None => Some(take().to_string()),
+            };
+            let command = cron_command(rest.trim());
+            if command.is_empty() {
+                continue;
+            }
+
+            self.jobs.push(ScheduledJob {
+                source: path.to_path_buf(),
+                schedule,
+                user,
+                command,
+                unit: None,
+            });
+        }
+    }
+
+    // "period delay job-identifier command"
+    fn read_anacrontab(&mut self, path: &Path) {
+        let Some(content) = self.read(path) else {
+            return;
+        };
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || is_assignment(line) {
+                continue;
+            }
+            let fields: Vec<&str> = line.splitn(4, char::is_whitespace).collect();
+            let [period, _, _, command] = fields[..] else {
+                continue;
+            };
+            self.jobs.push(ScheduledJob {
+                source: path.to_path_buf(),
+                schedule: format!("every {} days", period.trim_start_matches('@')),
+                user: Some("root".to_string()),
+                command: command.trim().to_string(),
+                unit: None,
+            });
+        }
+    }
+
+    // at spools a shell script that restores the environment, the job itself comes last
+    fn read_at_job(&mut self, path: &Path) {
+        let Some(content) = self.read(path) else {
+            return;
+        };
+        let Some(command) = content
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty() && !line.starts_with('#') && *line != "}")
+        else {
+            return;
+        };
+
+        self.jobs.push(ScheduledJob {
+            source: path.to_path_buf(),
+            schedule: "at".to_string(),
+            user: None,
+            command: command.to_string(),
+            unit: None,
+        });
+    }
+
+    fn read_timer(&mut self, path: &Path) {
+        let Some(timer) = self.read(path) else {
+            return;
+        };
+        let Some(name) = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+        else {
+            return;
+        };
+
+        let schedule = unit_values(&timer, "Timer")
+            .filter(|(key, _)| key.starts_with("On"))
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if schedule.is_empty() {
+            return;
+        }
+
+        let service = unit_values(&timer, "Timer")
+            .find(|(key, _)| *key == "Unit")
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_else(|| format!("{}.service", name));
+        let Some(service_file) = SYSTEMD_UNIT_DIRS
+            .iter()
+            .map(|directory| Path::new(directory).join(&service))
+            .find(|file| file.is_file())
+        else {
+            return;
+        };
+        let Some(content) = self.read(&service_file) else {
+            return;
+        };
+
+        let user = unit_values(&content, "Service")
+            .find(|(key, _)| *key == "User")
+            .map(|(_, value)| value.to_string());
+        for (_, exec) in unit_values(&content, "Service").filter(|(key, _)| *key == "ExecStart") {
+            // "-", "@", "+", "!" and ":" prefixes change how systemd runs the command
+            let command = exec
+                .trim_start_matches(['-', '@', '+', '!', ':'])
+                .to_string();
+            self.jobs.push(ScheduledJob {
+                source: path.to_path_buf(),
+                schedule: schedule.clone(),
+                user: user.clone(),
+                command,
+                unit: Some(service.clone()),
+            });
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// crontabs may set variables, e.g. "SHELL=/bin/sh" or "MAILTO = root"
+fn is_assignment(line: &str) -> bool {
+    let name_end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(line.len());
+    name_end > 0 && line[name_end..].trim_start().starts_with('=')
+}
+
+// an unescaped % ends the command, whatever follows is fed to it on stdin
+fn cron_command(raw: &str) -> String {
+    let mut command = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'%') => {
+                command.push('%');
+                chars.next();
+            }
+            '%' => break,
+            _ => command.push(c),
+        }
+    }
+    command.trim().to_string()
+}
+
+// key/value pairs of one [section] of a systemd unit file
+fn unit_values<'a>(content: &'a str, section: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+    let mut current = "";
+    content.lines().filter_map(move |line| {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = name;
+            return None;
+        }
+        if current != section || line.starts_with('#') || line.starts_with(';') {
+            return None;
+        }
+        line.split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+    })
+}
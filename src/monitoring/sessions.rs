@@ -0,0 +1,155 @@
+use rustc_hash::FxHashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::core::event::{Event, SessionEvent, SessionKind};
+use crate::core::logger::Logger;
+use crate::utils::shutdown::{self, Worker};
+use crate::utils::throttle::{self, Priority};
+
+// struct utmp as glibc lays it out on 64-bit linux
+const UTMP_RECORD_SIZE: usize = 384;
+const UTMP_LINE: std::ops::Range<usize> = 8..40;
+const UTMP_USER: std::ops::Range<usize> = 44..76;
+const UTMP_HOST: std::ops::Range<usize> = 76..332;
+const USER_PROCESS: i16 = 7;
+
+struct Session {
+    user: String,
+    uid: Option<u32>,
+    host: Option<String>,
+    pid: u32,
+}
+
+// rereads utmp whenever it changes, sessions that exist when rspy starts are not reported
+pub struct SessionMonitor {
+    sender: Sender<Event>,
+    path: PathBuf,
+    interval: Duration,
+    // by tty
+    sessions: FxHashMap<String, Session>,
+    mtime: Option<SystemTime>,
+}
+
+impl SessionMonitor {
+    pub fn new(sender: Sender<Event>, path: PathBuf, interval: Duration) -> Self {
+        Self {
+            sender,
+            path,
+            interval,
+            sessions: FxHashMap::default(),
+            mtime: None,
+        }
+    }
+
+    pub fn start(mut self, running: Arc<AtomicBool>, priority: Option<Priority>) -> Worker {
+        let worker = thread::spawn(move || {
+            throttle::enter_collector_thread(priority);
+            self.mtime = self.modified();
+            self.sessions = self.read_sessions();
+
+            while shutdown::sleep(&running, self.interval) {
+                if let Err(e) = self.poll() {
+                    Logger::error(format!("session monitor stopped: {}", e));
+                    break;
+                }
+            }
+        });
+        ("session monitor", worker)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    // a missing utmp (containers, minimal systems) simply has no sessions
+    fn read_sessions(&self) -> FxHashMap<String, Session> {
+        let content = std::fs::read(&self.path).unwrap_or_default();
+        content
+            .chunks_exact(UTMP_RECORD_SIZE)
+            .filter_map(parse_record)
+            .collect()
+    }
+
+    fn poll(&mut self) -> std::result::Result<(), String> {
+        let mtime = self.modified();
+        if mtime == self.mtime {
+            return Ok(());
+        }
+        self.mtime = mtime;
+
+        let current = self.read_sessions();
+        let mut events = Vec::new();
+        for (tty, session) in &self.sessions {
+            if current.get(tty).is_none_or(|now| now.pid != session.pid) {
+                events.push(session_event(SessionKind::Logout, tty, session));
+            }
+        }
+        for (tty, session) in &current {
+            if self
+                .sessions
+                .get(tty)
+                .is_none_or(|before| before.pid != session.pid)
+            {
+                events.push(session_event(SessionKind::Login, tty, session));
+            }
+        }
+        self.sessions = current;
+
+        for event in events {
+            self.sender
+                .send(event)
+                .map_err(|e| format!("failed to send session event: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+fn session_event(kind: SessionKind, tty: &str, session: &Session) -> Event {
+    Event::Session(SessionEvent {
+        kind,
+        user: session.user.clone(),
+        uid: session.uid,
+        tty: tty.to_string(),
+        host: session.host.clone(),
+        pid: (session.pid > 0).then_some(session.pid),
+        observed_at: SystemTime::now(),
+    })
+}
+
+// logouts leave the record in place as DEAD_PROCESS, so only live sessions are kept
+fn parse_record(record: &[u8]) -> Option<(String, Session)> {
+    let kind = i16::from_ne_bytes(record[0..2].try_into().ok()?);
+    if kind != USER_PROCESS {
+        return None;
+    }
+    let pid = u32::from_ne_bytes(record[4..8].try_into().ok()?);
+    let tty = c_string(&record[UTMP_LINE])?;
+    let user = c_string(&record[UTMP_USER])?;
+    let host = c_string(&record[UTMP_HOST]);
+    // login hands the tty over to the user
+    let uid = std::fs::metadata(format!("/dev/{}", tty))
+        .ok()
+        .map(|metadata| metadata.uid());
+    Some((
+        tty,
+        Session {
+            user,
+            uid,
+            host,
+            pid,
+        },
+    ))
+}
+
+fn c_string(field: &[u8]) -> Option<String> {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    (end > 0).then(|| String::from_utf8_lossy(&field[..end]).into_owned())
+}
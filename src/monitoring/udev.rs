@@ -0,0 +1,161 @@
+use libc::{self, AF_NETLINK, NETLINK_KOBJECT_UEVENT, SOCK_CLOEXEC, SOCK_RAW, sockaddr_nl};
+use rustc_hash::FxHashMap;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+
+use crate::core::{
+    error::Result,
+    event::{DeviceEvent, Event},
+    logger::Logger,
+};
+use crate::monitoring::reactor::Source;
+
+const BUFFER_SIZE: usize = 8192;
+
+// uevents as the kernel sends them, group 2 carries udevd's own rebroadcast which needs
+// udevd to be running and is framed differently
+const UEVENT_KERNEL_GROUP: u32 = 1;
+
+// bind, change and the like follow almost every add and say little on their own
+const REPORTED_ACTIONS: [&str; 2] = ["add", "remove"];
+
+pub struct UdevListener {
+    fd: RawFd,
+    sender: Sender<Event>,
+    buffer: Vec<u8>,
+}
+
+impl UdevListener {
+    pub fn new(sender: Sender<Event>) -> Result<Self> {
+        let fd =
+            unsafe { libc::socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_KOBJECT_UEVENT) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut addr: sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = AF_NETLINK as u16;
+        addr.nl_groups = UEVENT_KERNEL_GROUP;
+
+        let result = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<sockaddr_nl>() as u32,
+            )
+        };
+        if result == -1 {
+            let err = io::Error::last_os_error();
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(err.into());
+        }
+
+        Ok(Self {
+            fd,
+            sender,
+            buffer: vec![0; BUFFER_SIZE],
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // reads and handles one uevent, recv flags allow non-blocking reads from async callers
+    pub fn receive(&mut self, flags: libc::c_int) -> io::Result<()> {
+        let read_size = unsafe {
+            libc::recv(
+                self.fd,
+                self.buffer.as_mut_ptr() as *mut libc::c_void,
+                self.buffer.len(),
+                flags,
+            )
+        };
+        if read_size < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOBUFS) {
+                Logger::debug("uevent netlink buffer overflowed, device events were dropped");
+                return Ok(());
+            }
+            return Err(err);
+        }
+
+        if let Some(event) = parse_uevent(&self.buffer[..read_size as usize]) {
+            let _ = self.sender.send(Event::Device(event));
+        }
+        Ok(())
+    }
+}
+
+// "add@/devices/...\0ACTION=add\0DEVPATH=/devices/...\0SUBSYSTEM=usb\0..."
+fn parse_uevent(datagram: &[u8]) -> Option<DeviceEvent> {
+    let mut parts = datagram
+        .split(|&b| b == 0)
+        .filter(|part| !part.is_empty())
+        .map(String::from_utf8_lossy);
+    // the "action@devpath" header is repeated by the fields
+    parts.next()?.split_once('@')?;
+
+    let fields: FxHashMap<String, String> = parts
+        .filter_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+
+    let action = fields.get("ACTION")?;
+    if !REPORTED_ACTIONS.contains(&action.as_str()) {
+        return None;
+    }
+    // kernel objects such as the queues of a network interface come and go with their
+    // device and have none of these
+    if !["DEVNAME", "INTERFACE", "PRODUCT"]
+        .iter()
+        .any(|key| fields.contains_key(*key))
+    {
+        return None;
+    }
+    Some(DeviceEvent {
+        action: action.clone(),
+        subsystem: fields.get("SUBSYSTEM").cloned(),
+        devtype: fields.get("DEVTYPE").cloned(),
+        devpath: fields.get("DEVPATH")?.clone(),
+        devname: fields.get("DEVNAME").cloned(),
+        interface: fields.get("INTERFACE").cloned(),
+        product: fields.get("PRODUCT").cloned(),
+        observed_at: SystemTime::now(),
+    })
+}
+
+impl Source for UdevListener {
+    fn name(&self) -> &'static str {
+        "udev listener"
+    }
+
+    fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    fn on_readable(&mut self) -> io::Result<()> {
+        loop {
+            match self.receive(0) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for UdevListener {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
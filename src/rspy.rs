@@ -1,30 +1,118 @@
-pub mod core;
-pub mod monitoring;
-pub mod utils;
-
-use crate::core::config::Config;
-use crate::core::error::Result;
-use crate::core::logger::Logger;
-use crate::monitoring::{dbus::DBusScanner, filesystem::FsWatcher, scanner::Scanner};
-use crate::utils::format::format_duration;
+use rspy::core::baseline::Baseline;
+use rspy::core::check;
+use rspy::core::config::{Command, Config};
+use rspy::core::constants::STEALTH_PROCESS_NAME;
+use rspy::core::control::{self, ControlCommand, ControlRequest};
+use rspy::core::correlate::Correlator;
+use rspy::core::dedup::CommandDedup;
+use rspy::core::diff;
+use rspy::core::error::Result;
+use rspy::core::event::{Event, ProcessEvent};
+use rspy::core::filter::{EventFilter, ExcludedPids};
+use rspy::core::interactive;
+use rspy::core::logger::Logger;
+use rspy::core::metrics::{self, METRICS};
+use rspy::core::output::{self, OutputFormat};
+use rspy::core::record::{self, Recorder};
+use rspy::core::rules::{RuleOutcome, Rules};
+use rspy::core::sandbox;
+use rspy::core::severity::Severity;
+use rspy::core::sinks::journald::JournaldSink;
+use rspy::core::sinks::remote::{RemoteSink, TlsFiles};
+use rspy::core::sinks::route::Router;
+#[cfg(feature = "sqlite")]
+use rspy::core::sinks::sqlite::SqliteSink;
+#[cfg(feature = "webhook")]
+use rspy::core::sinks::webhook::WebhookSink;
+use rspy::core::sinks::{Sink, exec::ExecSink};
+use rspy::core::snapshot;
+use rspy::core::stats::Stats;
+use rspy::core::tree;
+use rspy::core::writability::WritabilityAnalyzer;
+use rspy::monitoring::schedulers::{ScheduleIndex, ScheduleMatch};
+use rspy::utils::{daemon, format::format_duration, preserve::ExePreserver, signals, users};
+use rspy::{Backend, Rspy, RspyBuilder};
 
 use colored::*;
+use rustc_hash::FxHashSet;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{self, Receiver, channel};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Instant, SystemTime};
 
 struct Runtime {
     config: Config,
     running: Arc<AtomicBool>,
+    filter: EventFilter,
+    excluded_pids: ExcludedPids,
+    displayed_pids: FxHashSet<u32>,
+    sinks: Vec<Box<dyn Sink>>,
+    // only see what passed the filters, e.g. alerting
+    match_sinks: Vec<Box<dyn Sink>>,
+    rules: Option<Rules>,
+    schedules: Option<ScheduleIndex>,
+    stats: Stats,
+    // set once monitoring starts when --duration is given
+    deadline: Option<Instant>,
+    printed_events: u64,
+    baseline: Option<Baseline>,
+    // when --learn stops recording the baseline and starts enforcing it
+    learn_deadline: Option<Instant>,
+    control: Option<Receiver<ControlRequest>>,
+    correlator: Option<Correlator>,
+    writability: Option<WritabilityAnalyzer>,
+    router: Option<Router>,
+    // by SIGUSR1 until SIGUSR2, or pause until resume on the control socket
+    paused: bool,
 }
 
 impl Runtime {
     fn new(config: Config) -> Self {
+        let limits = config.cache_limits();
+        let mut filter = EventFilter::new(
+            config.filter.clone(),
+            config.exclude.clone(),
+            config.uids.clone(),
+            config.excluded_uids.clone(),
+            config
+                .unique_cmd
+                .then(|| CommandDedup::new(limits.commands)),
+        );
+        filter.set_dedup_window(config.dedup_window, limits.commands);
+        let excluded_pids = ExcludedPids::new(config.ignore_self, &config.ignore_pids);
+        let correlator = config.correlate.map(Correlator::new);
+        let writability = config.analyze_writability.map(WritabilityAnalyzer::new);
+        let mut stats = Stats::new();
+        if let Some(threshold) = config.lifetimes {
+            // a recording does not tell which interval it was scanned with
+            let interval = match config.command {
+                Some(Command::Replay { .. }) => None,
+                _ => config.scan_interval(),
+            };
+            stats.track_lifetimes(threshold, interval);
+        }
         Self {
             config,
             running: Arc::new(AtomicBool::new(true)),
+            filter,
+            excluded_pids,
+            displayed_pids: FxHashSet::default(),
+            sinks: Vec::new(),
+            match_sinks: Vec::new(),
+            rules: None,
+            schedules: None,
+            stats,
+            deadline: None,
+            printed_events: 0,
+            baseline: None,
+            learn_deadline: None,
+            control: None,
+            correlator,
+            writability,
+            router: None,
+            paused: false,
         }
     }
 
@@ -59,6 +147,9 @@ impl Runtime {
 
     fn display_config_info(&self) -> Result<()> {
         println!("\n{}", "configuration:".cyan().bold());
+        if let Some(profile) = self.config.profile {
+            println!("  profile: {}", profile.as_str().cyan());
+        }
         println!(
             "  print file system events: {}",
             if self.config.print_filesystem_events {
@@ -78,6 +169,18 @@ impl Runtime {
                 ),
                 None => println!("  process scanning: {}", "inotify events only".green()),
             }
+
+            if self.config.burst_count() > 1 {
+                println!(
+                    "  triggered scans: {}",
+                    format!(
+                        "bursts of {} every {}",
+                        self.config.burst_count(),
+                        format_duration(Some(self.config.burst_interval()))
+                    )
+                    .green()
+                );
+            }
         }
 
         if !self.config.dbus_only {
@@ -120,6 +223,71 @@ impl Runtime {
             );
         }
 
+        println!(
+            "  hidden process detection: {}",
+            if self.config.detect_hidden {
+                "enabled".green()
+            } else {
+                "disabled".red()
+            }
+        );
+
+        if let Some(filter) = &self.config.filter {
+            println!("  command filter: {}", filter.as_str().cyan());
+        }
+        if let Some(exclude) = &self.config.exclude {
+            println!("  command exclude: {}", exclude.as_str().cyan());
+        }
+
+        if !self.config.uids.is_empty() {
+            println!("  only uids: {:?}", self.config.uids);
+        }
+        if !self.config.excluded_uids.is_empty() {
+            println!("  excluded uids: {:?}", self.config.excluded_uids);
+        }
+        if let Some(severity) = self.config.min_severity {
+            println!("  minimum severity: {}", severity.as_str().cyan());
+        }
+        if self.config.suid_only {
+            println!("  only setuid/setgid execs: {}", "enabled".green());
+        }
+
+        if let Some(path) = &self.config.baseline {
+            match self.config.learn {
+                Some(learn) => println!(
+                    "  baseline: {}",
+                    format!(
+                        "learning {} into {}",
+                        format_duration(Some(learn)),
+                        path.display()
+                    )
+                    .cyan()
+                ),
+                None => println!("  baseline: {}", path.display().to_string().cyan()),
+            }
+        }
+
+        println!(
+            "  unique commands only: {}",
+            if self.config.unique_cmd {
+                "enabled".green()
+            } else {
+                "disabled".red()
+            }
+        );
+        if let Some(window) = self.config.dedup_window {
+            println!(
+                "  repeated commands: {}",
+                format!("collapsed within {}", format_duration(Some(window))).green()
+            );
+        }
+        if let Some(window) = self.config.correlate {
+            println!(
+                "  correlation: {}",
+                format!("stories within {}", format_duration(Some(window))).green()
+            );
+        }
+
         Ok(())
     }
 
@@ -161,91 +329,372 @@ impl Runtime {
         Ok(())
     }
 
-    fn run(self) -> Result<()> {
-        self.display_banner_and_config()?;
+    fn run(mut self) -> Result<()> {
+        // the banner would end up in the SIEM's input
+        if !self.config.unattended() && self.config.output_format == OutputFormat::Text {
+            self.display_banner_and_config()?;
 
-        if !self.confirm_configuration()? {
-            std::process::exit(0);
-        }
+            // nohup, systemd units and reverse shells have no one to answer the prompt
+            let interactive_stdin = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+            if !self.config.yes && interactive_stdin && !self.confirm_configuration()? {
+                return Ok(());
+            }
 
-        println!();
+            println!();
+        }
         self.setup_signal_handler()?;
+        signals::install()
+            .map_err(|e| format!("error setting SIGUSR1/SIGUSR2/SIGQUIT handlers: {}", e))?;
 
-        if (self.config.dbus || self.config.dbus_only) && !DBusScanner::is_available() {
-            Logger::error("dbus is not available on this system. exiting...".to_string());
-            std::process::exit(1);
+        // optional outputs, losing one is no reason to stop monitoring
+        if let Some(addr) = self.config.metrics_listen
+            && let Err(e) = metrics::serve(addr)
+        {
+            Logger::error(format!(
+                "failed to serve metrics on {} ({}), continuing without them",
+                addr, e
+            ));
         }
 
-        let (tx, rx) = channel();
-        let (trigger_tx, trigger_rx) = mpsc::channel();
+        // created before the watches so their files can be excluded from them
+        self.open_sinks()?;
+        self.load_rules()?;
+        self.load_baseline()?;
+        if let Some(analyzer) = &self.writability {
+            Logger::info(format!(
+                "alerting on root processes that uid {} could change",
+                analyzer.uid()
+            ));
+        }
 
-        let directories: Vec<PathBuf> = self
-            .config
-            .get_recursive_watch_dirs()
-            .iter()
-            .map(PathBuf::from)
-            .collect();
-
-        let mut fs_watcher = if !self.config.dbus_only {
-            Some(FsWatcher::new(
-                tx.clone(),
-                trigger_tx,
-                directories,
-                self.config
-                    .get_direct_watch_dirs()
-                    .iter()
-                    .map(PathBuf::from)
-                    .collect(),
-                self.config.print_filesystem_events,
-                self.config.low_resource,
-                self.config.debug,
-            )?)
+        if self.config.schedules {
+            let schedules = ScheduleIndex::load();
+            Logger::info(format!("found {} scheduled jobs", schedules.len()));
+            self.schedules = Some(schedules);
+        }
+
+        let mut builder = RspyBuilder::from_config(&self.config)
+            .excluded_paths(self.own_output_paths())
+            .running(Arc::clone(&self.running));
+        if let Some(directory) = &self.config.preserve_exe {
+            let preserver = ExePreserver::new(directory, self.config.preserve_environ)
+                .map_err(|e| format!("failed to create {}: {}", directory.display(), e))?;
+            Logger::info(format!(
+                "preserving new binaries in {}",
+                preserver.directory().display()
+            ));
+            builder = builder.preserver(Some(preserver));
+        }
+        let collectors = builder.start()?;
+        Self::report_backends(collectors.backends());
+
+        if let Some(path) = &self.config.control_socket {
+            let (tx, rx) = mpsc::channel();
+            match control::serve(path, tx) {
+                Ok(()) => self.control = Some(rx),
+                Err(e) => Logger::error(format!(
+                    "failed to listen on {} ({}), continuing without the control socket",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+
+        if interactive::is_available() {
+            interactive::start();
+        }
+        // last, everything that needs more than the allowlist has been set up by now
+        if self.config.sandbox {
+            let allowed = sandbox::install()?;
+            Logger::info(format!("sandboxed to {} system calls", allowed));
+        }
+        if self.config.service {
+            daemon::notify("READY=1");
+        }
+
+        self.event_loop(collectors)
+    }
+
+    // the failures were logged as they happened, this is what is left running
+    fn report_backends(backends: &[Backend]) {
+        let (active, failed): (Vec<_>, Vec<_>) =
+            backends.iter().partition(|backend| backend.error.is_none());
+        let names = |backends: Vec<&Backend>| {
+            backends
+                .iter()
+                .map(|backend| backend.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if failed.is_empty() {
+            Logger::info(format!("collectors: {}", names(active)));
         } else {
-            None
+            Logger::info(format!(
+                "collectors: {} (failed: {})",
+                names(active),
+                names(failed)
+            ));
+        }
+    }
+
+    fn load_rules(&mut self) -> Result<()> {
+        if let Some(path) = &self.config.rules {
+            let mut rules = Rules::load(path)?;
+            Logger::info(format!(
+                "loaded {} rules from {}",
+                rules.len(),
+                path.display()
+            ));
+            if self.config.sandbox {
+                let dropped = rules.drop_run_actions();
+                if !dropped.is_empty() {
+                    Logger::error(format!(
+                        "--sandbox forbids running programs, ignoring the run action of rules {}",
+                        dropped.join(", ")
+                    ));
+                }
+            }
+            self.rules = Some(rules);
+        }
+        Ok(())
+    }
+
+    fn load_baseline(&mut self) -> Result<()> {
+        let Some(path) = &self.config.baseline else {
+            return Ok(());
         };
+        let baseline = if self.config.learn.is_some() {
+            Baseline::learn(path)?
+        } else {
+            Baseline::enforce(path)?
+        };
+        if !baseline.is_learning() {
+            Logger::info(format!(
+                "loaded {} baseline entries from {}",
+                baseline.len(),
+                path.display()
+            ));
+        }
+        self.baseline = Some(baseline);
+        Ok(())
+    }
 
-        if let Some(watcher) = fs_watcher.as_mut()
-            && let Err(e) = watcher.setup_watches()
+    fn finish_learning(&mut self) {
+        let Some(baseline) = self
+            .baseline
+            .as_mut()
+            .filter(|baseline| baseline.is_learning())
+        else {
+            return;
+        };
+        self.learn_deadline = None;
+        match baseline.save() {
+            Ok(()) => Logger::info(format!(
+                "wrote {} baseline entries to {}, only new processes are printed from now on",
+                baseline.len(),
+                baseline.path().display()
+            )),
+            Err(e) => Logger::error(e.to_string()),
+        }
+    }
+
+    fn check_learning(&mut self) {
+        if self
+            .learn_deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
         {
-            Logger::error(format!("failed to setup filesystem watches: {}", e));
-            std::process::exit(1);
+            self.finish_learning();
         }
+    }
 
-        let mut scanner = Scanner::new(
-            self.config.scan_interval(),
-            trigger_rx,
-            self.config.dbus_only,
-            self.config.dbus,
-            self.config.dbus_interval(),
-        );
+    fn schedule_tag(&mut self, process: &ProcessEvent) -> Option<ColoredString> {
+        match self.schedules.as_mut()?.classify(process)? {
+            ScheduleMatch::Expected(job) => {
+                Some(format!("[expected by {}]", job.describe()).dimmed())
+            }
+            ScheduleMatch::Unexpected(scheduler) => Some(
+                format!("[UNEXPECTED {} JOB]", scheduler.to_uppercase())
+                    .red()
+                    .bold(),
+            ),
+        }
+    }
 
-        scanner.set_active(true);
-        scanner.start();
+    // rules may change the severity, what they leave decides against --min-severity
+    fn apply_rules(&mut self, event: &Event) -> RuleOutcome {
+        let mut outcome = self
+            .rules
+            .as_mut()
+            .map(|rules| rules.apply(event))
+            .unwrap_or_default();
+        let severity = outcome.severity.unwrap_or_else(|| Severity::of(event));
+        if self.config.min_severity.is_some_and(|min| severity < min) {
+            outcome.suppressed = true;
+        }
+        if let Some(tag) = Logger::severity_tag(severity) {
+            outcome.tags.insert(0, tag);
+        }
+        outcome.severity = Some(severity);
+        outcome
+    }
 
-        if let Some(watcher) = fs_watcher
-            && let Err(e) = watcher.start_watching()
+    // true when the event was printed in a structured format instead of by the logger, or
+    // --route sent it somewhere else
+    fn print_structured(&mut self, event: &Event, outcome: &RuleOutcome) -> bool {
+        let severity = outcome.severity.unwrap_or(Severity::Info);
+        if let Some(router) = self.router.as_mut()
+            && !router.route(event, severity, &outcome.rules)
         {
-            Logger::error(format!("failed to start filesystem watcher: {}", e));
-            std::process::exit(1);
+            return true;
+        }
+        match output::format_event(self.config.output_format, event, severity, &outcome.rules) {
+            Some(line) => {
+                Logger::structured_event(&line);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn open_sinks(&mut self) -> Result<()> {
+        if let Some(path) = &self.config.record {
+            self.sinks.push(Box::new(Recorder::create(path)?));
+            Logger::info(format!("recording events to {}", path.display()));
+        }
+
+        #[cfg(feature = "sqlite")]
+        if let Some(path) = &self.config.sqlite {
+            self.sinks.push(Box::new(SqliteSink::open(path)?));
+            Logger::info(format!("storing events in {}", path.display()));
         }
 
-        self.event_loop(rx)
+        if let Some(address) = &self.config.remote {
+            let tls = self.config.tls.then(|| TlsFiles {
+                ca: self.config.tls_ca.clone(),
+                cert: self.config.tls_cert.clone(),
+                key: self.config.tls_key.clone(),
+            });
+            self.sinks
+                .push(Box::new(RemoteSink::new(address.clone(), tls.as_ref())?));
+            Logger::info(format!(
+                "shipping events to {}{}",
+                address,
+                if self.config.tls { " over tls" } else { "" }
+            ));
+        }
+
+        if !self.config.routes.is_empty() {
+            self.router = Some(Router::open(&self.config.routes)?);
+            for route in &self.config.routes {
+                Logger::info(format!("routing {}", route));
+            }
+        }
+
+        if let Some(command) = &self.config.exec_on_match {
+            self.match_sinks
+                .push(Box::new(ExecSink::new(command.clone())));
+            Logger::info(format!("running {:?} for matching events", command));
+        }
+
+        if self.config.journald {
+            self.match_sinks.push(Box::new(JournaldSink::open()?));
+            Logger::info("sending events to the systemd journal".to_string());
+        }
+
+        #[cfg(feature = "webhook")]
+        if let Some(url) = &self.config.webhook {
+            self.match_sinks
+                .push(Box::new(WebhookSink::new(url.clone())));
+            Logger::info(format!("posting matching events to {}", url));
+        }
+
+        Ok(())
+    }
+
+    fn flush_sinks(&mut self) {
+        if let Some(router) = self.router.as_mut() {
+            router.flush();
+        }
+        for sinks in [&mut self.sinks, &mut self.match_sinks] {
+            sinks.retain_mut(|sink| match sink.flush() {
+                Ok(()) => true,
+                Err(e) => {
+                    Logger::error(format!(
+                        "{} sink failed and was disabled: {}",
+                        sink.name(),
+                        e
+                    ));
+                    false
+                }
+            });
+        }
     }
 
-    fn event_loop(self, rx: Receiver<String>) -> Result<()> {
+    fn write_sinks(sinks: &mut Vec<Box<dyn Sink>>, event: &Event) {
+        sinks.retain_mut(|sink| match sink.write(event) {
+            Ok(()) => true,
+            Err(e) => {
+                Logger::error(format!(
+                    "{} sink failed and was disabled: {}",
+                    sink.name(),
+                    e
+                ));
+                false
+            }
+        });
+    }
+
+    fn own_output_paths(&self) -> Vec<PathBuf> {
+        // stdout/stderr redirected into a file, e.g. `rspy > /tmp/rspy.log`
+        [libc::STDOUT_FILENO, libc::STDERR_FILENO]
+            .iter()
+            .filter_map(|fd| std::fs::read_link(format!("/proc/self/fd/{}", fd)).ok())
+            .filter(|path| path.is_file())
+            .chain(self.config.record.clone())
+            .chain(self.config.pidfile.clone())
+            .chain(self.config.preserve_exe.clone())
+            .chain(
+                self.config
+                    .routes
+                    .iter()
+                    .filter_map(|route| route.path().map(Path::to_path_buf)),
+            )
+            .chain(self.config.sqlite.iter().flat_map(|path| {
+                // sqlite keeps its journal next to the database
+                ["", "-wal", "-shm", "-journal"].map(|suffix| {
+                    let mut file = path.clone().into_os_string();
+                    file.push(suffix);
+                    PathBuf::from(file)
+                })
+            }))
+            .collect()
+    }
+
+    fn event_loop(mut self, mut collectors: Rspy) -> Result<()> {
+        self.start_limits();
         loop {
+            self.check_deadline();
+            self.check_learning();
             if !self.running.load(Ordering::SeqCst) {
                 Logger::info("shutting down gracefully...".to_string());
                 break;
             }
 
-            match rx.recv_timeout(std::time::Duration::from_millis(100)) {
-                Ok(event) => {
-                    if self.config.print_filesystem_events {
-                        Logger::fs(event);
-                    }
-                }
+            self.handle_control_requests(&collectors);
+            self.handle_pause_signals(&collectors);
+            if signals::take_stats_request() {
+                self.stats.print_live();
+            }
+
+            match collectors.recv_timeout(std::time::Duration::from_millis(100)) {
+                Ok(event) => self.handle_event(event),
                 Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    self.flush_sinks();
+                    self.print_expired_repeats(Some(SystemTime::now()));
+                    self.print_stories(Some(SystemTime::now()));
+                    if let Some(schedules) = self.schedules.as_mut() {
+                        schedules.refresh();
+                    }
                     continue;
                 }
                 Err(e) => {
@@ -255,22 +704,399 @@ impl Runtime {
             }
         }
 
+        if self.config.service {
+            daemon::notify("STOPPING=1");
+        }
+        // the workers stop on the same flag, joining them closes their fds and makes sure
+        // nothing is still being produced when the sinks are flushed
+        collectors.stop();
+        self.flush_sinks();
+        self.finish_learning();
+        if let Some(path) = &self.config.control_socket {
+            let _ = std::fs::remove_file(path);
+        }
+
+        interactive::restore_terminal();
+        self.print_expired_repeats(None);
+        self.print_stories(None);
+        self.print_repeated_commands();
+        self.stats.print();
         Logger::info("rspy terminated".to_string());
         Ok(())
     }
+
+    fn handle_pause_signals(&mut self, collectors: &Rspy) {
+        let Some(paused) = signals::take_pause_request() else {
+            return;
+        };
+        if !self.set_paused(collectors, paused) {
+            return;
+        }
+        if paused {
+            Logger::info("received SIGUSR1, paused until SIGUSR2".to_string());
+        } else {
+            Logger::info("received SIGUSR2, resumed".to_string());
+        }
+    }
+
+    // false when it already was
+    fn set_paused(&mut self, collectors: &Rspy, paused: bool) -> bool {
+        if paused == self.paused {
+            return false;
+        }
+        self.paused = paused;
+        collectors.set_paused(paused);
+        true
+    }
+
+    fn handle_control_requests(&mut self, collectors: &Rspy) {
+        let Some(control) = self.control.as_ref() else {
+            return;
+        };
+        let requests: Vec<ControlRequest> = control.try_iter().collect();
+
+        for request in requests {
+            let reply = match request.command {
+                ControlCommand::AddWatch(path) => match collectors.add_watch(path.clone()) {
+                    Ok(()) => format!("ok watching {}", path.display()),
+                    Err(e) => format!("error: {}", e),
+                },
+                ControlCommand::SetFilter(pattern) => {
+                    let reply = match &pattern {
+                        Some(pattern) => format!("ok filter {}", pattern),
+                        None => "ok filter cleared".to_string(),
+                    };
+                    Logger::info(format!("control: {}", &reply[3..]));
+                    self.filter.set_include(pattern);
+                    reply
+                }
+                ControlCommand::Pause => {
+                    if self.set_paused(collectors, true) {
+                        Logger::info("control: paused until resume".to_string());
+                    }
+                    "ok paused".to_string()
+                }
+                ControlCommand::Resume => {
+                    if self.set_paused(collectors, false) {
+                        Logger::info("control: resumed".to_string());
+                    }
+                    "ok resumed".to_string()
+                }
+                ControlCommand::Stats => {
+                    format!("ok {}\n{}", self.stats.live_summary(), self.stats.summary())
+                }
+            };
+            let _ = request.reply.send(reply);
+        }
+    }
+
+    fn handle_event(&mut self, mut event: Event) {
+        // not even recorded, rspy's own activity is never part of what is observed
+        let excluded = match &event {
+            Event::Process(process) => self
+                .excluded_pids
+                .excludes_process(process.pid, process.ppid),
+            Event::Exit(exit) => {
+                let excluded = self.excluded_pids.excludes(Some(exit.pid));
+                self.excluded_pids.forget(exit.pid);
+                excluded
+            }
+            Event::Fs(fs_event) => self.excluded_pids.excludes(fs_event.pid),
+            Event::Connection(connection) => self.excluded_pids.excludes(connection.pid),
+            Event::Bus(bus) => self.excluded_pids.excludes(bus.pid),
+            Event::Authorization(auth) => self.excluded_pids.excludes(auth.subject_pid),
+            Event::Session(_) | Event::Device(_) => false,
+        };
+        // what arrives while paused is dropped, not only hidden, so baselines and recordings
+        // carry on as if the window never happened
+        if excluded {
+            return;
+        }
+        if self.paused {
+            METRICS.record_dropped_event();
+            return;
+        }
+        // before the sinks, so recordings keep what was writable at the time
+        if let (Some(analyzer), Event::Process(process)) = (&self.writability, &mut event) {
+            process.writable = analyzer.analyze(process);
+        }
+
+        // sinks keep everything so recordings can be replayed later with different filters
+        Self::write_sinks(&mut self.sinks, &event);
+        self.stats.record(&event);
+        if let Some(correlator) = self.correlator.as_mut() {
+            correlator.observe(&event);
+        }
+
+        match &event {
+            Event::Process(process) => {
+                // windows that closed before this event are reported ahead of it
+                self.print_expired_repeats(Some(process.observed_at));
+                self.print_stories(Some(process.observed_at));
+                if self.config.suid_only && !process.exe_setuid && !process.exe_setgid {
+                    return;
+                }
+                if !self
+                    .filter
+                    .allows(process.uid, &process.cmdline, process.observed_at)
+                {
+                    return;
+                }
+                if let Some(baseline) = self.baseline.as_mut()
+                    && !baseline.check(process)
+                {
+                    return;
+                }
+                let mut outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if let Some(tag) = self.schedule_tag(process) {
+                    outcome.tags.push(tag);
+                }
+                if self.config.track_exits() {
+                    self.displayed_pids.insert(process.pid);
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::process_event(process, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+                self.count_printed_event();
+            }
+            Event::Exit(exit) => {
+                // only report exits of processes whose start made it through the filters
+                if !self.displayed_pids.remove(&exit.pid) {
+                    return;
+                }
+                let mut outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if let Some(threshold) = self.config.lifetimes
+                    && exit.lifetime.is_some_and(|lifetime| lifetime < threshold)
+                {
+                    outcome.tags.push("[transient]".yellow());
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::exit_event(exit, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+            }
+            Event::Fs(fs_event) => {
+                // rules see filesystem events even when they are not printed
+                let outcome = self.apply_rules(&event);
+                if (self.config.print_filesystem_events || fs_event.file_modified)
+                    && !outcome.suppressed
+                {
+                    if !self.print_structured(&event, &outcome) {
+                        Logger::fs_event(fs_event, &outcome.tags);
+                    }
+                    Self::write_sinks(&mut self.match_sinks, &event);
+                }
+            }
+            Event::Connection(connection) => {
+                let outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::connection_event(connection, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+            }
+            Event::Session(session) => {
+                let outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::session_event(session, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+            }
+            Event::Device(device) => {
+                let outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::device_event(device, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+            }
+            Event::Bus(bus) => {
+                let outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::bus_event(bus, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+            }
+            Event::Authorization(auth) => {
+                let outcome = self.apply_rules(&event);
+                if outcome.suppressed {
+                    return;
+                }
+                if !self.print_structured(&event, &outcome) {
+                    Logger::authorization_event(auth, &outcome.tags);
+                }
+                Self::write_sinks(&mut self.match_sinks, &event);
+            }
+        }
+    }
+
+    fn replay(mut self, path: &Path, speed: f64) -> Result<()> {
+        self.setup_signal_handler()?;
+        self.open_sinks()?;
+        self.load_rules()?;
+        self.load_baseline()?;
+        Logger::info(format!("replaying {}", path.display()));
+
+        let running = Arc::clone(&self.running);
+        self.start_limits();
+        let replayed = record::replay(path, speed, |event| {
+            self.handle_event(event);
+            self.check_deadline();
+            self.check_learning();
+            running.load(Ordering::SeqCst)
+        })?;
+        self.finish_learning();
+
+        self.print_expired_repeats(None);
+        self.print_stories(None);
+        self.print_repeated_commands();
+        self.stats.print();
+        Logger::info(format!("replayed {} events", replayed));
+        Ok(())
+    }
+
+    fn start_limits(&mut self) {
+        if let Some(duration) = self.config.duration {
+            self.deadline = Some(Instant::now() + duration);
+            Logger::info(format!(
+                "stopping after {}",
+                format_duration(Some(duration))
+            ));
+        }
+        if let Some(learn) = self.config.learn {
+            self.learn_deadline = Some(Instant::now() + learn);
+            Logger::info(format!(
+                "learning a baseline for {}",
+                format_duration(Some(learn))
+            ));
+        }
+    }
+
+    fn check_deadline(&self) {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+            && self.running.swap(false, Ordering::SeqCst)
+        {
+            Logger::info("monitoring window elapsed".to_string());
+        }
+    }
+
+    fn count_printed_event(&mut self) {
+        self.printed_events += 1;
+        if self.config.max_events == Some(self.printed_events)
+            && self.running.swap(false, Ordering::SeqCst)
+        {
+            Logger::info(format!("captured {} process events", self.printed_events));
+        }
+    }
+
+    fn print_expired_repeats(&mut self, now: Option<SystemTime>) {
+        let Some(window) = self.filter.dedup_window() else {
+            return;
+        };
+        for (uid, cmd, count) in self.filter.expired_repeats(now) {
+            Logger::repeated_command(uid, count, window, &cmd);
+        }
+    }
+
+    fn print_stories(&mut self, now: Option<SystemTime>) {
+        let Some(correlator) = self.correlator.as_mut() else {
+            return;
+        };
+        for story in correlator.expire(now) {
+            Logger::story(story.pid, story.uid, &story.text);
+        }
+    }
+
+    fn print_repeated_commands(&self) {
+        let repeated = self.filter.repeated_commands();
+        if repeated.is_empty() {
+            return;
+        }
+
+        Logger::info(format!(
+            "{} repeated commands were suppressed:",
+            repeated.len()
+        ));
+        for (uid, cmd, count) in repeated {
+            Logger::repeat_summary(uid, count, cmd);
+        }
+    }
 }
 
 fn main() {
     let config = Config::new();
+    if config.stealth
+        && let Err(e) = daemon::disguise(STEALTH_PROCESS_NAME)
+    {
+        eprintln!("failed to disguise rspy: {}", e);
+        std::process::exit(1);
+    }
+    // before any thread exists, fork only takes the calling one along
+    if config.daemon
+        && let Err(e) = daemon::daemonize(config.log_file.as_deref())
+    {
+        eprintln!("failed to start in the background: {}", e);
+        std::process::exit(1);
+    }
     Logger::init(if config.debug {
         log::Level::Debug
     } else {
         log::Level::Info
     });
+    Logger::set_color(config.use_color());
+    Logger::set_timestamp_format(config.timestamp_format);
+    Logger::set_structured(config.output_format != OutputFormat::Text);
+    if let Some(template) = config.format_template.clone() {
+        Logger::set_template(template);
+    }
+    if config.resolve_users {
+        users::enable();
+    }
+    if config.daemon {
+        Logger::info(format!(
+            "running in the background as pid {}",
+            std::process::id()
+        ));
+    }
+    let pidfile = config.pidfile.clone();
+    if let Some(path) = &pidfile
+        && let Err(e) = daemon::write_pidfile(path)
+    {
+        Logger::error(e);
+        std::process::exit(1);
+    }
 
-    let runtime = Runtime::new(config);
-
-    if let Err(e) = runtime.run() {
+    let result = match config.command.clone() {
+        Some(Command::Replay { file, speed }) => Runtime::new(config).replay(&file, speed),
+        Some(Command::Snapshot) => snapshot::run(&config),
+        Some(Command::Check) => check::run(),
+        Some(Command::Tree { interval }) => tree::run(interval),
+        Some(Command::Diff { first, second }) => diff::run(&first, &second),
+        Some(Command::Monitor { .. }) | None => Runtime::new(config).run(),
+    };
+    if let Some(path) = &pidfile {
+        let _ = std::fs::remove_file(path);
+    }
+    if let Err(e) = result {
         Logger::error(format!("runtime error: {}", e));
         std::process::exit(1);
     }
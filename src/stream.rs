@@ -0,0 +1,654 @@
+use futures_core::Stream;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc as std_mpsc};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{Notify, mpsc};
+use tokio::task::{self, JoinSet};
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use crate::core::constants::{ASYNC_EVENT_QUEUE_SIZE, WORKER_POLL_INTERVAL_MS};
+#[cfg(feature = "dbus")]
+use crate::core::constants::{DBUS_MAX_BACKOFF_SECS, DBUS_MIN_BACKOFF_MS};
+use crate::core::error::Result;
+use crate::core::event::Event;
+use crate::core::logger::Logger;
+#[cfg(feature = "dbus")]
+use crate::core::metrics::METRICS;
+#[cfg(feature = "dbus")]
+use crate::monitoring::dbus::{self, DBusScanner};
+use crate::monitoring::{
+    audit::AuditListener,
+    contents::ContentWatcher,
+    filesystem::FsWatcher,
+    process::ProcessScanner,
+    reactor::{Source, set_nonblocking},
+    udev::UdevListener,
+};
+use crate::{Backend, Rspy, RspyBuilder};
+
+// every collector as a task on one tokio executor instead of a thread each. the collectors
+// still hand their events over through unbounded channels, so a slow consumer is not
+// pushed back on and they buffer in memory
+pub struct EventStream {
+    events: mpsc::Receiver<Event>,
+    // dropping the stream aborts the collectors, which closes their fds
+    tasks: JoinSet<()>,
+    backends: Vec<Backend>,
+    watch_requests: Option<std_mpsc::Sender<PathBuf>>,
+    // the procfs scanner's switch, see Rspy::set_paused
+    scanning: Arc<AtomicBool>,
+}
+
+impl EventStream {
+    // picked up by the inotify task within a poll interval
+    pub fn add_watch(&self, path: PathBuf) -> Result<()> {
+        let Some(requests) = &self.watch_requests else {
+            return Err("adding watches needs the inotify watcher".into());
+        };
+        requests
+            .send(path)
+            .map_err(|_| "the inotify watcher is not running")?;
+        Ok(())
+    }
+
+    // procfs scans only, see Rspy::set_paused
+    pub fn set_paused(&self, paused: bool) {
+        self.scanning.store(!paused, Ordering::Relaxed);
+    }
+
+    pub async fn next(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+
+    pub fn collectors(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn backends(&self) -> &[Backend] {
+        &self.backends
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl RspyBuilder {
+    // has to be called from within a tokio runtime, the collectors are spawned onto it
+    pub fn start_stream(mut self) -> Result<EventStream> {
+        tokio::runtime::Handle::try_current()
+            .map_err(|e| format!("start_stream needs a tokio runtime: {}", e))?;
+        let mut backends = Vec::new();
+        self.check_dbus(&mut backends);
+        let content_files = self.content_files();
+        if self.fanotify {
+            return Err("fanotify is not supported by the async pipeline".into());
+        }
+        if self.detect_hidden {
+            return Err("hidden process detection is not supported by the async pipeline".into());
+        }
+        if self.network || self.unix_sockets {
+            return Err("network monitoring is not supported by the async pipeline".into());
+        }
+        if self.sessions {
+            return Err("session monitoring is not supported by the async pipeline".into());
+        }
+        if self.dbus_sniff {
+            return Err("dbus sniffing is not supported by the async pipeline".into());
+        }
+        if self.polkit {
+            return Err("polkit monitoring is not supported by the async pipeline".into());
+        }
+        if self.priority.is_some() || self.cpu_limit.is_some() || self.scan_jitter.is_some() {
+            return Err(
+                "priorities, cpu limits and scan jitter are not supported by the async pipeline"
+                    .into(),
+            );
+        }
+
+        let (tx, rx) = mpsc::channel(ASYNC_EVENT_QUEUE_SIZE);
+        let mut tasks = JoinSet::new();
+        let trigger = Arc::new(Notify::new());
+        let scanning = Arc::new(AtomicBool::new(true));
+        let mut watch_requests = None;
+        #[cfg(feature = "dbus")]
+        let units = dbus::unit_resolver(self.show_unit);
+
+        if !self.dbus_only {
+            let (events_tx, events_rx) = std_mpsc::channel();
+            let (trigger_tx, trigger_rx) = std_mpsc::channel();
+            let watcher = FsWatcher::new(
+                events_tx,
+                trigger_tx,
+                self.recursive_dirs,
+                self.direct_dirs,
+                self.filesystem_events,
+                self.low_resource,
+                self.debug,
+            )
+            .and_then(|mut watcher| {
+                watcher.set_excluded_paths(&self.excluded_paths);
+                watcher.set_watch_exclude(&self.watch_exclude)?;
+                watcher.set_max_watches(self.max_watches);
+                watcher.set_watch_events(self.watch_events);
+                watcher.set_watch_masks(self.watch_masks);
+                watcher.setup_watches()?;
+                set_nonblocking(watcher.fd())?;
+                Ok(watcher)
+            });
+            let report = watcher.as_ref().ok().map(FsWatcher::watch_report);
+            match watcher {
+                Ok(mut watcher) => {
+                    watch_requests = Some(watcher.watch_requests());
+                    tasks.spawn(watch_filesystem(
+                        watcher,
+                        events_rx,
+                        trigger_rx,
+                        tx.clone(),
+                        Arc::clone(&trigger),
+                    ));
+                    backends.push(Backend::active("inotify"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "failed to setup filesystem watches ({}), continuing with procfs scans alone",
+                        e
+                    ));
+                    backends.push(Backend::failed("inotify", e));
+                }
+            }
+            crate::check_watch_coverage(self.strict_watches, report)?;
+
+            let (events_tx, events_rx) = std_mpsc::channel();
+            let mut scanner = ProcessScanner::new(events_tx);
+            scanner.set_show_tree(self.show_tree);
+            scanner.set_show_exits(self.show_exits);
+            scanner.set_hash_exe(self.hash_exe, self.cache_limits.hashes);
+            scanner.set_show_fds(self.show_fds);
+            scanner.set_show_cgroup(self.show_cgroup);
+            scanner.set_show_caps(self.show_caps);
+            scanner.set_show_cwd(self.show_cwd);
+            scanner.set_highlight_tmp_exec(self.highlight_tmp_exec);
+            scanner.set_check_libraries(self.check_libraries);
+            #[cfg(feature = "dbus")]
+            scanner.set_units(units.clone());
+            scanner.set_scan_threads(self.scan_threads);
+            scanner.set_track_cmdline(self.track_cmdline);
+            scanner.set_preserver(self.preserver);
+            tasks.spawn(scan_processes(
+                scanner,
+                events_rx,
+                tx.clone(),
+                Arc::clone(&trigger),
+                Arc::clone(&scanning),
+                ScanSchedule {
+                    interval: self.scan_interval,
+                    burst_count: self.burst_count.max(1),
+                    burst_interval: self.burst_interval,
+                },
+            ));
+            backends.push(Backend::active("procfs"));
+        }
+
+        if self.audit {
+            let (events_tx, events_rx) = std_mpsc::channel();
+            match AuditListener::new(events_tx) {
+                Ok(mut listener) => {
+                    listener.set_highlight_tmp_exec(self.highlight_tmp_exec);
+                    listener.set_check_libraries(self.check_libraries);
+                    set_nonblocking(listener.fd())?;
+                    tasks.spawn(listen_audit(listener, events_rx, tx.clone()));
+                    backends.push(Backend::active("audit"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "audit netlink is not available ({}), continuing without it",
+                        e
+                    ));
+                    backends.push(Backend::failed("audit", e));
+                }
+            }
+        }
+
+        if self.udev {
+            let (events_tx, events_rx) = std_mpsc::channel();
+            match UdevListener::new(events_tx) {
+                Ok(listener) => {
+                    set_nonblocking(listener.fd())?;
+                    tasks.spawn(listen_udev(listener, events_rx, tx.clone()));
+                    backends.push(Backend::active("udev"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "uevent netlink is not available ({}), continuing without it",
+                        e
+                    ));
+                    backends.push(Backend::failed("udev", e));
+                }
+            }
+        }
+
+        if !content_files.is_empty() {
+            let (events_tx, events_rx) = std_mpsc::channel();
+            match ContentWatcher::new(events_tx, &content_files) {
+                Ok(watcher) => {
+                    tasks.spawn(listen_contents(watcher, events_rx, tx.clone()));
+                    backends.push(Backend::active("contents"));
+                }
+                Err(e) => {
+                    Logger::error(format!(
+                        "failed to watch the contents of {} files ({}), continuing without it",
+                        content_files.len(),
+                        e
+                    ));
+                    backends.push(Backend::failed("contents", e));
+                }
+            }
+        }
+
+        #[cfg(feature = "dbus")]
+        if self.dbus || self.dbus_only {
+            let (events_tx, events_rx) = std_mpsc::channel();
+            let mut scanner = DBusScanner::new(self.dbus_interval, events_tx);
+            scanner.set_max_pids(self.cache_limits.pids);
+            scanner.set_show_exits(self.show_exits);
+            scanner.set_highlight_tmp_exec(self.highlight_tmp_exec);
+            scanner.set_check_libraries(self.check_libraries);
+            scanner.set_units(units);
+            let trigger = (!self.dbus_only).then_some(trigger);
+            tasks.spawn(poll_dbus(scanner, events_rx, tx.clone(), trigger));
+        }
+
+        Ok(EventStream {
+            events: rx,
+            tasks,
+            backends,
+            watch_requests,
+            scanning,
+        })
+    }
+}
+
+// the rspy binary keeps its synchronous event loop, the pipeline then gets a thread of
+// its own and hands events over to the regular Rspy interface
+pub(crate) fn start_on_thread(builder: RspyBuilder) -> Result<Rspy> {
+    let running = Arc::clone(&builder.running);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    // setup errors such as failing watches still reach the caller
+    let mut stream = runtime.block_on(async { builder.start_stream() })?;
+    let backends = stream.backends.clone();
+    let watch_requests = stream.watch_requests.clone();
+    let scanning = Arc::clone(&stream.scanning);
+
+    let (tx, rx) = std_mpsc::channel();
+    let thread_running = Arc::clone(&running);
+    let worker = thread::spawn(move || {
+        runtime.block_on(async move {
+            let mut ticker = time::interval(Duration::from_millis(WORKER_POLL_INTERVAL_MS));
+            while thread_running.load(Ordering::SeqCst) {
+                tokio::select! {
+                    event = stream.next() => match event {
+                        Some(event) => {
+                            if tx.send(event).is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => {}
+                }
+            }
+        });
+    });
+
+    Ok(Rspy {
+        events: rx,
+        running,
+        workers: vec![("async collectors", worker)],
+        wakers: Vec::new(),
+        watch_requests,
+        scanning: Some(scanning),
+        backends,
+    })
+}
+
+struct ScanSchedule {
+    interval: Option<Duration>,
+    burst_count: u32,
+    burst_interval: Duration,
+}
+
+async fn scan_processes(
+    mut scanner: ProcessScanner,
+    events: std_mpsc::Receiver<Event>,
+    tx: mpsc::Sender<Event>,
+    trigger: Arc<Notify>,
+    scanning: Arc<AtomicBool>,
+    schedule: ScanSchedule,
+) {
+    let mut ticker = schedule.interval.map(|interval| {
+        let mut ticker = time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        ticker
+    });
+
+    loop {
+        // Notify keeps at most one pending wakeup, a storm of filesystem events that
+        // arrives during a scan results in a single follow-up scan
+        let triggered = tokio::select! {
+            _ = tick(&mut ticker) => false,
+            _ = trigger.notified() => true,
+        };
+        if !scanning.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let scans = if triggered { schedule.burst_count } else { 1 };
+        for scan in 0..scans {
+            if scan > 0 {
+                time::sleep(schedule.burst_interval).await;
+            }
+            // procfs reads, hashing and unit lookups block, on a current-thread runtime they
+            // would hold up every other collector
+            let result;
+            (scanner, result) = match task::spawn_blocking(move || {
+                let result = scanner.scan_processes();
+                (scanner, result)
+            })
+            .await
+            {
+                Ok(done) => done,
+                Err(e) => {
+                    Logger::error(format!("process scan task failed: {}", e));
+                    return;
+                }
+            };
+            if let Err(e) = result {
+                Logger::error(format!("process scan failed: {}", e));
+            }
+            let batch: Vec<Event> = events.try_iter().collect();
+            if !forward(batch, &tx).await {
+                return;
+            }
+        }
+    }
+}
+
+async fn tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        // --no-interval, only filesystem triggers start scans
+        None => std::future::pending().await,
+    }
+}
+
+async fn watch_filesystem(
+    mut watcher: FsWatcher,
+    events: std_mpsc::Receiver<Event>,
+    triggers: std_mpsc::Receiver<()>,
+    tx: mpsc::Sender<Event>,
+    trigger: Arc<Notify>,
+) {
+    let fd = match AsyncFd::new(watcher.fd()) {
+        Ok(fd) => fd,
+        Err(e) => {
+            Logger::error(format!("failed to register the inotify fd: {}", e));
+            return;
+        }
+    };
+
+    // watches asked for with add_watch are added on the next tick
+    let mut requests = time::interval(Duration::from_millis(WORKER_POLL_INTERVAL_MS));
+    requests.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        let readable = tokio::select! {
+            readable = fd.readable() => readable,
+            _ = requests.tick() => {
+                watcher.on_wake();
+                continue;
+            }
+        };
+        let mut guard = match readable {
+            Ok(guard) => guard,
+            Err(e) => {
+                Logger::error(format!("error waiting for inotify events: {}", e));
+                return;
+            }
+        };
+        match guard.try_io(|_| watcher.read_batch()) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                Logger::error(format!("error reading events: {}", e));
+                return;
+            }
+            Err(_would_block) => continue,
+        }
+
+        if triggers.try_iter().count() > 0 {
+            trigger.notify_one();
+        }
+        let batch: Vec<Event> = events.try_iter().collect();
+        if !forward(batch, &tx).await {
+            return;
+        }
+    }
+}
+
+async fn listen_audit(
+    mut listener: AuditListener,
+    events: std_mpsc::Receiver<Event>,
+    tx: mpsc::Sender<Event>,
+) {
+    let fd = match AsyncFd::new(listener.fd()) {
+        Ok(fd) => fd,
+        Err(e) => {
+            Logger::error(format!("failed to register the audit socket: {}", e));
+            return;
+        }
+    };
+
+    loop {
+        let mut guard = match fd.readable().await {
+            Ok(guard) => guard,
+            Err(e) => {
+                Logger::error(format!("error waiting for audit records: {}", e));
+                return;
+            }
+        };
+        match guard.try_io(|_| listener.receive(0)) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {}
+            Ok(Err(e)) => {
+                Logger::error(format!("error reading audit records: {}", e));
+                return;
+            }
+            Err(_would_block) => continue,
+        }
+
+        let batch: Vec<Event> = events.try_iter().collect();
+        if !forward(batch, &tx).await {
+            return;
+        }
+    }
+}
+
+async fn listen_udev(
+    mut listener: UdevListener,
+    events: std_mpsc::Receiver<Event>,
+    tx: mpsc::Sender<Event>,
+) {
+    let fd = match AsyncFd::new(listener.fd()) {
+        Ok(fd) => fd,
+        Err(e) => {
+            Logger::error(format!("failed to register the uevent socket: {}", e));
+            return;
+        }
+    };
+
+    loop {
+        let mut guard = match fd.readable().await {
+            Ok(guard) => guard,
+            Err(e) => {
+                Logger::error(format!("error waiting for uevents: {}", e));
+                return;
+            }
+        };
+        match guard.try_io(|_| listener.receive(0)) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {}
+            Ok(Err(e)) => {
+                Logger::error(format!("error reading uevents: {}", e));
+                return;
+            }
+            Err(_would_block) => continue,
+        }
+
+        let batch: Vec<Event> = events.try_iter().collect();
+        if !forward(batch, &tx).await {
+            return;
+        }
+    }
+}
+
+async fn listen_contents(
+    mut watcher: ContentWatcher,
+    events: std_mpsc::Receiver<Event>,
+    tx: mpsc::Sender<Event>,
+) {
+    let fd = match AsyncFd::new(watcher.fd()) {
+        Ok(fd) => fd,
+        Err(e) => {
+            Logger::error(format!("failed to register the content watcher: {}", e));
+            return;
+        }
+    };
+
+    loop {
+        let mut guard = match fd.readable().await {
+            Ok(guard) => guard,
+            Err(e) => {
+                Logger::error(format!("error waiting for file changes: {}", e));
+                return;
+            }
+        };
+        match guard.try_io(|_| watcher.read_batch()) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {}
+            Ok(Err(e)) => {
+                Logger::error(format!("error reading file changes: {}", e));
+                return;
+            }
+            Err(_would_block) => continue,
+        }
+
+        let batch: Vec<Event> = events.try_iter().collect();
+        if !forward(batch, &tx).await {
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "dbus")]
+async fn poll_dbus(
+    mut scanner: DBusScanner,
+    events: std_mpsc::Receiver<Event>,
+    tx: mpsc::Sender<Event>,
+    // the procfs scanner's, unless it is --dbus-only
+    trigger: Option<Arc<Notify>>,
+) {
+    let mut proxy = None;
+    let mut backoff = Duration::from_millis(DBUS_MIN_BACKOFF_MS);
+    // only the first failure of an outage is reported
+    let mut reachable = true;
+    let mut ticker = time::interval(scanner.interval());
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        let Some(manager) = &proxy else {
+            match dbus::connect_manager_async().await {
+                Ok(manager) => {
+                    if !reachable {
+                        Logger::info("reconnected to the system dbus".to_string());
+                    }
+                    proxy = Some(manager);
+                    reachable = true;
+                    backoff = Duration::from_millis(DBUS_MIN_BACKOFF_MS);
+                }
+                Err(e) => {
+                    if reachable {
+                        Logger::error(format!("failed to connect to system dbus, retrying: {}", e));
+                    }
+                    reachable = false;
+                    time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(DBUS_MAX_BACKOFF_SECS));
+                }
+            }
+            continue;
+        };
+
+        ticker.tick().await;
+        match dbus::slice_processes_async(manager).await {
+            Ok(listing) => {
+                // the GetUnitByPID lookups of --show-unit are blocking calls
+                let new;
+                (scanner, new) = match task::spawn_blocking(move || {
+                    let new = scanner.handle_processes(listing);
+                    (scanner, new)
+                })
+                .await
+                {
+                    Ok(done) => done,
+                    Err(e) => {
+                        Logger::error(format!("dbus task failed: {}", e));
+                        return;
+                    }
+                };
+                if new > 0
+                    && let Some(trigger) = &trigger
+                {
+                    trigger.notify_one();
+                }
+            }
+            Err(e) => {
+                METRICS.record_dbus_poll_failure();
+                Logger::error(format!(
+                    "failed to get processes from dbus, reconnecting: {}",
+                    e
+                ));
+                proxy = None;
+                reachable = false;
+            }
+        }
+
+        let batch: Vec<Event> = events.try_iter().collect();
+        if !forward(batch, &tx).await {
+            return;
+        }
+    }
+}
+
+// the collectors still emit into their own std channels, each task moves what one step
+// produced into the shared queue, false once the consumer is gone
+async fn forward(batch: Vec<Event>, tx: &mpsc::Sender<Event>) -> bool {
+    for event in batch {
+        if tx.send(event).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
@@ -0,0 +1,92 @@
+// capability numbers from linux/capability.h, the index is the bit in CapEff and friends
+const NAMES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+// each of these is a known way from the capability to full root
+const DANGEROUS: [&str; 11] = [
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "setgid",
+    "setuid",
+    "net_admin",
+    "sys_module",
+    "sys_rawio",
+    "sys_ptrace",
+    "sys_admin",
+    "bpf",
+];
+
+// "CAP_SYS_ADMIN", "cap_sys_admin" and "sys_admin" all name the same bit
+pub fn parse(name: &str) -> Option<u64> {
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_prefix("cap_").unwrap_or(&name);
+    NAMES
+        .iter()
+        .position(|known| *known == name)
+        .map(|bit| 1u64 << bit)
+}
+
+pub fn names(mask: u64) -> Vec<String> {
+    (0..64)
+        .filter(|bit| mask & (1u64 << bit) != 0)
+        .map(|bit| match NAMES.get(bit) {
+            Some(name) => format!("cap_{}", name),
+            None => format!("cap_{}", bit),
+        })
+        .collect()
+}
+
+// every capability this kernel header knows about
+pub fn known() -> u64 {
+    (1u64 << NAMES.len()) - 1
+}
+
+pub fn dangerous(mask: u64) -> u64 {
+    DANGEROUS
+        .iter()
+        .filter_map(|name| parse(name))
+        .fold(0, |dangerous, bit| dangerous | bit)
+        & mask
+}
@@ -0,0 +1,90 @@
+use crate::core::event::Cgroup;
+
+const CONTAINER_ID_LEN: usize = 64;
+const CONTAINER_ID_PREFIXES: [&str; 5] = [
+    "docker-",
+    "cri-containerd-",
+    "crio-",
+    "libpod-",
+    "containerd-",
+];
+
+pub fn read(pid: i32) -> Option<Cgroup> {
+    let content = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    parse(&content)
+}
+
+// lines look like "0::/system.slice/cron.service" (v2) or "4:memory:/docker/<id>" (v1),
+// the unified hierarchy is preferred, then the systemd one, but on hybrid hosts the
+// container often only shows up in the v1 controllers
+pub fn parse(content: &str) -> Option<Cgroup> {
+    let mut entries: Vec<(u8, &str)> = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let id = fields.next()?;
+            let controllers = fields.next()?;
+            let path = fields.next()?;
+            let priority = match (id, controllers) {
+                ("0", _) => 0,
+                (_, "name=systemd") => 1,
+                _ => 2,
+            };
+            Some((priority, path))
+        })
+        .filter(|(_, path)| *path != "/")
+        .collect();
+    entries.sort_by_key(|(priority, _)| *priority);
+
+    let described: Vec<Cgroup> = entries.iter().map(|(_, path)| describe(path)).collect();
+    let workload = described
+        .iter()
+        .position(|cgroup| cgroup.container_id.is_some() || cgroup.pod.is_some());
+    described.into_iter().nth(workload.unwrap_or(0))
+}
+
+fn describe(path: &str) -> Cgroup {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+
+    let container_id = components.iter().rev().find_map(|c| parse_container_id(c));
+    let pod = components.iter().rev().find_map(|c| parse_pod_uid(c));
+    let unit = components
+        .iter()
+        .rev()
+        .find(|c| c.ends_with(".service"))
+        .or_else(|| {
+            components
+                .iter()
+                .rev()
+                .find(|c| c.ends_with(".scope") && parse_container_id(c).is_none())
+        })
+        .map(|unit| unit.to_string());
+
+    Cgroup {
+        path: path.to_string(),
+        container_id,
+        pod,
+        unit,
+    }
+}
+
+// "docker-<id>.scope" with the systemd driver, a bare "<id>" with cgroupfs
+fn parse_container_id(component: &str) -> Option<String> {
+    let trimmed = component.strip_suffix(".scope").unwrap_or(component);
+    let id = CONTAINER_ID_PREFIXES
+        .iter()
+        .find_map(|prefix| trimmed.strip_prefix(prefix))
+        .unwrap_or(trimmed);
+
+    (id.len() == CONTAINER_ID_LEN && id.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| id.to_string())
+}
+
+// "kubepods-besteffort-pod<uid with _>.slice" with the systemd driver, "pod<uid>" with cgroupfs
+fn parse_pod_uid(component: &str) -> Option<String> {
+    let trimmed = component.strip_suffix(".slice").unwrap_or(component);
+    let (_, uid) = trimmed.rsplit_once("pod")?;
+    let uid = uid.replace('_', "-");
+
+    (uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-')).then_some(uid)
+}
@@ -0,0 +1,154 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::core::{constants::STEALTH_ARGV_FD_ENV, logger::Logger};
+
+// detaches from the terminal with the usual double fork, only the grandchild returns.
+// must run before any thread is spawned. stdout and stderr go to `log_file`, appended,
+// or /dev/null. the working directory is kept so relative paths given on the command line
+// still work
+pub fn daemonize(log_file: Option<&Path>) -> io::Result<()> {
+    // opened first so a bad path is still reported on the terminal
+    let output = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let null = File::open("/dev/null")?;
+
+    fork_and_exit_parent()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // no longer a session leader, so opening a tty can not make it the controlling one
+    fork_and_exit_parent()?;
+
+    for (source, target) in [
+        (null.as_raw_fd(), libc::STDIN_FILENO),
+        (output.as_raw_fd(), libc::STDOUT_FILENO),
+        (output.as_raw_fd(), libc::STDERR_FILENO),
+    ] {
+        if unsafe { libc::dup2(source, target) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+// --stealth, executes rspy again with `name` as its whole command line, so ps and
+// /proc/pid/cmdline show none of the flags. they travel in a memfd the executed rspy reads
+// and closes, /proc/pid/environ would keep them for as long as it runs. in the executed
+// rspy it only sets the comm top and pgrep show
+pub fn disguise(name: &str) -> io::Result<()> {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    if argv.len() == 1 && argv[0] == name {
+        let comm = CString::new(name.trim_matches(['[', ']'])).map_err(io::Error::other)?;
+        if unsafe { libc::prctl(libc::PR_SET_NAME, comm.as_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(());
+    }
+
+    let mut flags = Vec::new();
+    for (i, flag) in argv.iter().skip(1).enumerate() {
+        if i > 0 {
+            flags.push(0x1f);
+        }
+        flags.extend_from_slice(flag.as_bytes());
+    }
+    // without MFD_CLOEXEC, it is the one descriptor meant to survive the exec
+    let fd = unsafe { libc::memfd_create(c"argv".as_ptr(), 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut memfd = unsafe { File::from_raw_fd(fd) };
+    memfd.write_all(&flags)?;
+    memfd.seek(SeekFrom::Start(0))?;
+    // only returns when it failed
+    Err(Command::new("/proc/self/exe")
+        .arg0(name)
+        .env(STEALTH_ARGV_FD_ENV, fd.to_string())
+        .exec())
+}
+
+// the flags handed over by disguise, None when rspy was not executed by it. the memfd is
+// closed once read
+pub fn take_disguised_flags() -> Option<io::Result<Vec<OsString>>> {
+    let fd = std::env::var_os(STEALTH_ARGV_FD_ENV)?;
+    // nothing else runs yet, and children should not inherit it
+    unsafe { std::env::remove_var(STEALTH_ARGV_FD_ENV) };
+    let read = || -> io::Result<Vec<OsString>> {
+        let fd: i32 = fd
+            .to_str()
+            .and_then(|fd| fd.parse().ok())
+            .filter(|&fd| fd > libc::STDERR_FILENO)
+            .ok_or_else(|| io::Error::other(format!("invalid {}", STEALTH_ARGV_FD_ENV)))?;
+        let mut flags = Vec::new();
+        unsafe { File::from_raw_fd(fd) }.read_to_end(&mut flags)?;
+        if flags.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(flags
+            .split(|&b| b == 0x1f)
+            .map(|flag| OsStr::from_bytes(flag).to_os_string())
+            .collect())
+    };
+    Some(read())
+}
+
+// refuses to overwrite the pidfile of an instance that is still running, a stale one
+// is replaced
+pub fn write_pidfile(path: &Path) -> Result<(), String> {
+    if let Ok(content) = std::fs::read_to_string(path)
+        && let Ok(pid) = content.trim().parse::<u32>()
+        && pid != std::process::id()
+        && Path::new(&format!("/proc/{}", pid)).exists()
+    {
+        return Err(format!(
+            "{} belongs to pid {}, which is still running",
+            path.display(),
+            pid
+        ));
+    }
+    std::fs::write(path, format!("{}\n", std::process::id()))
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+// sd_notify(3) without libsystemd, does nothing when not started by systemd with
+// Type=notify
+pub fn notify(state: &str) {
+    let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let socket_path = socket_path.to_string_lossy().into_owned();
+    let address = match socket_path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&socket_path),
+    };
+
+    let sent = address.and_then(|address| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(state.as_bytes(), &address)
+    });
+    if let Err(e) = sent {
+        Logger::error(format!(
+            "failed to notify systemd at {}: {}",
+            socket_path, e
+        ));
+    }
+}
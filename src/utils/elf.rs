@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_SONAME: u64 = 14;
+
+// anything larger is not a table a linker wrote
+const MAX_TABLE_BYTES: u64 = 1024 * 1024;
+
+// what the dynamic loader reads from a binary or shared object
+#[derive(Clone, Debug, Default)]
+pub struct Dynamic {
+    pub interpreter: Option<String>,
+    pub soname: Option<String>,
+    pub needed: Vec<String>,
+}
+
+struct Header {
+    wide: bool,
+    little_endian: bool,
+}
+
+impl Header {
+    fn word(&self, bytes: &[u8], offset: usize) -> u64 {
+        if self.wide {
+            let value = bytes[offset..offset + 8].try_into().unwrap_or_default();
+            if self.little_endian {
+                u64::from_le_bytes(value)
+            } else {
+                u64::from_be_bytes(value)
+            }
+        } else {
+            self.u32(bytes, offset) as u64
+        }
+    }
+
+    fn u32(&self, bytes: &[u8], offset: usize) -> u32 {
+        let value = bytes[offset..offset + 4].try_into().unwrap_or_default();
+        if self.little_endian {
+            u32::from_le_bytes(value)
+        } else {
+            u32::from_be_bytes(value)
+        }
+    }
+
+    fn u16(&self, bytes: &[u8], offset: usize) -> u16 {
+        let value = bytes[offset..offset + 2].try_into().unwrap_or_default();
+        if self.little_endian {
+            u16::from_le_bytes(value)
+        } else {
+            u16::from_be_bytes(value)
+        }
+    }
+}
+
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+// the dynamic section of an ELF file, statically linked ones have none and come back empty
+pub fn dynamic(file: &File) -> io::Result<Dynamic> {
+    let mut ident = [0u8; 64];
+    file.read_exact_at(&mut ident, 0)?;
+    if &ident[..4] != b"\x7fELF" {
+        return Err(invalid("not an ELF file"));
+    }
+    let header = Header {
+        wide: ident[4] == 2,
+        little_endian: ident[5] == 1,
+    };
+    let (phoff, phentsize, phnum) = if header.wide {
+        (
+            header.word(&ident, 32),
+            header.u16(&ident, 54),
+            header.u16(&ident, 56),
+        )
+    } else {
+        (
+            header.u32(&ident, 28) as u64,
+            header.u16(&ident, 42),
+            header.u16(&ident, 44),
+        )
+    };
+    let minimum = if header.wide { 56 } else { 32 };
+    if (phentsize as usize) < minimum {
+        return Err(invalid("program headers too small"));
+    }
+
+    let table = read_table(file, phoff, phentsize as u64 * phnum as u64)?;
+    let segments: Vec<Segment> = table
+        .chunks_exact(phentsize as usize)
+        .map(|entry| {
+            if header.wide {
+                Segment {
+                    kind: header.u32(entry, 0),
+                    offset: header.word(entry, 8),
+                    vaddr: header.word(entry, 16),
+                    filesz: header.word(entry, 32),
+                }
+            } else {
+                Segment {
+                    kind: header.u32(entry, 0),
+                    offset: header.u32(entry, 4) as u64,
+                    vaddr: header.u32(entry, 8) as u64,
+                    filesz: header.u32(entry, 16) as u64,
+                }
+            }
+        })
+        .collect();
+
+    let mut result = Dynamic::default();
+    if let Some(interp) = segments.iter().find(|segment| segment.kind == PT_INTERP) {
+        let bytes = read_table(file, interp.offset, interp.filesz)?;
+        result.interpreter = string_at(&bytes, 0);
+    }
+    let Some(dynamic) = segments.iter().find(|segment| segment.kind == PT_DYNAMIC) else {
+        return Ok(result);
+    };
+
+    let entries = read_table(file, dynamic.offset, dynamic.filesz)?;
+    let size = if header.wide { 16 } else { 8 };
+    let (mut strtab, mut strsz, mut soname, mut needed) = (None, None, None, Vec::new());
+    for entry in entries.chunks_exact(size) {
+        let tag = header.word(entry, 0);
+        let value = header.word(entry, size / 2);
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed.push(value),
+            DT_STRTAB => strtab = Some(value),
+            DT_STRSZ => strsz = Some(value),
+            DT_SONAME => soname = Some(value),
+            _ => {}
+        }
+    }
+    let (Some(strtab), Some(strsz)) = (strtab, strsz) else {
+        return Ok(result);
+    };
+    // DT_STRTAB is an address, the load segment holding it tells where it is in the file
+    let offset = segments
+        .iter()
+        .find(|segment| {
+            segment.kind == PT_LOAD
+                && segment.vaddr <= strtab
+                && strtab < segment.vaddr + segment.filesz
+        })
+        .map(|segment| strtab - segment.vaddr + segment.offset)
+        .ok_or_else(|| invalid("string table outside of the loaded segments"))?;
+    let strings = read_table(file, offset, strsz)?;
+
+    result.soname = soname.and_then(|index| string_at(&strings, index));
+    result.needed = needed
+        .into_iter()
+        .filter_map(|index| string_at(&strings, index))
+        .collect();
+    Ok(result)
+}
+
+fn read_table(file: &File, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+    if size > MAX_TABLE_BYTES {
+        return Err(invalid("table too large"));
+    }
+    let mut bytes = vec![0u8; size as usize];
+    file.read_exact_at(&mut bytes, offset)?;
+    Ok(bytes)
+}
+
+fn string_at(bytes: &[u8], index: u64) -> Option<String> {
+    let rest = bytes.get(index as usize..)?;
+    let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+    (end > 0).then(|| String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,150 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::path::Path;
+
+use crate::core::constants::DELETED_EXE_SUFFIX;
+use crate::core::event::OpenFd;
+use crate::utils::process::parent_and_comm;
+use crate::utils::sockets::{self, link_inode};
+
+// the fds of a process that say something about it: sockets, deleted files and pipes to
+// other processes, plain files and ttys are left out. returns at most `limit` of them and
+// how many more there were
+pub fn interesting(pid: i32, ppid: Option<u32>, limit: usize) -> (Vec<OpenFd>, usize) {
+    let links = fd_links(pid);
+    let mut fds = Vec::new();
+    let mut sockets = Vec::new();
+    let mut pipes = Vec::new();
+
+    for (fd, link) in links {
+        if let Some(inode) = link_inode(&link, "socket") {
+            sockets.push((fd, inode));
+        } else if let Some(inode) = link_inode(&link, "pipe") {
+            pipes.push((fd, inode));
+        } else if let Some(path) = link.strip_suffix(DELETED_EXE_SUFFIX)
+            && link.starts_with('/')
+        {
+            fds.push(OpenFd {
+                fd,
+                target: format!("deleted {}", path),
+            });
+        }
+    }
+
+    if !sockets.is_empty() {
+        fds.extend(describe_sockets(pid, &sockets));
+    }
+    if !pipes.is_empty() {
+        fds.extend(describe_pipes(pid, ppid, &pipes));
+    }
+
+    fds.sort_by_key(|fd| fd.fd);
+    let omitted = fds.len().saturating_sub(limit);
+    fds.truncate(limit);
+    (fds, omitted)
+}
+
+fn fd_links(pid: i32) -> Vec<(u32, String)> {
+    let Ok(entries) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            let link = std::fs::read_link(entry.path()).ok()?;
+            Some((fd, link.to_string_lossy().into_owned()))
+        })
+        .collect()
+}
+
+// the tables of the process's own network namespace, unnamed unix sockets such as
+// socketpairs are too common to be worth a line
+fn describe_sockets(pid: i32, sockets: &[(u32, u64)]) -> Vec<OpenFd> {
+    let net = Path::new("/proc").join(pid.to_string()).join("net");
+    let mut descriptions: FxHashMap<u64, String> = sockets::inet_sockets(&net)
+        .into_iter()
+        .map(|socket| (socket.inode, socket.describe()))
+        .collect();
+    descriptions.extend(
+        sockets::unix_sockets(&net)
+            .into_iter()
+            .filter(|socket| socket.path.is_some())
+            .map(|socket| (socket.inode, socket.describe())),
+    );
+
+    sockets
+        .iter()
+        .filter_map(|(fd, inode)| {
+            Some(OpenFd {
+                fd: *fd,
+                target: descriptions.get(inode)?.clone(),
+            })
+        })
+        .collect()
+}
+
+// the other end of a pipe is usually the parent or a sibling, e.g. `curl ... | sh`, so
+// only those are searched instead of every process
+fn describe_pipes(pid: i32, ppid: Option<u32>, pipes: &[(u32, u64)]) -> Vec<OpenFd> {
+    let Some(ppid) = ppid.filter(|&ppid| ppid > 0) else {
+        return Vec::new();
+    };
+    let siblings = std::fs::read_to_string(format!("/proc/{}/task/{}/children", ppid, ppid))
+        .unwrap_or_default();
+    let candidates = std::iter::once(ppid as i32).chain(
+        siblings
+            .split_whitespace()
+            .filter_map(|pid| pid.parse().ok())
+            .filter(|&sibling| sibling != pid),
+    );
+
+    let mut owners: FxHashMap<u64, i32> = FxHashMap::default();
+    for candidate in candidates {
+        for (_, link) in fd_links(candidate) {
+            if let Some(inode) = link_inode(&link, "pipe")
+                && pipes.iter().any(|(_, pipe)| *pipe == inode)
+            {
+                owners.entry(inode).or_insert(candidate);
+            }
+        }
+    }
+
+    pipes
+        .iter()
+        .filter_map(|(fd, inode)| {
+            let owner = *owners.get(inode)?;
+            let comm = parent_and_comm(owner as u32)
+                .map(|(_, comm)| comm)
+                .unwrap_or_default();
+            Some(OpenFd {
+                fd: *fd,
+                target: format!("pipe to {} ({})", owner, comm),
+            })
+        })
+        .collect()
+}
+
+// which process holds each of these sockets, every fd table is searched until all are found
+pub fn socket_owners(inodes: &FxHashSet<u64>) -> FxHashMap<u64, i32> {
+    let mut owners = FxHashMap::default();
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return owners;
+    };
+
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        for (_, link) in fd_links(pid) {
+            if let Some(inode) = link_inode(&link, "socket")
+                && inodes.contains(&inode)
+            {
+                owners.entry(inode).or_insert(pid);
+            }
+        }
+        if owners.len() == inodes.len() {
+            break;
+        }
+    }
+    owners
+}
@@ -19,3 +19,175 @@ pub fn format_duration(duration: Option<Duration>) -> String {
         None => "disabled".to_string(),
     }
 }
+
+// "90" (seconds), "500ms", "30s", "5m", "2h" or "1d"
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    parse_with_unit(value, "s")
+}
+
+// the same for the interval flags that used to take plain milliseconds, "250" is still 250ms
+pub fn parse_millis(value: &str) -> Result<Duration, String> {
+    parse_with_unit(value, "ms")
+}
+
+fn parse_with_unit(value: &str, default_unit: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| {
+        format!(
+            "invalid duration {:?}, expected e.g. 500ms, 30s, 5m or 2h",
+            value
+        )
+    })?;
+    let millis = match if unit.is_empty() { default_unit } else { unit } {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => {
+            return Err(format!(
+                "unknown duration unit {:?}, use ms, s, m, h or d",
+                unit
+            ));
+        }
+    };
+    Ok(Duration::from_millis(number.saturating_mul(millis)))
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+// --format-template, text with `{field}` placeholders, `{{` and `}}` for literal braces
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str, fields: &[&str]) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed {{ in {:?}", template))?;
+                    let field = &rest[..end];
+                    if !fields.contains(&field) {
+                        return Err(format!(
+                            "unknown field {{{}}}, use {}",
+                            field,
+                            fields
+                                .iter()
+                                .map(|field| format!("{{{}}}", field))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field.to_string()));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    return Err(format!(
+                        "unmatched }} in {:?}, write }}}} for a brace",
+                        template
+                    ));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(field) => value(field),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration(" 5m "), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn millis_by_default() {
+        assert_eq!(parse_millis("250"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_millis("2s"), Ok(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for value in [
+            "",
+            "ms",
+            "-5s",
+            "1.5s",
+            "5 m",
+            "5M",
+            "5sec",
+            "99999999999999999999",
+        ] {
+            assert!(parse_duration(value).is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn saturates() {
+        assert_eq!(
+            parse_duration(&format!("{}d", u64::MAX)),
+            Ok(Duration::from_millis(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn formatted() {
+        assert_eq!(format_duration(None), "disabled");
+        assert_eq!(format_duration(Some(Duration::from_millis(250))), "250ms");
+        assert_eq!(format_duration(Some(Duration::from_millis(2050))), "2.050s");
+        assert_eq!(format_duration(Some(Duration::from_secs(301))), "5m01.000s");
+        assert_eq!(
+            format_duration(Some(Duration::from_millis(3_723_004))),
+            "1h02m03.004s"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use super::lru::Lru;
+use crate::core::constants::{HASH_CACHE_CAPACITY, HASH_MAX_FILE_BYTES};
+
+struct CachedHash {
+    // an in-place rewrite keeps the inode, so the entry is only valid for the same mtime and size
+    mtime: i64,
+    size: u64,
+    digest: String,
+}
+
+pub struct HashCache {
+    // (device, inode)
+    entries: Lru<(u64, u64), CachedHash>,
+}
+
+impl Default for HashCache {
+    fn default() -> Self {
+        Self::with_capacity(HASH_CACHE_CAPACITY)
+    }
+}
+
+impl HashCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Lru::new("hash", capacity),
+        }
+    }
+
+    // opening /proc/<pid>/exe reaches the binary even if it was deleted or lives in a memfd
+    pub fn sha256(&mut self, path: &Path) -> io::Result<String> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.len() > HASH_MAX_FILE_BYTES {
+            return Err(io::Error::other(format!(
+                "{} bytes exceeds the hashing limit",
+                metadata.len()
+            )));
+        }
+
+        let key = (metadata.dev(), metadata.ino());
+        if let Some(entry) = self.entries.get_mut(&key)
+            && entry.mtime == metadata.mtime()
+            && entry.size == metadata.len()
+        {
+            return Ok(entry.digest.clone());
+        }
+
+        let digest = hash_reader(&mut file)?;
+        self.entries.insert(
+            key,
+            CachedHash {
+                mtime: metadata.mtime(),
+                size: metadata.len(),
+                digest: digest.clone(),
+            },
+        );
+        Ok(digest)
+    }
+}
+
+fn hash_reader(reader: &mut impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    Ok(hex)
+}
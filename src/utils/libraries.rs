@@ -0,0 +1,137 @@
+use rustc_hash::FxHashSet;
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::elf::{self, Dynamic};
+use super::lru::Lru;
+use super::process::Environ;
+use crate::core::constants::{DELETED_EXE_SUFFIX, LIBRARY_CACHE_CAPACITY, LOADER_ENV_VARIABLES};
+
+// by (device, inode), libc and friends are mapped by nearly everything
+static DYNAMIC_CACHE: Mutex<Option<Lru<(u64, u64), CachedDynamic>>> = Mutex::new(None);
+
+struct CachedDynamic {
+    mtime: i64,
+    dynamic: Dynamic,
+}
+
+// the variables that make the loader pull in other code than the binary asks for, as
+// NAME=value
+pub fn loader_env(environ: Option<&Environ>) -> Vec<String> {
+    let Some(environ) = environ else {
+        return Vec::new();
+    };
+    environ
+        .entries()
+        .filter(|entry| {
+            LOADER_ENV_VARIABLES.iter().any(|name| {
+                entry.starts_with(name.as_bytes()) && entry.get(name.len()) == Some(&b'=')
+            })
+        })
+        .map(|entry| String::from_utf8_lossy(entry).into_owned())
+        .collect()
+}
+
+// shared objects mapped into the process that neither the binary nor the libraries it
+// loads name as NEEDED, e.g. an LD_PRELOAD or /etc/ld.so.preload library, an injected
+// one, or a plugin the program dlopen'ed itself
+pub fn unexpected(pid: u32) -> Vec<PathBuf> {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    let Ok(maps) = std::fs::read_to_string(proc_dir.join("maps")) else {
+        return Vec::new();
+    };
+    let Some(exe) = dynamic_of(&proc_dir.join("exe")) else {
+        return Vec::new();
+    };
+    // a static binary has nothing to compare against
+    if exe.needed.is_empty() && exe.interpreter.is_none() {
+        return Vec::new();
+    }
+    let exe_path = std::fs::read_link(proc_dir.join("exe")).ok();
+
+    let mut mapped: Vec<(PathBuf, Option<Dynamic>)> = Vec::new();
+    for path in maps.lines().filter_map(mapped_object) {
+        if Some(&path) == exe_path.as_ref() || mapped.iter().any(|(seen, _)| *seen == path) {
+            continue;
+        }
+        // through the root of the process, it may live in another mount namespace
+        let dynamic = dynamic_of(
+            &proc_dir
+                .join("root")
+                .join(path.strip_prefix("/").unwrap_or(&path)),
+        );
+        mapped.push((path, dynamic));
+    }
+
+    // follow the NEEDED chain from the binary over what is mapped
+    let mut wanted: FxHashSet<String> = exe.needed.into_iter().collect();
+    wanted.extend(exe.interpreter.as_deref().and_then(file_name));
+    let mut expected = vec![false; mapped.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, (path, dynamic)) in mapped.iter().enumerate() {
+            if expected[i] {
+                continue;
+            }
+            let soname = dynamic.as_ref().and_then(|dynamic| dynamic.soname.clone());
+            let names = [file_name(&path.to_string_lossy()), soname];
+            if names.iter().flatten().any(|name| wanted.contains(name)) {
+                expected[i] = true;
+                changed = true;
+                if let Some(dynamic) = dynamic {
+                    wanted.extend(dynamic.needed.iter().cloned());
+                }
+            }
+        }
+    }
+    mapped
+        .into_iter()
+        .zip(expected)
+        .filter(|(_, expected)| !expected)
+        .map(|((path, _), _)| path)
+        .collect()
+}
+
+// the file backing a line of /proc/<pid>/maps when it looks like a shared object
+fn mapped_object(line: &str) -> Option<PathBuf> {
+    let mut fields = line.splitn(6, ' ');
+    let inode = fields.nth(4)?;
+    let path = fields.next()?.trim_start();
+    if inode == "0" || !path.starts_with('/') {
+        return None;
+    }
+    // an unlinked library is the more interesting for it
+    let name = file_name(path.strip_suffix(DELETED_EXE_SUFFIX).unwrap_or(path))?;
+    (name.ends_with(".so") || name.contains(".so.")).then(|| PathBuf::from(path))
+}
+
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+fn dynamic_of(path: &Path) -> Option<Dynamic> {
+    let file = File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    let key = (metadata.dev(), metadata.ino());
+    let mut cache = DYNAMIC_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(|| Lru::new("library", LIBRARY_CACHE_CAPACITY));
+    if let Some(entry) = cache.get_mut(&key)
+        && entry.mtime == metadata.mtime()
+    {
+        return Some(entry.dynamic.clone());
+    }
+    let dynamic = elf::dynamic(&file).ok()?;
+    cache.insert(
+        key,
+        CachedDynamic {
+            mtime: metadata.mtime(),
+            dynamic: dynamic.clone(),
+        },
+    );
+    Some(dynamic)
+}
@@ -0,0 +1,118 @@
+use rustc_hash::FxHashMap;
+use std::hash::Hash;
+
+use crate::core::constants::{
+    CACHE_EVICTION_PERCENT, COMMAND_CACHE_CAPACITY, COMMAND_CACHE_ENTRY_BYTES, HASH_CACHE_CAPACITY,
+    HASH_CACHE_ENTRY_BYTES, PID_CACHE_CAPACITY, PID_CACHE_ENTRY_BYTES,
+};
+use crate::core::logger::Logger;
+
+// how many entries the long lived caches may hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheLimits {
+    // --unique-cmd and --dedup-window
+    pub commands: usize,
+    // --hash-exe
+    pub hashes: usize,
+    // processes the dbus scanner already reported
+    pub pids: usize,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self {
+            commands: COMMAND_CACHE_CAPACITY,
+            hashes: HASH_CACHE_CAPACITY,
+            pids: PID_CACHE_CAPACITY,
+        }
+    }
+}
+
+impl CacheLimits {
+    // --max-memory-mb, command lines are the bulk so they get most of it
+    pub fn from_megabytes(megabytes: u64) -> Self {
+        let bytes = megabytes.saturating_mul(1024 * 1024);
+        let entries = |percent: u64, entry_bytes: u64| {
+            ((bytes / 100 * percent) / entry_bytes).clamp(1, usize::MAX as u64) as usize
+        };
+        Self {
+            commands: entries(60, COMMAND_CACHE_ENTRY_BYTES),
+            hashes: entries(20, HASH_CACHE_ENTRY_BYTES),
+            pids: entries(20, PID_CACHE_ENTRY_BYTES),
+        }
+    }
+}
+
+// a map that forgets its least recently used entries once it is full, a batch at a time
+// so a full cache does not search for the oldest entry on every insert
+pub struct Lru<K, V> {
+    name: &'static str,
+    entries: FxHashMap<K, (V, u64)>,
+    capacity: usize,
+    tick: u64,
+    evicted: u64,
+}
+
+impl<K: Hash + Eq, V> Lru<K, V> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            entries: FxHashMap::default(),
+            capacity: capacity.max(1),
+            tick: 0,
+            evicted: 0,
+        }
+    }
+
+    // counts as a use
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(key).map(|(value, last_used)| {
+            *last_used = tick;
+            value
+        })
+    }
+
+    // counts as a use, for caches that are sets
+    pub fn touch(&mut self, key: &K) -> bool {
+        self.get_mut(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.evict();
+        }
+        self.tick += 1;
+        self.entries
+            .insert(key, (value, self.tick))
+            .map(|(previous, _)| previous)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|(value, _)| value)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &mut V) -> bool) {
+        self.entries.retain(|key, (value, _)| keep(key, value));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, (value, _))| (key, value))
+    }
+
+    fn evict(&mut self) {
+        let batch = (self.capacity * CACHE_EVICTION_PERCENT / 100).max(1);
+        let mut ticks: Vec<u64> = self.entries.values().map(|(_, tick)| *tick).collect();
+        let cutoff = *ticks.select_nth_unstable(batch - 1).1;
+        let before = self.entries.len();
+        self.entries.retain(|_, (_, tick)| *tick > cutoff);
+
+        let dropped = (before - self.entries.len()) as u64;
+        self.evicted += dropped;
+        Logger::debug(format!(
+            "{} cache reached {} entries, evicted the {} least recently used ({} so far)",
+            self.name, self.capacity, dropped, self.evicted
+        ));
+    }
+}
@@ -1 +1,18 @@
+pub mod caps;
+pub mod cgroup;
+pub mod daemon;
+pub mod elf;
+pub mod fds;
 pub mod format;
+pub mod hashcache;
+pub mod libraries;
+pub mod lru;
+pub mod preserve;
+pub mod process;
+pub mod procparse;
+pub mod shutdown;
+pub mod signals;
+pub mod sockets;
+pub mod textdiff;
+pub mod throttle;
+pub mod users;
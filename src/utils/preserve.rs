@@ -0,0 +1,102 @@
+use rustc_hash::FxHashSet;
+use std::fs::{self, DirBuilder, File, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::constants::PRESERVE_MAX_FILE_BYTES;
+
+// copies the binaries of new processes into an evidence directory while they still run,
+// one entry per distinct binary so busy hosts do not fill the disk with copies of `sleep`
+pub struct ExePreserver {
+    directory: PathBuf,
+    environ: bool,
+    // (dev, ino, size, mtime) of binaries that were already preserved
+    preserved: FxHashSet<(u64, u64, u64, i64)>,
+}
+
+impl ExePreserver {
+    pub fn new(directory: &Path, environ: bool) -> io::Result<Self> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(directory)?;
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            environ,
+            preserved: FxHashSet::default(),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // returns the evidence directory when the binary had not been seen before
+    pub fn preserve(&mut self, pid: i32, name: &str) -> io::Result<Option<PathBuf>> {
+        let proc_dir = Path::new("/proc").join(pid.to_string());
+        // /proc/<pid>/exe still opens binaries that deleted themselves or live in a memfd
+        let mut exe = File::open(proc_dir.join("exe"))?;
+        let metadata = exe.metadata()?;
+        let key = (
+            metadata.dev(),
+            metadata.ino(),
+            metadata.size(),
+            metadata.mtime(),
+        );
+        if self.preserved.contains(&key) {
+            return Ok(None);
+        }
+        if metadata.len() > PRESERVE_MAX_FILE_BYTES {
+            return Err(io::Error::other(format!(
+                "{} bytes exceeds the preservation limit",
+                metadata.len()
+            )));
+        }
+
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let evidence = self
+            .directory
+            .join(format!("{}-{}-{}", secs, pid, sanitize(name)));
+        DirBuilder::new().mode(0o700).create(&evidence)?;
+
+        let copy = evidence.join("exe");
+        io::copy(&mut exe, &mut File::create(&copy)?)?;
+        // never leave an executable copy of whatever was caught lying around
+        fs::set_permissions(&copy, Permissions::from_mode(0o400))?;
+
+        if let Ok(cmdline) = fs::read(proc_dir.join("cmdline")) {
+            fs::write(evidence.join("cmdline"), cmdline)?;
+        }
+        if self.environ
+            && let Ok(environ) = fs::read(proc_dir.join("environ"))
+        {
+            fs::write(evidence.join("environ"), environ)?;
+        }
+
+        self.preserved.insert(key);
+        Ok(Some(evidence))
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect();
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name
+    }
+}
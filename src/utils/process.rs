@@ -0,0 +1,85 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+// the full command line when it can still be read, [comm] or "exited" otherwise
+pub fn process_name(pid: i32) -> String {
+    match std::fs::read(format!("/proc/{}/cmdline", pid)) {
+        Ok(cmdline) if !cmdline.is_empty() => cmdline
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(" "),
+        // the process is usually gone already for short-lived opens
+        _ => std::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .map(|comm| format!("[{}]", comm.trim()))
+            .unwrap_or_else(|_| "exited".to_string()),
+    }
+}
+
+// (ppid, comm) from /proc/<pid>/stat, comm may itself contain spaces and parentheses
+pub fn parent_and_comm(pid: u32) -> Option<(u32, String)> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, rest) = stat.split_once('(')?;
+    let (comm, rest) = rest.rsplit_once(')')?;
+    let ppid = rest.split_whitespace().nth(1)?.parse().ok()?;
+    Some((ppid, comm.to_string()))
+}
+
+// the variables the process was started with, read once for everything that asks. only
+// readable for our own processes unless rspy runs as root
+pub struct Environ(Vec<u8>);
+
+impl Environ {
+    pub fn read(pid: u32) -> Option<Self> {
+        std::fs::read(format!("/proc/{}/environ", pid))
+            .ok()
+            .map(Environ)
+    }
+
+    // NAME=value
+    pub fn entries(&self) -> impl Iterator<Item = &[u8]> {
+        self.0.split(|&b| b == 0).filter(|entry| !entry.is_empty())
+    }
+
+    pub fn var(&self, name: &str) -> Option<&OsStr> {
+        self.entries().find_map(|entry| {
+            entry
+                .strip_prefix(name.as_bytes())?
+                .strip_prefix(b"=")
+                .map(OsStr::from_bytes)
+        })
+    }
+}
+
+// the options of the /proc mount that limit who sees which processes
+pub struct ProcMount {
+    // hidepid= other than 0/off, only processes of the same uid are visible then
+    pub hidepid: Option<String>,
+    // the group that is exempt from hidepid
+    pub gid: Option<String>,
+}
+
+// none when /proc is not mounted at all
+pub fn proc_mount() -> Option<ProcMount> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    // "36 25 0:22 / /proc rw,nosuid - proc proc rw,hidepid=2,gid=27", the last mount on
+    // /proc is the one on top
+    let options = mountinfo.lines().rev().find_map(|line| {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let mut fields = filesystem.split_whitespace();
+        (mount.split_whitespace().nth(4) == Some("/proc") && fields.next() == Some("proc"))
+            .then(|| fields.nth(1).unwrap_or_default().to_string())
+    })?;
+
+    let option = |name: &str| {
+        options
+            .split(',')
+            .find_map(|option| option.strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    Some(ProcMount {
+        hidepid: option("hidepid").filter(|value| value != "0" && value != "off"),
+        gid: option("gid"),
+    })
+}
@@ -0,0 +1,268 @@
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Read};
+
+// the fields of /proc/<pid>/stat the scanner needs, comm borrows from the read buffer
+pub struct Stat<'a> {
+    pub comm: Cow<'a, str>,
+    pub ppid: i32,
+    pub starttime: u64,
+}
+
+// the fields of /proc/<pid>/status the scanner needs
+pub struct Status<'a> {
+    pub name: Cow<'a, str>,
+    pub ppid: i32,
+    pub ruid: u32,
+    pub rgid: u32,
+    // the pid inside the innermost pid namespace, only when it is not ours
+    pub ns_pid: Option<i32>,
+    pub capeff: u64,
+    pub capprm: u64,
+}
+
+// reads /proc files into buffers that are kept between scans, a scan of a few thousand
+// processes then allocates next to nothing. what a method returns borrows the reader until
+// the next read
+#[derive(Default)]
+pub struct ProcReader {
+    path: String,
+    stat: Vec<u8>,
+    status: Vec<u8>,
+    cmdline: Vec<u8>,
+}
+
+impl ProcReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the stat of a process, or of one of its threads
+    pub fn stat(&mut self, pid: i32, tid: Option<i32>) -> io::Result<Stat<'_>> {
+        self.path.clear();
+        let _ = match tid {
+            Some(tid) => write!(self.path, "/proc/{}/task/{}/stat", pid, tid),
+            None => write!(self.path, "/proc/{}/stat", pid),
+        };
+        read_into(&self.path, &mut self.stat)?;
+        parse_stat(&self.stat).ok_or_else(|| malformed(pid, "stat"))
+    }
+
+    pub fn status(&mut self, pid: i32) -> io::Result<Status<'_>> {
+        self.path.clear();
+        let _ = write!(self.path, "/proc/{}/status", pid);
+        read_into(&self.path, &mut self.status)?;
+        parse_status(&self.status).ok_or_else(|| malformed(pid, "status"))
+    }
+
+    // the raw NUL separated arguments, empty for kernel threads and exiting processes
+    pub fn cmdline(&mut self, pid: i32) -> io::Result<&[u8]> {
+        self.path.clear();
+        let _ = write!(self.path, "/proc/{}/cmdline", pid);
+        read_into(&self.path, &mut self.cmdline)?;
+        Ok(&self.cmdline)
+    }
+}
+
+fn read_into(path: &str, buffer: &mut Vec<u8>) -> io::Result<()> {
+    buffer.clear();
+    File::open(path)?.read_to_end(buffer)?;
+    Ok(())
+}
+
+fn malformed(pid: i32, file: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed /proc/{}/{}", pid, file),
+    )
+}
+
+// "pid (comm) state ppid ...", comm may itself contain spaces and parentheses so the
+// fields after it are counted from the last ')'
+pub fn parse_stat(content: &[u8]) -> Option<Stat<'_>> {
+    let open = content.iter().position(|&b| b == b'(')?;
+    let close = content.iter().rposition(|&b| b == b')')?;
+    let comm = String::from_utf8_lossy(content.get(open + 1..close)?);
+
+    let mut fields = content[close + 1..]
+        .split(|&b| b == b' ')
+        .filter(|field| !field.is_empty());
+    // state
+    fields.next()?;
+    let ppid = parse_number(fields.next()?)?;
+    // starttime is field 22, the 20th after comm
+    let starttime = parse_number(fields.nth(17)?)?;
+    Some(Stat {
+        comm,
+        ppid,
+        starttime,
+    })
+}
+
+pub fn parse_status(content: &[u8]) -> Option<Status<'_>> {
+    let mut name = None;
+    let mut ppid = None;
+    let mut ruid = None;
+    let mut rgid = None;
+    let mut ns_pid = None;
+    let mut capeff = 0;
+    let mut capprm = 0;
+
+    for line in content.split(|&b| b == b'\n') {
+        let Some(colon) = line.iter().position(|&b| b == b':') else {
+            continue;
+        };
+        let value = line[colon + 1..].trim_ascii();
+        match &line[..colon] {
+            b"Name" => name = Some(String::from_utf8_lossy(value)),
+            b"PPid" => ppid = parse_number(value),
+            // real, effective, saved and filesystem ids
+            b"Uid" => ruid = first_field(value).and_then(parse_number),
+            b"Gid" => rgid = first_field(value).and_then(parse_number),
+            // from our namespace down to the process's own one
+            b"NSpid" => {
+                let mut pids = value
+                    .split(|b| b.is_ascii_whitespace())
+                    .filter(|pid| !pid.is_empty());
+                pids.next();
+                ns_pid = pids.next_back().and_then(parse_number);
+            }
+            b"CapEff" => capeff = parse_hex(value).unwrap_or_default(),
+            b"CapPrm" => capprm = parse_hex(value).unwrap_or_default(),
+            _ => {}
+        }
+    }
+
+    Some(Status {
+        name: name?,
+        ppid: ppid?,
+        ruid: ruid?,
+        rgid: rgid?,
+        ns_pid,
+        capeff,
+        capprm,
+    })
+}
+
+// the arguments of a raw cmdline, the terminating NUL does not start another one
+pub fn split_cmdline(raw: &[u8]) -> Vec<String> {
+    if raw.is_empty() {
+        return Vec::new();
+    }
+    let raw = raw.strip_suffix(&[0]).unwrap_or(raw);
+    raw.split(|&b| b == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+// whether a raw cmdline is the same as one that was joined with spaces before, without
+// building the string again
+pub fn cmdline_matches(raw: &[u8], joined: &str) -> bool {
+    let trimmed = raw.strip_suffix(&[0]).unwrap_or(raw);
+    if std::str::from_utf8(trimmed).is_err() {
+        // the joined one went through a lossy conversion
+        return split_cmdline(raw).join(" ") == joined;
+    }
+    trimmed.len() == joined.len()
+        && trimmed
+            .iter()
+            .zip(joined.as_bytes())
+            .all(|(&a, &b)| a == b || (a == 0 && b == b' '))
+}
+
+fn first_field(value: &[u8]) -> Option<&[u8]> {
+    value
+        .split(|b| b.is_ascii_whitespace())
+        .find(|field| !field.is_empty())
+}
+
+fn parse_number<T: std::str::FromStr>(field: &[u8]) -> Option<T> {
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
+
+fn parse_hex(field: &[u8]) -> Option<u64> {
+    u64::from_str_radix(std::str::from_utf8(field).ok()?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fields 5 to 21 hold their own number, starttime is 987654
+    fn stat_line(comm: &str) -> String {
+        let middle: Vec<String> = (5..=21).map(|field| field.to_string()).collect();
+        format!(
+            "1234 ({}) S 77 {} 987654 4096 0 0\n",
+            comm,
+            middle.join(" ")
+        )
+    }
+
+    #[test]
+    fn stat() {
+        let line = stat_line("bash");
+        let stat = parse_stat(line.as_bytes()).unwrap();
+        assert_eq!(stat.comm, "bash");
+        assert_eq!(stat.ppid, 77);
+        assert_eq!(stat.starttime, 987654);
+    }
+
+    #[test]
+    fn stat_comm_with_spaces_and_parentheses() {
+        for comm in ["tmux: server", "a) S 1 2 (b", "))", "(", ""] {
+            let line = stat_line(comm);
+            let stat = parse_stat(line.as_bytes()).unwrap();
+            assert_eq!(stat.comm, comm);
+            assert_eq!(stat.ppid, 77);
+            assert_eq!(stat.starttime, 987654);
+        }
+    }
+
+    #[test]
+    fn stat_malformed() {
+        assert!(parse_stat(b"").is_none());
+        assert!(parse_stat(b"1234 (bash S 77").is_none());
+        assert!(parse_stat(b"1234 (bash) S 77 5 6").is_none());
+        assert!(
+            parse_stat(b"1234 (bash) S x 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn status() {
+        let content = b"Name:\tmy (weird) name\nUmask:\t0022\nState:\tS (sleeping)\n\
+            PPid:\t77\nUid:\t1000\t0\t0\t0\nGid:\t100\t100\t100\t100\n\
+            NSpid:\t1234\t56\t1\nCapPrm:\t000001ffffffffff\nCapEff:\t0000000000000400\n";
+        let status = parse_status(content).unwrap();
+        assert_eq!(status.name, "my (weird) name");
+        assert_eq!(status.ppid, 77);
+        assert_eq!(status.ruid, 1000);
+        assert_eq!(status.rgid, 100);
+        assert_eq!(status.ns_pid, Some(1));
+        assert_eq!(status.capprm, 0x1ff_ffff_ffff);
+        assert_eq!(status.capeff, 0x400);
+    }
+
+    #[test]
+    fn status_own_namespace() {
+        let content = b"Name:\tsleep\nPPid:\t1\nUid:\t0\t0\t0\t0\nGid:\t0\t0\t0\t0\nNSpid:\t42\n";
+        let status = parse_status(content).unwrap();
+        assert_eq!(status.ns_pid, None);
+        assert_eq!(status.capeff, 0);
+        assert!(parse_status(b"Name:\tsleep\nPPid:\t1\nUid:\t0\t0\t0\t0\n").is_none());
+    }
+
+    #[test]
+    fn cmdlines() {
+        assert!(split_cmdline(b"").is_empty());
+        assert_eq!(split_cmdline(b"ls\0-la\0\0"), ["ls", "-la", ""]);
+        assert_eq!(split_cmdline(b"sh\0-c\0echo a b"), ["sh", "-c", "echo a b"]);
+
+        assert!(cmdline_matches(b"ls\0-la\0", "ls -la"));
+        assert!(cmdline_matches(b"ls -la", "ls -la"));
+        assert!(!cmdline_matches(b"ls\0-l\0", "ls -la"));
+        assert!(cmdline_matches(b"cat\0\xff\0", "cat \u{fffd}"));
+    }
+}
@@ -0,0 +1,64 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::core::constants::{WORKER_JOIN_TIMEOUT_SECS, WORKER_POLL_INTERVAL_MS};
+use crate::core::logger::Logger;
+
+pub type Worker = (&'static str, JoinHandle<()>);
+
+// sleeps in short slices so a stop request is noticed, returns false once stopped
+pub fn sleep(running: &AtomicBool, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(WORKER_POLL_INTERVAL_MS)));
+    }
+    false
+}
+
+// blocks until fd has data to read, returns false once stopped so blocking reads never
+// keep a worker alive past shutdown
+pub fn wait_readable(fd: RawFd, running: &AtomicBool) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    while running.load(Ordering::SeqCst) {
+        let ready = unsafe { libc::poll(&mut pollfd, 1, WORKER_POLL_INTERVAL_MS as libc::c_int) };
+        if ready > 0 {
+            return Ok(true);
+        }
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+    Ok(false)
+}
+
+// waits for the workers to notice the stop request, one stuck in a slow call is left
+// behind rather than hanging the exit
+pub fn join(workers: Vec<Worker>) {
+    let deadline = Instant::now() + Duration::from_secs(WORKER_JOIN_TIMEOUT_SECS);
+    for (name, worker) in workers {
+        while !worker.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if !worker.is_finished() {
+            Logger::debug(format!("{} did not stop in time", name));
+            continue;
+        }
+        if worker.join().is_err() {
+            Logger::error(format!("{} panicked", name));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+const NONE: u8 = 0;
+const PAUSE: u8 = 1;
+const RESUME: u8 = 2;
+
+// the last of SIGUSR1 (pause) and SIGUSR2 (resume) not yet taken by the event loop
+static PENDING: AtomicU8 = AtomicU8::new(NONE);
+// SIGQUIT, like the thread dumps of a jvm
+static STATS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle(signal: libc::c_int) {
+    match signal {
+        libc::SIGUSR1 => PENDING.store(PAUSE, Ordering::SeqCst),
+        libc::SIGUSR2 => PENDING.store(RESUME, Ordering::SeqCst),
+        _ => STATS_REQUESTED.store(true, Ordering::SeqCst),
+    }
+}
+
+// `kill -USR1` pauses rspy and `kill -USR2` resumes it, e.g. around a maintenance window.
+// `kill -QUIT` (or ctrl-\) prints the live statistics
+pub fn install() -> io::Result<()> {
+    for signal in [libc::SIGUSR1, libc::SIGUSR2, libc::SIGQUIT] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // blocking reads of the collectors carry on instead of failing with EINTR
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn take_stats_request() -> bool {
+    STATS_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+// Some(true) to pause, Some(false) to resume
+pub fn take_pause_request() -> Option<bool> {
+    match PENDING.swap(NONE, Ordering::SeqCst) {
+        PAUSE => Some(true),
+        RESUME => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,160 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+
+pub const TCP_ESTABLISHED: u8 = 0x01;
+pub const TCP_LISTEN: u8 = 0x0a;
+
+// __SO_ACCEPTCON in the flags column of /proc/net/unix
+const UNIX_ACCEPTCON: u32 = 0x10000;
+
+const INET_TABLES: [(&str, &str); 4] = [
+    ("tcp", "tcp"),
+    ("tcp6", "tcp"),
+    ("udp", "udp"),
+    ("udp6", "udp"),
+];
+
+#[derive(Clone, Debug)]
+pub struct InetSocket {
+    pub protocol: &'static str,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub state: u8,
+    pub uid: u32,
+    pub inode: u64,
+}
+
+impl InetSocket {
+    pub fn is_listening(&self) -> bool {
+        match self.protocol {
+            "tcp" => self.state == TCP_LISTEN,
+            // an unconnected udp socket receives from anyone
+            _ => self.remote.port() == 0,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_listening() {
+            format!("{} listening on {}", self.protocol, self.local)
+        } else {
+            format!("{} {} -> {}", self.protocol, self.local, self.remote)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UnixSocket {
+    pub inode: u64,
+    // abstract names start with '@', socketpairs and unbound sockets have none
+    pub path: Option<String>,
+    pub listening: bool,
+}
+
+impl UnixSocket {
+    pub fn describe(&self) -> String {
+        let path = self.path.as_deref().unwrap_or("(unnamed)");
+        if self.listening {
+            format!("unix listening on {}", path)
+        } else {
+            format!("unix {}", path)
+        }
+    }
+}
+
+// `net` is /proc/net or /proc/<pid>/net, the latter shows the sockets of that process's
+// network namespace
+pub fn inet_sockets(net: &Path) -> Vec<InetSocket> {
+    INET_TABLES
+        .iter()
+        .filter_map(|(table, protocol)| {
+            let content = std::fs::read_to_string(net.join(table)).ok()?;
+            Some(
+                content
+                    .lines()
+                    .skip(1)
+                    .filter_map(|line| parse_inet_line(line, protocol))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+// "  1: 0100007F:0277 00000000:0000 0A 00000000:00000000 00:00000000 00000000  0  0 12345 ..."
+fn parse_inet_line(line: &str, protocol: &'static str) -> Option<InetSocket> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    Some(InetSocket {
+        protocol,
+        local: parse_address(fields[1])?,
+        remote: parse_address(fields[2])?,
+        state: u8::from_str_radix(fields[3], 16).ok()?,
+        uid: fields[7].parse().ok()?,
+        inode: fields[9].parse().ok()?,
+    })
+}
+
+// the address is printed as the raw in-memory words of the kernel, the port in host order
+fn parse_address(field: &str) -> Option<SocketAddr> {
+    let (address, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    let mut words = Vec::with_capacity(4);
+    for chunk in address.as_bytes().chunks(8) {
+        let word = u32::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+        words.push(word.to_ne_bytes());
+    }
+    let ip = match words.as_slice() {
+        [word] => IpAddr::V4(Ipv4Addr::from(*word)),
+        [a, b, c, d] => {
+            let mut octets = [0u8; 16];
+            for (i, word) in [a, b, c, d].iter().enumerate() {
+                octets[i * 4..i * 4 + 4].copy_from_slice(*word);
+            }
+            let ip = Ipv6Addr::from(octets);
+            // dual-stack sockets report ipv4 peers as ::ffff:a.b.c.d
+            match ip.to_ipv4_mapped() {
+                Some(ip) => IpAddr::V4(ip),
+                None => IpAddr::V6(ip),
+            }
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+pub fn unix_sockets(net: &Path) -> Vec<UnixSocket> {
+    let Ok(content) = std::fs::read_to_string(net.join("unix")) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .skip(1)
+        .filter_map(parse_unix_line)
+        .collect()
+}
+
+// "0000000000000000: 00000002 00000000 00010000 0001 01 12345 /run/systemd/notify"
+fn parse_unix_line(line: &str) -> Option<UnixSocket> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 7 {
+        return None;
+    }
+    let flags = u32::from_str_radix(fields[3], 16).ok()?;
+    Some(UnixSocket {
+        inode: fields[6].parse().ok()?,
+        path: (fields.len() > 7).then(|| fields[7..].join(" ")),
+        listening: flags & UNIX_ACCEPTCON != 0,
+    })
+}
+
+// the inode behind a "socket:[12345]" or "pipe:[12345]" fd link
+pub fn link_inode(link: &str, kind: &str) -> Option<u64> {
+    link.strip_prefix(kind)?
+        .strip_prefix(":[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
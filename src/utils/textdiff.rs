@@ -0,0 +1,132 @@
+// line diffs of the small files rspy keeps a copy of, longest common subsequence over
+// whatever lies between the common head and tail
+const MAX_TABLE_CELLS: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Copy)]
+enum Op {
+    Equal(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// the hunks of a unified diff with `context` lines around each change, without the
+// ---/+++ header
+pub fn unified(old: &str, new: &str, context: usize) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let ops = operations(&old, &new);
+
+    // (index into ops, line in old, line in new) at every op
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut line_old, mut line_new) = (0, 0);
+    for op in &ops {
+        positions.push((line_old, line_new));
+        match op {
+            Op::Equal(_) => {
+                line_old += 1;
+                line_new += 1;
+            }
+            Op::Delete(_) => line_old += 1,
+            Op::Insert(_) => line_new += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..ops.len())
+        .filter(|&i| !matches!(ops[i], Op::Equal(_)))
+        .collect();
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        // changes closer than twice the context share a hunk
+        let mut last = i;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * context + 1 {
+            last += 1;
+        }
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[last] + context + 1).min(ops.len());
+
+        let mut lines = Vec::new();
+        let (mut count_old, mut count_new) = (0, 0);
+        for op in &ops[start..end] {
+            match *op {
+                Op::Equal(index) => {
+                    lines.push(format!(" {}", old[index]));
+                    count_old += 1;
+                    count_new += 1;
+                }
+                Op::Delete(index) => {
+                    lines.push(format!("-{}", old[index]));
+                    count_old += 1;
+                }
+                Op::Insert(index) => {
+                    lines.push(format!("+{}", new[index]));
+                    count_new += 1;
+                }
+            }
+        }
+        let (start_old, start_new) = positions[start];
+        // an empty side names the line before it, as diff -u does
+        let first = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+        hunks.push(format!(
+            "@@ -{},{} +{},{} @@",
+            first(start_old, count_old),
+            count_old,
+            first(start_new, count_new),
+            count_new
+        ));
+        hunks.extend(lines);
+        i = last + 1;
+    }
+    hunks
+}
+
+fn operations(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let head = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let tail = old[head..]
+        .iter()
+        .rev()
+        .zip(new[head..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let middle_old = &old[head..old.len() - tail];
+    let middle_new = &new[head..new.len() - tail];
+
+    let mut ops: Vec<Op> = (0..head).map(Op::Equal).collect();
+    let (n, m) = (middle_old.len(), middle_new.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_TABLE_CELLS {
+        // too much changed to be worth lining up, all of it was replaced
+        ops.extend((head..head + n).map(Op::Delete));
+        ops.extend((head..head + m).map(Op::Insert));
+    } else {
+        // lengths[i][j], the longest common subsequence of middle_old[i..] and middle_new[j..]
+        let width = m + 1;
+        let mut lengths = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i * width + j] = if middle_old[i] == middle_new[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && middle_old[i] == middle_new[j] {
+                ops.push(Op::Equal(head + i));
+                i += 1;
+                j += 1;
+            } else if j == m
+                || (i < n && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+            {
+                ops.push(Op::Delete(head + i));
+                i += 1;
+            } else {
+                ops.push(Op::Insert(head + j));
+                j += 1;
+            }
+        }
+    }
+    ops.extend((old.len() - tail..old.len()).map(Op::Equal));
+    ops
+}
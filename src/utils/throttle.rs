@@ -0,0 +1,120 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::core::constants::{CPU_BUDGET_MAX_BACKOFF_SECS, CPU_BUDGET_WINDOW_MS};
+use crate::core::logger::Logger;
+
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+const IOPRIO_CLASS_BE: i32 = 2;
+const IOPRIO_CLASS_IDLE: i32 = 3;
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Priority {
+    pub nice: i32,
+    // only read the disk when nothing else wants to, otherwise the best-effort level
+    // follows the niceness the way ionice(1) does by default
+    pub idle_io: bool,
+}
+
+// called first thing by every collector thread, niceness and io priority are per thread
+// on linux so the event loop and the sinks keep their own
+pub fn enter_collector_thread(priority: Option<Priority>) {
+    let Some(priority) = priority else {
+        return;
+    };
+    if let Err(e) = apply(priority) {
+        Logger::error(format!(
+            "failed to lower the priority of a collector thread: {}",
+            e
+        ));
+    }
+}
+
+fn apply(priority: Priority) -> io::Result<()> {
+    // who 0 is the calling thread
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, priority.nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let ioprio = if priority.idle_io {
+        IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT
+    } else {
+        (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | ((priority.nice + 20) / 5).clamp(0, 7)
+    };
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// rspy's own cpu time from /proc/self/stat against wall time, scans are held off for as
+// long as it takes to get back under the limit
+pub struct CpuBudget {
+    limit: f64,
+    ticks_per_second: f64,
+    window_start: Instant,
+    window_ticks: u64,
+}
+
+impl CpuBudget {
+    pub fn new(percent: u32) -> Self {
+        Self {
+            limit: percent as f64 / 100.0,
+            ticks_per_second: procfs::ticks_per_second().unwrap_or(100) as f64,
+            window_start: Instant::now(),
+            window_ticks: own_cpu_ticks().unwrap_or_default(),
+        }
+    }
+
+    // zero while within the budget
+    pub fn backoff(&mut self) -> Duration {
+        let Some((ticks, elapsed, backoff)) = self.measure() else {
+            return Duration::ZERO;
+        };
+
+        // a new window starts once the old one is long enough for the tick granularity,
+        // the backoff pays off what it used
+        if elapsed >= Duration::from_millis(CPU_BUDGET_WINDOW_MS) {
+            self.window_start = Instant::now() + backoff;
+            self.window_ticks = ticks;
+        }
+        backoff
+    }
+
+    // the same check without starting a new window, e.g. between the scans of a burst
+    pub fn exceeded(&self) -> bool {
+        self.measure()
+            .is_some_and(|(_, _, backoff)| !backoff.is_zero())
+    }
+
+    fn measure(&self) -> Option<(u64, Duration, Duration)> {
+        let ticks = own_cpu_ticks()?;
+        let elapsed = self.window_start.elapsed();
+        let used = ticks.saturating_sub(self.window_ticks) as f64 / self.ticks_per_second;
+        let backoff = Duration::from_secs_f64((used / self.limit - elapsed.as_secs_f64()).max(0.0))
+            .min(Duration::from_secs(CPU_BUDGET_MAX_BACKOFF_SECS));
+        Some((ticks, elapsed, backoff))
+    }
+}
+
+// utime + stime of all threads
+fn own_cpu_ticks() -> Option<u64> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let (_, rest) = stat.rsplit_once(')')?;
+    let mut fields = rest.split_whitespace();
+    // utime and stime are fields 14 and 15, the 12th and 13th after comm
+    let utime: u64 = fields.nth(11)?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+// `interval` moved by a random amount of up to `percent` of it either way, so scans do not
+// tick at a fixed rate. the std hasher keys are random enough and cost no system call
+pub fn jitter(interval: Duration, percent: u32) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now());
+    let spread = interval.as_secs_f64() * f64::from(percent.min(100)) / 100.0;
+    let offset = (random as f64 / u64::MAX as f64) * 2.0 - 1.0;
+    Duration::from_secs_f64((interval.as_secs_f64() + spread * offset).max(0.0))
+}
@@ -0,0 +1,163 @@
+use rustc_hash::FxHashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::core::constants::{
+    GROUP_FILE, LOGIN_DEFS_FILE, PASSWD_FILE, USER_DB_RECHECK_INTERVAL_MS, USER_UID_MAX,
+    USER_UID_MIN,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DATABASE: Mutex<Option<Database>> = Mutex::new(None);
+static REGULAR_UIDS: OnceLock<(u32, u32)> = OnceLock::new();
+
+// names are resolved from the local files only, NSS sources like LDAP are not consulted
+#[derive(Default)]
+struct Database {
+    users: FxHashMap<u32, String>,
+    groups: FxHashMap<u32, String>,
+    passwd_mtime: Option<SystemTime>,
+    group_mtime: Option<SystemTime>,
+    last_check: Option<Instant>,
+}
+
+impl Database {
+    // accounts created while rspy runs are interesting, so a miss rereads changed files,
+    // at most once per interval so unknown uids (e.g. from containers) stay cheap
+    fn refresh(&mut self) {
+        if self
+            .last_check
+            .is_some_and(|last| last.elapsed() < Duration::from_millis(USER_DB_RECHECK_INTERVAL_MS))
+        {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+
+        let passwd_mtime = mtime(Path::new(PASSWD_FILE));
+        if passwd_mtime != self.passwd_mtime {
+            self.users = read_names(Path::new(PASSWD_FILE));
+            self.passwd_mtime = passwd_mtime;
+        }
+
+        let group_mtime = mtime(Path::new(GROUP_FILE));
+        if group_mtime != self.group_mtime {
+            self.groups = read_names(Path::new(GROUP_FILE));
+            self.group_mtime = group_mtime;
+        }
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn user_name(uid: u32) -> Option<String> {
+    lookup(uid, |database| &database.users)
+}
+
+pub fn group_name(gid: u32) -> Option<String> {
+    lookup(gid, |database| &database.groups)
+}
+
+fn lookup(id: u32, table: impl Fn(&Database) -> &FxHashMap<u32, String>) -> Option<String> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let mut database = DATABASE.lock().ok()?;
+    let database = database.get_or_insert_with(Database::default);
+    if let Some(name) = table(database).get(&id) {
+        return Some(name.clone());
+    }
+    database.refresh();
+    table(database).get(&id).cloned()
+}
+
+// uids handed out to people rather than services, per UID_MIN/UID_MAX in login.defs
+pub fn is_regular_user(uid: u32) -> bool {
+    let (min, max) = *REGULAR_UIDS.get_or_init(|| {
+        let defs = std::fs::read_to_string(LOGIN_DEFS_FILE).unwrap_or_default();
+        let value = |key: &str| {
+            defs.lines()
+                .filter_map(|line| line.split_once(char::is_whitespace))
+                .find(|(name, _)| *name == key)
+                .and_then(|(_, value)| value.trim().parse().ok())
+        };
+        (
+            value("UID_MIN").unwrap_or(USER_UID_MIN),
+            value("UID_MAX").unwrap_or(USER_UID_MAX),
+        )
+    });
+    (min..=max).contains(&uid)
+}
+
+// the primary group from passwd and every group listing the user as a member, read from
+// the local files whether or not --resolve-users is on
+pub fn groups_of(uid: u32) -> Vec<u32> {
+    let passwd = std::fs::read_to_string(PASSWD_FILE).unwrap_or_default();
+    let Some((name, gid)) = passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() > 3 && fields[2].parse() == Ok(uid))
+            .then(|| (fields[0].to_string(), fields[3].parse::<u32>().ok()))
+    }) else {
+        return Vec::new();
+    };
+
+    let mut groups: Vec<u32> = gid.into_iter().collect();
+    let group = std::fs::read_to_string(GROUP_FILE).unwrap_or_default();
+    for line in group.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        if let [_, _, gid, members] = fields[..]
+            && members.split(',').any(|member| member == name)
+            && let Ok(gid) = gid.parse()
+            && !groups.contains(&gid)
+        {
+            groups.push(gid);
+        }
+    }
+    groups
+}
+
+// the home directories in passwd that exist, each once, e.g. for the files of every user
+// that --persistence-watch keeps an eye on
+pub fn home_directories() -> Vec<PathBuf> {
+    let passwd = std::fs::read_to_string(PASSWD_FILE).unwrap_or_default();
+    let mut homes: Vec<PathBuf> = Vec::new();
+    for line in passwd.lines().filter(|line| !line.starts_with('#')) {
+        let Some(home) = line.split(':').nth(5).filter(|home| *home != "/") else {
+            continue;
+        };
+        let home = PathBuf::from(home);
+        if home.is_dir() && !homes.contains(&home) {
+            homes.push(home);
+        }
+    }
+    homes
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// passwd and group share the "name:x:id:..." layout, the first entry for an id wins like
+// it does for getpwuid
+fn read_names(path: &Path) -> FxHashMap<u32, String> {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    let mut names = FxHashMap::default();
+    for line in content.lines().filter(|line| !line.starts_with('#')) {
+        let mut fields = line.split(':');
+        let (Some(name), Some(id)) = (fields.next(), fields.nth(1)) else {
+            continue;
+        };
+        if let Ok(id) = id.parse() {
+            names.entry(id).or_insert_with(|| name.to_string());
+        }
+    }
+    names
+}